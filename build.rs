@@ -0,0 +1,107 @@
+include!("src/help.rs");
+
+fn roff(section: &str) -> String {
+    section
+        .lines()
+        .skip(1)
+        .map(|line| line.trim_start().replace('\\', "\\\\").replace('-', "\\-"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_owned())
+}
+
+fn locked_version(lockfile: &str, package: &str) -> String {
+    let name = format!("name = \"{}\"", package);
+
+    lockfile
+        .split("[[package]]")
+        .find(|entry| entry.lines().any(|line| line.trim() == name))
+        .and_then(|entry| {
+            entry
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("version = "))
+        })
+        .map(|version| version.trim_matches('"').to_owned())
+        .unwrap_or_else(|| "unknown".into())
+}
+
+fn build_info() {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let lockfile = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(str::to_lowercase))
+        .collect();
+    features.sort();
+
+    let vars = [
+        (
+            "RURL_GIT_HASH",
+            command_output("git", &["rev-parse", "--short", "HEAD"])
+                .unwrap_or_else(|| "unknown".into()),
+        ),
+        (
+            "RURL_RUSTC_VERSION",
+            command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".into()),
+        ),
+        ("RURL_TARGET", std::env::var("TARGET").unwrap_or_default()),
+        ("RURL_PROFILE", std::env::var("PROFILE").unwrap_or_default()),
+        ("RURL_FEATURES", features.join(",")),
+        ("RURL_HYPER_VERSION", locked_version(&lockfile, "hyper")),
+        ("RURL_RUSTLS_VERSION", locked_version(&lockfile, "rustls")),
+        ("RURL_TOKIO_VERSION", locked_version(&lockfile, "tokio")),
+        ("RURL_SYNTECT_VERSION", locked_version(&lockfile, "syntect")),
+    ];
+
+    for (key, value) in vars.iter() {
+        println!("cargo:rustc-env={}={}", key, value);
+    }
+
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+}
+
+fn main() {
+    build_info();
+
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let man = format!(
+        r#".TH {name} 1 "" "{name} {version}"
+.SH NAME
+{name} \- HTTPie, but in rust
+.SH SYNOPSIS
+.B {name}
+[\fIOPTIONS\fR] \fIMETHOD\fR \fIURI\fR [\fIREQUEST_ITEM\fR]...
+.SH DESCRIPTION
+Make an HTTP request and print the highlighted request and response.
+Run \fB{name} \-\-help\fR for the full list of options.
+.SH REQUEST ITEMS
+.nf
+{request_items}
+.fi
+.SH EXAMPLES
+.nf
+{examples}
+.fi
+"#,
+        name = env!("CARGO_PKG_NAME"),
+        version = env!("CARGO_PKG_VERSION"),
+        request_items = roff(REQUEST_ITEMS),
+        examples = roff(EXAMPLES),
+    );
+
+    std::fs::write(std::path::Path::new(&out_dir).join("rurl.1"), man).unwrap();
+    println!("cargo:rerun-if-changed=src/help.rs");
+}
@@ -0,0 +1,301 @@
+use crate::encoding;
+use hyper::{header, HeaderMap, StatusCode};
+
+/// Bodies smaller than this rarely benefit from compression.
+const MIN_COMPRESSIBLE_SIZE: usize = 1024;
+
+#[derive(Default)]
+struct Report {
+    output: String,
+}
+
+impl Report {
+    fn ok(&mut self, check: &str, message: impl std::fmt::Display) {
+        self.output += &format!("ok     {:<12} {}\n", check, message);
+    }
+
+    fn warn(&mut self, check: &str, message: impl std::fmt::Display) {
+        self.output += &format!("warn   {:<12} {}\n", check, message);
+    }
+
+    fn info(&mut self, check: &str, message: impl std::fmt::Display) {
+        self.output += &format!("info   {:<12} {}\n", check, message);
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn is_compressible(content_type: Option<&str>) -> bool {
+    let content_type = content_type.unwrap_or_default().to_ascii_lowercase();
+
+    content_type.starts_with("text/")
+        || ["json", "xml", "javascript", "yaml", "csv", "graphql"]
+            .iter()
+            .any(|kind| content_type.contains(kind))
+}
+
+fn percent(part: usize, whole: usize) -> usize {
+    (part * 100).checked_div(whole).unwrap_or(0)
+}
+
+fn check_compression(report: &mut Report, headers: &HeaderMap, body: &[u8]) {
+    let encoding = header_str(headers, header::CONTENT_ENCODING).map(str::to_ascii_lowercase);
+
+    match encoding.as_deref() {
+        Some("gzip") | Some("x-gzip") => match encoding::gunzip(body) {
+            Some(decoded) => report.ok(
+                "compression",
+                format!(
+                    "gzip, {} bytes from {} ({}% of the original)",
+                    body.len(),
+                    decoded.len(),
+                    percent(body.len(), decoded.len())
+                ),
+            ),
+            None => report.ok("compression", format!("gzip, {} bytes", body.len())),
+        },
+        Some("identity") | None => {
+            if body.len() < MIN_COMPRESSIBLE_SIZE
+                || !is_compressible(header_str(headers, header::CONTENT_TYPE))
+            {
+                report.ok(
+                    "compression",
+                    format!("none, not worthwhile for {} bytes of this type", body.len()),
+                );
+                return;
+            }
+
+            let compressed = encoding::gzip(body);
+
+            report.warn(
+                "compression",
+                format!(
+                    "{} bytes sent uncompressed; gzip would send {} bytes, saving {}% \
+                     (send Accept-Encoding:gzip to check whether the server negotiates it)",
+                    body.len(),
+                    compressed.len(),
+                    100 - percent(compressed.len(), body.len()).min(100)
+                ),
+            );
+        }
+        Some(encoding) => report.ok("compression", format!("{}, {} bytes", encoding, body.len())),
+    }
+}
+
+fn check_caching(report: &mut Report, status: StatusCode, headers: &HeaderMap) {
+    let cache_control = header_str(headers, header::CACHE_CONTROL)
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let directives: Vec<&str> = cache_control.split(',').map(str::trim).collect();
+    let directive = |name: &str| {
+        directives
+            .iter()
+            .find(|directive| directive.split('=').next() == Some(name))
+            .map(|directive| directive.split_once('=').map_or("", |(_, value)| value))
+    };
+
+    let vary = header_str(headers, header::VARY).unwrap_or_default();
+    let validators: Vec<&str> = [
+        (header::ETAG, "ETag"),
+        (header::LAST_MODIFIED, "Last-Modified"),
+    ]
+    .iter()
+    .filter(|(name, _)| headers.contains_key(name))
+    .map(|(_, label)| *label)
+    .collect();
+
+    if directive("no-store").is_some() {
+        report.info("cache", "no-store, never cached");
+    } else if vary.trim() == "*" {
+        report.warn("cache", "Vary: * makes the response uncacheable");
+    } else if let Some(max_age) = directive("s-maxage").or_else(|| directive("max-age")) {
+        let scope = if directive("private").is_some() {
+            "browsers only"
+        } else {
+            "shared caches too"
+        };
+
+        report.ok("cache", format!("fresh for {}s, {}", max_age, scope));
+    } else if directive("no-cache").is_some() {
+        report.info("cache", "no-cache, revalidated on every use");
+    } else if headers.contains_key(header::EXPIRES) {
+        report.ok(
+            "cache",
+            "freshness from Expires (prefer Cache-Control: max-age)",
+        );
+    } else if status.is_success() {
+        report.warn(
+            "cache",
+            "no Cache-Control, caches will guess a freshness lifetime heuristically",
+        );
+    }
+
+    if validators.is_empty() {
+        report.warn(
+            "validators",
+            "no ETag or Last-Modified, so clients cannot revalidate with 304s",
+        );
+    } else {
+        report.ok("validators", validators.join(", "));
+    }
+
+    if !vary.is_empty() && vary.trim() != "*" {
+        report.info("vary", format!("cached separately per {}", vary));
+    }
+}
+
+fn check_length(report: &mut Report, headers: &HeaderMap, body: &[u8]) {
+    match header_str(headers, header::CONTENT_LENGTH) {
+        Some(length) if length.parse::<usize>().ok() == Some(body.len()) => {
+            report.ok("length", format!("Content-Length {}", length))
+        }
+        Some(length) => report.warn(
+            "length",
+            format!(
+                "Content-Length {} but {} bytes were received",
+                length,
+                body.len()
+            ),
+        ),
+        None if headers.contains_key(header::TRANSFER_ENCODING) => report.info(
+            "length",
+            "streamed with Transfer-Encoding and no Content-Length, so clients cannot show progress",
+        ),
+        None => report.warn(
+            "length",
+            "no Content-Length, the body ends when the connection closes",
+        ),
+    }
+}
+
+/// Performance hygiene notes on compression, cacheability, and framing.
+pub fn analyze(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> String {
+    let mut report = Report::default();
+
+    check_compression(&mut report, headers, body);
+    check_caching(&mut report, status, headers);
+    check_length(&mut report, headers, body);
+
+    report.output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    fn cache(pairs: &[(header::HeaderName, &str)]) -> String {
+        let mut report = Report::default();
+        check_caching(&mut report, StatusCode::OK, &headers(pairs));
+        report.output.lines().next().unwrap_or_default().to_owned()
+    }
+
+    #[test]
+    fn passes_a_well_behaved_response() {
+        let json = "{\"id\": 1}".repeat(200);
+        let body = encoding::gzip(json.as_bytes());
+        let headers = headers(&[
+            (header::CONTENT_TYPE, "application/json"),
+            (header::CONTENT_ENCODING, "gzip"),
+            (header::CONTENT_LENGTH, &body.len().to_string()),
+            (header::CACHE_CONTROL, "public, max-age=60"),
+            (header::ETAG, "\"v1\""),
+            (header::VARY, "Accept-Encoding"),
+        ]);
+
+        let report = analyze(StatusCode::OK, &headers, &body);
+
+        assert_eq!(
+            report,
+            format!(
+                "ok     compression  gzip, {} bytes from {} ({}% of the original)\n\
+                 ok     cache        fresh for 60s, shared caches too\n\
+                 ok     validators   ETag\n\
+                 info   vary         cached separately per Accept-Encoding\n\
+                 ok     length       Content-Length {}\n",
+                body.len(),
+                json.len(),
+                body.len() * 100 / json.len(),
+                body.len()
+            )
+        );
+    }
+
+    #[test]
+    fn warns_about_a_careless_response() {
+        let body = "{\"id\": 1}".repeat(200);
+        let headers = headers(&[(header::CONTENT_TYPE, "application/json")]);
+
+        let report = analyze(StatusCode::OK, &headers, body.as_bytes());
+        let warnings: Vec<&str> = report
+            .lines()
+            .filter(|line| line.starts_with("warn"))
+            .map(|line| line.split_whitespace().nth(1).unwrap())
+            .collect();
+
+        assert_eq!(warnings, ["compression", "cache", "validators", "length"]);
+        assert!(report.contains("1800 bytes sent uncompressed; gzip would send"));
+    }
+
+    #[test]
+    fn small_or_binary_bodies_need_no_compression() {
+        let mut report = Report::default();
+        let png = headers(&[(header::CONTENT_TYPE, "image/png")]);
+        check_compression(&mut report, &png, &[0; 4096]);
+        check_compression(&mut report, &HeaderMap::new(), b"{}");
+
+        assert!(report.output.lines().all(|line| line.starts_with("ok")));
+        assert!(is_compressible(Some("application/vnd.api+JSON")));
+        assert!(!is_compressible(None));
+    }
+
+    #[test]
+    fn reads_cache_control_in_order_of_precedence() {
+        assert!(cache(&[(header::CACHE_CONTROL, "no-store, max-age=60")]).contains("no-store"));
+        assert!(
+            cache(&[(header::VARY, "*"), (header::CACHE_CONTROL, "max-age=60")])
+                .starts_with("warn   cache        Vary: *")
+        );
+        assert!(
+            cache(&[(header::CACHE_CONTROL, "private, max-age=60, s-maxage=600")])
+                .ends_with("fresh for 600s, browsers only")
+        );
+        assert!(cache(&[(header::CACHE_CONTROL, "no-cache")]).contains("revalidated"));
+        assert!(cache(&[(header::EXPIRES, "Thu, 01 Jan 2026 00:00:00 GMT")])
+            .contains("freshness from Expires"));
+
+        let mut report = Report::default();
+        check_caching(&mut report, StatusCode::NOT_FOUND, &HeaderMap::new());
+        assert!(!report.output.contains("cache "));
+    }
+
+    #[test]
+    fn checks_the_length_against_the_body() {
+        let length = |pairs: &[(header::HeaderName, &str)]| {
+            let mut report = Report::default();
+            check_length(&mut report, &headers(pairs), b"hello");
+            report.output
+        };
+
+        assert!(length(&[(header::CONTENT_LENGTH, "5")]).starts_with("ok"));
+        assert!(length(&[(header::CONTENT_LENGTH, "10")])
+            .contains("Content-Length 10 but 5 bytes were received"));
+        assert!(length(&[(header::TRANSFER_ENCODING, "chunked")]).starts_with("info"));
+        assert!(length(&[]).starts_with("warn"));
+    }
+
+    #[test]
+    fn percentages_of_nothing_are_zero() {
+        assert_eq!(percent(1, 4), 25);
+        assert_eq!(percent(5, 0), 0);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{
+    aws::{self, AwsOptions},
+    decode,
+    hmac::{self, HmacOptions},
+    plugin, prompt,
+};
+use anyhow::Result;
+use hyper::{Body, Request};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("unknown auth type {0}, expected aws4, hmac, or the NAME of a rurl-auth-NAME plugin")]
+    UnknownType(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthType {
+    Aws4,
+    Hmac,
+    /// Run the `rurl-auth-NAME` plugin.
+    Plugin(String),
+}
+
+impl std::str::FromStr for AuthType {
+    type Err = AuthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "aws4" | "aws-sigv4" => Ok(Self::Aws4),
+            "hmac" => Ok(Self::Hmac),
+            name if plugin::is_valid_name(name) => Ok(Self::Plugin(name.into())),
+            _ => Err(Self::Err::UnknownType(s.into())),
+        }
+    }
+}
+
+/// Signs or otherwise authenticates a request once it is fully built.
+#[derive(Clone, Debug, Default)]
+pub struct Auth {
+    pub auth_type: Option<AuthType>,
+    pub aws: AwsOptions,
+    pub hmac: HmacOptions,
+}
+
+impl Auth {
+    pub fn apply(&self, req: &mut Request<Body>, body: &[u8]) -> Result<()> {
+        match &self.auth_type {
+            Some(AuthType::Aws4) => aws::sign(req, body, &self.aws),
+            Some(AuthType::Hmac) => hmac::sign(req, body, &self.hmac),
+            Some(AuthType::Plugin(name)) => plugin::authenticate(name, req, body),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The `Authorization` value for `--auth USER[:PASSWORD]`, prompting for
+/// the password when it is left out.
+pub fn basic(credentials: &str) -> Result<String> {
+    let credentials = match credentials.find(':') {
+        Some(_) => credentials.to_owned(),
+        None => {
+            let password = prompt::secret(&format!("password for {}", credentials))?;
+            format!("{}:{}", credentials, password)
+        }
+    };
+
+    Ok(format!(
+        "Basic {}",
+        decode::encode_base64(credentials.as_bytes())
+    ))
+}
@@ -0,0 +1,177 @@
+use crate::{
+    cancel::CancellationToken,
+    client::{self, HttpClient},
+    rate::RateLimiter,
+    retry::{CircuitBreaker, RetryBudget},
+};
+use hyper::{Body, Request};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Default)]
+pub struct BenchReport {
+    pub latencies: Vec<Duration>,
+    pub statuses: BTreeMap<u16, usize>,
+    pub errors: BTreeMap<String, usize>,
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    pub fn requests(&self) -> usize {
+        self.latencies.len() + self.errors.values().sum::<usize>()
+    }
+
+    fn percentile(&self, percentile: usize) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::default();
+        }
+
+        let index = (self.latencies.len() * percentile / 100).min(self.latencies.len() - 1);
+        self.latencies[index]
+    }
+
+    pub fn format(&self, deterministic: bool) -> String {
+        let duration = |duration: Duration| {
+            if deterministic {
+                crate::deterministic::PLACEHOLDER.to_owned()
+            } else {
+                format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+            }
+        };
+
+        let requests = self.requests();
+        let throughput = if deterministic {
+            crate::deterministic::PLACEHOLDER.to_owned()
+        } else {
+            format!(
+                "{:.1}",
+                requests as f64 / self.elapsed.as_secs_f64().max(1e-9)
+            )
+        };
+
+        let mut output = format!(
+            "requests:   {}\nthroughput: {} req/s\nlatency:    p50 {}  p90 {}  p99 {}  max {}\n",
+            requests,
+            throughput,
+            duration(self.percentile(50)),
+            duration(self.percentile(90)),
+            duration(self.percentile(99)),
+            duration(self.latencies.last().copied().unwrap_or_default()),
+        );
+
+        output += "statuses:  ";
+        for (status, count) in self.statuses.iter() {
+            output += &format!(" {}×{}", status, count);
+        }
+        output += "\n";
+
+        for (error, count) in self.errors.iter() {
+            output += &format!("error:      {}×{}\n", count, error);
+        }
+
+        output
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Bench {
+    pub repeat: usize,
+    pub concurrency: usize,
+    pub rate: Option<Arc<RateLimiter>>,
+    pub budget: Arc<RetryBudget>,
+    pub breaker: Arc<CircuitBreaker>,
+    pub cancel: CancellationToken,
+}
+
+/// Sends `req` `bench.repeat` times over at most `bench.concurrency`
+/// simultaneous connections, sharing one retry budget and circuit breaker,
+/// and starting no faster than `bench.rate`.
+pub async fn run(
+    bench: Bench,
+    client: HttpClient,
+    req: Arc<Request<Body>>,
+    body: Arc<Vec<u8>>,
+) -> BenchReport {
+    let Bench {
+        repeat,
+        concurrency,
+        rate,
+        budget,
+        breaker,
+        cancel,
+    } = bench;
+    let next = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+
+    let workers: Vec<_> = (0..concurrency.max(1).min(repeat))
+        .map(|_| {
+            let client = client.clone();
+            let req = req.clone();
+            let body = body.clone();
+            let rate = rate.clone();
+            let budget = budget.clone();
+            let breaker = breaker.clone();
+            let cancel = cancel.clone();
+            let next = next.clone();
+
+            tokio::spawn(async move {
+                let mut results = Vec::new();
+
+                while next.fetch_add(1, Ordering::SeqCst) < repeat {
+                    if let Some(rate) = &rate {
+                        rate.acquire().await;
+                    }
+
+                    let start = Instant::now();
+                    let res = client::send(&client, &req, &body, &budget, &breaker, &cancel).await;
+
+                    let result = match res {
+                        Ok(res) => {
+                            let status = res.status().as_u16();
+
+                            match hyper::body::to_bytes(res.into_body()).await {
+                                Ok(_) => Ok((status, start.elapsed())),
+                                Err(err) => Err(err.to_string()),
+                            }
+                        }
+                        Err(err) => Err(err.to_string()),
+                    };
+
+                    let cancelled = cancel.is_cancelled();
+                    results.push(result);
+
+                    if cancelled {
+                        break;
+                    }
+                }
+
+                results
+            })
+        })
+        .collect();
+
+    let mut report = BenchReport::default();
+
+    for worker in workers {
+        for result in worker.await.unwrap_or_default() {
+            match result {
+                Ok((status, latency)) => {
+                    *report.statuses.entry(status).or_default() += 1;
+                    report.latencies.push(latency);
+                }
+                Err(err) => *report.errors.entry(err).or_default() += 1,
+            }
+        }
+    }
+
+    report.latencies.sort();
+    report.elapsed = start.elapsed();
+
+    report
+}
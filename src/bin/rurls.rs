@@ -0,0 +1,23 @@
+//! `rurls`: rurl with https as the scheme for URIs typed without one, like
+//! HTTPie's `https` executable. It runs the `rurl` installed next to it.
+
+use std::process::Command;
+
+fn main() {
+    let rurl = std::env::current_exe()
+        .map(|exe| exe.with_file_name(format!("rurl{}", std::env::consts::EXE_SUFFIX)))
+        .unwrap_or_else(|_| "rurl".into());
+
+    let status = Command::new(&rurl)
+        .args(std::env::args_os().skip(1))
+        .env("RURL_DEFAULT_SCHEME", "https")
+        .status();
+
+    match status {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(err) => {
+            eprintln!("error: could not run {}: {}", rurl.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
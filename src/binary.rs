@@ -0,0 +1,31 @@
+pub const NOTE: &str = "\
++-----------------------------------------+
+| NOTE: binary data not shown in terminal |
+|  use --output FILE or pipe to save it   |
++-----------------------------------------+";
+
+const SNIFF_LEN: usize = 1024;
+
+pub fn is_binary(content_type: Option<&mime::Mime>, body: &[u8]) -> bool {
+    if let Some(content_type) = content_type {
+        match (content_type.type_(), content_type.subtype()) {
+            (mime::TEXT, _) => return false,
+            (mime::APPLICATION, mime::JSON) | (mime::APPLICATION, mime::XML) => return false,
+            (mime::IMAGE, _) | (mime::AUDIO, _) | (mime::VIDEO, _) | (mime::FONT, _) => {
+                return true
+            }
+            (mime::APPLICATION, mime::OCTET_STREAM) | (mime::APPLICATION, mime::PDF) => {
+                return true
+            }
+            (mime::APPLICATION, subtype) => match subtype.as_str() {
+                "gzip" | "zip" | "x-protobuf" | "protobuf" | "grpc" | "msgpack" | "wasm" => {
+                    return true
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    body.iter().take(SNIFF_LEN).any(|byte| *byte == 0) || std::str::from_utf8(body).is_err()
+}
@@ -0,0 +1,657 @@
+use crate::{msgpack, protobuf::Protobuf, request_item::RequestItem, xml, yaml};
+use anyhow::Result;
+use hyper::{header::HeaderName, Uri};
+use json::JsonValue;
+use std::{str::FromStr as _, sync::Arc};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ModeError {
+    #[error("Missing mode options")]
+    MissingMode,
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum Mode {
+    Form,
+    Graphql,
+    #[default]
+    Json,
+    Msgpack,
+    /// Not parsed from a name, as it needs a descriptor set, see `--proto`.
+    Protobuf(Arc<Protobuf>),
+    Xml,
+    Yaml,
+}
+
+impl Mode {
+    /// The `Accept` to send by default: JSON for the modes that expect it
+    /// back, and anything for the rest.
+    pub fn accept(&self) -> Option<&'static str> {
+        match self {
+            Self::Json | Self::Graphql => Some("application/json"),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Mode {
+    type Err = ModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "form" => Ok(Self::Form),
+            "graphql" => Ok(Self::Graphql),
+            "json" => Ok(Self::Json),
+            "msgpack" => Ok(Self::Msgpack),
+            "xml" => Ok(Self::Xml),
+            "yaml" => Ok(Self::Yaml),
+            _ => Err(Self::Err::MissingMode),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RequestBody {
+    pub content: Vec<u8>,
+    pub content_type: Option<String>,
+    pub language: String,
+}
+
+/// Parses a URI as typed, adding `default_scheme` when it has none, so
+/// `example.com/users` and `localhost:8080` are accepted.
+pub fn parse_uri(uri: &str, default_scheme: &str) -> Result<Uri> {
+    if uri.contains("://") {
+        return Ok(uri.parse()?);
+    }
+
+    Ok(format!("{}://{}", default_scheme, uri).parse()?)
+}
+
+pub fn normalize_uri(uri: Uri) -> Result<Uri> {
+    use hyper::http::uri::*;
+
+    let mut parts = uri.into_parts();
+
+    match parts.scheme {
+        Some(_) => {}
+        None => parts.scheme = Some(Scheme::HTTP),
+    }
+
+    match parts.path_and_query {
+        Some(_) => {}
+        None => parts.path_and_query = Some(PathAndQuery::from_str("/")?),
+    }
+
+    Ok(Uri::from_parts(parts)?)
+}
+
+/// RFC 3986's remove_dot_segments, as browsers and curl apply it before
+/// sending: `/a/./b/../c` becomes `/a/c`. Empty segments (`//`) are kept.
+pub fn remove_dot_segments(path: &str) -> String {
+    let mut segments = path.split('/').skip(1).peekable();
+    let mut output = Vec::new();
+
+    while let Some(segment) = segments.next() {
+        match segment {
+            "." | ".." => {
+                if segment == ".." {
+                    output.pop();
+                }
+
+                // `/a/b/..` ends in a directory, so keep its slash
+                if segments.peek().is_none() {
+                    output.push("");
+                }
+            }
+            segment => output.push(segment),
+        }
+    }
+
+    format!("/{}", output.join("/"))
+}
+
+/// `uri` with the `key==value` items added to the end of its query.
+pub fn append_search_params(uri: Uri, request_items: &[RequestItem]) -> Result<Uri> {
+    let params: Vec<String> = request_items
+        .iter()
+        .filter_map(|request_item| match request_item {
+            RequestItem::SearchParam { key, value } => Some(format!(
+                "{}={}",
+                urlencoding::encode(key),
+                urlencoding::encode(value)
+            )),
+            _ => None,
+        })
+        .collect();
+
+    if params.is_empty() {
+        return Ok(uri);
+    }
+
+    let path_and_query = match uri.query().filter(|query| !query.is_empty()) {
+        Some(query) => format!("{}?{}&{}", uri.path(), query, params.join("&")),
+        None => format!("{}?{}", uri.path(), params.join("&")),
+    };
+
+    let mut parts = uri.into_parts();
+    parts.path_and_query = Some(path_and_query.parse()?);
+
+    Ok(Uri::from_parts(parts)?)
+}
+
+/// `uri` with the dot segments removed from its path, unless `--path-as-is`.
+pub fn resolve_dot_segments(uri: Uri) -> Result<Uri> {
+    let path = uri.path();
+
+    if !path
+        .split('/')
+        .any(|segment| segment == "." || segment == "..")
+    {
+        return Ok(uri);
+    }
+
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", remove_dot_segments(path), query),
+        None => remove_dot_segments(path),
+    };
+    crate::trace!("sending {} as {}", path, path_and_query);
+
+    let mut parts = uri.into_parts();
+    parts.path_and_query = Some(path_and_query.parse()?);
+
+    Ok(Uri::from_parts(parts)?)
+}
+
+pub fn has_header(request_items: &[RequestItem], name: &HeaderName) -> bool {
+    request_items.iter().any(|request_item| {
+        matches!(
+            request_item,
+            RequestItem::Header { key, .. }
+                | RequestItem::EmptyHeader { key }
+                | RequestItem::UnsetHeader { key } if key == name
+        )
+    })
+}
+
+pub fn content_type_from_path(path: &std::path::Path) -> String {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "json" => mime::APPLICATION_JSON.to_string(),
+        "xml" => "application/xml".into(),
+        "html" | "htm" => mime::TEXT_HTML.to_string(),
+        "txt" => mime::TEXT_PLAIN.to_string(),
+        "csv" => "text/csv".into(),
+        "yaml" | "yml" => "application/yaml".into(),
+        "js" => "application/javascript".into(),
+        _ => mime::APPLICATION_OCTET_STREAM.to_string(),
+    }
+}
+
+/// Builds the body the items send in `mode`. `deterministic` fixes what
+/// would otherwise vary between runs, such as the multipart boundary.
+pub fn build_body(
+    mode: Option<&Mode>,
+    request_items: &[RequestItem],
+    deterministic: bool,
+) -> Result<RequestBody> {
+    let raw_bodies: Vec<_> = request_items
+        .iter()
+        .filter_map(|request_item| match request_item {
+            RequestItem::RawBody { path, value } => Some((path, value)),
+            _ => None,
+        })
+        .collect();
+
+    if raw_bodies.len() > 1 {
+        anyhow::bail!(
+            "only one raw body can be sent, from @file, @clipboard, --gen-body, --dav, or \
+             --from-json, but {} were given",
+            raw_bodies.len()
+        );
+    }
+
+    if let Some(&(path, value)) = raw_bodies.first() {
+        if request_items.iter().any(|request_item| {
+            matches!(
+                request_item,
+                RequestItem::Data { .. } | RequestItem::JsonData { .. }
+            )
+        }) {
+            anyhow::bail!("a raw @file body cannot be combined with data items");
+        }
+
+        let content_type = if has_header(request_items, &hyper::header::CONTENT_TYPE) {
+            None
+        } else {
+            Some(content_type_from_path(path))
+        };
+
+        return Ok(RequestBody {
+            content: value.clone(),
+            content_type,
+            language: path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .unwrap_or("txt")
+                .to_owned(),
+        });
+    }
+
+    let files = request_items
+        .iter()
+        .any(|request_item| matches!(request_item, RequestItem::FormFile { .. }));
+
+    if files && !matches!(mode, Some(Mode::Form)) {
+        anyhow::bail!("key@file items are multipart fields and need --form");
+    }
+
+    let (body, language) = match mode {
+        Some(Mode::Form) if files => (multipart(request_items, deterministic), "txt"),
+        Some(Mode::Json) | None => match data_object(request_items)? {
+            Some(body) => (
+                RequestBody {
+                    content: body.dump().into_bytes(),
+                    content_type: Some(mime::APPLICATION_JSON.to_string()),
+                    ..Default::default()
+                },
+                "json",
+            ),
+            None => (RequestBody::default(), "json"),
+        },
+        Some(Mode::Xml) => structured(
+            request_items,
+            |body| xml::from_json(body).into_bytes(),
+            "application/xml",
+            "xml",
+        )?,
+        Some(Mode::Yaml) => structured(
+            request_items,
+            |body| yaml::from_json(body).into_bytes(),
+            "application/yaml",
+            "yaml",
+        )?,
+        Some(Mode::Msgpack) => structured(
+            request_items,
+            msgpack::encode,
+            "application/msgpack",
+            "msgpack",
+        )?,
+        Some(Mode::Protobuf(proto)) => match data_object(request_items)? {
+            Some(body) => (
+                RequestBody {
+                    content: proto.encode(&body)?,
+                    content_type: Some(proto.content_type().into()),
+                    ..Default::default()
+                },
+                "protobuf",
+            ),
+            None => (RequestBody::default(), "protobuf"),
+        },
+        Some(Mode::Form) => {
+            let body = request_items
+                .iter()
+                .flat_map(form_fields)
+                .map(|(key, value)| {
+                    format!(
+                        "{}={}",
+                        urlencoding::encode(key),
+                        urlencoding::encode(&value)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("&");
+
+            let content_type = if !body.is_empty() {
+                Some(format!(
+                    "{}; charset=utf-8",
+                    mime::APPLICATION_WWW_FORM_URLENCODED
+                ))
+            } else {
+                None
+            };
+
+            (
+                RequestBody {
+                    content: body.into_bytes(),
+                    content_type,
+                    ..Default::default()
+                },
+                "json",
+            )
+        }
+        Some(Mode::Graphql) => {
+            let mut body = JsonValue::new_object();
+            let mut variables = JsonValue::new_object();
+            // `variables:=` or `vars:=` gives the whole object, and the
+            // other items are merged into it
+            let mut all_variables = None;
+
+            for request_item in request_items {
+                match request_item {
+                    RequestItem::Data { key, value }
+                        if key == "query" || key == "operationName" =>
+                    {
+                        body[key.as_str()] = value.as_str().into();
+                    }
+                    RequestItem::Data { key, value } => {
+                        variables[key.as_str()] = value.as_str().into()
+                    }
+                    RequestItem::JsonData { key, value } if key == "variables" || key == "vars" => {
+                        if !value.is_object() {
+                            anyhow::bail!("--graphql {}:= must be a JSON object", key);
+                        }
+                        all_variables = Some(value.clone());
+                    }
+                    RequestItem::JsonData { key, value } => variables[key.as_str()] = value.clone(),
+                    _ => {}
+                }
+            }
+
+            let variables = match all_variables {
+                Some(mut all_variables) => {
+                    for (key, value) in variables.entries() {
+                        all_variables[key] = value.clone();
+                    }
+                    all_variables
+                }
+                None => variables,
+            };
+
+            if !body.has_key("query") {
+                anyhow::bail!("--graphql requires a query=... or query=@file item");
+            }
+
+            if !variables.is_empty() {
+                body["variables"] = variables;
+            }
+
+            (
+                RequestBody {
+                    content: body.dump().into_bytes(),
+                    content_type: Some(mime::APPLICATION_JSON.to_string()),
+                    ..Default::default()
+                },
+                "json",
+            )
+        }
+    };
+
+    Ok(RequestBody {
+        language: language.into(),
+        ..body
+    })
+}
+
+/// How a `key:=json` item goes out in a form: strings as they are, numbers
+/// and booleans as their JSON text, null as an empty value, and objects as
+/// JSON. Each element of an array is a field of its own, like repeating
+/// the item, so `ids:='[1, 2]'` sends `ids=1&ids=2`.
+fn form_values(value: &JsonValue) -> Vec<String> {
+    let coerce = |value: &JsonValue| match value {
+        JsonValue::Null => String::new(),
+        value => value.as_str().map_or_else(|| value.dump(), str::to_owned),
+    };
+
+    match value {
+        JsonValue::Array(values) => values.iter().map(coerce).collect(),
+        value => vec![coerce(value)],
+    }
+}
+
+/// The name and value of each form field an item sends, in order.
+fn form_fields(request_item: &RequestItem) -> Vec<(&str, String)> {
+    match request_item {
+        RequestItem::Data { key, value } => vec![(key.as_str(), value.clone())],
+        RequestItem::JsonData { key, value } => form_values(value)
+            .into_iter()
+            .map(|value| (key.as_str(), value))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// A boundary that appears in none of the parts, counting up from the
+/// time, or from zero when `deterministic`.
+fn boundary(parts: &[(String, Vec<u8>)], deterministic: bool) -> String {
+    let start = if deterministic {
+        0
+    } else {
+        std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default()
+    };
+
+    (0..)
+        .map(|n: u64| format!("rurl-{:016x}", start.wrapping_add(n)))
+        .find(|boundary| {
+            parts.iter().all(|(_, content)| {
+                !content
+                    .windows(boundary.len())
+                    .any(|window| window == boundary.as_bytes())
+            })
+        })
+        .unwrap()
+}
+
+/// `--form` with `key@file` items: a multipart/form-data body, with data
+/// items as plain fields and files sent as their raw bytes.
+fn multipart(request_items: &[RequestItem], deterministic: bool) -> RequestBody {
+    let mut parts = Vec::new();
+
+    for request_item in request_items {
+        let (headers, content) = match request_item {
+            RequestItem::FormFile {
+                key,
+                value,
+                content,
+                content_type,
+                filename,
+            } => {
+                let filename = match filename {
+                    Some(filename) => filename.clone(),
+                    None => value
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                };
+                let content_type = content_type
+                    .clone()
+                    .unwrap_or_else(|| content_type_from_path(value));
+
+                (
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+                         Content-Type: {}",
+                        quote(key),
+                        quote(&filename),
+                        content_type
+                    ),
+                    content.clone(),
+                )
+            }
+            request_item => {
+                for (key, value) in form_fields(request_item) {
+                    parts.push((
+                        format!("Content-Disposition: form-data; name=\"{}\"", quote(key)),
+                        value.into_bytes(),
+                    ));
+                }
+
+                continue;
+            }
+        };
+
+        parts.push((headers, content));
+    }
+
+    let boundary = boundary(&parts, deterministic);
+    let mut content = Vec::new();
+
+    for (headers, part) in parts {
+        content.extend_from_slice(format!("--{}\r\n{}\r\n\r\n", boundary, headers).as_bytes());
+        content.extend_from_slice(&part);
+        content.extend_from_slice(b"\r\n");
+    }
+    content.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    RequestBody {
+        content,
+        content_type: Some(format!("multipart/form-data; boundary={}", boundary)),
+        ..Default::default()
+    }
+}
+
+/// Escapes a name or filename for a quoted Content-Disposition parameter.
+fn quote(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Data items as one object, as the JSON mode sends them, or `None` when
+/// there are none.
+fn data_object(request_items: &[RequestItem]) -> Result<Option<JsonValue>> {
+    let mut body = JsonValue::new_object();
+    let mut any = false;
+
+    for request_item in request_items {
+        if let RequestItem::Data { key, value } = request_item {
+            body[key.as_str()] = json::parse(&json::stringify(value.as_str()))?;
+            any = true;
+        } else if let RequestItem::JsonData { key, value } = request_item {
+            body[key.as_str()] = value.clone();
+            any = true;
+        }
+    }
+
+    Ok(if any { Some(body) } else { None })
+}
+
+/// The data object in another format, for `--xml`, `--yaml`, and `--msgpack`.
+fn structured(
+    request_items: &[RequestItem],
+    serialize: impl Fn(&JsonValue) -> Vec<u8>,
+    content_type: &str,
+    language: &'static str,
+) -> Result<(RequestBody, &'static str)> {
+    let body = match data_object(request_items)? {
+        Some(body) => RequestBody {
+            content: serialize(&body),
+            content_type: Some(content_type.into()),
+            ..Default::default()
+        },
+        None => RequestBody::default(),
+    };
+
+    Ok((body, language))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_dot_segments() {
+        assert_eq!(remove_dot_segments("/a/b/c/./../../g"), "/a/g");
+        assert_eq!(remove_dot_segments("/a/b/.."), "/a/");
+        assert_eq!(remove_dot_segments("/a/./"), "/a/");
+        assert_eq!(remove_dot_segments("/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(remove_dot_segments("/a//b/../c"), "/a//c");
+        assert_eq!(remove_dot_segments("/a/..b/.c"), "/a/..b/.c");
+    }
+
+    #[test]
+    fn appends_search_params_to_the_query() {
+        let items = vec![
+            "q==a b".parse().unwrap(),
+            "name=ezra".parse().unwrap(),
+            "tag==x&y".parse().unwrap(),
+        ];
+        let uri = |uri: &str| append_search_params(uri.parse().unwrap(), &items).unwrap();
+
+        assert_eq!(
+            uri("http://example.com/search").to_string(),
+            "http://example.com/search?q=a%20b&tag=x%26y"
+        );
+        assert_eq!(
+            uri("http://example.com/search?page=2").to_string(),
+            "http://example.com/search?page=2&q=a%20b&tag=x%26y"
+        );
+        assert_eq!(
+            append_search_params("http://example.com/?a=1".parse().unwrap(), &[])
+                .unwrap()
+                .to_string(),
+            "http://example.com/?a=1"
+        );
+    }
+
+    #[test]
+    fn takes_graphql_variables_whole_or_one_by_one() {
+        let body = |items: &[&str]| {
+            let items: Vec<RequestItem> = items.iter().map(|item| item.parse().unwrap()).collect();
+            let body = build_body(Some(&Mode::Graphql), &items, false)?;
+            Ok::<_, anyhow::Error>(json::parse(std::str::from_utf8(&body.content)?)?)
+        };
+
+        assert_eq!(
+            body(&["query=q", r#"vars:={"id":1}"#]).unwrap()["variables"],
+            json::object! { "id" => 1 }
+        );
+        assert_eq!(
+            body(&["query=q", "name=ezra", r#"variables:={"id":1,"name":"x"}"#]).unwrap()
+                ["variables"],
+            json::object! { "id" => 1, "name" => "ezra" }
+        );
+        assert_eq!(
+            body(&["query=q", "id:=1"]).unwrap()["variables"],
+            json::object! { "id" => 1 }
+        );
+        assert!(body(&["query=q", "vars:=[1]"]).is_err());
+    }
+
+    #[test]
+    fn coerces_json_values_for_forms() {
+        let values = |value: &str| form_values(&json::parse(value).unwrap());
+
+        assert_eq!(values(r#""ezra""#), ["ezra"]);
+        assert_eq!(values("3.5"), ["3.5"]);
+        assert_eq!(values("true"), ["true"]);
+        assert_eq!(values("null"), [""]);
+        assert_eq!(values(r#"[1, "a", null, [2]]"#), ["1", "a", "", "[2]"]);
+        assert_eq!(values("[]"), Vec::<String>::new());
+        assert_eq!(values(r#"{"a": 1}"#), [r#"{"a":1}"#]);
+    }
+
+    #[test]
+    fn only_one_raw_body_is_sent() {
+        let raw = |path: &str| RequestItem::RawBody {
+            path: path.into(),
+            value: b"{}".to_vec(),
+        };
+
+        assert!(build_body(None, &[raw("a.json")], false).is_ok());
+
+        let err = build_body(None, &[raw("a.json"), raw("gen-body.json")], false).unwrap_err();
+        assert!(err.to_string().contains("but 2 were given"), "{}", err);
+    }
+
+    #[test]
+    fn deterministic_boundaries_are_fixed_but_unused_by_the_parts() {
+        let parts = vec![("a".to_owned(), b"hello".to_vec())];
+        assert_eq!(boundary(&parts, true), "rurl-0000000000000000");
+
+        let parts = vec![(
+            "a".to_owned(),
+            b"--rurl-0000000000000000 and rurl-0000000000000001".to_vec(),
+        )];
+        assert_eq!(boundary(&parts, true), "rurl-0000000000000002");
+    }
+}
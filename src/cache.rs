@@ -0,0 +1,273 @@
+//! `--cache-dir`: GET responses with an `ETag` or `Last-Modified` are kept
+//! on disk, keyed by the URI and request headers. The next identical
+//! request is made conditional, and a `304 Not Modified` is answered with
+//! the kept response.
+//!
+//! Each entry is two files named by the key's hash: `KEY.json` with the
+//! status and headers, and `KEY.body` with the body as it was received.
+
+use crate::{
+    digest::{hex, sha256},
+    state,
+};
+use anyhow::Result;
+use hyper::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Body, Request, Response, StatusCode,
+};
+use json::JsonValue;
+use std::path::{Path, PathBuf};
+
+pub struct ResponseCache {
+    path: PathBuf,
+}
+
+pub struct Entry {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl ResponseCache {
+    /// The cache entry for `req`, before any validators are added to it.
+    pub fn new(dir: &Path, req: &Request<Body>) -> Self {
+        let mut key = format!("{} {}\n", req.method(), req.uri());
+
+        for (name, value) in req.headers() {
+            key += &format!("{}: {}\n", name, String::from_utf8_lossy(value.as_bytes()));
+        }
+
+        Self {
+            path: dir.join(hex(&sha256(key.as_bytes()))),
+        }
+    }
+
+    pub fn lookup(&self) -> Option<Entry> {
+        let meta =
+            json::parse(&std::fs::read_to_string(self.path.with_extension("json")).ok()?).ok()?;
+        let body = std::fs::read(self.path.with_extension("body")).ok()?;
+
+        let mut headers = HeaderMap::new();
+        for pair in meta["headers"].members() {
+            let name: HeaderName = pair[0].as_str()?.parse().ok()?;
+            let value: HeaderValue = pair[1].as_str()?.parse().ok()?;
+            headers.append(name, value);
+        }
+
+        Some(Entry {
+            status: StatusCode::from_u16(meta["status"].as_u16()?).ok()?,
+            headers,
+            body,
+        })
+    }
+
+    /// Keeps a response that can be revalidated later, that is a 200 with
+    /// a validator and without `Cache-Control: no-store`.
+    pub fn store(&self, status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Result<bool> {
+        let no_store = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .any(|value| value.to_str().is_ok_and(|value| value.contains("no-store")));
+        let validated =
+            headers.contains_key(header::ETAG) || headers.contains_key(header::LAST_MODIFIED);
+
+        if status != StatusCode::OK || no_store || !validated {
+            return Ok(false);
+        }
+
+        let mut meta = JsonValue::new_object();
+        meta["status"] = status.as_u16().into();
+        meta["headers"] = JsonValue::new_array();
+
+        for (name, value) in headers {
+            if let Ok(value) = value.to_str() {
+                meta["headers"].push(json::array![name.as_str(), value])?;
+            }
+        }
+
+        state::write_atomic(&self.path.with_extension("body"), body, 0o666)?;
+        state::write_atomic(
+            &self.path.with_extension("json"),
+            meta.dump().as_bytes(),
+            0o666,
+        )?;
+
+        Ok(true)
+    }
+}
+
+impl Entry {
+    /// `If-None-Match` and `If-Modified-Since` from the kept validators.
+    pub fn validators(&self) -> Vec<(HeaderName, HeaderValue)> {
+        let mut validators = Vec::new();
+
+        if let Some(etag) = self.headers.get(header::ETAG) {
+            validators.push((header::IF_NONE_MATCH, etag.clone()));
+        }
+
+        if let Some(modified) = self.headers.get(header::LAST_MODIFIED) {
+            validators.push((header::IF_MODIFIED_SINCE, modified.clone()));
+        }
+
+        validators
+    }
+
+    pub fn into_response(self) -> Response<Body> {
+        let mut res = Response::new(Body::from(self.body));
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers;
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rurl-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn request(method: &str, uri: &str, headers: &[(&str, &str)]) -> Request<Body> {
+        let mut builder = Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    fn validated(pairs: &[(HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn keys_entries_by_method_uri_and_headers() {
+        let dir = scratch_dir("keys");
+        let key = |req: &Request<Body>| ResponseCache::new(&dir, req).path;
+        let get = request("GET", "http://example.com/a", &[("accept", "text/html")]);
+
+        assert_eq!(
+            key(&get),
+            key(&request(
+                "GET",
+                "http://example.com/a",
+                &[("Accept", "text/html")]
+            ))
+        );
+        for other in &[
+            request("HEAD", "http://example.com/a", &[("accept", "text/html")]),
+            request("GET", "http://example.com/b", &[("accept", "text/html")]),
+            request(
+                "GET",
+                "http://example.com/a?v=2",
+                &[("accept", "text/html")],
+            ),
+            request(
+                "GET",
+                "http://example.com/a",
+                &[("accept", "application/json")],
+            ),
+            request("GET", "http://example.com/a", &[]),
+        ] {
+            assert_ne!(key(&get), key(other), "{:?}", other);
+        }
+    }
+
+    #[test]
+    fn keeps_and_reads_back_validated_responses() {
+        let dir = scratch_dir("round-trip");
+        let cache = ResponseCache::new(&dir, &request("GET", "http://example.com/", &[]));
+        let mut headers = validated(&[
+            (header::ETAG, "\"v1\""),
+            (header::LAST_MODIFIED, "Tue, 01 Jun 2021 00:00:00 GMT"),
+        ]);
+        headers.append(header::SET_COOKIE, HeaderValue::from_static("a=1"));
+        headers.append(header::SET_COOKIE, HeaderValue::from_static("b=2"));
+
+        assert!(cache.lookup().is_none());
+        assert!(cache.store(StatusCode::OK, &headers, b"\x00body").unwrap());
+
+        let entry = cache.lookup().unwrap();
+        assert_eq!(entry.status, StatusCode::OK);
+        assert_eq!(entry.headers, headers);
+        assert_eq!(entry.body, b"\x00body");
+        assert_eq!(
+            entry.validators(),
+            [
+                (header::IF_NONE_MATCH, HeaderValue::from_static("\"v1\"")),
+                (
+                    header::IF_MODIFIED_SINCE,
+                    HeaderValue::from_static("Tue, 01 Jun 2021 00:00:00 GMT")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_newer_response_replaces_the_kept_one() {
+        let dir = scratch_dir("replace");
+        let cache = ResponseCache::new(&dir, &request("GET", "http://example.com/", &[]));
+
+        cache
+            .store(
+                StatusCode::OK,
+                &validated(&[(header::ETAG, "\"v1\"")]),
+                b"old",
+            )
+            .unwrap();
+        cache
+            .store(
+                StatusCode::OK,
+                &validated(&[(header::ETAG, "\"v2\"")]),
+                b"new",
+            )
+            .unwrap();
+
+        let entry = cache.lookup().unwrap();
+        assert_eq!(entry.headers[header::ETAG], "\"v2\"");
+        assert_eq!(entry.body, b"new");
+    }
+
+    #[test]
+    fn only_revalidatable_responses_are_kept() {
+        let dir = scratch_dir("skipped");
+        let cache = ResponseCache::new(&dir, &request("GET", "http://example.com/", &[]));
+
+        let etag = validated(&[(header::ETAG, "\"v1\"")]);
+        let no_store = validated(&[
+            (header::ETAG, "\"v1\""),
+            (header::CACHE_CONTROL, "private, no-store"),
+        ]);
+
+        assert!(!cache.store(StatusCode::OK, &HeaderMap::new(), b"").unwrap());
+        assert!(!cache.store(StatusCode::OK, &no_store, b"").unwrap());
+        assert!(!cache.store(StatusCode::CREATED, &etag, b"").unwrap());
+        assert!(!cache.store(StatusCode::NOT_MODIFIED, &etag, b"").unwrap());
+        assert!(cache.lookup().is_none());
+    }
+
+    #[test]
+    fn unreadable_entries_are_misses() {
+        let dir = scratch_dir("corrupt");
+        let cache = ResponseCache::new(&dir, &request("GET", "http://example.com/", &[]));
+
+        std::fs::write(cache.path.with_extension("json"), "{\"status\": 200").unwrap();
+        std::fs::write(cache.path.with_extension("body"), "kept").unwrap();
+        assert!(cache.lookup().is_none());
+
+        std::fs::write(
+            cache.path.with_extension("json"),
+            "{\"status\": 200, \"headers\": []}",
+        )
+        .unwrap();
+        std::fs::remove_file(cache.path.with_extension("body")).unwrap();
+        assert!(cache.lookup().is_none());
+    }
+}
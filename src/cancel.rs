@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::watch;
+
+#[derive(Debug, Error)]
+#[error("cancelled {0}")]
+pub struct CancelledError(pub String);
+
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    tx: Arc<watch::Sender<bool>>,
+    rx: watch::Receiver<bool>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        let (tx, rx) = watch::channel(false);
+
+        Self {
+            tx: Arc::new(tx),
+            rx,
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        let _ = self.tx.broadcast(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    pub async fn cancelled(&self) {
+        let mut rx = self.rx.clone();
+
+        while !*rx.borrow() {
+            if rx.recv().await.is_none() {
+                break;
+            }
+        }
+    }
+
+    pub fn cancel_on_ctrl_c(&self) {
+        let token = self.clone();
+
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                token.cancel();
+            }
+        });
+    }
+}
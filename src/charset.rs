@@ -0,0 +1,87 @@
+//! Response text in the charset the server declared: a byte order mark
+//! first, then the Content-Type `charset` parameter, then UTF-8.
+
+use encoding_rs::{Encoding, UTF_8};
+
+/// The `charset` parameter of a Content-Type value, unquoted.
+fn charset_label(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_at(param.find('=')?);
+
+        if name.trim().eq_ignore_ascii_case("charset") {
+            Some(value[1..].trim().trim_matches('"'))
+        } else {
+            None
+        }
+    })
+}
+
+/// The encoding `body` declares, by byte order mark or by the charset in
+/// `content_type`, if either names one rurl knows.
+pub fn declared(content_type: Option<&str>, body: &[u8]) -> Option<&'static Encoding> {
+    match Encoding::for_bom(body) {
+        Some((encoding, _)) => Some(encoding),
+        None => Encoding::for_label(content_type.and_then(charset_label)?.as_bytes()),
+    }
+}
+
+/// Decodes `body` to UTF-8. Bytes that are invalid in the declared
+/// encoding become U+FFFD, with a warning to print next to the body.
+pub fn decode(content_type: Option<&str>, body: &[u8]) -> (String, Option<String>) {
+    let label = content_type.and_then(charset_label);
+    let encoding = declared(content_type, body).unwrap_or(UTF_8);
+
+    let unknown = match label {
+        Some(label) if Encoding::for_label(label.as_bytes()).is_none() => Some(label),
+        _ => None,
+    };
+
+    // decode() sniffs the byte order mark again and drops it
+    let (text, _, had_errors) = encoding.decode(body);
+
+    let warning = match (unknown, had_errors) {
+        (Some(label), true) => Some(format!(
+            "unknown charset {}, decoded the body as lossy UTF-8",
+            label
+        )),
+        (Some(label), false) => Some(format!(
+            "unknown charset {}, decoded the body as UTF-8",
+            label
+        )),
+        (None, true) => Some(format!(
+            "the body is not valid {}, invalid bytes are shown as \u{fffd}",
+            encoding.name()
+        )),
+        (None, false) => None,
+    };
+
+    (text.into_owned(), warning)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_the_declared_charset() {
+        assert_eq!(
+            decode(Some("text/plain; charset=ISO-8859-1"), b"caf\xe9"),
+            ("café".to_owned(), None)
+        );
+        assert_eq!(
+            decode(Some("text/plain; charset=\"utf-8\""), b"\xff\xfeh\0i\0"),
+            ("hi".to_owned(), None)
+        );
+        assert_eq!(
+            decode(Some("application/json"), b"{\"a\": \"\xe9\"}"),
+            (
+                "{\"a\": \"\u{fffd}\"}".to_owned(),
+                Some("the body is not valid UTF-8, invalid bytes are shown as \u{fffd}".into())
+            )
+        );
+        assert_eq!(
+            decode(Some("text/plain; charset=klingon"), b"ok").1,
+            Some("unknown charset klingon, decoded the body as UTF-8".into())
+        );
+    }
+}
@@ -0,0 +1,665 @@
+use crate::{
+    auth::Auth,
+    body::{self, Mode, RequestBody},
+    cancel::{CancellationToken, CancelledError},
+    dns::DnsResolver,
+    note,
+    preconnect::PrimedConnector,
+    progress,
+    request_item::RequestItem,
+    resolve::{Family, Hosts, OverrideResolver},
+    retry::{self, CircuitBreaker, RetryBudget},
+    timing::{Recorder, TimedConnector, TimedResolver},
+    tls::{self, TlsConnector, TlsOptions},
+    wire::{self, Tapped, TappedConnector},
+};
+use anyhow::Result;
+use hyper::{
+    body::{Bytes, HttpBody},
+    client::HttpConnector,
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Body, Client, Method, Request, Response, Uri,
+};
+use hyper_rustls::MaybeHttpsStream;
+use std::{
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{net::TcpStream, sync::oneshot};
+
+pub type Resolver = TimedResolver<OverrideResolver<DnsResolver>>;
+pub type Connector = PrimedConnector<
+    TappedConnector<TlsConnector<TimedConnector<HttpConnector<Resolver>>>>,
+    Tapped<MaybeHttpsStream<TcpStream>>,
+>;
+pub type HttpClient = Client<Connector, Upload>;
+
+/// Bodies this large are sent with `Expect: 100-continue`.
+pub const EXPECT_CONTINUE_MIN: usize = 1 << 20;
+
+/// How long a body sent with `Expect: 100-continue` waits for the server's
+/// 100 or its refusal. Like curl, the body goes out after this even if the
+/// server never answers, as servers that don't know the header won't.
+const CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a connection attempt to one address gets before one to the
+/// next, of the other family if there is one, races it (RFC 8305).
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Where and how the connector opens connections.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectOptions {
+    pub hosts: Hosts,
+    /// Only connect over IPv4 or IPv6.
+    pub family: Option<Family>,
+    /// Bind outgoing sockets to this local address.
+    pub local_address: Option<IpAddr>,
+    /// Ask these DNS servers rather than the system resolver.
+    pub dns_servers: Vec<SocketAddr>,
+    /// Or ask this DNS-over-HTTPS server.
+    pub doh_url: Option<Uri>,
+    pub tls: TlsOptions,
+}
+
+pub fn build_connector(recorder: &Recorder, options: ConnectOptions) -> Result<Connector> {
+    // a local address only reaches servers of its own family
+    let family = options
+        .family
+        .or_else(|| options.local_address.as_ref().map(Family::of));
+
+    let resolver = TimedResolver::new(
+        OverrideResolver::new(
+            DnsResolver::new(options.dns_servers, options.doh_url)?,
+            options.hosts,
+            family,
+        ),
+        recorder.clone(),
+    );
+
+    let mut http = HttpConnector::new_with_resolver(resolver);
+    http.enforce_http(false);
+    http.set_local_address(options.local_address);
+    http.set_happy_eyeballs_timeout(Some(CONNECTION_ATTEMPT_DELAY));
+
+    // the tap sits above TLS, so --raw-response shows the decrypted bytes
+    Ok(PrimedConnector::new(TappedConnector::new(
+        TlsConnector::new(
+            TimedConnector::new(http, recorder.clone()),
+            tls::client_config(&options.tls)?,
+            recorder.clone(),
+        ),
+    )))
+}
+
+/// A client sharing `connector`'s warm connections, so connections opened
+/// with `Connector::preconnect` are used by its requests. Idle connections
+/// stay pooled for `keepalive`, or close after each response when `None`.
+/// `http2` speaks HTTP/2 from the start, without upgrading.
+pub fn build_client(connector: Connector, keepalive: Option<Duration>, http2: bool) -> HttpClient {
+    let mut builder = Client::builder();
+
+    match keepalive {
+        Some(idle_timeout) => builder.pool_idle_timeout(idle_timeout),
+        None => builder.pool_max_idle_per_host(0),
+    };
+
+    builder.http2_only(http2).build(connector)
+}
+
+/// Trailers to send after a request's body, kept in the request's
+/// extensions so every attempt at it sends them.
+#[derive(Clone, Debug)]
+pub struct Trailers(pub HeaderMap);
+
+/// A request body followed by its trailers. hyper only sends trailers over
+/// HTTP/2; its HTTP/1.1 client leaves them out.
+#[derive(Debug)]
+pub struct Upload {
+    body: Body,
+    trailers: Option<HeaderMap>,
+}
+
+impl From<Body> for Upload {
+    fn from(body: Body) -> Self {
+        Self {
+            body,
+            trailers: None,
+        }
+    }
+}
+
+impl HttpBody for Upload {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.body).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(self.trailers.take()))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream() && self.trailers.is_none()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RurlRequestBuilder {
+    method: Method,
+    uri: Uri,
+    mode: Option<Mode>,
+    accept: Option<HeaderValue>,
+    empty_body: bool,
+    path_as_is: bool,
+    deterministic: bool,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    trailers: HeaderMap,
+    request_items: Vec<RequestItem>,
+}
+
+impl RurlRequestBuilder {
+    pub fn new(method: Method, uri: Uri) -> Self {
+        Self {
+            method,
+            uri,
+            mode: None,
+            accept: None,
+            empty_body: false,
+            path_as_is: false,
+            deterministic: false,
+            headers: Vec::new(),
+            trailers: HeaderMap::new(),
+            request_items: Vec::new(),
+        }
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn uri(mut self, uri: Uri) -> Self {
+        self.uri = uri;
+        self
+    }
+
+    pub fn mode(mut self, mode: Option<Mode>) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Replaces the default `Accept`, which otherwise follows the mode.
+    pub fn accept(mut self, accept: HeaderValue) -> Self {
+        self.accept = Some(accept);
+        self
+    }
+
+    /// Sends no body at all, rather than `{}`, when `--json` has no data.
+    pub fn empty_body(mut self, empty_body: bool) -> Self {
+        self.empty_body = empty_body;
+        self
+    }
+
+    /// Sends the path as given, without removing `.` and `..` segments.
+    pub fn path_as_is(mut self, path_as_is: bool) -> Self {
+        self.path_as_is = path_as_is;
+        self
+    }
+
+    /// Builds the same body on every run, with a fixed multipart boundary.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Adds a default header, left out when a user item sets the same name.
+    pub fn header(mut self, key: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((key, value));
+        self
+    }
+
+    /// Adds a trailer to send after the body, announced in `Trailer`.
+    pub fn trailer(mut self, key: HeaderName, value: HeaderValue) -> Self {
+        self.trailers.append(key, value);
+        self
+    }
+
+    pub fn request_item(mut self, request_item: RequestItem) -> Self {
+        self.request_items.push(request_item);
+        self
+    }
+
+    pub fn request_items(mut self, request_items: Vec<RequestItem>) -> Self {
+        self.request_items.extend(request_items);
+        self
+    }
+
+    pub fn has_header(&self, key: &HeaderName) -> bool {
+        self.headers.iter().any(|(name, _)| name == key)
+            || body::has_header(&self.request_items, key)
+    }
+
+    /// Like HTTPie, `--json` with nothing to send still sends a document on
+    /// methods that take one.
+    fn sends_empty_object(&self) -> bool {
+        matches!(self.mode, Some(Mode::Json))
+            && !self.empty_body
+            && matches!(self.method, Method::POST | Method::PUT | Method::PATCH)
+            && !self
+                .request_items
+                .iter()
+                .any(|request_item| matches!(request_item, RequestItem::RawBody { .. }))
+    }
+
+    pub fn build(self) -> Result<(Request<Body>, RequestBody)> {
+        let mut body =
+            body::build_body(self.mode.as_ref(), &self.request_items, self.deterministic)?;
+
+        if body.content.is_empty() && self.sends_empty_object() {
+            body = RequestBody {
+                content: b"{}".to_vec(),
+                content_type: Some(mime::APPLICATION_JSON.to_string()),
+                language: "json".into(),
+            };
+        }
+
+        let accept = match (self.accept, self.mode.as_ref().and_then(Mode::accept)) {
+            (Some(accept), _) => accept,
+            (None, Some(accept)) => HeaderValue::from_static(accept),
+            (None, None) => mime::STAR_STAR.to_string().parse()?,
+        };
+
+        let mut defaults = vec![
+            (header::ACCEPT, accept),
+            (
+                header::USER_AGENT,
+                HeaderValue::from_static(concat!(
+                    env!("CARGO_PKG_NAME"),
+                    "/",
+                    env!("CARGO_PKG_VERSION")
+                )),
+            ),
+        ];
+
+        defaults.extend(self.headers);
+
+        if let Some(content_type) = &body.content_type {
+            defaults.push((header::CONTENT_TYPE, content_type.parse()?));
+        }
+
+        if !body.content.is_empty() {
+            defaults.push((header::CONTENT_LENGTH, body.content.len().into()));
+        }
+
+        if !self.trailers.is_empty() {
+            let names: Vec<&str> = self.trailers.keys().map(HeaderName::as_str).collect();
+            defaults.push((header::TRAILER, names.join(", ").parse()?));
+        }
+
+        // header names are case-insensitive and always parsed lowercase, so
+        // `ACCEPT:...` replaces the default accept rather than duplicating it
+        let mut headers = HeaderMap::new();
+
+        for (key, value) in defaults {
+            if !body::has_header(&self.request_items, &key) {
+                headers.append(key, value);
+            }
+        }
+
+        for request_item in self.request_items.iter() {
+            match request_item {
+                RequestItem::Header { key, value } => {
+                    headers.append(key.clone(), value.clone());
+                }
+                RequestItem::EmptyHeader { key } => {
+                    headers.append(key.clone(), HeaderValue::from_static(""));
+                }
+                RequestItem::UnsetHeader { key } => {
+                    headers.remove(key);
+                }
+                _ => {}
+            }
+        }
+
+        let uri = body::normalize_uri(self.uri)?;
+        let uri = body::append_search_params(uri, &self.request_items)?;
+        let uri = if self.path_as_is {
+            uri
+        } else {
+            body::resolve_dot_segments(uri)?
+        };
+
+        let mut req = Request::builder()
+            .method(self.method)
+            .uri(uri)
+            .body(Body::from(body.content.clone()))?;
+
+        *req.headers_mut() = headers;
+
+        if !self.trailers.is_empty() {
+            req.extensions_mut().insert(Trailers(self.trailers));
+        }
+
+        Ok((req, body))
+    }
+}
+
+pub fn clone_request(req: &Request<Body>, body: &[u8]) -> Request<Body> {
+    with_body(req, progress::upload(body, None))
+}
+
+fn with_body(req: &Request<Body>, body: Body) -> Request<Body> {
+    let mut clone = Request::new(body);
+
+    *clone.method_mut() = req.method().clone();
+    *clone.uri_mut() = req.uri().clone();
+    *clone.version_mut() = req.version();
+    *clone.headers_mut() = req.headers().clone();
+
+    if let Some(trailers) = req.extensions().get::<Trailers>() {
+        clone.extensions_mut().insert(trailers.clone());
+    }
+
+    clone
+}
+
+pub async fn send(
+    client: &HttpClient,
+    req: &Request<Body>,
+    body: &[u8],
+    budget: &RetryBudget,
+    breaker: &CircuitBreaker,
+    cancel: &CancellationToken,
+) -> Result<Response<Body>> {
+    let host = req.uri().host().unwrap_or_default().to_owned();
+    let retries = budget.allows(req.method());
+    let mut retried = 0;
+
+    loop {
+        breaker.check(&host)?;
+
+        // the wire sends on `answered` when the server says 100, and drops
+        // it on a final status, which stops the held body; kept here until
+        // `select!` is done when another request is already waiting
+        let (answered, hold) = oneshot::channel();
+        let (_waiting, hold) = if expects_continue(req) {
+            (
+                Some(wire::await_continue(answered)),
+                Some((hold, CONTINUE_TIMEOUT)),
+            )
+        } else {
+            (None, None)
+        };
+
+        let res = tokio::select! {
+            res = client.request(attempt(req, body, hold)) => res,
+            _ = cancel.cancelled() => {
+                return Err(CancelledError(format!("{} {}", req.method(), req.uri())).into());
+            }
+        };
+
+        match res {
+            Ok(res) if !res.status().is_server_error() => {
+                breaker.record_success(&host);
+                return Ok(res);
+            }
+            Ok(res) => {
+                breaker.record_failure(&host);
+
+                if !retries || !budget.try_acquire() {
+                    return Ok(res);
+                }
+
+                note!("retrying after {} from {}", res.status(), host);
+            }
+            Err(err) => {
+                breaker.record_failure(&host);
+
+                if !retries || !budget.try_acquire() {
+                    return Err(err.into());
+                }
+
+                note!("retrying after error from {}: {}", host, err);
+            }
+        }
+
+        let backoff = retry::backoff(retried, retry::jitter());
+        crate::trace!(
+            "retry {} of {} {} in {:?}",
+            retried + 1,
+            req.method(),
+            req.uri(),
+            backoff
+        );
+
+        tokio::select! {
+            _ = tokio::time::delay_for(backoff) => {}
+            _ = cancel.cancelled() => {
+                return Err(CancelledError(format!("{} {}", req.method(), req.uri())).into());
+            }
+        }
+
+        retried = retried.saturating_add(1);
+    }
+}
+
+fn attempt(
+    req: &Request<Body>,
+    body: &[u8],
+    hold: Option<(oneshot::Receiver<()>, Duration)>,
+) -> Request<Upload> {
+    let trailers = req
+        .extensions()
+        .get::<Trailers>()
+        .map(|trailers| trailers.0.clone());
+
+    with_body(req, progress::upload(body, hold)).map(|body| Upload { body, trailers })
+}
+
+fn expects_continue(req: &Request<Body>) -> bool {
+    req.headers()
+        .get(header::EXPECT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("100-continue"))
+}
+
+pub async fn next_chunk(
+    res: &mut Response<Body>,
+    cancel: &CancellationToken,
+) -> Result<Option<hyper::body::Bytes>> {
+    let status = res.status();
+
+    tokio::select! {
+        chunk = res.body_mut().data() => Ok(chunk.transpose()?),
+        _ = cancel.cancelled() => {
+            Err(CancelledError(format!("reading body of {} response", status)).into())
+        }
+    }
+}
+
+/// The trailers sent after the body, once `next_chunk` has read all of it.
+pub async fn trailers(
+    res: &mut Response<Body>,
+    cancel: &CancellationToken,
+) -> Result<Option<HeaderMap>> {
+    let status = res.status();
+
+    tokio::select! {
+        trailers = res.body_mut().trailers() => Ok(trailers?),
+        _ = cancel.cancelled() => {
+            Err(CancelledError(format!("reading trailers of {} response", status)).into())
+        }
+    }
+}
+
+pub async fn fetch_etag(
+    client: &HttpClient,
+    builder: &RurlRequestBuilder,
+    auth: &Auth,
+    cancel: &CancellationToken,
+) -> Result<HeaderValue> {
+    // not every server sends an ETag for HEAD, so fall back to GET
+    for method in [Method::HEAD, Method::GET].iter() {
+        // the request's own headers and credentials, without its body
+        let mut builder = builder.clone().method(method.clone());
+        builder.trailers.clear();
+        builder.request_items.retain(|request_item| {
+            matches!(
+                request_item,
+                RequestItem::Header { .. }
+                    | RequestItem::EmptyHeader { .. }
+                    | RequestItem::UnsetHeader { .. }
+            )
+        });
+
+        let (mut req, _) = builder.build()?;
+        auth.apply(&mut req, b"")?;
+        let uri = req.uri().clone();
+
+        let res = tokio::select! {
+            res = client.request(req.map(Upload::from)) => res?,
+            _ = cancel.cancelled() => {
+                return Err(CancelledError(format!("{} {}", method, uri)).into());
+            }
+        };
+
+        if let Some(etag) = res.headers().get(hyper::header::ETAG) {
+            return Ok(etag.clone());
+        }
+    }
+
+    anyhow::bail!("{} did not return an ETag to use for If-Match", builder.uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(request_items: &[&str]) -> Request<Body> {
+        let request_items = request_items
+            .iter()
+            .map(|request_item| request_item.parse().unwrap())
+            .collect();
+
+        RurlRequestBuilder::new(Method::GET, "http://example.com/".parse().unwrap())
+            .request_items(request_items)
+            .build()
+            .unwrap()
+            .0
+    }
+
+    fn values<'a>(req: &'a Request<Body>, key: &str) -> Vec<&'a str> {
+        req.headers()
+            .get_all(key)
+            .iter()
+            .map(|value| value.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sends_defaults() {
+        let req = build(&[]);
+
+        assert_eq!(values(&req, "accept"), ["*/*"]);
+        assert_eq!(values(&req, "user-agent").len(), 1);
+    }
+
+    #[test]
+    fn json_modes_accept_json() {
+        let uri: Uri = "http://example.com/".parse().unwrap();
+        let request = |builder: RurlRequestBuilder| builder.build().unwrap().0;
+
+        let json =
+            request(RurlRequestBuilder::new(Method::GET, uri.clone()).mode(Some(Mode::Json)));
+        assert_eq!(values(&json, "accept"), ["application/json"]);
+
+        let form =
+            request(RurlRequestBuilder::new(Method::GET, uri.clone()).mode(Some(Mode::Form)));
+        assert_eq!(values(&form, "accept"), ["*/*"]);
+
+        let chosen = request(
+            RurlRequestBuilder::new(Method::GET, uri)
+                .mode(Some(Mode::Json))
+                .accept(HeaderValue::from_static("application/problem+json")),
+        );
+        assert_eq!(values(&chosen, "accept"), ["application/problem+json"]);
+    }
+
+    #[test]
+    fn json_posts_send_an_empty_object() {
+        let uri: Uri = "http://example.com/".parse().unwrap();
+        let body = |method: Method, empty_body: bool| {
+            RurlRequestBuilder::new(method, uri.clone())
+                .mode(Some(Mode::Json))
+                .empty_body(empty_body)
+                .build()
+                .unwrap()
+                .1
+        };
+
+        let post = body(Method::POST, false);
+        assert_eq!(post.content, b"{}");
+        assert_eq!(post.content_type.as_deref(), Some("application/json"));
+
+        assert!(body(Method::POST, true).content.is_empty());
+        assert!(body(Method::GET, false).content.is_empty());
+    }
+
+    #[test]
+    fn user_headers_replace_defaults() {
+        let req = build(&["Accept:application/json", "User-Agent:test/1.0"]);
+
+        assert_eq!(values(&req, "accept"), ["application/json"]);
+        assert_eq!(values(&req, "user-agent"), ["test/1.0"]);
+    }
+
+    #[test]
+    fn header_names_match_case_insensitively() {
+        for item in &["accept:text/html", "ACCEPT:text/html", "aCcEpT:text/html"] {
+            assert_eq!(values(&build(&[item]), "Accept"), ["text/html"]);
+        }
+    }
+
+    #[test]
+    fn repeated_user_headers_are_all_sent() {
+        let req = build(&["Accept:text/html", "accept:application/json"]);
+
+        assert_eq!(values(&req, "accept"), ["text/html", "application/json"]);
+    }
+
+    #[test]
+    fn user_content_type_replaces_the_body_default() {
+        let req = build(&["Content-Type:application/vnd.api+json", "name=ezra"]);
+
+        assert_eq!(values(&req, "content-type"), ["application/vnd.api+json"]);
+    }
+
+    #[test]
+    fn builder_headers_yield_to_user_headers() {
+        let (req, _) = RurlRequestBuilder::new(Method::GET, "http://example.com/".parse().unwrap())
+            .header(header::ACCEPT_LANGUAGE, HeaderValue::from_static("en"))
+            .request_item("Accept-Language:fr".parse().unwrap())
+            .build()
+            .unwrap();
+
+        assert_eq!(values(&req, "accept-language"), ["fr"]);
+    }
+
+    #[test]
+    fn unset_and_empty_headers() {
+        let req = build(&["User-Agent:", "Accept;"]);
+
+        assert!(values(&req, "user-agent").is_empty());
+        assert_eq!(values(&req, "accept"), [""]);
+    }
+}
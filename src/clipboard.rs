@@ -0,0 +1,95 @@
+//! The system clipboard, through arboard. On X11 and Wayland, whichever
+//! process set the clipboard has to stay around to serve it, so a copy hands
+//! the text to a copy of rurl left in the background, which exits once
+//! something else is copied.
+
+use arboard::Clipboard;
+use thiserror::Error;
+
+/// Set in the background copy of rurl that serves the clipboard.
+#[cfg(target_os = "linux")]
+const SERVE_VAR: &str = "RURL_CLIPBOARD_SERVE";
+
+#[derive(Debug, Error)]
+pub enum ClipboardError {
+    #[error("the clipboard is unavailable: {0}")]
+    Unavailable(String),
+    #[error("the clipboard holds no text")]
+    Empty,
+    #[error("only text can be copied to the clipboard")]
+    NotText,
+}
+
+fn clipboard() -> Result<Clipboard, ClipboardError> {
+    Clipboard::new().map_err(|err| ClipboardError::Unavailable(err.to_string()))
+}
+
+/// Puts `content` on the clipboard.
+pub fn copy(content: &[u8]) -> Result<(), ClipboardError> {
+    let text = std::str::from_utf8(content).map_err(|_| ClipboardError::NotText)?;
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::{
+            io::Write as _,
+            process::{Command, Stdio},
+        };
+
+        // fail here, rather than silently in the background
+        clipboard()?;
+
+        let unavailable = |err: std::io::Error| ClipboardError::Unavailable(err.to_string());
+        let mut server = Command::new(std::env::current_exe().map_err(unavailable)?)
+            .env(SERVE_VAR, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(unavailable)?;
+
+        // dropping stdin closes it, and the server isn't waited on
+        let mut stdin = server.stdin.take().expect("stdin is piped");
+        stdin.write_all(text.as_bytes()).map_err(unavailable)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    clipboard()?
+        .set_text(text)
+        .map_err(|err| ClipboardError::Unavailable(err.to_string()))
+}
+
+/// In the background copy of rurl `copy` starts, puts stdin on the
+/// clipboard and serves it until something replaces it. Returns whether
+/// this process is that copy.
+#[cfg(target_os = "linux")]
+pub fn serve_if_asked() -> bool {
+    use arboard::SetExtLinux as _;
+    use std::io::Read as _;
+
+    if std::env::var_os(SERVE_VAR).is_none() {
+        return false;
+    }
+
+    let mut text = String::new();
+    if std::io::stdin().read_to_string(&mut text).is_ok() {
+        if let Ok(mut clipboard) = clipboard() {
+            let _ = clipboard.set().wait().text(text);
+        }
+    }
+
+    true
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn serve_if_asked() -> bool {
+    false
+}
+
+/// What is on the clipboard.
+pub fn paste() -> Result<Vec<u8>, ClipboardError> {
+    match clipboard()?.get_text() {
+        Ok(text) => Ok(text.into_bytes()),
+        Err(arboard::Error::ContentNotAvailable) => Err(ClipboardError::Empty),
+        Err(err) => Err(ClipboardError::Unavailable(err.to_string())),
+    }
+}
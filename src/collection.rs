@@ -0,0 +1,391 @@
+//! `.http` files in the REST Client format, run with `rurl run`:
+//!
+//! ```text
+//! @host = https://api.example.com
+//!
+//! ### login
+//! POST {{host}}/login
+//! Content-Type: application/json
+//!
+//! {"user": "ezra"}
+//!
+//! ###
+//! # @name me
+//! GET {{host}}/me
+//! Authorization: Bearer {{login.response.body.$.token}}
+//! ```
+//!
+//! Requests are separated by `###`, and a later request can use the status,
+//! headers, or JSON body of an earlier named one. A block titled
+//! `### rollback:` undoes the request before it, and is sent only when a
+//! later request fails:
+//!
+//! ```text
+//! ### user
+//! POST {{host}}/users
+//!
+//! ### rollback:
+//! DELETE {{host}}/users/{{user.response.body.$.id}}
+//! ```
+
+use crate::{jsonpath, template::Template};
+use anyhow::Result;
+use hyper::HeaderMap;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CollectionError {
+    #[error("line {0}: {1}")]
+    ParseError(usize, String),
+    #[error("{{{{{0}}}}} refers to a request that has not run, name it with `# @name`")]
+    UnknownRequest(String),
+    #[error("{{{{{0}}}}} is not a status, a header, or a JSONPath into the body")]
+    UnknownReference(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    /// Set with `### NAME` or `# @name NAME`; others are named by position.
+    pub name: String,
+    pub method: String,
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// What undoes this request, from the `### rollback:` block after it.
+    pub rollback: Option<Box<Request>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Collection {
+    /// `@NAME = VALUE` lines, in order, so a value can use earlier ones.
+    pub vars: Vec<(String, String)>,
+    pub requests: Vec<Request>,
+}
+
+/// What a later request can refer to as `{{NAME.response...}}`.
+#[derive(Debug)]
+pub struct Captured {
+    pub name: String,
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+fn directive<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let comment = line
+        .strip_prefix('#')
+        .or_else(|| line.strip_prefix("//"))?
+        .trim_start();
+
+    comment
+        .strip_prefix(name)
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .map(str::trim)
+}
+
+impl std::str::FromStr for Collection {
+    type Err = CollectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut collection = Self::default();
+        let mut lines = s.lines().enumerate().peekable();
+
+        while lines.peek().is_some() {
+            let mut name = None;
+            let mut rollback = false;
+            let mut request_line = None;
+
+            // the separator, comments, and variables before the request line
+            for (number, line) in lines.by_ref() {
+                let line = line.trim();
+
+                if let Some(title) = line.strip_prefix("###") {
+                    rollback = title.trim().starts_with("rollback:");
+                    name = Some(title.trim().to_owned())
+                        .filter(|title| !title.is_empty() && !rollback);
+                } else if let Some(value) = directive(line, "@name") {
+                    name = Some(value.to_owned());
+                } else if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                    continue;
+                } else if let Some(var) = line.strip_prefix('@') {
+                    match var.find('=') {
+                        Some(i) => collection
+                            .vars
+                            .push((var[..i].trim().to_owned(), var[i + 1..].trim().to_owned())),
+                        None => {
+                            return Err(CollectionError::ParseError(
+                                number + 1,
+                                format!("expected @NAME = VALUE, got {}", line),
+                            ))
+                        }
+                    }
+                } else {
+                    request_line = Some((number, line));
+                    break;
+                }
+            }
+
+            let (number, request_line) = match request_line {
+                Some(request_line) => request_line,
+                None => break,
+            };
+
+            // `METHOD URI [HTTP/1.1]`, or just the URI for a GET
+            let parts: Vec<&str> = request_line.split_whitespace().collect();
+            let (method, uri) = match parts.as_slice() {
+                [uri] => ("GET", *uri),
+                [method, uri] | [method, uri, _] => (*method, *uri),
+                _ => {
+                    return Err(CollectionError::ParseError(
+                        number + 1,
+                        format!("expected METHOD URI [HTTP/VERSION], got {}", request_line),
+                    ))
+                }
+            };
+
+            let mut headers = Vec::new();
+            while let Some((number, line)) = lines
+                .next_if(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with("###"))
+            {
+                let line = line.trim();
+
+                match line.find(':') {
+                    Some(i) if i > 0 => {
+                        headers.push((line[..i].trim().to_owned(), line[i + 1..].trim().to_owned()))
+                    }
+                    _ => {
+                        return Err(CollectionError::ParseError(
+                            number + 1,
+                            format!("expected a header, got {}", line),
+                        ))
+                    }
+                }
+            }
+
+            let mut body = Vec::new();
+            while let Some((_, line)) = lines.next_if(|(_, line)| !line.trim().starts_with("###")) {
+                body.push(line);
+            }
+
+            let mut request = Request {
+                name: name.unwrap_or_else(|| (collection.requests.len() + 1).to_string()),
+                method: method.to_owned(),
+                uri: uri.to_owned(),
+                headers,
+                body: body.join("\n").trim().to_owned(),
+                rollback: None,
+            };
+
+            if !rollback {
+                collection.requests.push(request);
+                continue;
+            }
+
+            match collection.requests.last_mut() {
+                Some(undone) if undone.rollback.is_none() => {
+                    request.name = format!("{} rollback", undone.name);
+                    undone.rollback = Some(Box::new(request));
+                }
+                _ => {
+                    return Err(CollectionError::ParseError(
+                        number + 1,
+                        "a rollback: block must follow the request it undoes".into(),
+                    ))
+                }
+            }
+        }
+
+        Ok(collection)
+    }
+}
+
+/// The value of `{{NAME.response.status}}`, `{{NAME.response.headers.X}}`,
+/// or `{{NAME.response.body.$.path}}`, or `None` for other placeholders.
+fn reference(placeholder: &str, captured: &[Captured]) -> Result<Option<String>> {
+    let (name, path) = match placeholder.find(".response.") {
+        Some(i) => (&placeholder[..i], &placeholder[i + ".response.".len()..]),
+        None => return Ok(None),
+    };
+
+    let response = captured
+        .iter()
+        .rev()
+        .find(|response| response.name == name)
+        .ok_or_else(|| CollectionError::UnknownRequest(placeholder.into()))?;
+    let unknown = || CollectionError::UnknownReference(placeholder.into());
+
+    let value = if path == "status" {
+        response.status.to_string()
+    } else if let Some(header) = path.strip_prefix("headers.") {
+        response
+            .headers
+            .get(header)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(unknown)?
+            .to_owned()
+    } else if path == "body" || path == "body.*" {
+        response.body.clone()
+    } else if let Some(path) = path.strip_prefix("body.") {
+        let document = json::parse(&response.body).map_err(|_| unknown())?;
+        let selected = jsonpath::select_one(&document, path)?;
+
+        selected
+            .as_str()
+            .map_or_else(|| selected.dump(), String::from)
+    } else {
+        return Err(unknown().into());
+    };
+
+    Ok(Some(value))
+}
+
+/// Fills in a request's placeholders from the file's variables and earlier
+/// responses, leaving the rest to [`Template`]. Filled-in values are not
+/// searched for placeholders again.
+pub fn render(
+    input: &str,
+    vars: &[(String, String)],
+    captured: &[Captured],
+    template: &Template,
+) -> Result<String> {
+    let mut output = String::new();
+    let mut rest = input;
+
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        let placeholder = rest[start + "{{".len()..end].trim();
+        let value = match vars.iter().rev().find(|(name, _)| name == placeholder) {
+            Some((_, value)) => Some(value.clone()),
+            None => reference(placeholder, captured)?,
+        };
+
+        output += &rest[..start];
+        match value {
+            Some(value) => output += &value,
+            None => output += &template.render(&rest[start..end + "}}".len()])?,
+        }
+        rest = &rest[end + "}}".len()..];
+    }
+
+    Ok(output + rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_requests_with_names_headers_and_bodies() {
+        let collection: Collection = "\
+@host = https://api.test
+
+### login
+POST {{host}}/login HTTP/1.1
+Content-Type: application/json
+
+{\"user\": \"ezra\"}
+
+###
+# @name me
+{{host}}/me
+Authorization: Bearer {{login.response.body.$.token}}
+
+###
+DELETE {{host}}/session
+"
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            collection.vars,
+            vec![("host".into(), "https://api.test".into())]
+        );
+        assert_eq!(
+            collection.requests,
+            vec![
+                Request {
+                    name: "login".into(),
+                    method: "POST".into(),
+                    uri: "{{host}}/login".into(),
+                    headers: vec![("Content-Type".into(), "application/json".into())],
+                    body: "{\"user\": \"ezra\"}".into(),
+                    rollback: None,
+                },
+                Request {
+                    name: "me".into(),
+                    method: "GET".into(),
+                    uri: "{{host}}/me".into(),
+                    headers: vec![(
+                        "Authorization".into(),
+                        "Bearer {{login.response.body.$.token}}".into()
+                    )],
+                    body: "".into(),
+                    rollback: None,
+                },
+                Request {
+                    name: "3".into(),
+                    method: "DELETE".into(),
+                    uri: "{{host}}/session".into(),
+                    headers: vec![],
+                    body: "".into(),
+                    rollback: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rollback_blocks_belong_to_the_request_before() {
+        let collection: Collection = "\
+### user
+POST /users
+
+### rollback: remove the user
+DELETE /users/{{user.response.body.$.id}}
+
+### login
+POST /login
+"
+        .parse()
+        .unwrap();
+
+        assert_eq!(collection.requests.len(), 2);
+        let rollback = collection.requests[0].rollback.as_ref().unwrap();
+        assert_eq!(rollback.name, "user rollback");
+        assert_eq!(rollback.method, "DELETE");
+        assert_eq!(collection.requests[1].rollback, None);
+
+        assert!("### rollback:\nDELETE /users/1\n"
+            .parse::<Collection>()
+            .is_err());
+    }
+
+    #[test]
+    fn reads_earlier_responses() {
+        let captured = vec![Captured {
+            name: "login".into(),
+            status: 201,
+            headers: HeaderMap::new(),
+            body: "{\"token\": \"abc\", \"user\": {\"id\": 7}}".into(),
+        }];
+
+        assert_eq!(
+            reference("login.response.body.$.token", &captured).unwrap(),
+            Some("abc".into())
+        );
+        assert_eq!(
+            reference("login.response.body.$.user", &captured).unwrap(),
+            Some("{\"id\":7}".into())
+        );
+        assert_eq!(
+            reference("login.response.status", &captured).unwrap(),
+            Some("201".into())
+        );
+        assert!(reference("me.response.status", &captured).is_err());
+        assert_eq!(reference("host", &captured).unwrap(), None);
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::Result;
+use structopt::clap::{App, Shell};
+
+const USAGE: &str = "usage: rurl completions bash|zsh|fish|powershell";
+
+/// Offered where METHOD goes, along with the subcommands.
+const METHODS: &[&str] = &[
+    "GET",
+    "HEAD",
+    "POST",
+    "PUT",
+    "PATCH",
+    "DELETE",
+    "OPTIONS",
+    "TRACE",
+    "PROPFIND",
+    "PROPPATCH",
+    "MKCOL",
+    "COPY",
+    "MOVE",
+    "LOCK",
+    "UNLOCK",
+    "REPORT",
+];
+
+const SUBCOMMANDS: &[&str] = &[
+    "doctor",
+    "var",
+    "fragment",
+    "completions",
+    "run",
+    "history",
+    "replay",
+];
+
+/// Offered as `Name:` request items once METHOD is given.
+const HEADERS: &[&str] = &[
+    "Accept",
+    "Accept-Encoding",
+    "Accept-Language",
+    "Authorization",
+    "Cache-Control",
+    "Content-Type",
+    "Cookie",
+    "If-Match",
+    "If-Modified-Since",
+    "If-None-Match",
+    "Origin",
+    "Range",
+    "Referer",
+    "User-Agent",
+    "X-Request-Id",
+];
+
+/// Completes METHOD and header names, then falls back to clap's script.
+fn bash() -> String {
+    format!(
+        r#"
+_rurl_dynamic() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" word
+    local methods="{methods}"
+
+    if [[ ${{cur}} == -* ]]; then
+        _rurl
+        return 0
+    fi
+
+    for word in "${{COMP_WORDS[@]:1:COMP_CWORD-1}}"; do
+        if [[ " ${{methods}} " == *" ${{word}} "* ]]; then
+            if [[ ${{cur}} != *[:=@]* ]]; then
+                compopt -o nospace
+                COMPREPLY=( $(compgen -S : -W "{headers}" -- "${{cur}}") )
+            fi
+            return 0
+        fi
+    done
+
+    COMPREPLY=( $(compgen -W "${{methods}} {subcommands}" -- "${{cur}}") )
+}}
+
+complete -F _rurl_dynamic -o bashdefault -o default rurl
+"#,
+        methods = METHODS.join(" "),
+        headers = HEADERS.join(" "),
+        subcommands = SUBCOMMANDS.join(" "),
+    )
+}
+
+fn zsh(script: &str) -> String {
+    let methods = format!(":({} {})'", METHODS.join(" "), SUBCOMMANDS.join(" "));
+
+    // the METHOD spec carries its help text, as `':METHOD -- help:_files'`
+    let script: String = script
+        .split_inclusive('\n')
+        .map(|line| match line.strip_suffix(":_files' \\\n") {
+            Some(spec) if spec.starts_with("':METHOD") => format!("{}{} \\\n", spec, methods),
+            _ => line.to_owned(),
+        })
+        .collect();
+    let script = script
+        .replace("':URI:_files'", "':URI:_urls'")
+        .replace("'::REQUEST_ITEM:_files'", "'*::REQUEST_ITEM:_rurl_headers'");
+
+    // clap's script ends by calling _rurl, so define the helper before it,
+    // though after the #compdef line
+    let (compdef, script) = script.split_at(script.find('\n').map_or(0, |i| i + 1));
+
+    format!(
+        "{}\n_rurl_headers() {{\n    compset -P '*[:=@]' && {{ _files; return }}\n    \
+         compadd -S : -- {}\n}}\n{}",
+        compdef,
+        HEADERS.join(" "),
+        script
+    )
+}
+
+fn fish() -> String {
+    format!(
+        r#"
+function __rurl_has_method
+    for token in (commandline -opc)[2..-1]
+        contains -- $token {methods}; and return 0
+    end
+    return 1
+end
+
+complete -c rurl -n "not __rurl_has_method" -f -a "{methods} {subcommands}"
+complete -c rurl -n "__rurl_has_method" -a "{headers}"
+"#,
+        methods = METHODS.join(" "),
+        subcommands = SUBCOMMANDS.join(" "),
+        headers = HEADERS
+            .iter()
+            .map(|header| format!("{}:", header))
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+/// The completion script for `shell`. PowerShell gets clap's flag
+/// completion only.
+fn script(mut app: App, shell: Shell) -> Result<String> {
+    let mut script = Vec::new();
+    app.gen_completions_to(env!("CARGO_PKG_NAME"), shell, &mut script);
+    let script = String::from_utf8(script)?;
+
+    Ok(match shell {
+        Shell::Bash => script + &bash(),
+        Shell::Zsh => zsh(&script),
+        Shell::Fish => script + &fish(),
+        _ => script,
+    })
+}
+
+fn shell_of(args: &[String]) -> Result<Shell> {
+    match args {
+        [shell] => shell.parse().map_err(|_| anyhow::anyhow!(USAGE)),
+        _ => anyhow::bail!(USAGE),
+    }
+}
+
+/// Prints the completion script for a shell.
+pub fn run(app: App, args: &[String]) -> Result<()> {
+    print!("{}", script(app, shell_of(args)?)?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt as _;
+
+    fn script_for(shell: Shell) -> String {
+        script(crate::Opt::clap(), shell).unwrap()
+    }
+
+    #[test]
+    fn takes_one_known_shell() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert!(matches!(shell_of(&args(&["zsh"])), Ok(Shell::Zsh)));
+        for bad in &[&[][..], &["tcsh"], &["bash", "zsh"]] {
+            assert_eq!(shell_of(&args(bad)).unwrap_err().to_string(), USAGE);
+        }
+    }
+
+    #[test]
+    fn bash_completes_methods_then_headers() {
+        let script = script_for(Shell::Bash);
+
+        assert!(script.contains("_rurl()"));
+        assert!(script.contains(&format!("local methods=\"{}\"", METHODS.join(" "))));
+        assert!(script.contains("compgen -S : -W \"Accept Accept-Encoding"));
+        assert!(script.ends_with("complete -F _rurl_dynamic -o bashdefault -o default rurl\n"));
+    }
+
+    #[test]
+    fn zsh_replaces_clap_file_completion() {
+        let script = script_for(Shell::Zsh);
+
+        // fails if clap changes how it writes these, leaving files offered
+        assert!(!script.contains(":_files'"));
+        assert!(!script.contains("':URI:_files'"));
+        assert!(!script.contains("'::REQUEST_ITEM:_files'"));
+        assert!(script.contains("':URI:_urls'"));
+        assert!(script.contains(":(GET HEAD POST"));
+
+        let mut lines = script.lines();
+        assert!(lines.next().unwrap().starts_with("#compdef rurl"));
+        assert_eq!(lines.nth(1), Some("_rurl_headers() {"));
+    }
+
+    #[test]
+    fn fish_offers_headers_with_a_colon() {
+        let script = script_for(Shell::Fish);
+
+        assert!(script.contains("-a \"GET HEAD"));
+        assert!(script.contains("doctor var fragment completions run history replay\""));
+        assert!(script.contains("-a \"Accept: Accept-Encoding:"));
+    }
+}
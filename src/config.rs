@@ -0,0 +1,144 @@
+use crate::state::{self, Lock};
+use anyhow::Result;
+use json::JsonValue;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("could not parse config file {0}: {1}")]
+    ParseError(String, String),
+    #[error("unknown variable {0}, set it with `rurl var set {0} VALUE`")]
+    UnknownVar(String),
+    #[error("unknown fragment {0}, save one with `rurl fragment add {0} ITEM...`")]
+    UnknownFragment(String),
+}
+
+pub fn dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("RURL_CONFIG_DIR") {
+        return Some(dir.into());
+    }
+
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("rurl"));
+    }
+
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("rurl"))
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub path: Option<PathBuf>,
+    value: JsonValue,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            path: None,
+            value: JsonValue::new_object(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dir().map(|dir| dir.join("config.json"))
+    }
+
+    /// Serializes load-modify-save cycles across concurrent rurl processes.
+    pub async fn lock() -> Result<Option<Lock>> {
+        match Self::path() {
+            Some(path) => Ok(Some(Lock::acquire(&path).await?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Ok(Self::default()),
+        };
+
+        let value = match std::fs::read_to_string(&path) {
+            Ok(contents) => json::parse(&contents).map_err(|err| {
+                ConfigError::ParseError(path.display().to_string(), err.to_string())
+            })?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => JsonValue::new_object(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            value,
+        })
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.value[key].as_str()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => anyhow::bail!("no config directory (set RURL_CONFIG_DIR or HOME)"),
+        };
+
+        state::write_atomic(path, (self.value.pretty(2) + "\n").as_bytes(), 0o666)?;
+
+        Ok(())
+    }
+
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.value["vars"][name].as_str()
+    }
+
+    pub fn vars(&self) -> Vec<(&str, &str)> {
+        self.value["vars"]
+            .entries()
+            .filter_map(|(name, value)| value.as_str().map(|value| (name, value)))
+            .collect()
+    }
+
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        if !self.value["vars"].is_object() {
+            self.value["vars"] = JsonValue::new_object();
+        }
+
+        self.value["vars"][name] = value.into();
+    }
+
+    pub fn unset_var(&mut self, name: &str) -> bool {
+        !self.value["vars"].remove(name).is_null()
+    }
+
+    /// The request items saved under `name` with `rurl fragment add`.
+    pub fn fragment(&self, name: &str) -> Option<Vec<&str>> {
+        let items = &self.value["fragments"][name];
+
+        if items.is_array() {
+            Some(items.members().filter_map(JsonValue::as_str).collect())
+        } else {
+            None
+        }
+    }
+
+    pub fn fragments(&self) -> Vec<(&str, Vec<&str>)> {
+        self.value["fragments"]
+            .entries()
+            .filter_map(|(name, _)| Some((name, self.fragment(name)?)))
+            .collect()
+    }
+
+    pub fn set_fragment(&mut self, name: &str, items: &[&str]) {
+        if !self.value["fragments"].is_object() {
+            self.value["fragments"] = JsonValue::new_object();
+        }
+
+        self.value["fragments"][name] = items.into();
+    }
+
+    pub fn unset_fragment(&mut self, name: &str) -> bool {
+        !self.value["fragments"].remove(name).is_null()
+    }
+}
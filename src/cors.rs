@@ -0,0 +1,278 @@
+//! CORS preflight requests and a readable summary of OPTIONS responses.
+
+use hyper::{
+    header::{self, HeaderName, HeaderValue},
+    HeaderMap, Method,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PreflightError {
+    #[error("invalid --preflight {0}, expected origin=URL[,method=METHOD][,headers=NAME,...]")]
+    ParseError(String),
+}
+
+/// `--preflight origin=URL,method=PUT,headers=content-type,x-token`: the
+/// cross-origin request a browser would check with the server first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preflight {
+    pub origin: String,
+    pub method: Method,
+    pub headers: Vec<String>,
+}
+
+impl std::str::FromStr for Preflight {
+    type Err = PreflightError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || PreflightError::ParseError(s.into());
+
+        let mut origin = None;
+        let mut method = Method::GET;
+        let mut headers = Vec::new();
+        let mut key = "";
+
+        // header lists contain commas too, so a part without a known key
+        // continues the previous one
+        for part in s.split(',') {
+            let (value, continued) = match part.find('=').map(|i| (&part[..i], &part[i + 1..])) {
+                Some((name @ "origin", value))
+                | Some((name @ "method", value))
+                | Some((name @ "headers", value)) => {
+                    key = name;
+                    (value, false)
+                }
+                _ => (part, true),
+            };
+
+            match key {
+                "origin" if !continued && origin.is_none() => origin = Some(value.to_owned()),
+                "method" if !continued => method = value.parse().map_err(|_| err())?,
+                "headers" => headers.extend(
+                    Some(value.trim().to_ascii_lowercase()).filter(|name| !name.is_empty()),
+                ),
+                _ => return Err(err()),
+            }
+        }
+
+        Ok(Self {
+            origin: origin.filter(|origin| !origin.is_empty()).ok_or_else(err)?,
+            method,
+            headers,
+        })
+    }
+}
+
+impl Preflight {
+    /// The `Origin` and `Access-Control-Request-*` headers to send.
+    pub fn headers(&self) -> Result<Vec<(HeaderName, HeaderValue)>, header::InvalidHeaderValue> {
+        let mut headers = vec![
+            (header::ORIGIN, self.origin.parse()?),
+            (
+                header::ACCESS_CONTROL_REQUEST_METHOD,
+                self.method.as_str().parse()?,
+            ),
+        ];
+
+        if !self.headers.is_empty() {
+            headers.push((
+                header::ACCESS_CONTROL_REQUEST_HEADERS,
+                self.headers.join(", ").parse()?,
+            ));
+        }
+
+        Ok(headers)
+    }
+}
+
+fn list(headers: &HeaderMap, name: HeaderName) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|item| item.trim().to_owned())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn allows(allowed: &[String], wanted: &str) -> bool {
+    allowed
+        .iter()
+        .any(|item| item == "*" || item.eq_ignore_ascii_case(wanted))
+}
+
+/// Lists what an OPTIONS response allows, and whether it grants
+/// `preflight`, or `None` when it has neither Allow nor CORS headers.
+pub fn summary(headers: &HeaderMap, preflight: Option<&Preflight>) -> Option<String> {
+    let allow = list(headers, header::ALLOW);
+    let origin = headers
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .and_then(|value| value.to_str().ok());
+    let methods = list(headers, header::ACCESS_CONTROL_ALLOW_METHODS);
+    let allowed_headers = list(headers, header::ACCESS_CONTROL_ALLOW_HEADERS);
+
+    let mut output = String::new();
+    let mut line = |name: &str, value: String| {
+        if !value.is_empty() {
+            output += &format!("{:<18} {}\n", name, value);
+        }
+    };
+
+    line("allow", allow.join(", "));
+    line("cors origin", origin.unwrap_or_default().into());
+    line("cors methods", methods.join(", "));
+    line("cors headers", allowed_headers.join(", "));
+    line(
+        "cors credentials",
+        headers
+            .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .into(),
+    );
+    line(
+        "cors max age",
+        headers
+            .get(header::ACCESS_CONTROL_MAX_AGE)
+            .and_then(|value| value.to_str().ok())
+            .map(|seconds| format!("{}s", seconds))
+            .unwrap_or_default(),
+    );
+    line(
+        "cors exposes",
+        list(headers, header::ACCESS_CONTROL_EXPOSE_HEADERS).join(", "),
+    );
+
+    if let Some(preflight) = preflight {
+        let mut denied = Vec::new();
+
+        if !matches!(origin, Some(origin) if origin == "*" || origin == preflight.origin) {
+            denied.push(format!("origin {}", preflight.origin));
+        }
+
+        // simple methods need no Access-Control-Allow-Methods entry
+        let simple = [Method::GET, Method::HEAD, Method::POST].contains(&preflight.method);
+        if !simple && !allows(&methods, preflight.method.as_str()) {
+            denied.push(format!("method {}", preflight.method));
+        }
+
+        for name in &preflight.headers {
+            if !allows(&allowed_headers, name) {
+                denied.push(format!("header {}", name));
+            }
+        }
+
+        line(
+            "preflight",
+            if denied.is_empty() {
+                format!("{} from {} is allowed", preflight.method, preflight.origin)
+            } else {
+                format!("denied: {}", denied.join(", "))
+            },
+        );
+    }
+
+    if output.is_empty() {
+        None
+    } else {
+        Some(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_preflights_with_header_lists() {
+        assert_eq!(
+            "origin=https://app.test,method=PUT,headers=Content-Type,X-Token"
+                .parse::<Preflight>()
+                .unwrap(),
+            Preflight {
+                origin: "https://app.test".into(),
+                method: Method::PUT,
+                headers: vec!["content-type".into(), "x-token".into()],
+            }
+        );
+        assert!("method=PUT".parse::<Preflight>().is_err());
+        assert!("origin=https://app.test,nope".parse::<Preflight>().is_err());
+    }
+
+    #[test]
+    fn summarizes_denied_preflights() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ALLOW, HeaderValue::from_static("GET, PUT"));
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("https://app.test"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("PUT"),
+        );
+
+        let preflight: Preflight = "origin=https://app.test,method=PUT,headers=x-token"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            summary(&headers, Some(&preflight)).unwrap(),
+            "allow              GET, PUT\n\
+             cors origin        https://app.test\n\
+             cors methods       PUT\n\
+             preflight          denied: header x-token\n"
+        );
+        assert_eq!(summary(&HeaderMap::new(), None), None);
+    }
+
+    #[test]
+    fn wildcards_and_simple_methods_allow_preflights() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("*"),
+        );
+        headers.append(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("X-Request-Id"),
+        );
+        headers.append(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("x-token, content-type"),
+        );
+
+        let preflight: Preflight = "origin=https://app.test,method=POST,headers=X-Token"
+            .parse()
+            .unwrap();
+        let summary = summary(&headers, Some(&preflight)).unwrap();
+
+        assert!(summary.contains("cors headers       X-Request-Id, x-token, content-type\n"));
+        assert!(summary.ends_with("preflight          POST from https://app.test is allowed\n"));
+    }
+
+    #[test]
+    fn sends_the_request_headers_a_browser_would() {
+        let preflight: Preflight = "origin=https://app.test".parse().unwrap();
+        let names = |preflight: &Preflight| -> Vec<HeaderName> {
+            preflight
+                .headers()
+                .unwrap()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+
+        assert_eq!(preflight.method, Method::GET);
+        assert_eq!(
+            names(&preflight),
+            [header::ORIGIN, header::ACCESS_CONTROL_REQUEST_METHOD]
+        );
+
+        let preflight: Preflight = "origin=https://app.test,headers=a,b".parse().unwrap();
+        let headers = preflight.headers().unwrap();
+        assert_eq!(headers[2].0, header::ACCESS_CONTROL_REQUEST_HEADERS);
+        assert_eq!(headers[2].1, "a, b");
+    }
+}
@@ -0,0 +1,138 @@
+use hyper::{Body, Request};
+
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c))
+    {
+        return arg.into();
+    }
+
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Bytes that are not UTF-8, such as a MessagePack body, as an ANSI-C
+/// `$'...'` string with everything outside printable ASCII escaped.
+fn quote_bytes(bytes: &[u8]) -> String {
+    let escaped: String = bytes
+        .iter()
+        .map(|byte| match byte {
+            b'\\' | b'\'' => format!("\\{}", *byte as char),
+            0x20..=0x7e => (*byte as char).to_string(),
+            _ => format!("\\x{:02x}", byte),
+        })
+        .collect();
+
+    format!("$'{}'", escaped)
+}
+
+/// Renders the equivalent `curl` invocation for a built request.
+pub fn command(req: &Request<Body>, body: &[u8]) -> String {
+    let mut args = vec!["curl".to_owned()];
+
+    // `-X HEAD` would have curl wait for a body that never comes
+    if req.method() == hyper::Method::HEAD {
+        args.push("-I".into());
+    } else if req.method() != hyper::Method::GET || !body.is_empty() {
+        args.push("-X".into());
+        args.push(quote(req.method().as_str()));
+    }
+
+    args.push(quote(&req.uri().to_string()));
+
+    for (name, value) in req.headers() {
+        // curl computes these itself
+        if name == hyper::header::CONTENT_LENGTH || name == hyper::header::HOST {
+            continue;
+        }
+
+        args.push("-H".into());
+        args.push(quote(&format!(
+            "{}: {}",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        )));
+    }
+
+    if !body.is_empty() {
+        args.push("--data-binary".into());
+        args.push(match std::str::from_utf8(body) {
+            Ok(body) => quote(body),
+            Err(_) => quote_bytes(body),
+        });
+    }
+
+    args.join(" \\\n  ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri("http://example.com/a?b=c")
+            .header("Host", "example.com")
+            .header("Content-Length", "7")
+            .header("Content-Type", "application/json")
+            .header("X-Note", "it's")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn args(command: &str) -> Vec<&str> {
+        command.split(" \\\n  ").collect()
+    }
+
+    #[test]
+    fn quotes_only_what_the_shell_would_split() {
+        assert_eq!(quote("example.com/a=b@c"), "example.com/a=b@c");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's $HOME"), r"'it'\''s $HOME'");
+    }
+
+    #[test]
+    fn quotes_non_utf8_bytes_as_ansi_c() {
+        assert_eq!(quote_bytes(b"\x81a'\\\n"), r"$'\x81a\'\\\x0a'");
+    }
+
+    #[test]
+    fn renders_method_headers_and_body() {
+        assert_eq!(
+            args(&command(&request("POST"), br#"{"a":1}"#)),
+            vec![
+                "curl",
+                "-X",
+                "POST",
+                "'http://example.com/a?b=c'",
+                "-H",
+                "'content-type: application/json'",
+                "-H",
+                r"'x-note: it'\''s'",
+                "--data-binary",
+                r#"'{"a":1}'"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_non_utf8_bodies_escaped() {
+        assert_eq!(
+            args(&command(&request("PUT"), &[0x81, 0x00])).last(),
+            Some(&r"$'\x81\x00'")
+        );
+    }
+
+    #[test]
+    fn gets_need_no_method_and_heads_use_i() {
+        assert_eq!(
+            args(&command(&request("GET"), b""))[1],
+            "'http://example.com/a?b=c'"
+        );
+        assert_eq!(args(&command(&request("GET"), b"a"))[1..3], ["-X", "GET"]);
+        assert_eq!(args(&command(&request("HEAD"), b""))[1], "-I");
+    }
+}
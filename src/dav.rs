@@ -0,0 +1,268 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DavError {
+    #[error("unknown DAV request {0}, expected propfind, calendar-query, addressbook-query, or sync-collection")]
+    UnknownKind(String),
+    #[error("--time-range must be START/END in iCalendar UTC form, e.g. 20240101T000000Z/20240201T000000Z")]
+    InvalidTimeRange,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DavKind {
+    Propfind,
+    CalendarQuery,
+    AddressbookQuery,
+    SyncCollection,
+}
+
+impl std::str::FromStr for DavKind {
+    type Err = DavError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "propfind" => Ok(Self::Propfind),
+            "calendar-query" => Ok(Self::CalendarQuery),
+            "addressbook-query" => Ok(Self::AddressbookQuery),
+            "sync-collection" => Ok(Self::SyncCollection),
+            _ => Err(Self::Err::UnknownKind(s.into())),
+        }
+    }
+}
+
+fn time_range_element(time_range: Option<&str>) -> Result<String, DavError> {
+    let time_range = match time_range {
+        Some(time_range) => time_range,
+        None => return Ok(String::new()),
+    };
+
+    let mut parts = time_range.splitn(2, '/');
+    let (start, end) = match (parts.next(), parts.next()) {
+        (Some(start), Some(end)) if !start.is_empty() && !end.is_empty() => (start, end),
+        _ => return Err(DavError::InvalidTimeRange),
+    };
+
+    Ok(format!(
+        "\n          <c:time-range start=\"{}\" end=\"{}\"/>",
+        start, end
+    ))
+}
+
+/// The XML body for a PROPFIND or REPORT request of the given kind.
+pub fn body(
+    kind: DavKind,
+    time_range: Option<&str>,
+    sync_token: Option<&str>,
+) -> Result<String, DavError> {
+    let body = match kind {
+        DavKind::Propfind => r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:" xmlns:cs="http://calendarserver.org/ns/">
+  <d:prop>
+    <d:displayname/>
+    <d:resourcetype/>
+    <d:getetag/>
+    <cs:getctag/>
+    <d:sync-token/>
+  </d:prop>
+</d:propfind>
+"#
+        .into(),
+        DavKind::CalendarQuery => format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:prop>
+    <d:getetag/>
+    <c:calendar-data/>
+  </d:prop>
+  <c:filter>
+    <c:comp-filter name="VCALENDAR">
+      <c:comp-filter name="VEVENT">{}
+      </c:comp-filter>
+    </c:comp-filter>
+  </c:filter>
+</c:calendar-query>
+"#,
+            time_range_element(time_range)?
+        ),
+        DavKind::AddressbookQuery => r#"<?xml version="1.0" encoding="utf-8"?>
+<card:addressbook-query xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav">
+  <d:prop>
+    <d:getetag/>
+    <card:address-data/>
+  </d:prop>
+</card:addressbook-query>
+"#
+        .into(),
+        DavKind::SyncCollection => format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<d:sync-collection xmlns:d="DAV:">
+  <d:sync-token>{}</d:sync-token>
+  <d:sync-level>1</d:sync-level>
+  <d:prop>
+    <d:getetag/>
+  </d:prop>
+</d:sync-collection>
+"#,
+            sync_token.unwrap_or_default()
+        ),
+    };
+
+    Ok(body)
+}
+
+/// Every element named `name`, ignoring namespace prefixes.
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut found = Vec::new();
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+
+        let tag_end = match rest.find('>') {
+            Some(tag_end) => tag_end,
+            None => break,
+        };
+        let tag = &rest[..tag_end];
+        let tag_name = tag.split_whitespace().next().unwrap_or_default();
+        let local_name = tag_name.rsplit(':').next().unwrap_or_default();
+
+        if local_name != name || tag.starts_with('/') || tag.ends_with('/') {
+            continue;
+        }
+
+        let content = &rest[tag_end + 1..];
+        let close = format!("</{}>", tag_name);
+
+        if let Some(end) = content.find(&close) {
+            found.push(&content[..end]);
+            rest = &content[end + close.len()..];
+        }
+    }
+
+    found
+}
+
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    elements(xml, name).into_iter().next().map(str::trim)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Reads `KEY:value` (or `KEY;params:value`) from iCalendar or vCard data.
+fn property(data: &str, key: &str) -> Option<String> {
+    data.lines().find_map(|line| {
+        let (name, value) = line.split_at(line.find(':')?);
+        let name = name.split(';').next()?;
+
+        if name.eq_ignore_ascii_case(key) {
+            Some(value[1..].trim().to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+/// Summarizes a 207 multistatus response as one line per resource.
+pub fn listing(xml: &str) -> String {
+    let mut output = String::new();
+
+    for response in elements(xml, "response") {
+        let href = element(response, "href").map(unescape).unwrap_or_default();
+        let status = element(response, "status").unwrap_or_default();
+        let mut line = href;
+
+        if let Some(name) = element(response, "displayname") {
+            line += &format!("  \"{}\"", unescape(name));
+        }
+
+        if let Some(data) = element(response, "calendar-data").map(unescape) {
+            line += &format!(
+                "  {}  {}",
+                property(&data, "DTSTART").unwrap_or_default(),
+                property(&data, "SUMMARY").unwrap_or_default()
+            );
+        }
+
+        if let Some(data) = element(response, "address-data").map(unescape) {
+            line += &format!(
+                "  {}  {}",
+                property(&data, "FN").unwrap_or_default(),
+                property(&data, "EMAIL").unwrap_or_default()
+            );
+        }
+
+        if !status.is_empty() && !status.contains(" 200 ") {
+            line += &format!("  [{}]", status);
+        }
+
+        output += &line;
+        output += "\n";
+    }
+
+    if let Some(token) = element(xml, "sync-token") {
+        output += &format!("sync-token: {}\n", unescape(token));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_report_bodies() {
+        let query = body(
+            DavKind::CalendarQuery,
+            Some("20240101T000000Z/20240201T000000Z"),
+            None,
+        )
+        .unwrap();
+        assert!(
+            query.contains(r#"<c:time-range start="20240101T000000Z" end="20240201T000000Z"/>"#)
+        );
+
+        let sync = body(DavKind::SyncCollection, None, Some("token-1")).unwrap();
+        assert!(sync.contains("<d:sync-token>token-1</d:sync-token>"));
+
+        assert!(body(DavKind::CalendarQuery, Some("20240101T000000Z"), None).is_err());
+        assert!("mkcalendar".parse::<DavKind>().is_err());
+    }
+
+    #[test]
+    fn lists_multistatus_responses() {
+        let xml = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:response>
+    <d:href>/cal/a&amp;b.ics</d:href>
+    <d:propstat>
+      <d:prop>
+        <c:calendar-data>BEGIN:VEVENT
+DTSTART;TZID=UTC:20240105T090000
+SUMMARY:Standup
+END:VEVENT</c:calendar-data>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/cal/gone.ics</d:href>
+    <d:status>HTTP/1.1 404 Not Found</d:status>
+  </d:response>
+  <d:sync-token>http://example.com/sync/2</d:sync-token>
+</d:multistatus>"#;
+
+        assert_eq!(
+            listing(xml),
+            "/cal/a&b.ics  20240105T090000  Standup\n\
+             /cal/gone.ics  [HTTP/1.1 404 Not Found]\n\
+             sync-token: http://example.com/sync/2\n"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    jsonpath::{self, JsonPathError},
+    note,
+};
+use json::JsonValue;
+
+fn sextet(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    }
+}
+
+/// Decodes standard or URL-safe base64, with or without padding.
+pub fn base64(input: &str) -> Option<Vec<u8>> {
+    let input: Vec<u8> = input.bytes().filter(|c| !c.is_ascii_whitespace()).collect();
+    let input = match input.iter().position(|&c| c == b'=') {
+        Some(padding) if input[padding..].iter().all(|&c| c == b'=') => &input[..padding],
+        Some(_) => return None,
+        None => &input[..],
+    };
+
+    if input.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for &c in input {
+        buffer = (buffer << 6) | sextet(c)? as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(output)
+}
+
+/// Encodes as standard base64 with padding.
+pub fn encode_base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+
+    for chunk in input.chunks(3) {
+        let buffer = chunk.iter().enumerate().fold(0u32, |buffer, (i, &c)| {
+            buffer | ((c as u32) << (16 - 8 * i))
+        });
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[((buffer >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+
+    output
+}
+
+/// Replaces a base64 string with its decoded text, or with the JSON it contains.
+fn decode_value(value: &mut JsonValue, path: &str) {
+    let decoded = match value.as_str().and_then(base64) {
+        Some(decoded) => decoded,
+        None => {
+            note!("{} is not a base64 string, leaving it as is", path);
+            return;
+        }
+    };
+
+    let text = match String::from_utf8(decoded) {
+        Ok(text) => text,
+        Err(err) => {
+            note!(
+                "{} decodes to {} bytes of binary data, leaving it as is",
+                path,
+                err.as_bytes().len()
+            );
+            return;
+        }
+    };
+
+    *value = match json::parse(&text) {
+        Ok(nested) if nested.is_object() || nested.is_array() => nested,
+        _ => text.into(),
+    };
+}
+
+/// Decodes the base64 fields matched by each of the comma-separated `paths`.
+pub fn decode_fields(value: &mut JsonValue, paths: &str) -> Result<(), JsonPathError> {
+    for path in paths
+        .split(',')
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+    {
+        jsonpath::update(value, path, |value| decode_value(value, path))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_standard_and_url_safe_base64() {
+        assert_eq!(base64("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64("aGVsbG8").unwrap(), b"hello");
+        assert_eq!(base64("aGVs\n bG8=").unwrap(), b"hello");
+        assert_eq!(base64("+/8=").unwrap(), [0xfb, 0xff]);
+        assert_eq!(base64("-_8").unwrap(), [0xfb, 0xff]);
+        assert_eq!(base64("").unwrap(), b"");
+    }
+
+    #[test]
+    fn rejects_what_is_not_base64() {
+        assert_eq!(base64("not base64!"), None);
+        assert_eq!(base64("aGVs=bG8"), None);
+        assert_eq!(base64("aGVsb"), None);
+    }
+
+    #[test]
+    fn encodes_with_padding() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"h"), "aA==");
+        assert_eq!(encode_base64(b"he"), "aGU=");
+        assert_eq!(encode_base64(b"hello"), "aGVsbG8=");
+        assert_eq!(encode_base64(&[0xfb, 0xff, 0xfe]), "+//+");
+
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(base64(&encode_base64(&bytes)).unwrap(), bytes);
+    }
+
+    #[test]
+    fn decodes_fields_to_text_or_the_json_they_hold() {
+        let mut value = json::object! {
+            "token" => "aGVsbG8=",
+            "claims" => "eyJhIjoxfQ==",
+            "number" => "MTI=",
+            "items" => json::array![{ "data" => "aGk=" }, { "data" => "aGV5" }],
+        };
+
+        decode_fields(&mut value, "$.token, $.claims,$.number, $.items[*].data,").unwrap();
+
+        assert_eq!(value["token"], "hello");
+        assert_eq!(value["claims"], json::object! { "a" => 1 });
+        // only objects and arrays are taken as nested JSON
+        assert_eq!(value["number"], "12");
+        assert_eq!(value["items"][0]["data"], "hi");
+        assert_eq!(value["items"][1]["data"], "hey");
+    }
+
+    #[test]
+    fn leaves_fields_that_do_not_decode_to_text_as_they_are() {
+        let mut value = json::object! {
+            "plain" => "not base64!",
+            "binary" => "//4=",
+            "number" => 7,
+        };
+        let before = value.clone();
+
+        decode_fields(&mut value, "$.plain,$.binary,$.number,$.missing").unwrap();
+        assert_eq!(value, before);
+
+        assert!(decode_fields(&mut value, "$.items[0").is_err());
+    }
+}
@@ -0,0 +1,203 @@
+const VOLATILE_HEADERS: &[&str] = &[
+    "age",
+    "date",
+    "expires",
+    "last-modified",
+    "server-timing",
+    "x-amzn-trace-id",
+    "x-request-id",
+    "x-runtime",
+];
+
+const DAYS: &[&str] = &["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+pub const PLACEHOLDER: &str = "<volatile>";
+
+pub fn is_volatile_header(name: &str) -> bool {
+    VOLATILE_HEADERS.contains(&name)
+}
+
+fn is_word(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+fn is_boundary(input: &[u8], i: usize) -> bool {
+    let before = i > 0 && is_word(input[i - 1]);
+    let after = i < input.len() && is_word(input[i]);
+
+    before != after
+}
+
+/// Matches `input` against a template where `9` is a digit, `x` a hex digit,
+/// `a` a word character, `T` either `T` or a space, and anything else itself.
+fn matches(input: &[u8], template: &str) -> bool {
+    input.len() >= template.len()
+        && template.bytes().zip(input).all(|(t, &c)| match t {
+            b'9' => c.is_ascii_digit(),
+            b'x' => c.is_ascii_hexdigit(),
+            b'a' => is_word(c),
+            b'T' => c == b'T' || c == b' ',
+            _ => c == t,
+        })
+}
+
+fn digits(input: &[u8]) -> usize {
+    input.iter().take_while(|c| c.is_ascii_digit()).count()
+}
+
+fn uuid(input: &[u8]) -> Option<usize> {
+    if matches(input, "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx") && is_boundary(input, 36) {
+        Some(36)
+    } else {
+        None
+    }
+}
+
+fn iso_8601(input: &[u8]) -> Option<usize> {
+    if !matches(input, "9999-99-99T99:99:99") {
+        return None;
+    }
+
+    let mut end = 19;
+
+    if input.get(end) == Some(&b'.') && digits(&input[end + 1..]) > 0 {
+        end += 1 + digits(&input[end + 1..]);
+    }
+
+    // prefer the longest zone that still ends on a word boundary
+    let zones = [
+        Some(1).filter(|_| input.get(end) == Some(&b'Z')),
+        Some(6).filter(|_| matches(&input[end..], "+99:99") || matches(&input[end..], "-99:99")),
+        Some(5).filter(|_| matches(&input[end..], "+9999") || matches(&input[end..], "-9999")),
+        Some(0),
+    ];
+
+    zones
+        .iter()
+        .flatten()
+        .map(|zone| end + zone)
+        .find(|&end| is_boundary(input, end))
+}
+
+fn rfc_1123(input: &[u8]) -> Option<usize> {
+    let day = DAYS.iter().any(|day| input.starts_with(day.as_bytes()));
+
+    if day && matches(&input[3..], ", 99 aaa 9999 99:99:99 GMT") && is_boundary(input, 29) {
+        Some(29)
+    } else {
+        None
+    }
+}
+
+pub fn normalize(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if is_boundary(bytes, i) {
+            let rest = &bytes[i..];
+            let replacement = uuid(rest)
+                .map(|end| (end, "00000000-0000-0000-0000-000000000000"))
+                .or_else(|| iso_8601(rest).map(|end| (end, "1970-01-01T00:00:00Z")))
+                .or_else(|| rfc_1123(rest).map(|end| (end, "Thu, 01 Jan 1970 00:00:00 GMT")));
+
+            if let Some((end, replacement)) = replacement {
+                output.extend_from_slice(replacement.as_bytes());
+                i += end;
+                continue;
+            }
+        }
+
+        output.push(bytes[i]);
+        i += 1;
+    }
+
+    // only whole ASCII spans were replaced, so the output is still valid UTF-8
+    String::from_utf8(output).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_uuids() {
+        assert_eq!(
+            normalize(r#"{"id": "3F2504E0-4F89-11D3-9A0C-0305E82C3301"}"#),
+            r#"{"id": "00000000-0000-0000-0000-000000000000"}"#
+        );
+        assert_eq!(
+            normalize("/users/3f2504e0-4f89-11d3-9a0c-0305e82c3301/posts"),
+            "/users/00000000-0000-0000-0000-000000000000/posts"
+        );
+    }
+
+    #[test]
+    fn leaves_uuids_inside_longer_words() {
+        let input = "a3f2504e0-4f89-11d3-9a0c-0305e82c3301 3f2504e0-4f89-11d3-9a0c-0305e82c33011";
+
+        assert_eq!(normalize(input), input);
+    }
+
+    #[test]
+    fn normalizes_iso_8601_timestamps() {
+        for input in [
+            "2024-03-01T12:34:56Z",
+            "2024-03-01T12:34:56.789Z",
+            "2024-03-01 12:34:56",
+            "2024-03-01T12:34:56+01:00",
+            "2024-03-01T12:34:56.5-0800",
+        ] {
+            assert_eq!(
+                normalize(&format!("at {}.", input)),
+                "at 1970-01-01T00:00:00Z.",
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_partial_timestamps() {
+        for input in ["2024-03-01", "2024-03-01T12:34", "12024-03-01T12:34:56Z"] {
+            assert_eq!(normalize(input), input);
+        }
+    }
+
+    #[test]
+    fn normalizes_rfc_1123_dates() {
+        assert_eq!(
+            normalize("expires Wed, 21 Oct 2015 07:28:00 GMT;"),
+            "expires Thu, 01 Jan 1970 00:00:00 GMT;"
+        );
+        assert_eq!(
+            normalize("Wed, 21 Oct 2015 07:28:00 UTC"),
+            "Wed, 21 Oct 2015 07:28:00 UTC"
+        );
+        assert_eq!(
+            normalize("Xyz, 21 Oct 2015 07:28:00 GMT"),
+            "Xyz, 21 Oct 2015 07:28:00 GMT"
+        );
+    }
+
+    #[test]
+    fn leaves_other_text_alone() {
+        let input = "naïve — 12:34 on day 2024, request 42";
+
+        assert_eq!(normalize(input), input);
+        assert_eq!(normalize(""), "");
+    }
+
+    #[test]
+    fn lists_volatile_headers() {
+        for name in ["date", "age", "expires", "last-modified", "x-request-id"] {
+            assert!(is_volatile_header(name), "{}", name);
+        }
+
+        assert!(!is_volatile_header("content-type"));
+        assert!(!is_volatile_header("etag"));
+        // names are matched as hyper lowercases them
+        assert!(!is_volatile_header("Date"));
+    }
+}
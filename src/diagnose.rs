@@ -0,0 +1,127 @@
+use crate::tls::{self, TlsOptions};
+use hyper::Uri;
+use rustls::Session as _;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const PROXY_VARS: &[&str] = &[
+    "HTTP_PROXY",
+    "http_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+
+fn port(uri: &Uri) -> u16 {
+    uri.port_u16().unwrap_or_else(|| match uri.scheme_str() {
+        Some("https") => 443,
+        _ => 80,
+    })
+}
+
+/// Completes a handshake with `addr`, returning the negotiated version.
+async fn handshake(host: &str, addr: &SocketAddr) -> anyhow::Result<String> {
+    let config = Arc::new(tls::client_config(&TlsOptions::default())?);
+    let name = webpki::DNSNameRef::try_from_ascii_str(host)
+        .map_err(|_| anyhow::anyhow!("{} is not a DNS name", host))?;
+
+    let tcp = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr))
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out connecting"))??;
+    let stream = tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        TlsConnector::from(config).connect(name, tcp),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("timed out"))??;
+
+    Ok(match stream.get_ref().1.get_protocol_version() {
+        Some(version) => format!("{:?}", version),
+        None => "connected".into(),
+    })
+}
+
+/// Runs quick DNS, TCP, TLS, and proxy checks against `uri` and summarizes
+/// the most likely reason a connection failed.
+pub async fn diagnose(uri: &Uri) -> String {
+    let host = uri.host().unwrap_or_default();
+    let port = port(uri);
+    let mut report = format!("diagnostics for {}:{}\n", host, port);
+    let mut cause = None;
+
+    // dns
+    let addrs: Vec<SocketAddr> = match tokio::net::lookup_host((host, port)).await {
+        Ok(addrs) => addrs.collect(),
+        Err(err) => {
+            report += &format!("  dns    failed: {}\n", err);
+            cause = cause.or(Some("the host name does not resolve"));
+            Vec::new()
+        }
+    };
+
+    if !addrs.is_empty() {
+        let addrs: Vec<String> = addrs.iter().map(|addr| addr.ip().to_string()).collect();
+        report += &format!("  dns    ok: {}\n", addrs.join(", "));
+    }
+
+    // tcp
+    let mut reachable = None;
+
+    for addr in addrs.iter() {
+        match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => {
+                report += &format!("  tcp    ok: connected to {}\n", addr);
+                reachable = Some(addr);
+                break;
+            }
+            Ok(Err(err)) => report += &format!("  tcp    failed: {}: {}\n", addr, err),
+            Err(_) => report += &format!("  tcp    failed: {}: timed out\n", addr),
+        }
+    }
+
+    if !addrs.is_empty() && reachable.is_none() {
+        cause = cause.or(Some("nothing is accepting connections on that port"));
+    }
+
+    // tls
+    if let (Some("https"), Some(addr)) = (uri.scheme_str(), reachable) {
+        match handshake(host, addr).await {
+            Ok(version) => report += &format!("  tls    ok: {}\n", version),
+            Err(err) => {
+                report += &format!("  tls    failed: {}\n", err);
+                cause = cause.or(Some("the TLS handshake failed"));
+            }
+        }
+    }
+
+    // proxy
+    let proxies: Vec<String> = PROXY_VARS
+        .iter()
+        .filter_map(|key| {
+            std::env::var(key)
+                .ok()
+                .map(|value| format!("{}={}", key, value))
+        })
+        .collect();
+
+    if proxies.is_empty() {
+        report += "  proxy  none configured\n";
+    } else {
+        report += &format!(
+            "  proxy  {} (rurl does not use proxies)\n",
+            proxies.join(", ")
+        );
+        cause = cause.or(Some("a proxy is configured but rurl connects directly"));
+    }
+
+    report += &format!(
+        "  why    {}\n",
+        cause.unwrap_or("the host is reachable, the failure happened after connecting")
+    );
+
+    report
+}
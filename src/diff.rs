@@ -0,0 +1,245 @@
+//! `rurl diff`: what changed between two responses, as colored lines. JSON
+//! bodies are compared by structure, so reordered keys are no change;
+//! anything else, heads included, is compared line by line.
+
+use crate::{
+    client::HttpClient,
+    multi::{self, Multi, MultiResponse},
+    note, progress,
+    protobuf::Protobuf,
+};
+use anyhow::Result;
+use hyper::{Body, Request};
+use json::JsonValue;
+
+/// Beyond this many cells, differing lines aren't matched up, and are all
+/// shown as removed and then added.
+const MAX_TABLE: usize = 4_000_000;
+
+fn removed(line: &str) -> String {
+    format!("\x1b[31m- {}\x1b[0m", line)
+}
+
+fn added(line: &str) -> String {
+    format!("\x1b[32m+ {}\x1b[0m", line)
+}
+
+/// The lines only in `a` as removed and those only in `b` as added, in
+/// order. Lines both have are left out.
+pub fn lines(a: &str, b: &str) -> Vec<String> {
+    let a: Vec<&str> = a.lines().collect();
+    let b: Vec<&str> = b.lines().collect();
+
+    // only what lies between a common prefix and suffix needs matching up
+    let prefix = a.iter().zip(&b).take_while(|(a, b)| a == b).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    if a.len() * b.len() > MAX_TABLE {
+        return a
+            .iter()
+            .map(|line| removed(line))
+            .chain(b.iter().map(|line| added(line)))
+            .collect();
+    }
+
+    // longest[i][j] is the longest common subsequence of a[i..] and b[j..]
+    let mut longest = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            longest[i][j] = if a[i] == b[j] {
+                longest[i + 1][j + 1] + 1
+            } else {
+                longest[i + 1][j].max(longest[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && longest[i + 1][j] >= longest[i][j + 1]) {
+            changes.push(removed(a[i]));
+            i += 1;
+        } else {
+            changes.push(added(b[j]));
+            j += 1;
+        }
+    }
+
+    changes
+}
+
+/// `$.name`, or `$["a key"]` when the key isn't a plain name.
+fn child(path: &str, key: &str) -> String {
+    let plain = !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+
+    if plain {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}[{}]", path, JsonValue::from(key).dump())
+    }
+}
+
+fn walk(path: &str, a: &JsonValue, b: &JsonValue, changes: &mut Vec<String>) {
+    match (a, b) {
+        (JsonValue::Object(_), JsonValue::Object(_)) => {
+            for (key, value) in a.entries() {
+                let path = child(path, key);
+
+                if b.has_key(key) {
+                    walk(&path, value, &b[key], changes);
+                } else {
+                    changes.push(removed(&format!("{}: {}", path, value.dump())));
+                }
+            }
+
+            for (key, value) in b.entries().filter(|(key, _)| !a.has_key(key)) {
+                changes.push(added(&format!("{}: {}", child(path, key), value.dump())));
+            }
+        }
+        (JsonValue::Array(a), JsonValue::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                let path = format!("{}[{}]", path, i);
+
+                match (a.get(i), b.get(i)) {
+                    (Some(a), Some(b)) => walk(&path, a, b, changes),
+                    (Some(a), None) => changes.push(removed(&format!("{}: {}", path, a.dump()))),
+                    (None, Some(b)) => changes.push(added(&format!("{}: {}", path, b.dump()))),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if a != b => changes.push(format!(
+            "\x1b[33m~ {}: {} → {}\x1b[0m",
+            path,
+            a.dump(),
+            b.dump()
+        )),
+        _ => {}
+    }
+}
+
+/// Each value removed, added, or changed between two JSON documents, by
+/// its JSONPath.
+pub fn json(a: &JsonValue, b: &JsonValue) -> Vec<String> {
+    let mut changes = Vec::new();
+    walk("$", a, b, &mut changes);
+    changes
+}
+
+/// The changes between two bodies: by structure when both are JSON, and
+/// line by line otherwise.
+pub fn bodies(a: &str, b: &str) -> Vec<String> {
+    match (json::parse(a), json::parse(b)) {
+        (Ok(a), Ok(b)) => json(&a, &b),
+        _ => lines(a, b),
+    }
+}
+
+/// Sends both requests and prints what changed from the first response to
+/// the second. Returns whether anything did.
+pub async fn run(
+    multi: Multi,
+    client: HttpClient,
+    requests: [(Request<Body>, Vec<u8>); 2],
+    proto: Option<&Protobuf>,
+) -> Result<bool> {
+    let deterministic = multi.deterministic;
+    let mut responses: Vec<Option<MultiResponse>> = vec![None, None];
+    let mut failed = None;
+
+    multi::run(multi, client, requests.into(), |response| match response {
+        Ok(response) => {
+            let index = response.index;
+            responses[index] = Some(response);
+        }
+        Err((uri, err)) => failed = Some(anyhow::anyhow!("{}: {}", uri, err)),
+    })
+    .await;
+
+    let (a, b) = match (failed, responses[0].take(), responses[1].take()) {
+        (None, Some(a), Some(b)) => (a, b),
+        (Some(err), _, _) => return Err(err),
+        _ => unreachable!("every request either responds or fails"),
+    };
+
+    let mut changes = lines(&a.head, &b.head);
+
+    match (a.text(proto, deterministic), b.text(proto, deterministic)) {
+        (Some((_, a)), Some((_, b))) => changes.extend(bodies(&a, &b)),
+        _ if a.body != b.body => changes.push(format!(
+            "\x1b[33m~ binary body: {} → {}\x1b[0m",
+            progress::format_bytes(a.body.len() as f64),
+            progress::format_bytes(b.body.len() as f64)
+        )),
+        _ => {}
+    }
+
+    if changes.is_empty() {
+        note!("no differences between {} and {}", a.uri, b.uri);
+        return Ok(false);
+    }
+
+    println!("\x1b[1m--- {}\n+++ {}\x1b[0m", a.uri, b.uri);
+    for change in changes {
+        println!("{}", change);
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(changes: Vec<String>) -> Vec<String> {
+        changes
+            .into_iter()
+            .map(|change| {
+                change
+                    .trim_start_matches(|c| c != '-' && c != '+' && c != '~')
+                    .trim_end_matches("\x1b[0m")
+                    .to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diffs_json_by_structure() {
+        let a = json::parse(r#"{"id": 1, "tags": ["a", "b"], "old": true, "a b": 1}"#).unwrap();
+        let b = json::parse(r#"{"a b": 2, "tags": ["a"], "id": 1, "new": null}"#).unwrap();
+
+        assert_eq!(
+            plain(json(&a, &b)),
+            [
+                "- $.tags[1]: \"b\"",
+                "- $.old: true",
+                "~ $[\"a b\"]: 1 → 2",
+                "+ $.new: null",
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_lines() {
+        let a = "HTTP/1.1 200 OK\nage: 1\nserver: a\nvary: accept\n";
+        let b = "HTTP/1.1 200 OK\nserver: b\nvary: accept\nx-new: 1\n";
+
+        assert_eq!(
+            plain(lines(a, b)),
+            ["- age: 1", "- server: a", "+ server: b", "+ x-new: 1"]
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! `--dns-servers` and `--doh-url`: lookups with trust-dns to the given
+//! servers, or as DNS messages over HTTPS (RFC 8484), instead of the system
+//! resolver, with answers kept for their TTL for the rest of the run, so
+//! repeated requests ask once.
+
+use crate::tls::{self, TlsOptions};
+use hyper::{
+    client::{
+        connect::dns::{GaiResolver, Name},
+        HttpConnector,
+    },
+    header,
+    service::Service,
+    Body, Client, Request, Uri,
+};
+use hyper_rustls::HttpsConnector;
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use trust_dns_resolver::{
+    config::{LookupIpStrategy, NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    error::{ResolveError, ResolveErrorKind},
+    proto::{
+        op::{Message, Query, ResponseCode},
+        rr::{self, RData, RecordType},
+    },
+    TokioAsyncResolver,
+};
+
+const PORT: u16 = 53;
+/// How long each server has to answer before the next is asked.
+const TIMEOUT: Duration = Duration::from_secs(2);
+const DNS_MESSAGE: &str = "application/dns-message";
+
+#[derive(Debug, Error)]
+pub enum DnsError {
+    #[error("invalid DNS server {0}, expected an address like 1.1.1.1 or [2606:4700::1111]:53")]
+    ServerParseError(String),
+}
+
+/// `1.1.1.1,8.8.8.8`, each with an optional port.
+#[derive(Clone, Debug, Default)]
+pub struct DnsServers(pub Vec<SocketAddr>);
+
+impl std::str::FromStr for DnsServers {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .map(|server| match server.parse::<IpAddr>() {
+                Ok(address) => Ok(SocketAddr::new(address, PORT)),
+                Err(_) => server
+                    .parse()
+                    .map_err(|_| DnsError::ServerParseError(server.into())),
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", name))
+}
+
+fn other(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::other(err)
+}
+
+/// The addresses in a response, with their TTLs. Recursive servers answer
+/// with the whole CNAME chain, so every A and AAAA record counts.
+fn addresses(message: &Message, name: &str) -> io::Result<Vec<(IpAddr, u32)>> {
+    match message.response_code() {
+        ResponseCode::NoError => {}
+        ResponseCode::NXDomain => return Err(not_found(name)),
+        code => return Err(other(format!("DNS lookup of {} failed: {}", name, code))),
+    }
+
+    Ok(message
+        .answers()
+        .iter()
+        .filter_map(|record| match record.rdata() {
+            RData::A(address) => Some((IpAddr::V4(*address), record.ttl())),
+            RData::AAAA(address) => Some((IpAddr::V6(*address), record.ttl())),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Asks `uri` for `name`'s records of `kind`, POSTing the query as RFC
+/// 8484 describes. The ID stays 0, as it recommends for HTTP caches.
+async fn ask(
+    client: &Client<HttpsConnector<HttpConnector>>,
+    uri: &Uri,
+    name: &str,
+    kind: RecordType,
+) -> io::Result<Vec<(IpAddr, u32)>> {
+    let mut query = Message::new();
+    query
+        .set_recursion_desired(true)
+        .add_query(Query::query(rr::Name::from_ascii(name)?, kind));
+
+    let req = Request::post(uri.clone())
+        .header(header::CONTENT_TYPE, DNS_MESSAGE)
+        .header(header::ACCEPT, DNS_MESSAGE)
+        .body(Body::from(query.to_vec()?))
+        .map_err(other)?;
+    let res = client.request(req).await.map_err(other)?;
+
+    if !res.status().is_success() {
+        return Err(other(format!("{} answered {}", uri, res.status())));
+    }
+
+    let body = hyper::body::to_bytes(res.into_body())
+        .await
+        .map_err(other)?;
+    addresses(&Message::from_vec(&body)?, name)
+}
+
+/// `name`'s addresses from the DNS-over-HTTPS server, and how long they may
+/// be kept.
+async fn lookup(
+    client: &Client<HttpsConnector<HttpConnector>>,
+    uri: &Uri,
+    name: &str,
+) -> io::Result<(Vec<IpAddr>, Duration)> {
+    let both = async {
+        tokio::join!(
+            ask(client, uri, name, RecordType::A),
+            ask(client, uri, name, RecordType::AAAA)
+        )
+    };
+
+    let answers = match both.await {
+        (Err(err), _) | (_, Err(err)) if err.kind() == io::ErrorKind::NotFound => return Err(err),
+        (Err(err), Err(_)) => return Err(err),
+        (v4, v6) => v4
+            .unwrap_or_default()
+            .into_iter()
+            .chain(v6.unwrap_or_default())
+            .collect::<Vec<_>>(),
+    };
+
+    crate::trace!("asked {} for {}", uri, name);
+
+    if answers.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no addresses", name),
+        ));
+    }
+
+    let ttl = answers.iter().map(|(_, ttl)| *ttl).min().unwrap_or(0);
+    let addresses = answers.into_iter().map(|(address, _)| address).collect();
+
+    Ok((addresses, Duration::from_secs(ttl.into())))
+}
+
+fn resolve_error(err: ResolveError, name: &str) -> io::Error {
+    match err.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => not_found(name),
+        ResolveErrorKind::Timeout => io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("no DNS server answered within {:?}", TIMEOUT),
+        ),
+        _ => other(err),
+    }
+}
+
+/// A resolver for `servers`, asking each in turn. Constructing one needs
+/// the runtime, so it's made on the first lookup.
+#[derive(Clone)]
+struct Servers {
+    config: ResolverConfig,
+    resolver: Arc<tokio::sync::Mutex<Option<TokioAsyncResolver>>>,
+}
+
+impl Servers {
+    fn new(servers: &[SocketAddr]) -> Self {
+        let servers: Vec<_> = servers
+            .iter()
+            .map(|&socket_addr| NameServerConfig {
+                socket_addr,
+                protocol: Protocol::Udp,
+                tls_dns_name: None,
+            })
+            .collect();
+
+        Self {
+            config: ResolverConfig::from_parts(None, Vec::new(), servers),
+            resolver: Arc::default(),
+        }
+    }
+
+    async fn resolver(&self) -> io::Result<TokioAsyncResolver> {
+        let mut resolver = self.resolver.lock().await;
+
+        if resolver.is_none() {
+            let options = ResolverOpts {
+                timeout: TIMEOUT,
+                ip_strategy: LookupIpStrategy::Ipv4AndIpv6,
+                use_hosts_file: false,
+                ..ResolverOpts::default()
+            };
+
+            let created = TokioAsyncResolver::tokio(self.config.clone(), options)
+                .await
+                .map_err(other)?;
+            *resolver = Some(created);
+        }
+
+        Ok(resolver.clone().unwrap())
+    }
+}
+
+type Cache = HashMap<String, (Vec<IpAddr>, Instant)>;
+
+#[derive(Clone)]
+struct Https {
+    uri: Uri,
+    client: Client<HttpsConnector<HttpConnector>>,
+    cache: Arc<Mutex<Cache>>,
+}
+
+#[derive(Clone)]
+enum Upstream {
+    System(GaiResolver),
+    Servers(Servers),
+    Https(Https),
+}
+
+/// The system resolver, or with servers or a DNS-over-HTTPS URL given,
+/// lookups to those.
+#[derive(Clone)]
+pub struct DnsResolver(Upstream);
+
+impl std::fmt::Debug for DnsResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Upstream::System(_) => f.write_str("DnsResolver(system)"),
+            Upstream::Servers(servers) => {
+                let addresses: Vec<_> = servers
+                    .config
+                    .name_servers()
+                    .iter()
+                    .map(|server| server.socket_addr)
+                    .collect();
+                write!(f, "DnsResolver({:?})", addresses)
+            }
+            Upstream::Https(https) => write!(f, "DnsResolver({})", https.uri),
+        }
+    }
+}
+
+impl DnsResolver {
+    pub fn new(servers: Vec<SocketAddr>, doh_url: Option<Uri>) -> anyhow::Result<Self> {
+        let upstream = match doh_url {
+            // the DoH server's own name comes from the system resolver
+            Some(uri) => {
+                let mut http = HttpConnector::new();
+                http.enforce_http(false);
+                let config = tls::client_config(&TlsOptions::default())?;
+
+                Upstream::Https(Https {
+                    uri,
+                    client: Client::builder().build(HttpsConnector::from((http, config))),
+                    cache: Arc::default(),
+                })
+            }
+            None if !servers.is_empty() => Upstream::Servers(Servers::new(&servers)),
+            None => Upstream::System(GaiResolver::new()),
+        };
+
+        Ok(Self(upstream))
+    }
+}
+
+impl Service<Name> for DnsResolver {
+    type Response = std::vec::IntoIter<IpAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.0 {
+            Upstream::System(system) => system.poll_ready(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let host = name.as_str().to_ascii_lowercase();
+
+        match &mut self.0 {
+            Upstream::System(system) => {
+                let resolving = system.call(name);
+                Box::pin(async move { Ok(resolving.await?.collect::<Vec<_>>().into_iter()) })
+            }
+            Upstream::Servers(servers) => {
+                let servers = servers.clone();
+
+                // trust-dns keeps answers for their TTL itself
+                Box::pin(async move {
+                    let lookup = servers
+                        .resolver()
+                        .await?
+                        .lookup_ip(host.as_str())
+                        .await
+                        .map_err(|err| resolve_error(err, &host))?;
+
+                    crate::trace!("asked DNS servers for {}", host);
+                    Ok(lookup.iter().collect::<Vec<_>>().into_iter())
+                })
+            }
+            Upstream::Https(https) => {
+                let cached = https.cache.lock().unwrap().get(&host).cloned();
+
+                if let Some((addresses, expires)) = cached {
+                    if Instant::now() < expires {
+                        return Box::pin(async move { Ok(addresses.into_iter()) });
+                    }
+                }
+
+                let https = https.clone();
+
+                Box::pin(async move {
+                    let (addresses, ttl) = lookup(&https.client, &https.uri, &host).await?;
+
+                    https
+                        .cache
+                        .lock()
+                        .unwrap()
+                        .insert(host, (addresses.clone(), Instant::now() + ttl));
+
+                    Ok(addresses.into_iter())
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trust_dns_resolver::proto::rr::Record;
+
+    #[test]
+    fn parses_servers() {
+        let servers: DnsServers = "1.1.1.1, 127.0.0.1:5353,[::1]:53".parse().unwrap();
+
+        assert_eq!(
+            servers.0,
+            [
+                "1.1.1.1:53".parse().unwrap(),
+                "127.0.0.1:5353".parse().unwrap(),
+                "[::1]:53".parse::<SocketAddr>().unwrap(),
+            ]
+        );
+        assert!("1.1.1".parse::<DnsServers>().is_err());
+    }
+
+    #[test]
+    fn reads_addresses_through_cnames() {
+        let www = rr::Name::from_ascii("www.example.com").unwrap();
+        let apex = rr::Name::from_ascii("example.com").unwrap();
+
+        let mut response = Message::new();
+        response
+            .add_query(Query::query(www.clone(), RecordType::A))
+            .add_answer(Record::from_rdata(www, 60, RData::CNAME(apex.clone())))
+            .add_answer(Record::from_rdata(
+                apex,
+                30,
+                RData::A("93.184.216.34".parse().unwrap()),
+            ));
+        let response = Message::from_vec(&response.to_vec().unwrap()).unwrap();
+
+        assert_eq!(
+            addresses(&response, "www.example.com").unwrap(),
+            [("93.184.216.34".parse().unwrap(), 30)]
+        );
+
+        let mut missing = Message::new();
+        missing.set_response_code(ResponseCode::NXDomain);
+        assert_eq!(
+            addresses(&missing, "nope.test").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use rurl::{config::Config, history, oauth2};
+use std::path::{Path, PathBuf};
+
+const CA_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/cert.pem",
+    "/usr/local/etc/openssl/cert.pem",
+];
+
+const PROXY_VARS: &[&str] = &["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY"];
+
+#[derive(Default)]
+struct Report {
+    errors: usize,
+}
+
+impl Report {
+    fn ok(&mut self, check: &str, message: impl std::fmt::Display) {
+        println!("ok     {:<10} {}", check, message);
+    }
+
+    fn warn(&mut self, check: &str, message: impl std::fmt::Display) {
+        println!("warn   {:<10} {}", check, message);
+    }
+
+    fn error(&mut self, check: &str, message: impl std::fmt::Display) {
+        self.errors += 1;
+        println!("error  {:<10} {}", check, message);
+    }
+}
+
+fn check_config(report: &mut Report) {
+    match (Config::path(), Config::load()) {
+        (None, _) => report.warn(
+            "config",
+            "no config directory (set RURL_CONFIG_DIR or HOME)",
+        ),
+        (Some(path), Ok(_)) if !path.exists() => {
+            report.ok("config", format!("{} (not created yet)", path.display()))
+        }
+        (Some(path), Ok(_)) => report.ok("config", path.display()),
+        (Some(_), Err(err)) => report.error("config", err),
+    }
+}
+
+#[cfg(unix)]
+fn readable_by_others(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o077 != 0)
+}
+
+#[cfg(not(unix))]
+fn readable_by_others(_: &Path) -> bool {
+    false
+}
+
+/// rurl keeps no keyring of its own: cached OAuth2 tokens work as passwords
+/// until they expire, so they and the history must be private to the user.
+fn check_keyring(report: &mut Report) {
+    let mut files: Vec<PathBuf> = oauth2::cache_dir()
+        .and_then(|dir| std::fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    files.extend(history::path().ok().filter(|path| path.exists()));
+
+    let exposed: Vec<_> = files
+        .iter()
+        .filter(|path| readable_by_others(path))
+        .collect();
+
+    for path in &exposed {
+        report.error(
+            "keyring",
+            format!(
+                "{} is readable by other users, run chmod 600 on it",
+                path.display()
+            ),
+        );
+    }
+
+    match files.len() {
+        0 => report.ok("keyring", "no cached tokens or history"),
+        _ if exposed.is_empty() => report.ok(
+            "keyring",
+            format!("{} token and history files, private to you", files.len()),
+        ),
+        _ => {}
+    }
+}
+
+fn check_ca_bundle(report: &mut Report) {
+    if let Some(path) = std::env::var_os("SSL_CERT_FILE") {
+        let path = std::path::PathBuf::from(path);
+
+        if path.is_file() {
+            report.ok("ca-bundle", format!("{} (SSL_CERT_FILE)", path.display()));
+        } else {
+            report.error(
+                "ca-bundle",
+                format!("SSL_CERT_FILE {} does not exist", path.display()),
+            );
+        }
+
+        return;
+    }
+
+    match CA_BUNDLES
+        .iter()
+        .find(|path| std::path::Path::new(path).is_file())
+    {
+        Some(path) => report.ok("ca-bundle", path),
+        None => report.warn("ca-bundle", "no system CA bundle found"),
+    }
+}
+
+fn check_proxy(report: &mut Report) {
+    let mut found = false;
+
+    for key in PROXY_VARS {
+        let upper = std::env::var(key).ok();
+        let lower = std::env::var(key.to_lowercase()).ok();
+
+        if let (Some(upper), Some(lower)) = (&upper, &lower) {
+            if upper != lower {
+                report.warn(
+                    "proxy",
+                    format!("{} and {} disagree", key, key.to_lowercase()),
+                );
+            }
+        }
+
+        if let Some(value) = upper.or(lower) {
+            found = true;
+
+            match value.parse::<hyper::Uri>() {
+                Ok(uri) if uri.scheme().is_some() && uri.host().is_some() => {
+                    report.ok("proxy", format!("{}={}", key, value))
+                }
+                _ => report.error(
+                    "proxy",
+                    format!("{}={} is not a valid proxy URL", key, value),
+                ),
+            }
+        }
+    }
+
+    if !found {
+        report.ok("proxy", "no proxy configured");
+    }
+}
+
+pub fn run() -> bool {
+    let mut report = Report::default();
+
+    check_config(&mut report);
+    check_keyring(&mut report);
+    check_ca_bundle(&mut report);
+    check_proxy(&mut report);
+
+    report.errors == 0
+}
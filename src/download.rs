@@ -0,0 +1,599 @@
+use crate::{digest::hex, encoding};
+use anyhow::Result;
+use std::{
+    borrow::Cow,
+    convert::TryFrom,
+    io::Read as _,
+    path::{Component, Path, PathBuf},
+};
+use thiserror::Error;
+
+const BLOCK_SIZE: usize = 512;
+
+#[derive(Debug, Error)]
+pub enum DownloadError {
+    #[error("invalid checksum {0}, expected sha256:HEX")]
+    ChecksumParseError(String),
+    #[error("checksum mismatch: expected sha256:{0}, got sha256:{1}")]
+    ChecksumMismatch(String, String),
+    #[error("refusing to extract {0}, it would land outside the target directory")]
+    UnsafePath(String),
+    #[error("corrupt archive: {0}")]
+    Corrupt(&'static str),
+    #[error("not a tar, tar.gz, or zip archive")]
+    UnknownFormat,
+    #[error("cannot extract {0}: {1}")]
+    Unsupported(String, &'static str),
+}
+
+#[derive(Clone, Debug)]
+pub struct Checksum(String);
+
+impl std::str::FromStr for Checksum {
+    type Err = DownloadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hash = s.strip_prefix("sha256:").unwrap_or(s).to_ascii_lowercase();
+
+        if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(Self(hash))
+        } else {
+            Err(DownloadError::ChecksumParseError(s.into()))
+        }
+    }
+}
+
+impl Checksum {
+    /// Compares against the SHA-256 of the contents, hashed as they were
+    /// written.
+    pub fn verify(&self, digest: &[u8; 32]) -> Result<(), DownloadError> {
+        let actual = hex(digest);
+
+        if actual == self.0 {
+            Ok(())
+        } else {
+            Err(DownloadError::ChecksumMismatch(self.0.clone(), actual))
+        }
+    }
+}
+
+/// Joins an archive entry name onto `dir`, rejecting absolute paths and `..`.
+fn safe_path(dir: &Path, name: &str) -> Result<Option<PathBuf>, DownloadError> {
+    let mut path = dir.to_path_buf();
+    let mut empty = true;
+
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => {
+                path.push(part);
+                empty = false;
+            }
+            Component::CurDir => {}
+            _ => return Err(DownloadError::UnsafePath(name.into())),
+        }
+    }
+
+    Ok(if empty { None } else { Some(path) })
+}
+
+fn field(header: &[u8], start: usize, len: usize) -> String {
+    let bytes = &header[start..start + len];
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(len);
+
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn octal(header: &[u8], start: usize, len: usize) -> Result<u64, DownloadError> {
+    let bytes = &header[start..start + len];
+
+    // GNU tar stores large sizes as big-endian base-256
+    if bytes[0] & 0x80 != 0 {
+        return bytes[1..]
+            .iter()
+            .try_fold(0u64, |value, &byte| {
+                Some(value.checked_mul(256)? | u64::from(byte))
+            })
+            .ok_or(DownloadError::Corrupt("invalid tar header"));
+    }
+
+    let text = field(header, start, len);
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+
+    if text.is_empty() {
+        return Ok(0);
+    }
+
+    u64::from_str_radix(text, 8).map_err(|_| DownloadError::Corrupt("invalid tar header"))
+}
+
+/// The `path` record of a pax extended header, e.g. `27 path=some/long/name\n`.
+fn pax_path(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+
+    text.lines()
+        .filter_map(|record| record.split_once(' ').map(|(_, record)| record))
+        .find_map(|record| record.strip_prefix("path="))
+        .map(Into::into)
+}
+
+enum Kind {
+    File { mode: u32 },
+    Directory,
+    Link,
+}
+
+struct Entry<'a> {
+    name: String,
+    kind: Kind,
+    /// Borrowed from a tar, inflated from a zip.
+    data: Cow<'a, [u8]>,
+}
+
+fn tar_entries(archive: &[u8]) -> Result<Vec<Entry<'_>>, DownloadError> {
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut offset = 0;
+
+    while offset + BLOCK_SIZE <= archive.len() {
+        let header = &archive[offset..offset + BLOCK_SIZE];
+
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+
+        // a crafted size may not even fit in memory, which is as corrupt
+        // as one past the end of the archive
+        let truncated = || DownloadError::Corrupt("truncated tar entry");
+        let size = usize::try_from(octal(header, 124, 12)?).map_err(|_| truncated())?;
+        let data_start = offset + BLOCK_SIZE;
+        let data = data_start
+            .checked_add(size)
+            .and_then(|data_end| archive.get(data_start..data_end))
+            .ok_or_else(truncated)?;
+        offset = data_start + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+
+        let prefix = if &header[257..262] == b"ustar" {
+            field(header, 345, 155)
+        } else {
+            String::new()
+        };
+        let name = match long_name.take() {
+            Some(name) => name,
+            None if prefix.is_empty() => field(header, 0, 100),
+            None => format!("{}/{}", prefix, field(header, 0, 100)),
+        };
+
+        let kind = match header[156] {
+            b'0' | b'\0' | b'7' => Kind::File {
+                mode: octal(header, 100, 8)? as u32,
+            },
+            b'5' => Kind::Directory,
+            b'1' | b'2' => Kind::Link,
+            b'L' => {
+                long_name = Some(field(data, 0, data.len()));
+                continue;
+            }
+            b'x' => {
+                long_name = pax_path(data);
+                continue;
+            }
+            _ => continue,
+        };
+
+        entries.push(Entry {
+            name,
+            kind,
+            data: data.into(),
+        });
+    }
+
+    Ok(entries)
+}
+
+fn write_entries(entries: Vec<Entry<'_>>, dir: &Path) -> Result<usize> {
+    // check every path before writing anything
+    let mut paths = Vec::new();
+    for entry in entries.iter() {
+        paths.push(safe_path(dir, &entry.name)?);
+    }
+
+    let mut count = 0;
+
+    for (entry, path) in entries.iter().zip(paths) {
+        let path = match path {
+            Some(path) => path,
+            None => continue,
+        };
+
+        match entry.kind {
+            Kind::Directory => std::fs::create_dir_all(&path)?,
+            Kind::Link => eprintln!("warning: skipping link {}", entry.name),
+            Kind::File { mode } => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                std::fs::write(&path, &entry.data)?;
+
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt as _;
+
+                    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode & 0o755))?;
+                }
+                #[cfg(not(unix))]
+                let _ = mode;
+
+                count += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<usize> {
+    let bytes = bytes.get(at..at + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    let bytes = bytes.get(at..at + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Stored or deflated data of a zip entry, checked against its size and CRC.
+fn inflate(
+    name: &str,
+    method: usize,
+    data: &[u8],
+    size: usize,
+    crc: u32,
+) -> Result<Vec<u8>, DownloadError> {
+    let corrupt = || DownloadError::Corrupt("invalid zip entry");
+
+    let inflated = match method {
+        0 => data.to_vec(),
+        8 => {
+            let mut inflated = Vec::with_capacity(size);
+
+            // one byte more than declared, to notice data that runs on
+            flate2::read::DeflateDecoder::new(data)
+                .take(size as u64 + 1)
+                .read_to_end(&mut inflated)
+                .map_err(|_| corrupt())?;
+
+            inflated
+        }
+        _ => {
+            return Err(DownloadError::Unsupported(
+                name.into(),
+                "only stored and deflated zip entries are supported",
+            ))
+        }
+    };
+
+    let mut actual = flate2::Crc::new();
+    actual.update(&inflated);
+
+    if inflated.len() != size || actual.sum() != crc {
+        return Err(corrupt());
+    }
+
+    Ok(inflated)
+}
+
+/// Every entry in a zip's central directory, with its data read from its
+/// local header and inflated.
+fn zip_entries(archive: &[u8]) -> Result<Vec<Entry<'_>>, DownloadError> {
+    let corrupt = || DownloadError::Corrupt("invalid zip central directory");
+
+    let end = (0..archive.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(archive, i) == Some(0x0605_4b50))
+        .ok_or_else(corrupt)?;
+    let count = u16_at(archive, end + 10).ok_or_else(corrupt)?;
+    let mut offset = u32_at(archive, end + 16).ok_or_else(corrupt)? as usize;
+
+    let mut entries = Vec::new();
+
+    for _ in 0..count {
+        if u32_at(archive, offset) != Some(0x0201_4b50) {
+            return Err(corrupt());
+        }
+
+        let made_by = u16_at(archive, offset + 4).ok_or_else(corrupt)?;
+        let flags = u16_at(archive, offset + 8).ok_or_else(corrupt)?;
+        let method = u16_at(archive, offset + 10).ok_or_else(corrupt)?;
+        let crc = u32_at(archive, offset + 16).ok_or_else(corrupt)?;
+        let compressed = u32_at(archive, offset + 20).ok_or_else(corrupt)?;
+        let size = u32_at(archive, offset + 24).ok_or_else(corrupt)?;
+        let name_len = u16_at(archive, offset + 28).ok_or_else(corrupt)?;
+        let extra_len = u16_at(archive, offset + 30).ok_or_else(corrupt)?;
+        let comment_len = u16_at(archive, offset + 32).ok_or_else(corrupt)?;
+        let attributes = u32_at(archive, offset + 38).ok_or_else(corrupt)?;
+        let local = u32_at(archive, offset + 42).ok_or_else(corrupt)? as usize;
+        let name = archive
+            .get(offset + 46..offset + 46 + name_len)
+            .ok_or_else(corrupt)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        offset += 46 + name_len + extra_len + comment_len;
+
+        if flags & 1 != 0 {
+            return Err(DownloadError::Unsupported(name, "the entry is encrypted"));
+        }
+
+        if [compressed, size, local as u32].contains(&u32::MAX) {
+            return Err(DownloadError::Unsupported(name, "zip64 is not supported"));
+        }
+
+        // the local header's sizes may be zero, with the real ones in a
+        // descriptor after the data, so only its name and extra lengths count
+        if u32_at(archive, local) != Some(0x0403_4b50) {
+            return Err(corrupt());
+        }
+
+        let data_start = local
+            + 30
+            + u16_at(archive, local + 26).ok_or_else(corrupt)?
+            + u16_at(archive, local + 28).ok_or_else(corrupt)?;
+        let data = archive
+            .get(data_start..data_start + compressed as usize)
+            .ok_or_else(corrupt)?;
+
+        // only unix zips have a mode, in the high half of the attributes
+        let mode = match made_by >> 8 {
+            3 => Some(attributes >> 16),
+            _ => None,
+        };
+
+        let kind = match mode {
+            _ if name.ends_with('/') => Kind::Directory,
+            Some(mode) if mode & 0o170_000 == 0o120_000 => Kind::Link,
+            Some(mode) if mode & 0o777 != 0 => Kind::File { mode },
+            _ => Kind::File { mode: 0o644 },
+        };
+
+        let data = match kind {
+            Kind::File { .. } => inflate(&name, method, data, size as usize, crc)?.into(),
+            _ => Cow::Borrowed(&[][..]),
+        };
+
+        entries.push(Entry { name, kind, data });
+    }
+
+    Ok(entries)
+}
+
+/// Unpacks the tar, tar.gz, or zip archive at `archive_path` into `dir`,
+/// detected by its contents rather than its name. Entries that would land
+/// outside `dir` fail the whole extraction before anything is written.
+/// Returns the number of files extracted.
+pub fn extract(archive_path: &Path, dir: &Path) -> Result<usize> {
+    let archive = std::fs::read(archive_path)?;
+    std::fs::create_dir_all(dir)?;
+
+    if archive.starts_with(b"PK\x03\x04") || archive.starts_with(b"PK\x05\x06") {
+        return write_entries(zip_entries(&archive)?, dir);
+    }
+
+    let archive = if archive.starts_with(&[0x1f, 0x8b]) {
+        encoding::gunzip(&archive).ok_or(DownloadError::Corrupt("gzip data is invalid"))?
+    } else {
+        archive
+    };
+
+    if archive.get(257..262) != Some(b"ustar") && archive.len() % BLOCK_SIZE != 0 {
+        return Err(DownloadError::UnknownFormat.into());
+    }
+
+    write_entries(tar_entries(&archive)?, dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ustar entry for `name`, its data padded to whole blocks.
+    fn entry(name: &str, kind: u8, data: &[u8]) -> Vec<u8> {
+        let mut entry = vec![0; BLOCK_SIZE];
+        entry[..name.len()].copy_from_slice(name.as_bytes());
+        entry[100..107].copy_from_slice(b"0000644");
+        entry[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+        entry[156] = kind;
+        entry[257..262].copy_from_slice(b"ustar");
+
+        entry.extend_from_slice(data);
+        entry.resize(BLOCK_SIZE + data.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+        entry
+    }
+
+    fn extract_tar(archive: &[u8], dir: &Path) -> Result<usize> {
+        write_entries(tar_entries(archive)?, dir)
+    }
+
+    /// A zip of `(name, unix mode, data)` entries, deflated when `deflate`.
+    fn zip(entries: &[(&str, u32, &[u8])], deflate: bool) -> Vec<u8> {
+        use std::io::Write as _;
+
+        let mut archive = Vec::new();
+        let mut central = Vec::new();
+
+        for (name, mode, data) in entries {
+            let mut crc = flate2::Crc::new();
+            crc.update(data);
+            let stored = if deflate {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                data.to_vec()
+            };
+
+            // method, CRC, compressed and uncompressed sizes, name and extra lengths
+            let mut fields = Vec::new();
+            fields.extend(&(if deflate { 8u16 } else { 0 }).to_le_bytes());
+            fields.extend(&[0; 4]);
+            fields.extend(&crc.sum().to_le_bytes());
+            fields.extend(&(stored.len() as u32).to_le_bytes());
+            fields.extend(&(data.len() as u32).to_le_bytes());
+            fields.extend(&(name.len() as u16).to_le_bytes());
+            fields.extend(&[0; 2]);
+
+            central.extend(&0x0201_4b50u32.to_le_bytes());
+            central.extend(&[20, 3, 20, 0, 0, 0]);
+            central.extend(&fields);
+            central.extend(&[0; 6]);
+            central.extend(&(mode << 16).to_le_bytes());
+            central.extend(&(archive.len() as u32).to_le_bytes());
+            central.extend(name.as_bytes());
+
+            archive.extend(&0x0403_4b50u32.to_le_bytes());
+            archive.extend(&[20, 0, 0, 0]);
+            archive.extend(&fields);
+            archive.extend(name.as_bytes());
+            archive.extend(&stored);
+        }
+
+        let offset = archive.len() as u32;
+        archive.extend(&central);
+        archive.extend(&0x0605_4b50u32.to_le_bytes());
+        archive.extend(&[0; 4]);
+        archive.extend(&(entries.len() as u16).to_le_bytes());
+        archive.extend(&(entries.len() as u16).to_le_bytes());
+        archive.extend(&(central.len() as u32).to_le_bytes());
+        archive.extend(&offset.to_le_bytes());
+        archive.extend(&[0; 2]);
+        archive
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rurl-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        dir
+    }
+
+    #[test]
+    fn keeps_entries_inside_the_directory() {
+        let dir = Path::new("/srv/out");
+
+        assert_eq!(
+            safe_path(dir, "./bin/tool").unwrap(),
+            Some(dir.join("bin/tool"))
+        );
+        assert_eq!(safe_path(dir, "./").unwrap(), None);
+        assert!(safe_path(dir, "../escape").is_err());
+        assert!(safe_path(dir, "bin/../../escape").is_err());
+        assert!(safe_path(dir, "/etc/passwd").is_err());
+        // even when it would stay inside
+        assert!(safe_path(dir, "bin/../tool").is_err());
+    }
+
+    #[test]
+    fn extracts_files_and_directories() {
+        let dir = scratch_dir("extract");
+        let mut archive = entry("tool/", b'5', b"");
+        archive.extend(entry("tool/README", b'0', b"read me"));
+        archive.extend(entry("tool/bin", b'0', &[7; 600]));
+
+        assert_eq!(extract_tar(&archive, &dir).unwrap(), 2);
+        assert_eq!(std::fs::read(dir.join("tool/README")).unwrap(), b"read me");
+        assert_eq!(std::fs::read(dir.join("tool/bin")).unwrap(), vec![7; 600]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_archives_that_escape_before_writing_anything() {
+        for name in &["../escape", "tool/../../escape", "/tmp/escape"] {
+            let dir = scratch_dir("escape");
+            let mut archive = entry("first", b'0', b"1");
+            archive.extend(entry(name, b'0', b"2"));
+
+            assert!(extract_tar(&archive, &dir).is_err(), "{}", name);
+            assert!(!dir.join("first").exists(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn refuses_truncated_archives() {
+        let archive = entry("big", b'0', &[1; 2000]);
+        assert!(tar_entries(&archive[..1024]).is_err());
+
+        // a base-256 size that would wrap around the end of memory
+        let mut archive = entry("huge", b'0', b"");
+        archive[124] = 0x80;
+        archive[128..136].copy_from_slice(&[0xff; 8]);
+        assert!(tar_entries(&archive).is_err());
+
+        let mut archive = entry("huger", b'0', b"");
+        archive[124] = 0x80;
+        archive[125..136].copy_from_slice(&[0xff; 11]);
+        assert!(tar_entries(&archive).is_err());
+    }
+
+    #[test]
+    fn extracts_stored_and_deflated_zips() {
+        for deflate in [false, true] {
+            let dir = scratch_dir("zip");
+            let archive = zip(
+                &[
+                    ("tool/", 0o040_755, b""),
+                    ("tool/README", 0o100_644, b"read me"),
+                    ("tool/bin", 0o100_755, &[7; 600]),
+                ],
+                deflate,
+            );
+
+            assert_eq!(
+                write_entries(zip_entries(&archive).unwrap(), &dir).unwrap(),
+                2
+            );
+            assert_eq!(std::fs::read(dir.join("tool/README")).unwrap(), b"read me");
+            assert_eq!(std::fs::read(dir.join("tool/bin")).unwrap(), vec![7; 600]);
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt as _;
+
+                let mode = std::fs::metadata(dir.join("tool/bin"))
+                    .unwrap()
+                    .permissions()
+                    .mode();
+                assert_eq!(mode & 0o777, 0o755);
+            }
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn refuses_zips_that_escape_before_writing_anything() {
+        for name in &["../escape", "tool/../../escape", "/tmp/escape"] {
+            let dir = scratch_dir("zip-escape");
+            let archive = zip(&[("first", 0o100_644, b"1"), (name, 0o100_644, b"2")], true);
+            let entries = zip_entries(&archive).unwrap();
+
+            assert!(write_entries(entries, &dir).is_err(), "{}", name);
+            assert!(!dir.join("first").exists(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn skips_zip_links_and_refuses_corrupt_data() {
+        let dir = scratch_dir("zip-link");
+        let archive = zip(&[("link", 0o120_777, b"/etc/passwd")], false);
+
+        assert_eq!(
+            write_entries(zip_entries(&archive).unwrap(), &dir).unwrap(),
+            0
+        );
+        assert!(!dir.join("link").exists());
+
+        let mut archive = zip(&[("file", 0o100_644, b"data")], false);
+        archive[34] ^= 0xff;
+        assert!(zip_entries(&archive).is_err());
+    }
+}
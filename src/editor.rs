@@ -0,0 +1,89 @@
+//! `--edit`: the request body opened in `$VISUAL` or `$EDITOR` before it is
+//! sent, so large payloads can be tweaked without shell quoting.
+
+use crate::{body::RequestBody, output::pretty_json};
+use std::{
+    path::PathBuf,
+    process::{Command, ExitStatus},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EditorError {
+    #[error("cannot edit a binary body")]
+    Binary,
+    #[error("could not run {0}: {1}")]
+    Spawn(String, std::io::Error),
+    #[error("{0} exited with {1}, not sending the request")]
+    Failed(String, ExitStatus),
+    #[error("the body is empty, not sending the request")]
+    Empty,
+    #[error("the edited body is not valid JSON: {0}")]
+    InvalidJson(json::Error),
+    #[error("could not use {} for the body: {1}", .0.display())]
+    TempFile(PathBuf, std::io::Error),
+}
+
+/// `$VISUAL`, then `$EDITOR`, then vi.
+pub fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".into())
+}
+
+/// Opens `content` in the editor, in a file named for `language` so it is
+/// highlighted, and returns what was saved.
+pub fn edit(content: &str, language: &str) -> Result<String, EditorError> {
+    let path = std::env::temp_dir().join(format!("rurl-{}-body.{}", std::process::id(), language));
+    std::fs::write(&path, content).map_err(|err| EditorError::TempFile(path.clone(), err))?;
+
+    let editor = editor();
+    // through the shell, as $EDITOR may carry arguments, e.g. `code --wait`
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("rurl")
+        .arg(&path)
+        .status();
+
+    let edited = match status {
+        Ok(status) if status.success() => {
+            std::fs::read_to_string(&path).map_err(|err| EditorError::TempFile(path.clone(), err))
+        }
+        Ok(status) => Err(EditorError::Failed(editor, status)),
+        Err(err) => Err(EditorError::Spawn(editor, err)),
+    };
+    let _ = std::fs::remove_file(&path);
+
+    match edited? {
+        edited if edited.trim().is_empty() => Err(EditorError::Empty),
+        edited => Ok(edited),
+    }
+}
+
+/// Replaces the body with what the user saves. JSON is pretty-printed for
+/// editing and sent compact again, and must still parse.
+pub fn edit_body(body: &mut RequestBody) -> Result<(), EditorError> {
+    let content = std::str::from_utf8(&body.content).map_err(|_| EditorError::Binary)?;
+    let json = body.language == "json";
+
+    let content = match json::parse(content) {
+        Ok(document) if json => pretty_json(&document),
+        _ => content.to_owned(),
+    };
+
+    let edited = edit(&content, &body.language)?;
+
+    body.content = if json {
+        json::parse(&edited)
+            .map_err(EditorError::InvalidJson)?
+            .dump()
+            .into_bytes()
+    } else {
+        edited.into_bytes()
+    };
+
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! Content-Encoding: decoding compressed bodies and reporting what the
+//! server chose against what the request offered.
+
+use flate2::{
+    write::{GzDecoder, GzEncoder, ZlibDecoder},
+    Compression,
+};
+use hyper::{header, HeaderMap};
+use std::io::{self, Write as _};
+
+/// Compresses `input` as gzip at the default level.
+pub fn gzip(input: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+    // writing to a Vec can't fail
+    encoder.write_all(input).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Decompresses gzip `input`, or `None` if it is not valid gzip.
+pub fn gunzip(input: &[u8]) -> Option<Vec<u8>> {
+    let mut decoder = Decoder::new("gzip")?;
+    let mut output = decoder.decode(input).ok()?;
+    output.extend(decoder.finish().ok()?);
+
+    Some(output)
+}
+
+/// Decodes a body a chunk at a time as it arrives, so a large download is
+/// never held in memory whole.
+#[derive(Debug)]
+pub enum Decoder {
+    Gzip(GzDecoder<Vec<u8>>),
+    Deflate(ZlibDecoder<Vec<u8>>),
+}
+
+impl Decoder {
+    /// A decoder for `coding`, or `None` when rurl can't decode it.
+    pub fn new(coding: &str) -> Option<Self> {
+        match coding {
+            "gzip" | "x-gzip" => Some(Self::Gzip(GzDecoder::new(Vec::new()))),
+            "deflate" => Some(Self::Deflate(ZlibDecoder::new(Vec::new()))),
+            _ => None,
+        }
+    }
+
+    /// What `chunk` decodes to, which may be nothing until more arrives.
+    pub fn decode(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip(decoder) => {
+                decoder.write_all(chunk)?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+            Self::Deflate(decoder) => {
+                decoder.write_all(chunk)?;
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+        }
+    }
+
+    /// The rest of the body, failing if it ended early.
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip(decoder) => decoder.finish(),
+            Self::Deflate(decoder) => decoder.finish(),
+        }
+    }
+}
+
+/// The response's Content-Encoding, lowercased, unless it is identity.
+pub fn content_encoding(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty() && value != "identity")
+}
+
+/// Decodes `body` per the response's Content-Encoding. Bodies that are
+/// not encoded come back as they are; `None` means the encoding is not
+/// one rurl can decode, or the body is corrupt.
+pub fn decode(headers: &HeaderMap, body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoder = match content_encoding(headers) {
+        Some(coding) => Decoder::new(&coding)?,
+        None => return Some(body.to_vec()),
+    };
+
+    let mut decoded = decoder.decode(body).ok()?;
+    decoded.extend(decoder.finish().ok()?);
+
+    Some(decoded)
+}
+
+/// Which encoding the server chose, next to what the request offered, or
+/// `None` when neither side mentioned one.
+pub fn negotiation(request: &HeaderMap, response: &HeaderMap) -> Option<String> {
+    let offered: Vec<String> = request
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|coding| {
+            coding
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        })
+        .filter(|coding| !coding.is_empty())
+        .collect();
+    let chosen = content_encoding(response);
+
+    if offered.is_empty() && chosen.is_none() {
+        return None;
+    }
+
+    let offered_text = if offered.is_empty() {
+        "nothing".to_owned()
+    } else {
+        offered.join(", ")
+    };
+
+    Some(match chosen {
+        Some(chosen)
+            if !offered
+                .iter()
+                .any(|coding| *coding == chosen || coding == "*") =>
+        {
+            format!(
+                "encoding: server chose {}, which was not offered (offered {})",
+                chosen, offered_text
+            )
+        }
+        Some(chosen) => format!(
+            "encoding: server chose {} (offered {})",
+            chosen, offered_text
+        ),
+        None => format!("encoding: server chose identity (offered {})", offered_text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_a_chunk_at_a_time() {
+        let body = "rurl ".repeat(10_000);
+        let compressed = gzip(body.as_bytes());
+
+        let mut decoder = Decoder::new("gzip").unwrap();
+        let mut decoded = Vec::new();
+        for chunk in compressed.chunks(100) {
+            decoded.extend(decoder.decode(chunk).unwrap());
+        }
+        decoded.extend(decoder.finish().unwrap());
+
+        assert_eq!(decoded, body.as_bytes());
+        assert!(gunzip(&compressed[..compressed.len() / 2]).is_none());
+    }
+}
@@ -0,0 +1,1152 @@
+//! A single request and its response: printing the request, sending it
+//! once, repeatedly under `--repeat`, or until `--until` holds, and showing,
+//! saving, or streaming what comes back.
+
+use crate::{
+    analyze,
+    auth::Auth,
+    bench::{self, Bench},
+    binary,
+    body::RequestBody,
+    cache::ResponseCache,
+    cancel::CancellationToken,
+    charset,
+    client::{self, HttpClient},
+    clipboard,
+    cors::{self, Preflight},
+    curl, dav, decode, deterministic, diagnose,
+    digest::Sha256,
+    download::{self, Checksum},
+    editor, encoding,
+    expect::Expectations,
+    hexdump,
+    highlight::highlight,
+    history,
+    image::{self, Protocol},
+    jsonpath,
+    limit::{BodyLimit, LowSpeed, Size},
+    ndjson, note,
+    notify::Notifier,
+    output::{self, HeaderLayout},
+    pager,
+    paginate::{self, Paginate},
+    pipe, plugin,
+    poll::{self, Poll},
+    progress,
+    protobuf::Protobuf,
+    redirect::{self, Redirects},
+    retry::{CircuitBreaker, RetryBudget},
+    sse, table,
+    timing::Recorder,
+    tls, trace,
+};
+use anyhow::Result;
+use hyper::{body::Bytes, Body, HeaderMap, Method, Request, Response};
+use std::{borrow::Cow, path::PathBuf, sync::Arc, time::Instant};
+
+/// How a response body is read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Reading {
+    /// Copied to stdout by `--raw-response -` as it is read.
+    Raw,
+    /// Written to `--output`.
+    Save,
+    /// Printed event by event.
+    Events,
+    /// Printed NDJSON line by line.
+    Lines,
+    /// Read whole, then shown.
+    Whole,
+}
+
+/// How to send the request and show the exchange, from the command line.
+pub struct Exchange {
+    pub recorder: Recorder,
+    /// The arguments to record in the history, under `--history`.
+    pub history: Option<Vec<String>>,
+    /// False under `--format json`, which prints the exchange once the
+    /// response is in.
+    pub text: bool,
+    pub deterministic: bool,
+    /// `--meta` or `--verbose`: print the timings at the end.
+    pub meta: bool,
+    pub header_layout: HeaderLayout,
+    pub print_full_url: bool,
+    pub edit: bool,
+    pub cache_dir: Option<PathBuf>,
+    pub curl: bool,
+    pub curl_only: bool,
+    pub hexdump: bool,
+    /// `--repeat` with more than one request.
+    pub bench: Option<Bench>,
+    pub progress: bool,
+    pub poll: Option<Poll>,
+    pub redirects: Option<Redirects>,
+    pub budget: Arc<RetryBudget>,
+    pub breaker: Arc<CircuitBreaker>,
+    pub cancel: CancellationToken,
+    pub auth: Auth,
+    pub diagnose: bool,
+    pub tls_info: bool,
+    pub if_match: bool,
+    pub preflight: Option<Preflight>,
+    pub expectations: Expectations,
+    pub max_body: Option<Size>,
+    pub low_speed: Option<LowSpeed>,
+    pub limit_rate: Option<Size>,
+    /// `--raw-response -`, which copies the body to stdout as it is read.
+    pub raw_to_stdout: bool,
+    pub output: Option<PathBuf>,
+    pub no_decompress: bool,
+    pub checksum: Option<Checksum>,
+    pub extract: Option<PathBuf>,
+    pub sse_timeout: Option<std::time::Duration>,
+    pub notifier: Notifier,
+    pub json_output: bool,
+    pub decode_fields: Option<String>,
+    pub paginate: Option<Paginate>,
+    pub filter: Option<String>,
+    pub copy: bool,
+    pub formatter: Option<String>,
+    pub pager: bool,
+    pub analyze: bool,
+    pub render_images: bool,
+    pub table: bool,
+    pub dav: bool,
+    pub graphql: bool,
+    pub pretty_max_size: Option<usize>,
+    /// A JSON mode asked for JSON back, so other types are warned about.
+    pub expects_json: bool,
+    pub proto: Option<Arc<Protobuf>>,
+}
+
+impl Exchange {
+    fn print_meta(&self, start: Instant) {
+        self.recorder
+            .update(|timings| timings.total = Some(start.elapsed()));
+        note!("\n{}", self.recorder.timings().format(self.deterministic));
+    }
+
+    /// Prints the timings if asked, then checks the expectations against
+    /// the response, so it is shown either way.
+    fn finish(&self, res: &Response<Body>, start: Instant, body: &[u8]) -> Result<()> {
+        if self.meta {
+            self.print_meta(start);
+        }
+
+        Ok(self.expectations.check(res.status(), res.headers(), body)?)
+    }
+
+    fn print_trailers(&self, trailers: Option<&hyper::HeaderMap>) -> Result<()> {
+        if let Some(trailers) = trailers {
+            note!(
+                "{}",
+                highlight(
+                    &output::trailers(trailers, self.deterministic, self.header_layout)?,
+                    "http"
+                )
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The request body as it is printed: highlighted text, a hexdump, or a
+    /// note standing in for binary data.
+    fn format_body(&self, body: &RequestBody) -> String {
+        let content = match std::str::from_utf8(&body.content) {
+            Ok(content) if self.deterministic => Some(deterministic::normalize(content)),
+            Ok(content) => Some(content.to_owned()),
+            Err(_) => None,
+        };
+
+        match content {
+            Some(content) if !self.hexdump => {
+                let end = if content.ends_with('\n') { "" } else { "\n" };
+                format!("{}\x1b[0m{}", highlight(&content, &body.language), end)
+            }
+            Some(content) => hexdump::dump(content.as_bytes()),
+            // MessagePack and protobuf are small and have no text to show; a binary file
+            // could be gigabytes
+            None if self.hexdump || matches!(body.language.as_str(), "msgpack" | "protobuf") => {
+                hexdump::dump(&body.content)
+            }
+            None => format!(
+                "\x1b[2m({} of binary data, shown with --hexdump)\x1b[0m\n",
+                progress::format_bytes(body.content.len() as f64)
+            ),
+        }
+    }
+
+    /// How the response body is read, from what was asked for and what the
+    /// response says it is.
+    fn reading(&self, res: &Response<Body>) -> Reading {
+        if self.raw_to_stdout {
+            Reading::Raw
+        } else if self.output.is_some() {
+            Reading::Save
+        } else if output::is_event_stream(res) {
+            Reading::Events
+        } else if self.text
+            && output::is_ndjson(res)
+            && encoding::content_encoding(res.headers()).is_none()
+            && !(self.json_output
+                || self.hexdump
+                || self.decode_fields.is_some()
+                || self.paginate.is_some())
+            && self.expectations.body.is_empty()
+        {
+            // each line is shown as it arrives, unless the body needs decoding first
+            Reading::Lines
+        } else {
+            Reading::Whole
+        }
+    }
+
+    async fn send(
+        &self,
+        client: &HttpClient,
+        req: &Request<Body>,
+        body: &[u8],
+    ) -> Result<Response<Body>> {
+        match (&self.poll, &self.redirects) {
+            (Some(poll), _) => poll::run(poll.clone(), client, req, body).await,
+            (None, Some(redirects)) => redirect::follow(redirects.clone(), client, req, body)
+                .await
+                .map(|(res, hops)| {
+                    if hops.len() > 1 {
+                        note!("{}", redirect::format_chain(&hops, self.deterministic));
+                    }
+
+                    res
+                }),
+            (None, None) => {
+                client::send(client, req, body, &self.budget, &self.breaker, &self.cancel).await
+            }
+        }
+    }
+
+    /// Writes the body to `--output`, decoded as it arrives, so even a
+    /// large body is never held whole.
+    async fn save(
+        &self,
+        res: &mut Response<Body>,
+        limit: &mut BodyLimit,
+        path: &std::path::Path,
+        start: Instant,
+    ) -> Result<()> {
+        use tokio::io::AsyncWriteExt as _;
+
+        let coding = encoding::content_encoding(res.headers()).unwrap_or_default();
+        let mut decoder = encoding::Decoder::new(&coding);
+        if !coding.is_empty() && decoder.is_none() && !self.no_decompress {
+            note!("cannot decode {}, writing the body as sent", coding);
+        }
+
+        let mut file = tokio::fs::File::create(path).await?;
+        let mut sent = 0;
+        // with --no-decompress, only counted for the note
+        let mut decoded = decoder.as_ref().map(|_| 0);
+        let no_decompress = self.no_decompress;
+        // hashed as written, rather than read back from the file
+        let mut hash = self.checksum.as_ref().map(|_| Sha256::new());
+        // body expectations need the whole document, so only then is it kept
+        let mut kept = if self.expectations.body.is_empty() {
+            None
+        } else {
+            Some(Vec::new())
+        };
+
+        let written: Result<()> = async {
+            let invalid = |err| anyhow::anyhow!("the {} body is invalid: {}", coding, err);
+            let mut finished = false;
+
+            while !finished {
+                let chunk = match limit.next_chunk(res, &self.cancel).await? {
+                    Some(chunk) => chunk,
+                    None => {
+                        finished = true;
+                        Bytes::new()
+                    }
+                };
+                self.recorder.update(|timings| timings.bytes += chunk.len());
+                sent += chunk.len();
+
+                let decoding = if finished {
+                    decoder.take().map(encoding::Decoder::finish)
+                } else {
+                    decoder.as_mut().map(|decoder| decoder.decode(&chunk))
+                };
+                let bytes = match decoding {
+                    Some(Ok(bytes)) => {
+                        decoded = decoded.map(|decoded| decoded + bytes.len());
+                        if no_decompress {
+                            Cow::Borrowed(&chunk[..])
+                        } else {
+                            Cow::Owned(bytes)
+                        }
+                    }
+                    Some(Err(_)) if no_decompress => {
+                        decoder = None;
+                        decoded = None;
+                        Cow::Borrowed(&chunk[..])
+                    }
+                    Some(Err(err)) => return Err(invalid(err)),
+                    None => Cow::Borrowed(&chunk[..]),
+                };
+
+                file.write_all(&bytes).await?;
+                if let Some(hash) = &mut hash {
+                    hash.update(&bytes);
+                }
+                if let Some(kept) = &mut kept {
+                    kept.extend_from_slice(&bytes);
+                }
+            }
+
+            Ok(file.flush().await?)
+        }
+        .await;
+
+        if let Err(err) = written {
+            drop(file);
+            let _ = tokio::fs::remove_file(path).await;
+
+            return Err(err);
+        }
+
+        if self.no_decompress && !coding.is_empty() {
+            match decoded {
+                Some(decoded) => note!(
+                    "kept {} bytes of {} as sent, {} bytes decoded",
+                    sent,
+                    coding,
+                    decoded
+                ),
+                None => note!("kept {} bytes of {} as sent", sent, coding),
+            }
+        }
+
+        if let (Some(checksum), Some(hash)) = (&self.checksum, hash) {
+            if let Err(err) = checksum.verify(&hash.finish()) {
+                let _ = tokio::fs::remove_file(path).await;
+
+                return Err(err.into());
+            }
+        }
+
+        if let Some(dir) = &self.extract {
+            let count = download::extract(path, dir)?;
+            note!("extracted {} files into {}", count, dir.display());
+        }
+
+        self.finish(res, start, kept.as_deref().unwrap_or_default())
+    }
+
+    /// Prints each server-sent event as it arrives, until the stream ends or
+    /// has been idle for `--sse-timeout`.
+    async fn events(
+        &self,
+        req: &Request<Body>,
+        res: &mut Response<Body>,
+        limit: &mut BodyLimit,
+    ) -> Result<()> {
+        let mut parser = sse::EventParser::new();
+
+        loop {
+            let chunk = match self.sse_timeout {
+                Some(idle) => {
+                    match tokio::time::timeout(idle, limit.next_chunk(res, &self.cancel)).await {
+                        Ok(chunk) => chunk?,
+                        Err(_) => {
+                            note!("no events for {}s, disconnecting", idle.as_secs());
+                            break;
+                        }
+                    }
+                }
+                None => limit.next_chunk(res, &self.cancel).await?,
+            };
+
+            match chunk {
+                Some(chunk) => {
+                    for event in parser.feed(&chunk) {
+                        output::print_event(&event);
+                    }
+                }
+                None => break,
+            }
+        }
+
+        self.notifier
+            .notify(&format!("event stream from {} ended", req.uri()));
+
+        Ok(self.expectations.check(res.status(), res.headers(), b"")?)
+    }
+
+    /// Prints each NDJSON line as it arrives.
+    async fn lines(
+        &self,
+        res: &mut Response<Body>,
+        limit: &mut BodyLimit,
+        start: Instant,
+    ) -> Result<()> {
+        let mut parser = ndjson::LineParser::new();
+        let print = |line: String| -> Result<()> {
+            let line = if self.deterministic {
+                deterministic::normalize(&line)
+            } else {
+                line
+            };
+
+            if let Some(line) = ndjson::render(&line, self.filter.as_deref())? {
+                println!("{}\x1b[0m", line);
+            }
+
+            Ok(())
+        };
+
+        while let Some(chunk) = limit.next_chunk(res, &self.cancel).await? {
+            self.recorder.update(|timings| timings.bytes += chunk.len());
+
+            for line in parser.feed(&chunk) {
+                print(line)?;
+            }
+        }
+
+        if let Some(line) = parser.finish() {
+            print(line)?;
+        }
+
+        self.finish(res, start, b"")
+    }
+
+    /// The body as it is shown: filtered, decoded, as a table, a WebDAV
+    /// listing, GraphQL sections, or highlighted by its type.
+    fn render(
+        &self,
+        res: &Response<Body>,
+        body: &str,
+        content_type: Option<&mime::Mime>,
+    ) -> Result<String> {
+        let document = if self.filter.is_some() || self.decode_fields.is_some() {
+            let flag = if self.filter.is_some() {
+                "--filter"
+            } else {
+                "--decode-fields"
+            };
+            let mut document = output::parse_json_body(body, flag, content_type)?;
+
+            if let Some(paths) = &self.decode_fields {
+                decode::decode_fields(&mut document, paths)?;
+            }
+
+            if let Some(path) = &self.filter {
+                document = jsonpath::filter(&document, path)?;
+            }
+
+            Some(document)
+        } else {
+            None
+        };
+
+        // CSV reads as a table on a terminal, and JSON only with --table
+        use std::io::IsTerminal as _;
+
+        let rows = match (&document, content_type.and_then(table::delimiter)) {
+            (None, Some(delimiter)) if self.table || std::io::stdout().is_terminal() => {
+                Some(table::parse_delimited(body, delimiter))
+            }
+            _ if self.table => {
+                let parsed;
+                let document = match &document {
+                    Some(document) => document,
+                    None => {
+                        parsed = output::parse_json_body(body, "--table", content_type)?;
+                        &parsed
+                    }
+                };
+
+                Some(
+                    table::from_json(document)
+                        .ok_or_else(|| anyhow::anyhow!("--table needs a JSON array of objects"))?,
+                )
+            }
+            _ => None,
+        };
+
+        Ok(if let Some(rows) = rows {
+            table::render(&rows, pager::terminal_width())
+                .trim_end()
+                .to_owned()
+        } else if let Some(document) = document {
+            highlight(&output::pretty_json(&document), "json")
+        } else if self.dav && res.status() == hyper::StatusCode::MULTI_STATUS {
+            dav::listing(body)
+        } else if self.graphql {
+            output::render_graphql(body)
+                .unwrap_or_else(|| output::render_body(content_type, body, self.pretty_max_size))
+        } else {
+            output::render_body(content_type, body, self.pretty_max_size)
+        })
+    }
+
+    /// Prints the whole body, `raw` as it was sent, in whichever form was
+    /// asked for.
+    async fn show(
+        &self,
+        client: &HttpClient,
+        req: &Request<Body>,
+        body: &RequestBody,
+        mut res: Response<Body>,
+        raw: Vec<u8>,
+        start: Instant,
+    ) -> Result<()> {
+        // hyper only passes trailers on over HTTP/2
+        let trailers = client::trailers(&mut res, &self.cancel).await?;
+        let trailers = trailers.as_ref();
+
+        let buf = match encoding::decode(res.headers(), &raw) {
+            Some(decoded) if !self.no_decompress => decoded,
+            Some(_) => raw.clone(),
+            None => {
+                note!(
+                    "cannot decode {}, showing the body as sent\n",
+                    encoding::content_encoding(res.headers()).unwrap_or_default()
+                );
+                raw.clone()
+            }
+        };
+
+        let buf = match &self.paginate {
+            Some(paginate) if res.status().is_success() => {
+                if let Some(path) = &paginate.next_jsonpath {
+                    jsonpath::parse(path)?;
+                }
+
+                paginate::rest(
+                    paginate.clone(),
+                    client,
+                    req,
+                    &body.content,
+                    res.headers(),
+                    buf,
+                )
+                .await?
+            }
+            _ => buf,
+        };
+
+        if self.copy {
+            match clipboard::copy(&buf) {
+                Ok(()) => note!(
+                    "copied {} to the clipboard\n",
+                    progress::format_bytes(buf.len() as f64)
+                ),
+                Err(err) => eprintln!("warning: {}", err),
+            }
+        }
+
+        if !self.text {
+            self.recorder
+                .update(|timings| timings.total = Some(start.elapsed()));
+            let document = pipe::exchange(
+                req,
+                &body.content,
+                &res,
+                &buf,
+                trailers,
+                &self.recorder.timings(),
+            );
+            println!("{}", document.dump());
+
+            return Ok(self.expectations.check(res.status(), res.headers(), &buf)?);
+        }
+
+        if self.json_output {
+            let document = pipe::envelope(res.status().as_u16(), res.headers(), &buf);
+            println!("{}", document.dump());
+
+            return Ok(self.expectations.check(res.status(), res.headers(), &buf)?);
+        }
+
+        if let Some(name) = &self.formatter {
+            let formatted = plugin::format(name, res.status(), res.headers(), &buf)?;
+            pager::print(&formatted, self.pager)?;
+            self.print_trailers(trailers)?;
+
+            return self.finish(&res, start, &buf);
+        }
+
+        let analyze = || {
+            if self.analyze {
+                note!("{}", analyze::analyze(res.status(), res.headers(), &raw));
+            }
+        };
+
+        if self.hexdump {
+            print!("{}", hexdump::dump(&buf));
+            self.print_trailers(trailers)?;
+            analyze();
+
+            return self.finish(&res, start, &buf);
+        }
+
+        let (content_type_header, content_type) = content_type(res.headers());
+
+        if self.expects_json
+            && res.status().is_success()
+            && !buf.is_empty()
+            && !content_type.as_ref().is_some_and(output::is_json)
+        {
+            note!(
+                "warning: asked for JSON, but the response is {}",
+                content_type_header.unwrap_or("untyped")
+            );
+        }
+
+        let decoded = output::decoded_json(content_type.as_ref(), &buf, self.proto.as_deref());
+        let content_type = match decoded {
+            Some(_) => Some("application/json".parse()?),
+            None => content_type,
+        };
+
+        // a declared charset or byte order mark makes the body text, even UTF-16
+        if decoded.is_none()
+            && charset::declared(content_type_header, &buf).is_none()
+            && binary::is_binary(content_type.as_ref(), &buf)
+        {
+            use std::io::{IsTerminal as _, Write as _};
+
+            let mut stdout = std::io::stdout();
+
+            let is_image = content_type
+                .as_ref()
+                .is_some_and(|content_type| content_type.type_() == mime::IMAGE);
+            let preview = if self.render_images && is_image && stdout.is_terminal() {
+                image::preview(
+                    Protocol::detect(),
+                    &buf,
+                    pager::terminal_width(),
+                    pager::terminal_height().saturating_sub(2),
+                )
+            } else {
+                None
+            };
+
+            if let Some(preview) = preview {
+                stdout.write_all(preview.as_bytes())?;
+            } else if stdout.is_terminal() {
+                note!("{}\n", binary::NOTE);
+            } else {
+                stdout.write_all(&buf)?;
+            }
+
+            self.print_trailers(trailers)?;
+            analyze();
+
+            return self.finish(&res, start, &buf);
+        }
+
+        let (text, warning) = match decoded {
+            Some(json) => (json, None),
+            None => charset::decode(content_type_header, &buf),
+        };
+        if let Some(warning) = warning {
+            eprintln!("warning: {}", warning);
+        }
+
+        let text = if self.deterministic {
+            deterministic::normalize(&text)
+        } else {
+            text
+        };
+
+        // undeclared NDJSON is recognized once it is all in, and --filter
+        // applies to each line
+        if self.decode_fields.is_none()
+            && (content_type.as_ref().is_some_and(ndjson::is_ndjson) || ndjson::sniff(&text))
+        {
+            let mut rendered = String::new();
+
+            for line in text.lines() {
+                if let Some(line) = ndjson::render(line, self.filter.as_deref())? {
+                    rendered += &format!("{}\x1b[0m\n", line);
+                }
+            }
+
+            pager::print(&rendered, self.pager)?;
+            self.print_trailers(trailers)?;
+
+            return self.finish(&res, start, &buf);
+        }
+
+        let rendered = self.render(&res, &text, content_type.as_ref())?;
+        if !rendered.is_empty() {
+            pager::print(&format!("{}\x1b[0m\n", rendered), self.pager)?;
+        }
+
+        self.print_trailers(trailers)?;
+        analyze();
+
+        self.finish(&res, start, &buf)
+    }
+}
+
+/// The Content-Type header as text and as a MIME type. A header that is not
+/// UTF-8 or not a MIME type leaves the body untyped rather than unshown.
+fn content_type(headers: &HeaderMap) -> (Option<&str>, Option<mime::Mime>) {
+    let header = headers
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|header| std::str::from_utf8(header.as_ref()).ok());
+
+    (header, header.and_then(|header| header.parse().ok()))
+}
+
+/// Builds the request, prints it, sends it, and shows the response.
+pub async fn run(
+    mut exchange: Exchange,
+    client: HttpClient,
+    builder: crate::RurlRequestBuilder,
+) -> Result<()> {
+    let expect_set = builder.has_header(&hyper::header::EXPECT);
+    let (mut req, mut body) = builder.build()?;
+
+    if exchange.edit {
+        editor::edit_body(&mut body)?;
+
+        let headers = req.headers_mut();
+        headers.insert(hyper::header::CONTENT_LENGTH, body.content.len().into());
+
+        // a body typed from scratch has no Content-Type yet
+        if body.language == "json" && !headers.contains_key(hyper::header::CONTENT_TYPE) {
+            headers.insert(
+                hyper::header::CONTENT_TYPE,
+                mime::APPLICATION_JSON.to_string().parse()?,
+            );
+        }
+    }
+
+    // a benchmark would wait on every request, see `client::send`
+    if body.content.len() >= client::EXPECT_CONTINUE_MIN && exchange.bench.is_none() && !expect_set
+    {
+        req.headers_mut().insert(
+            hyper::header::EXPECT,
+            hyper::header::HeaderValue::from_static("100-continue"),
+        );
+    }
+
+    // keyed before the validators go in, so revalidating finds the same entry
+    let cache = exchange
+        .cache_dir
+        .as_deref()
+        .filter(|_| req.method() == Method::GET)
+        .map(|dir| ResponseCache::new(dir, &req));
+    let conditional = req.headers().contains_key(hyper::header::IF_NONE_MATCH)
+        || req.headers().contains_key(hyper::header::IF_MODIFIED_SINCE);
+    let cached = match &cache {
+        Some(cache) if !conditional => cache.lookup(),
+        _ => None,
+    };
+
+    if let Some(entry) = &cached {
+        for (name, value) in entry.validators() {
+            req.headers_mut().insert(name, value);
+        }
+    }
+
+    exchange.auth.apply(&mut req, &body.content)?;
+
+    if exchange.curl_only {
+        println!("{}", curl::command(&req, &body.content));
+        return Ok(());
+    }
+
+    if exchange.curl {
+        note!("{}\n", curl::command(&req, &body.content));
+    }
+
+    // print request
+
+    if exchange.text {
+        note!(
+            "{}",
+            highlight(
+                &output::request_head(
+                    &req,
+                    exchange.deterministic,
+                    exchange.print_full_url,
+                    exchange.header_layout
+                )?,
+                "http"
+            )
+        );
+
+        if !body.content.is_empty() {
+            note!("{}", exchange.format_body(&body));
+        }
+    }
+
+    if let Some(bench) = exchange.bench.take() {
+        if !exchange.expectations.is_empty() {
+            anyhow::bail!("--expect-* cannot be combined with --repeat");
+        }
+
+        if !exchange.text {
+            anyhow::bail!("--format json cannot be combined with --repeat");
+        }
+
+        let report = bench::run(bench, client, Arc::new(req), Arc::new(body.content)).await;
+        history::keep(exchange.history.as_deref(), None).await;
+
+        println!("{}", report.format(exchange.deterministic));
+        println!(
+            "{}",
+            exchange.recorder.timings().format_reuse(report.requests())
+        );
+
+        return Ok(());
+    }
+
+    // make request
+
+    // one bar for one request, as --repeat and multiple URIs are done elsewhere
+    progress::set_enabled(exchange.progress);
+
+    let start = Instant::now();
+    let sent = exchange.send(&client, &req, &body.content).await;
+
+    history::keep(
+        exchange.history.as_deref(),
+        sent.as_ref().ok().map(|res| res.status().as_u16()),
+    )
+    .await;
+
+    let mut res = match sent {
+        Ok(res) => res,
+        Err(err) if exchange.diagnose && err.is::<hyper::Error>() => {
+            note!("{}", diagnose::diagnose(req.uri()).await);
+            return Err(err);
+        }
+        Err(err) => return Err(err),
+    };
+
+    exchange
+        .recorder
+        .update(|timings| timings.ttfb = Some(start.elapsed()));
+    trace!("using {:?}", res.version());
+
+    if exchange.tls_info {
+        match exchange.recorder.timings().session {
+            Some(session) => note!("{}", tls::describe(&session, std::time::SystemTime::now())),
+            None => note!("no TLS session, the request went over plain http\n"),
+        }
+    }
+
+    let cached = cached.filter(|_| res.status() == hyper::StatusCode::NOT_MODIFIED);
+    let from_cache = cached.is_some();
+    if let Some(entry) = cached {
+        if exchange.text {
+            note!("\x1b[1m304 Not Modified (served from cache)\x1b[0m\n");
+        }
+
+        res = entry.into_response();
+    }
+
+    if exchange.if_match && res.status() == hyper::StatusCode::PRECONDITION_FAILED {
+        note!(
+            "conflict: {} no longer matches {}, someone else modified it; \
+             fetch it again and re-apply your change\n",
+            req.uri(),
+            req.headers()[hyper::header::IF_MATCH]
+                .to_str()
+                .unwrap_or("the sent ETag"),
+        );
+    }
+
+    // print response
+
+    if exchange.text {
+        note!(
+            "{}",
+            highlight(
+                &output::response_head(&res, exchange.deterministic, exchange.header_layout)?,
+                "http"
+            )
+        );
+    }
+
+    if let Some(language) = res.headers().get(hyper::header::CONTENT_LANGUAGE) {
+        note!(
+            "\x1b[1mContent-Language: {}\x1b[0m\n",
+            String::from_utf8_lossy(language.as_bytes())
+        );
+    }
+
+    if let Some(report) = encoding::negotiation(req.headers(), res.headers()) {
+        note!("{}\n", report);
+    }
+
+    if req.method() == Method::OPTIONS {
+        if let Some(summary) = cors::summary(res.headers(), exchange.preflight.as_ref()) {
+            note!("{}", summary);
+        }
+    }
+
+    // a HEAD response never has a body, so don't wait on one
+    if req.method() == Method::HEAD {
+        if !exchange.text {
+            exchange
+                .recorder
+                .update(|timings| timings.total = Some(start.elapsed()));
+            let document = pipe::exchange(
+                &req,
+                &body.content,
+                &res,
+                b"",
+                None,
+                &exchange.recorder.timings(),
+            );
+            println!("{}", document.dump());
+        }
+
+        return exchange.finish(&res, start, b"");
+    }
+
+    // get response body
+
+    let mut limit = BodyLimit::new(
+        exchange.max_body,
+        exchange.low_speed,
+        exchange.limit_rate,
+        res.headers(),
+    )?;
+
+    match exchange.reading(&res) {
+        Reading::Raw => {
+            // reading the body is what copies it out
+            while limit
+                .next_chunk(&mut res, &exchange.cancel)
+                .await?
+                .is_some()
+            {}
+
+            return Ok(());
+        }
+        Reading::Save => {
+            let path = exchange.output.clone().unwrap_or_default();
+            return exchange.save(&mut res, &mut limit, &path, start).await;
+        }
+        Reading::Events => return exchange.events(&req, &mut res, &mut limit).await,
+        Reading::Lines => return exchange.lines(&mut res, &mut limit, start).await,
+        Reading::Whole => {}
+    }
+
+    let mut buf = Vec::new();
+    while let Some(chunk) = limit.next_chunk(&mut res, &exchange.cancel).await? {
+        buf.extend_from_slice(&chunk);
+    }
+
+    exchange
+        .recorder
+        .update(|timings| timings.bytes = buf.len());
+
+    // a response served from the cache is already in it
+    if let (Some(cache), false) = (&cache, from_cache) {
+        if cache.store(res.status(), res.headers(), &buf)? {
+            trace!("kept the response in the cache");
+        }
+    }
+
+    exchange.show(&client, &req, &body, res, buf, start).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::{HeaderValue, CONTENT_ENCODING, CONTENT_TYPE};
+    use std::time::Duration;
+
+    fn exchange() -> Exchange {
+        Exchange {
+            recorder: Recorder::default(),
+            history: None,
+            text: true,
+            deterministic: false,
+            meta: false,
+            header_layout: HeaderLayout::Full,
+            print_full_url: false,
+            edit: false,
+            cache_dir: None,
+            curl: false,
+            curl_only: false,
+            hexdump: false,
+            bench: None,
+            progress: false,
+            poll: None,
+            redirects: None,
+            budget: Arc::new(RetryBudget::new(0)),
+            breaker: Arc::new(CircuitBreaker::new(0, Duration::from_secs(0))),
+            cancel: CancellationToken::new(),
+            auth: Auth::default(),
+            diagnose: false,
+            tls_info: false,
+            if_match: false,
+            preflight: None,
+            expectations: Expectations::default(),
+            max_body: None,
+            low_speed: None,
+            limit_rate: None,
+            raw_to_stdout: false,
+            output: None,
+            no_decompress: false,
+            checksum: None,
+            extract: None,
+            sse_timeout: None,
+            notifier: Notifier::default(),
+            json_output: false,
+            decode_fields: None,
+            paginate: None,
+            filter: None,
+            copy: false,
+            formatter: None,
+            pager: false,
+            analyze: false,
+            render_images: false,
+            table: false,
+            dav: false,
+            graphql: false,
+            pretty_max_size: None,
+            expects_json: false,
+            proto: None,
+        }
+    }
+
+    fn response(content_type: &'static str) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_the_body_the_way_it_was_asked_for() {
+        let ndjson = response("application/x-ndjson");
+
+        assert_eq!(exchange().reading(&response("text/plain")), Reading::Whole);
+        assert_eq!(exchange().reading(&ndjson), Reading::Lines);
+        assert_eq!(
+            exchange().reading(&response("text/event-stream; charset=utf-8")),
+            Reading::Events
+        );
+
+        let saved = Exchange {
+            output: Some("out.json".into()),
+            ..exchange()
+        };
+        assert_eq!(saved.reading(&response("text/event-stream")), Reading::Save);
+
+        let raw = Exchange {
+            raw_to_stdout: true,
+            output: Some("out.json".into()),
+            ..exchange()
+        };
+        assert_eq!(raw.reading(&ndjson), Reading::Raw);
+    }
+
+    #[test]
+    fn reads_ndjson_whole_when_it_needs_more_than_printing() {
+        let json_output = Exchange {
+            text: false,
+            json_output: true,
+            ..exchange()
+        };
+        assert_eq!(
+            json_output.reading(&response("application/x-ndjson")),
+            Reading::Whole
+        );
+
+        let hexdump = Exchange {
+            hexdump: true,
+            ..exchange()
+        };
+        assert_eq!(
+            hexdump.reading(&response("application/x-ndjson")),
+            Reading::Whole
+        );
+
+        let mut gzipped = response("application/x-ndjson");
+        gzipped
+            .headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        assert_eq!(exchange().reading(&gzipped), Reading::Whole);
+    }
+
+    #[test]
+    fn formats_request_bodies_by_what_they_hold() {
+        let body = |content: &[u8], language: &str| RequestBody {
+            content: content.to_vec(),
+            content_type: None,
+            language: language.into(),
+        };
+
+        let text = exchange().format_body(&body(b"hello", "text"));
+        assert!(text.contains("hello"));
+        assert!(text.ends_with('\n'));
+        assert!(!exchange()
+            .format_body(&body(b"hello\n", "text"))
+            .ends_with("\n\n"));
+
+        let binary = exchange().format_body(&body(&[0xff, 0xfe, 0x00], "text"));
+        assert!(binary.contains("of binary data, shown with --hexdump"));
+
+        let hexdump = Exchange {
+            hexdump: true,
+            ..exchange()
+        };
+        assert_eq!(
+            hexdump.format_body(&body(b"hi", "text")),
+            hexdump::dump(b"hi")
+        );
+        assert_eq!(
+            exchange().format_body(&body(&[0x81, 0xa1], "msgpack")),
+            hexdump::dump(&[0x81, 0xa1])
+        );
+    }
+
+    #[test]
+    fn formats_request_bodies_deterministically() {
+        let deterministic = Exchange {
+            deterministic: true,
+            ..exchange()
+        };
+        let body = RequestBody {
+            content: b"sent at 2020-01-02T03:04:05Z".to_vec(),
+            content_type: None,
+            language: "text".into(),
+        };
+
+        assert!(!deterministic.format_body(&body).contains("2020-01-02"));
+    }
+
+    #[test]
+    fn malformed_content_types_leave_the_body_untyped() {
+        let headers = |value: HeaderValue| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, value);
+            headers
+        };
+
+        let json = headers(HeaderValue::from_static("application/json; charset=utf-8"));
+        let (header, mime) = content_type(&json);
+        assert_eq!(header, Some("application/json; charset=utf-8"));
+        assert_eq!(mime.unwrap().essence_str(), "application/json");
+
+        let malformed = headers(HeaderValue::from_static("text/;;"));
+        assert_eq!(content_type(&malformed), (Some("text/;;"), None));
+
+        let latin1 = headers(HeaderValue::from_bytes(b"text/plain; name=caf\xe9").unwrap());
+        assert_eq!(content_type(&latin1), (None, None));
+
+        assert_eq!(content_type(&HeaderMap::new()), (None, None));
+    }
+}
@@ -0,0 +1,237 @@
+//! Assertions on the response for CI: `--expect-status`, `--expect-header`,
+//! and `--expect-body-json`. Every failed assertion is reported, not just
+//! the first.
+
+use crate::poll::Condition;
+use hyper::{header::HeaderName, HeaderMap, StatusCode};
+use regex::Regex;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExpectError {
+    #[error("invalid --expect-status {0}, expected e.g. 200, 2xx, or 200,204")]
+    StatusParseError(String),
+    #[error("invalid --expect-header {0}, expected Name:value, Name~regex, or Name")]
+    HeaderParseError(String),
+    #[error("{} failed:\n  {}", plural(.0.len()), .0.join("\n  "))]
+    Failed(Vec<String>),
+}
+
+fn plural(count: usize) -> String {
+    match count {
+        1 => "1 expectation".into(),
+        count => format!("{} expectations", count),
+    }
+}
+
+/// `200`, `2xx`, or a comma-separated list of either.
+#[derive(Clone, Debug)]
+pub struct ExpectStatus {
+    source: String,
+    patterns: Vec<String>,
+}
+
+impl std::str::FromStr for ExpectStatus {
+    type Err = ExpectError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let patterns = s
+            .split(',')
+            .map(|pattern| pattern.trim().to_ascii_lowercase())
+            .collect::<Vec<_>>();
+
+        let valid = |pattern: &String| {
+            pattern.len() == 3
+                && pattern.starts_with(|c: char| ('1'..='5').contains(&c))
+                && pattern.chars().all(|c| c.is_ascii_digit() || c == 'x')
+        };
+
+        if !patterns.iter().all(valid) {
+            return Err(ExpectError::StatusParseError(s.into()));
+        }
+
+        Ok(Self {
+            source: s.into(),
+            patterns,
+        })
+    }
+}
+
+impl ExpectStatus {
+    fn check(&self, status: StatusCode) -> Result<(), String> {
+        let code = status.as_str();
+        let matches = |pattern: &String| {
+            pattern
+                .chars()
+                .zip(code.chars())
+                .all(|(expected, actual)| expected == 'x' || expected == actual)
+        };
+
+        if self.patterns.iter().any(matches) {
+            Ok(())
+        } else {
+            Err(format!("status is {}, expected {}", status, self.source))
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum HeaderCheck {
+    Present,
+    Equals(String),
+    Matches(Regex),
+}
+
+/// `Name:value` for an exact value, `Name~regex` for a match anywhere in
+/// the value, or a bare `Name` for any value at all.
+#[derive(Clone, Debug)]
+pub struct ExpectHeader {
+    name: HeaderName,
+    check: HeaderCheck,
+}
+
+impl std::str::FromStr for ExpectHeader {
+    type Err = ExpectError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ExpectError::HeaderParseError(s.into());
+
+        let (name, check) = match s.find(&[':', '~'][..]) {
+            Some(i) if s[i..].starts_with(':') => {
+                (&s[..i], HeaderCheck::Equals(s[i + 1..].trim().to_owned()))
+            }
+            Some(i) => (
+                &s[..i],
+                HeaderCheck::Matches(Regex::new(&s[i + 1..]).map_err(|_| err())?),
+            ),
+            None => (s, HeaderCheck::Present),
+        };
+
+        Ok(Self {
+            name: name.trim().parse().map_err(|_| err())?,
+            check,
+        })
+    }
+}
+
+impl ExpectHeader {
+    fn check(&self, headers: &HeaderMap) -> Result<(), String> {
+        let values: Vec<String> = headers
+            .get_all(&self.name)
+            .iter()
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+            .collect();
+
+        let (holds, expected) = match &self.check {
+            HeaderCheck::Present => (!values.is_empty(), "to be present".to_owned()),
+            HeaderCheck::Equals(expected) => (
+                values.iter().any(|value| value == expected),
+                format!("to be {}", expected),
+            ),
+            HeaderCheck::Matches(regex) => (
+                values.iter().any(|value| regex.is_match(value)),
+                format!("to match {}", regex),
+            ),
+        };
+
+        match (holds, values.is_empty()) {
+            (true, _) => Ok(()),
+            (false, true) => Err(format!(
+                "{} is missing, expected it {}",
+                self.name, expected
+            )),
+            (false, false) => Err(format!(
+                "{} is {}, expected it {}",
+                self.name,
+                values.join(", "),
+                expected
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Expectations {
+    pub status: Vec<ExpectStatus>,
+    pub headers: Vec<ExpectHeader>,
+    pub body: Vec<Condition>,
+}
+
+impl Expectations {
+    pub fn is_empty(&self) -> bool {
+        self.status.is_empty() && self.headers.is_empty() && self.body.is_empty()
+    }
+
+    /// Checks every expectation against the response, failing with all
+    /// that did not hold.
+    pub fn check(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(), ExpectError> {
+        let failures: Vec<String> = self
+            .status
+            .iter()
+            .map(|expect| expect.check(status))
+            .chain(self.headers.iter().map(|expect| expect.check(headers)))
+            .chain(self.body.iter().map(|condition| {
+                condition
+                    .check(body)
+                    .map_err(|found| format!("{}: {}", condition, found))
+            }))
+            .filter_map(Result::err)
+            .collect();
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(ExpectError::Failed(failures))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    #[test]
+    fn reports_every_failure() {
+        let expectations = Expectations {
+            status: vec!["2xx,304".parse().unwrap()],
+            headers: vec![
+                "content-type~^application/json".parse().unwrap(),
+                "ETag".parse().unwrap(),
+                "Cache-Control: no-store".parse().unwrap(),
+            ],
+            body: vec!["$.id == 7".parse().unwrap()],
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            hyper::header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json; charset=utf-8"),
+        );
+        headers.insert(
+            hyper::header::CACHE_CONTROL,
+            HeaderValue::from_static("no-cache"),
+        );
+
+        assert_eq!(
+            expectations
+                .check(StatusCode::NOT_FOUND, &headers, b"{\"id\": 8}")
+                .unwrap_err()
+                .to_string(),
+            "4 expectations failed:\n  \
+             status is 404 Not Found, expected 2xx,304\n  \
+             etag is missing, expected it to be present\n  \
+             cache-control is no-cache, expected it to be no-store\n  \
+             $.id == 7: $.id is 8"
+        );
+        assert!(expectations
+            .check(StatusCode::NOT_MODIFIED, &headers, b"")
+            .is_err());
+        assert!("20".parse::<ExpectStatus>().is_err());
+    }
+}
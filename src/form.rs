@@ -0,0 +1,619 @@
+use crate::{
+    body::Mode,
+    cancel::{CancellationToken, CancelledError},
+    client::{self, HttpClient, RurlRequestBuilder, Upload},
+    redirect,
+    request_item::RequestItem,
+};
+use anyhow::Result;
+use hyper::{header, Method, Uri};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FormError {
+    #[error("no form matching {0} on {1}")]
+    NotFound(String, Uri),
+    #[error("invalid form action {0}")]
+    InvalidAction(String),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Form {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub class: Option<String>,
+    pub action: Option<String>,
+    pub method: Option<String>,
+    /// Name/value pairs in document order, as a browser would submit them.
+    pub fields: Vec<(String, String)>,
+}
+
+struct Tag {
+    name: String,
+    closing: bool,
+    attributes: Vec<(String, String)>,
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn has_attribute(&self, name: &str) -> bool {
+        self.attribute(name).is_some()
+    }
+}
+
+/// Decodes the character references that show up in attribute values.
+fn decode_entities(s: &str) -> String {
+    let mut output = String::new();
+    let mut rest = s;
+
+    while let Some(i) = rest.find('&') {
+        output += &rest[..i];
+        rest = &rest[i..];
+
+        let end = match rest.find(';') {
+            Some(end) if end <= 10 => end,
+            _ => {
+                output.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                u32::from_str_radix(&entity[2..], 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+            }
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(std::char::from_u32),
+            _ => None,
+        };
+
+        match decoded {
+            Some(c) => {
+                output.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    output + rest
+}
+
+/// Parses the tag starting at `html[start] == '<'`, returning it and the
+/// index just past its `>`.
+fn parse_tag(html: &str, start: usize) -> Option<(Tag, usize)> {
+    let bytes = html.as_bytes();
+    let mut i = start + 1;
+
+    let closing = bytes.get(i) == Some(&b'/');
+    if closing {
+        i += 1;
+    }
+
+    let name_start = i;
+    while i < bytes.len() && bytes[i].is_ascii_alphanumeric() {
+        i += 1;
+    }
+
+    if i == name_start {
+        return None;
+    }
+
+    let name = html[name_start..i].to_ascii_lowercase();
+    let mut attributes = Vec::new();
+
+    loop {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+
+        match bytes.get(i) {
+            None => return None,
+            Some(b'>') => break,
+            _ => {}
+        }
+
+        let key_start = i;
+        while i < bytes.len()
+            && !bytes[i].is_ascii_whitespace()
+            && !matches!(bytes[i], b'=' | b'>' | b'/')
+        {
+            i += 1;
+        }
+        let key = html[key_start..i].to_ascii_lowercase();
+
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        if bytes.get(i) != Some(&b'=') {
+            attributes.push((key, String::new()));
+            continue;
+        }
+        i += 1;
+
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        let value = match bytes.get(i) {
+            Some(&quote) if quote == b'"' || quote == b'\'' => {
+                let end = i + 1 + html[i + 1..].find(quote as char)?;
+                let value = &html[i + 1..end];
+                i = end + 1;
+                value
+            }
+            _ => {
+                let value_start = i;
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                    i += 1;
+                }
+                &html[value_start..i]
+            }
+        };
+
+        attributes.push((key, decode_entities(value)));
+    }
+
+    Some((
+        Tag {
+            name,
+            closing,
+            attributes,
+        },
+        i + 1,
+    ))
+}
+
+/// The index of the closing `</name` tag at or after `from`.
+fn find_closing(html: &str, from: usize, name: &str) -> usize {
+    html[from..]
+        .to_ascii_lowercase()
+        .find(&format!("</{}", name))
+        .map_or(html.len(), |i| from + i)
+}
+
+struct Select {
+    name: String,
+    first: Option<String>,
+    selected: Vec<String>,
+    multiple: bool,
+}
+
+/// Every form in a page, with the fields a browser would submit by default:
+/// hidden and text inputs, checked boxes, textareas, selected options, and the
+/// first named submit button.
+pub fn parse(html: &str) -> Vec<Form> {
+    let mut forms: Vec<Form> = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut open_select: Option<Select> = None;
+    let mut submitter = false;
+    let mut i = 0;
+
+    while let Some(offset) = html[i..].find('<') {
+        let start = i + offset;
+
+        if html[start..].starts_with("<!--") {
+            i = html[start..]
+                .find("-->")
+                .map_or(html.len(), |end| start + end + 3);
+            continue;
+        }
+
+        let (tag, end) = match parse_tag(html, start) {
+            Some(parsed) => parsed,
+            None => {
+                i = start + 1;
+                continue;
+            }
+        };
+        i = end;
+
+        if !tag.closing && (tag.name == "script" || tag.name == "style") {
+            i = find_closing(html, i, &tag.name);
+            continue;
+        }
+
+        if tag.name == "form" {
+            if tag.closing {
+                current = None;
+            } else {
+                forms.push(Form {
+                    id: tag.attribute("id").map(Into::into),
+                    name: tag.attribute("name").map(Into::into),
+                    class: tag.attribute("class").map(Into::into),
+                    action: tag.attribute("action").map(Into::into),
+                    method: tag.attribute("method").map(Into::into),
+                    fields: Vec::new(),
+                });
+                current = Some(forms.len() - 1);
+                submitter = false;
+            }
+            continue;
+        }
+
+        let form = match current {
+            Some(index) => &mut forms[index],
+            None => continue,
+        };
+
+        if tag.closing {
+            if tag.name == "select" {
+                if let Some(select) = open_select.take() {
+                    let values = if !select.selected.is_empty() {
+                        select.selected
+                    } else if select.multiple {
+                        Vec::new()
+                    } else {
+                        select.first.into_iter().collect()
+                    };
+
+                    for value in values {
+                        form.fields.push((select.name.clone(), value));
+                    }
+                }
+            }
+            continue;
+        }
+
+        let name = match tag.attribute("name") {
+            Some(name) if !name.is_empty() => name.to_owned(),
+            _ if tag.name == "option" => String::new(),
+            _ => continue,
+        };
+
+        if tag.has_attribute("disabled") && tag.name != "option" {
+            continue;
+        }
+
+        match tag.name.as_str() {
+            "input" => {
+                let kind = tag.attribute("type").unwrap_or("text").to_ascii_lowercase();
+                let value = tag.attribute("value");
+
+                match kind.as_str() {
+                    "checkbox" | "radio" if tag.has_attribute("checked") => {
+                        form.fields.push((name, value.unwrap_or("on").into()))
+                    }
+                    "checkbox" | "radio" | "file" | "reset" | "button" | "image" => {}
+                    "submit" => {
+                        if !submitter {
+                            submitter = true;
+                            form.fields.push((name, value.unwrap_or_default().into()));
+                        }
+                    }
+                    _ => form.fields.push((name, value.unwrap_or_default().into())),
+                }
+            }
+            "button" => {
+                let kind = tag
+                    .attribute("type")
+                    .unwrap_or("submit")
+                    .to_ascii_lowercase();
+
+                if kind == "submit" && !submitter {
+                    submitter = true;
+                    form.fields
+                        .push((name, tag.attribute("value").unwrap_or_default().into()));
+                }
+            }
+            "textarea" => {
+                let close = find_closing(html, i, "textarea");
+                let text = decode_entities(&html[i..close]);
+                // a newline right after <textarea> is not part of the value
+                let text = text
+                    .strip_prefix("\r\n")
+                    .or_else(|| text.strip_prefix('\n'))
+                    .unwrap_or(&text)
+                    .to_owned();
+
+                form.fields.push((name, text));
+                i = close;
+            }
+            "select" => {
+                open_select = Some(Select {
+                    name,
+                    first: None,
+                    selected: Vec::new(),
+                    multiple: tag.has_attribute("multiple"),
+                })
+            }
+            "option" => {
+                if let Some(select) = open_select.as_mut() {
+                    let value = match tag.attribute("value") {
+                        Some(value) => value.to_owned(),
+                        None => {
+                            let end = html[i..].find('<').map_or(html.len(), |end| i + end);
+                            decode_entities(html[i..end].trim())
+                        }
+                    };
+
+                    if tag.has_attribute("disabled") {
+                        continue;
+                    }
+
+                    if select.first.is_none() {
+                        select.first = Some(value.clone());
+                    }
+
+                    if tag.has_attribute("selected") {
+                        if !select.multiple {
+                            select.selected.clear();
+                        }
+                        select.selected.push(value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    forms
+}
+
+/// Picks a form by `#id`, `.class`, zero-based index, or `name` attribute.
+pub fn select(forms: Vec<Form>, selector: &str) -> Option<Form> {
+    if let Some(id) = selector.strip_prefix('#') {
+        return forms
+            .into_iter()
+            .find(|form| form.id.as_deref() == Some(id));
+    }
+
+    if let Some(class) = selector.strip_prefix('.') {
+        return forms.into_iter().find(|form| {
+            form.class
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .any(|name| name == class)
+        });
+    }
+
+    if let Ok(index) = selector.parse::<usize>() {
+        return forms.into_iter().nth(index);
+    }
+
+    forms
+        .into_iter()
+        .find(|form| form.name.as_deref() == Some(selector))
+}
+
+/// Sets each user-supplied `key=value` on the form, replacing a field of the
+/// same name or adding it.
+fn merge(fields: &mut Vec<(String, String)>, key: &str, value: String) {
+    match fields.iter().position(|(name, _)| name == key) {
+        Some(i) => {
+            fields[i].1 = value;
+
+            let mut seen = false;
+            fields.retain(|(name, _)| {
+                let duplicate = name == key && seen;
+                seen |= name == key;
+                !duplicate
+            });
+        }
+        None => fields.push((key.to_owned(), value)),
+    }
+}
+
+/// `name=value` pairs from the page's `Set-Cookie` headers.
+fn cookies(headers: &header::HeaderMap) -> Option<String> {
+    let cookies: Vec<&str> = headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| value.split(';').next())
+        .map(str::trim)
+        .collect();
+
+    if cookies.is_empty() {
+        None
+    } else {
+        Some(cookies.join("; "))
+    }
+}
+
+/// GETs the page at `uri`, finds the form matching `selector`, and returns
+/// the request that submits it with the user's data items merged in. Header
+/// items are sent with both requests, and cookies set by the page (often the
+/// session its CSRF token belongs to) are carried over to the submission.
+pub async fn fill(
+    client: &HttpClient,
+    uri: &Uri,
+    selector: &str,
+    request_items: Vec<RequestItem>,
+    cancel: &CancellationToken,
+) -> Result<RurlRequestBuilder> {
+    let (headers, data): (Vec<RequestItem>, Vec<RequestItem>) =
+        request_items.into_iter().partition(|request_item| {
+            matches!(
+                request_item,
+                RequestItem::Header { .. }
+                    | RequestItem::EmptyHeader { .. }
+                    | RequestItem::UnsetHeader { .. }
+            )
+        });
+
+    let (req, _) = RurlRequestBuilder::new(Method::GET, uri.clone())
+        .request_items(headers.clone())
+        .build()?;
+
+    let mut res = tokio::select! {
+        res = client.request(req.map(Upload::from)) => res?,
+        _ = cancel.cancelled() => {
+            return Err(CancelledError(format!("GET {}", uri)).into());
+        }
+    };
+
+    let mut buf = Vec::new();
+    while let Some(chunk) = client::next_chunk(&mut res, cancel).await? {
+        buf.extend_from_slice(&chunk);
+    }
+
+    let form = select(parse(&String::from_utf8_lossy(&buf)), selector)
+        .ok_or_else(|| FormError::NotFound(selector.into(), uri.clone()))?;
+
+    let mut fields = form.fields;
+
+    for request_item in data {
+        match request_item {
+            RequestItem::Data { key, value } => merge(&mut fields, &key, value),
+            RequestItem::JsonData { key, value } => {
+                let value = match value.as_str() {
+                    Some(value) => value.to_owned(),
+                    None => value.dump(),
+                };
+                merge(&mut fields, &key, value)
+            }
+            _ => {}
+        }
+    }
+
+    // an empty or missing action submits back to the page itself
+    let action = match form.action.as_deref().map(str::trim) {
+        Some(action) if !action.is_empty() => {
+            redirect::resolve(uri, action).ok_or_else(|| FormError::InvalidAction(action.into()))?
+        }
+        _ => uri.clone(),
+    };
+
+    let get = form
+        .method
+        .as_deref()
+        .is_none_or(|method| method.eq_ignore_ascii_case("get"));
+
+    let mut builder = if get {
+        // GET forms replace the action's query string with their fields
+        let query = fields
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    "{}={}",
+                    urlencoding::encode(key),
+                    urlencoding::encode(value)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        let action = format!(
+            "{}://{}{}?{}",
+            action.scheme_str().unwrap_or("http"),
+            action
+                .authority()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+            action.path(),
+            query
+        );
+
+        RurlRequestBuilder::new(Method::GET, action.parse()?)
+    } else {
+        RurlRequestBuilder::new(Method::POST, action)
+            .mode(Some(Mode::Form))
+            .request_items(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| RequestItem::Data { key, value })
+                    .collect(),
+            )
+    };
+
+    builder = builder.header(header::REFERER, uri.to_string().parse()?);
+
+    if let Some(cookies) = cookies(res.headers()) {
+        builder = builder.header(header::COOKIE, cookies.parse()?);
+    }
+
+    Ok(builder.request_items(headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"
+        <form id="search" action="/search"><input name="q"></form>
+        <!-- <form id="old"></form> -->
+        <form class="login wide" name="login" method="POST" action="/session">
+          <input type="hidden" name="csrf" value="a&amp;b">
+          <input name="user" value="ezra">
+          <input type="checkbox" name="remember" checked>
+          <input type="checkbox" name="newsletter" value="yes">
+          <input name="disabled" value="x" disabled>
+          <textarea name="bio">
+first &lt;line&gt;</textarea>
+          <select name="plan"><option>free</option><option value="pro" selected>Pro</option></select>
+          <select name="tags" multiple><option>a</option><option>b</option></select>
+          <button name="go" value="1">Sign in</button>
+          <input type="submit" name="other" value="2">
+        </form>
+    "#;
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn submits_what_a_browser_would() {
+        let forms = parse(PAGE);
+        assert_eq!(forms.len(), 2);
+
+        let login = &forms[1];
+        assert_eq!(login.method.as_deref(), Some("POST"));
+        assert_eq!(
+            login.fields,
+            fields(&[
+                ("csrf", "a&b"),
+                ("user", "ezra"),
+                ("remember", "on"),
+                ("bio", "first <line>"),
+                ("plan", "pro"),
+                ("go", "1"),
+            ])
+        );
+    }
+
+    #[test]
+    fn selects_forms() {
+        let action = |selector| select(parse(PAGE), selector).and_then(|form| form.action);
+
+        assert_eq!(action("#search").as_deref(), Some("/search"));
+        assert_eq!(action(".wide").as_deref(), Some("/session"));
+        assert_eq!(action("1").as_deref(), Some("/session"));
+        assert_eq!(action("login").as_deref(), Some("/session"));
+        assert_eq!(action("#old"), None);
+    }
+
+    #[test]
+    fn merged_items_replace_every_field_of_the_same_name() {
+        let mut form = fields(&[("tag", "a"), ("user", "ezra"), ("tag", "b")]);
+        merge(&mut form, "tag", "c".into());
+        merge(&mut form, "plan", "pro".into());
+
+        assert_eq!(
+            form,
+            fields(&[("tag", "c"), ("user", "ezra"), ("plan", "pro")])
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use rurl::{config::Config, request_item};
+
+const USAGE: &str = "usage: rurl fragment add NAME ITEM... | show NAME | remove NAME | list";
+
+pub async fn run(args: &[String]) -> Result<()> {
+    let _lock = Config::lock().await?;
+    let mut config = Config::load()?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["add", name, items @ ..] if !items.is_empty() => {
+            // items are parsed when used, so `@file` and `@prompt` stay lazy
+            if let Some(item) = items
+                .iter()
+                .find(|item| !item.starts_with('@') && request_item::tokenize(item).is_none())
+            {
+                anyhow::bail!("could not parse request item {}", item);
+            }
+
+            config.set_fragment(name, items);
+            config.save()?;
+        }
+        ["show", name] => match config.fragment(name) {
+            Some(items) => {
+                for item in items {
+                    println!("{}", item);
+                }
+            }
+            None => anyhow::bail!("unknown fragment {}", name),
+        },
+        ["remove", name] => {
+            if !config.unset_fragment(name) {
+                anyhow::bail!("unknown fragment {}", name);
+            }
+
+            config.save()?;
+        }
+        ["list"] => {
+            for (name, items) in config.fragments() {
+                println!("{}: {}", name, items.join(" "));
+            }
+        }
+        _ => anyhow::bail!(USAGE),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1153 @@
+//! Example request bodies from a protobuf message, an Avro schema, or a JSON
+//! Schema. Protobuf messages are written in their proto3 JSON mapping and
+//! Avro values in Avro's JSON encoding, so every format produces a JSON body.
+
+use anyhow::Result;
+use json::JsonValue;
+use std::{collections::HashMap, path::Path, time::SystemTime};
+use thiserror::Error;
+
+/// Recursive schemas stop nesting past this depth.
+const MAX_DEPTH: usize = 5;
+
+#[derive(Debug, Error)]
+pub enum GenerateError {
+    #[error("cannot tell the schema format of {0}, expected .proto, .avsc, or .json")]
+    UnknownFormat(String),
+    #[error("could not parse schema {0}: {1}")]
+    ParseError(String, String),
+    #[error("{0} defines several types, pick one with {0}:NAME")]
+    MissingType(String),
+    #[error("no type {1} in {0}")]
+    UnknownType(String, String),
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Null,
+    Bool,
+    Int {
+        min: i64,
+        max: i64,
+    },
+    /// 64-bit protobuf integers, which proto3 JSON writes as strings.
+    IntString,
+    Float {
+        min: f64,
+        max: f64,
+    },
+    String {
+        format: Option<String>,
+        min: usize,
+        max: usize,
+    },
+    Enum(Vec<JsonValue>),
+    Const(JsonValue),
+    Array {
+        items: Box<Type>,
+        min: usize,
+        max: usize,
+    },
+    Map(Box<Type>),
+    Object(Vec<Field>),
+    /// Any one of the types.
+    OneOf(Vec<Type>),
+    /// An Avro union, written as `{"branch": value}` unless null.
+    Union(Vec<(String, Type)>),
+    Named(String),
+    Any,
+}
+
+#[derive(Clone, Debug)]
+struct Field {
+    name: String,
+    ty: Type,
+    required: bool,
+    /// Fields sharing a protobuf oneof; only one of them is generated.
+    oneof: Option<String>,
+}
+
+fn string() -> Type {
+    Type::String {
+        format: None,
+        min: 0,
+        max: usize::MAX,
+    }
+}
+
+fn int(min: i64, max: i64) -> Type {
+    Type::Int { min, max }
+}
+
+fn float() -> Type {
+    Type::Float {
+        min: f64::MIN,
+        max: f64::MAX,
+    }
+}
+
+fn parse_error(path: &str, message: impl std::fmt::Display) -> GenerateError {
+    GenerateError::ParseError(path.into(), message.to_string())
+}
+
+/// xorshift64*, seeded from the clock; plenty for example data.
+struct Rng(u64);
+
+impl Rng {
+    fn seeded() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+
+        Self(nanos | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            0
+        } else {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    /// Inclusive on both ends.
+    fn range(&mut self, min: i64, max: i64) -> i64 {
+        let span = (max as i128 - min as i128 + 1) as u128;
+        (min as i128 + (u128::from(self.next()) % span) as i128) as i64
+    }
+
+    fn chance(&mut self) -> bool {
+        self.next() & 1 == 0
+    }
+}
+
+// protobuf
+
+fn tokenize_proto(source: &str) -> Vec<String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                i += 1;
+            }
+            i += 2;
+        } else if c == '"' || c == '\'' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i += 1;
+            tokens.push(chars[start..i.min(chars.len())].iter().collect());
+        } else if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '.' | '-'))
+            {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            tokens.push(c.to_string());
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+/// `user_id` → `userId`, the proto3 JSON field name.
+pub(crate) fn lower_camel_case(name: &str) -> String {
+    let mut output = String::new();
+    let mut upper = false;
+
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            output.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            output.push(c);
+        }
+    }
+
+    output
+}
+
+fn proto_scalar(name: &str) -> Option<Type> {
+    Some(match name {
+        "double" | "float" => float(),
+        "int32" | "sint32" | "sfixed32" => int(i32::MIN.into(), i32::MAX.into()),
+        "uint32" | "fixed32" => int(0, u32::MAX.into()),
+        "int64" | "sint64" | "sfixed64" | "uint64" | "fixed64" => Type::IntString,
+        "bool" => Type::Bool,
+        "string" => string(),
+        "bytes" => Type::String {
+            format: Some("byte".into()),
+            min: 0,
+            max: usize::MAX,
+        },
+        "google.protobuf.Timestamp" => Type::String {
+            format: Some("date-time".into()),
+            min: 0,
+            max: usize::MAX,
+        },
+        "google.protobuf.Duration" => Type::String {
+            format: Some("duration".into()),
+            min: 0,
+            max: usize::MAX,
+        },
+        "google.protobuf.Empty" => Type::Object(Vec::new()),
+        "google.protobuf.Struct" | "google.protobuf.Value" | "google.protobuf.Any" => Type::Any,
+        _ => {
+            // wrappers like google.protobuf.Int32Value are written as the bare value
+            let wrapped = name
+                .strip_prefix("google.protobuf.")?
+                .strip_suffix("Value")?;
+            return proto_scalar(&wrapped.to_ascii_lowercase());
+        }
+    })
+}
+
+struct ProtoParser<'a> {
+    path: &'a str,
+    tokens: Vec<String>,
+    i: usize,
+    package: String,
+    types: HashMap<String, Type>,
+    /// Field type names, resolved once every type is known.
+    unresolved: Vec<(String, String)>,
+}
+
+impl<'a> ProtoParser<'a> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.i).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<String, GenerateError> {
+        let token = self
+            .tokens
+            .get(self.i)
+            .cloned()
+            .ok_or_else(|| parse_error(self.path, "unexpected end of file"))?;
+        self.i += 1;
+
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), GenerateError> {
+        let token = self.next()?;
+
+        if token == expected {
+            Ok(())
+        } else {
+            Err(parse_error(
+                self.path,
+                format!("expected {} but found {}", expected, token),
+            ))
+        }
+    }
+
+    /// Skips past the next `;`, or past a `{ ... }` block if one comes first.
+    fn skip_statement(&mut self) -> Result<(), GenerateError> {
+        let mut depth = 0;
+
+        loop {
+            match self.next()?.as_str() {
+                ";" if depth == 0 => return Ok(()),
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn qualify(&self, scope: &str, name: &str) -> String {
+        match (scope.is_empty(), self.package.is_empty()) {
+            (false, _) => format!("{}.{}", scope, name),
+            (true, false) => format!("{}.{}", self.package, name),
+            (true, true) => name.into(),
+        }
+    }
+
+    fn file(&mut self) -> Result<(), GenerateError> {
+        while let Some(token) = self.peek() {
+            match token {
+                "package" => {
+                    self.i += 1;
+                    self.package = self.next()?;
+                    self.expect(";")?;
+                }
+                "message" => self.message("")?,
+                "enum" => self.enumeration("")?,
+                _ => self.skip_statement()?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn enumeration(&mut self, scope: &str) -> Result<(), GenerateError> {
+        self.expect("enum")?;
+        let name = self.next()?;
+        let name = self.qualify(scope, &name);
+        self.expect("{")?;
+
+        let mut values = Vec::new();
+
+        while self.peek() != Some("}") {
+            let token = self.next()?;
+
+            if token == "option" || token == "reserved" {
+                self.skip_statement()?;
+                continue;
+            }
+
+            values.push(JsonValue::from(token));
+            self.skip_statement()?;
+        }
+        self.expect("}")?;
+
+        self.types.insert(name, Type::Enum(values));
+
+        Ok(())
+    }
+
+    fn field_type(&mut self, scope: &str, type_name: &str) -> Type {
+        match proto_scalar(type_name) {
+            Some(ty) => ty,
+            None => {
+                self.unresolved.push((scope.into(), type_name.into()));
+                Type::Named(type_name.into())
+            }
+        }
+    }
+
+    fn field(&mut self, scope: &str, oneof: Option<&str>) -> Result<Field, GenerateError> {
+        let mut label = self.next()?;
+
+        let ty = if label == "map" {
+            self.expect("<")?;
+            self.next()?;
+            self.expect(",")?;
+            let value = self.next()?;
+            self.expect(">")?;
+            Type::Map(Box::new(self.field_type(scope, &value)))
+        } else {
+            if label == "optional" || label == "required" || label == "repeated" {
+                let type_name = self.next()?;
+                let ty = self.field_type(scope, &type_name);
+
+                if label == "repeated" {
+                    label = String::new();
+                    Type::Array {
+                        items: Box::new(ty),
+                        min: 0,
+                        max: usize::MAX,
+                    }
+                } else {
+                    ty
+                }
+            } else {
+                self.field_type(scope, &label.clone())
+            }
+        };
+
+        let name = self.next()?;
+        self.skip_statement()?;
+
+        Ok(Field {
+            name: lower_camel_case(&name),
+            ty,
+            required: label == "required",
+            oneof: oneof.map(Into::into),
+        })
+    }
+
+    fn message(&mut self, scope: &str) -> Result<(), GenerateError> {
+        self.expect("message")?;
+        let name = self.next()?;
+        let name = self.qualify(scope, &name);
+        self.expect("{")?;
+
+        let mut fields = Vec::new();
+
+        while self.peek() != Some("}") {
+            match self.peek() {
+                Some("message") => self.message(&name)?,
+                Some("enum") => self.enumeration(&name)?,
+                Some("option") | Some("reserved") | Some("extensions") | Some("extend") => {
+                    self.skip_statement()?
+                }
+                Some(";") => self.i += 1,
+                Some("oneof") => {
+                    self.i += 1;
+                    let oneof = self.next()?;
+                    self.expect("{")?;
+
+                    while self.peek() != Some("}") {
+                        if self.peek() == Some("option") {
+                            self.skip_statement()?;
+                        } else {
+                            fields.push(self.field(&name, Some(&oneof))?);
+                        }
+                    }
+                    self.expect("}")?;
+                }
+                _ => fields.push(self.field(&name, None)?),
+            }
+        }
+        self.expect("}")?;
+
+        self.types.insert(name, Type::Object(fields));
+
+        Ok(())
+    }
+
+    /// Looks a type name up from the innermost scope outwards, like protoc.
+    fn resolve(&self, scope: &str, name: &str) -> Option<String> {
+        if let Some(absolute) = name.strip_prefix('.') {
+            return Some(absolute.to_owned()).filter(|name| self.types.contains_key(name));
+        }
+
+        let mut scope = scope;
+
+        loop {
+            let candidate = if scope.is_empty() {
+                name.to_owned()
+            } else {
+                format!("{}.{}", scope, name)
+            };
+
+            if self.types.contains_key(&candidate) {
+                return Some(candidate);
+            }
+
+            if scope.is_empty() {
+                return None;
+            }
+
+            scope = scope.rfind('.').map_or("", |i| &scope[..i]);
+        }
+    }
+}
+
+/// Replaces the relative type names of protobuf fields with qualified ones.
+fn qualify_names(ty: &mut Type, names: &HashMap<String, String>) {
+    match ty {
+        Type::Named(name) => {
+            if let Some(qualified) = names.get(name.as_str()) {
+                *name = qualified.clone();
+            }
+        }
+        Type::Array { items, .. } => qualify_names(items, names),
+        Type::Map(values) => qualify_names(values, names),
+        Type::Object(fields) => {
+            for field in fields.iter_mut() {
+                qualify_names(&mut field.ty, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse_proto(path: &str, source: &str) -> Result<HashMap<String, Type>, GenerateError> {
+    let mut parser = ProtoParser {
+        path,
+        tokens: tokenize_proto(source),
+        i: 0,
+        package: String::new(),
+        types: HashMap::new(),
+        unresolved: Vec::new(),
+    };
+    parser.file()?;
+
+    let mut types = parser.types.clone();
+
+    // resolve each message's field types relative to that message
+    for (scope, ty) in types.iter_mut() {
+        let names: HashMap<String, String> = parser
+            .unresolved
+            .iter()
+            .filter(|(field_scope, _)| field_scope == scope)
+            .filter_map(|(_, name)| Some((name.clone(), parser.resolve(scope, name)?)))
+            .collect();
+
+        qualify_names(ty, &names);
+    }
+
+    Ok(types)
+}
+
+// Avro
+
+fn avro_type(
+    schema: &JsonValue,
+    namespace: &str,
+    types: &mut HashMap<String, Type>,
+) -> Result<Type, String> {
+    let full_name = |name: &str, namespace: &str| {
+        if name.contains('.') || namespace.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}.{}", namespace, name)
+        }
+    };
+
+    if let Some(name) = schema.as_str() {
+        return Ok(match name {
+            "null" => Type::Null,
+            "boolean" => Type::Bool,
+            "int" => int(i32::MIN.into(), i32::MAX.into()),
+            "long" => int(i64::MIN, i64::MAX),
+            "float" | "double" => float(),
+            "bytes" | "string" => string(),
+            name if types.contains_key(name) => Type::Named(name.into()),
+            name => Type::Named(full_name(name, namespace)),
+        });
+    }
+
+    if schema.is_array() {
+        return schema
+            .members()
+            .map(|branch| {
+                let ty = avro_type(branch, namespace, types)?;
+                let label = match branch["type"].as_str() {
+                    Some("record") | Some("enum") | Some("fixed") => {
+                        full_name(branch["name"].as_str().unwrap_or_default(), namespace)
+                    }
+                    Some(primitive) => primitive.to_owned(),
+                    None => match &ty {
+                        Type::Named(name) => name.clone(),
+                        _ => branch.as_str().unwrap_or_default().to_owned(),
+                    },
+                };
+
+                Ok((label, ty))
+            })
+            .collect::<Result<_, _>>()
+            .map(Type::Union);
+    }
+
+    let namespace = schema["namespace"].as_str().unwrap_or(namespace).to_owned();
+    let name = schema["name"]
+        .as_str()
+        .map(|name| full_name(name, &namespace));
+
+    let ty = match schema["type"].as_str() {
+        Some("record") | Some("error") => {
+            let mut fields = Vec::new();
+
+            for field in schema["fields"].members() {
+                fields.push(Field {
+                    name: field["name"].as_str().unwrap_or_default().into(),
+                    ty: avro_type(&field["type"], &namespace, types)?,
+                    required: true,
+                    oneof: None,
+                });
+            }
+
+            Type::Object(fields)
+        }
+        Some("enum") => Type::Enum(schema["symbols"].members().cloned().collect()),
+        Some("array") => Type::Array {
+            items: Box::new(avro_type(&schema["items"], &namespace, types)?),
+            min: 0,
+            max: usize::MAX,
+        },
+        Some("map") => Type::Map(Box::new(avro_type(&schema["values"], &namespace, types)?)),
+        Some("fixed") => {
+            let size = schema["size"].as_usize().unwrap_or_default();
+            Type::String {
+                format: None,
+                min: size,
+                max: size,
+            }
+        }
+        Some(_) => avro_type(&schema["type"], &namespace, types)?,
+        None => return Err(format!("schema without a type: {}", schema.dump())),
+    };
+
+    match name {
+        Some(name) if matches!(ty, Type::Object(_) | Type::Enum(_) | Type::String { .. }) => {
+            types.insert(name.clone(), ty);
+            Ok(Type::Named(name))
+        }
+        _ => Ok(ty),
+    }
+}
+
+// JSON Schema
+
+fn json_schema_type(schema: &JsonValue, random: bool) -> Type {
+    if schema.has_key("$ref") {
+        return Type::Named(schema["$ref"].as_str().unwrap_or_default().into());
+    }
+
+    if !random {
+        if schema.has_key("default") {
+            return Type::Const(schema["default"].clone());
+        }
+
+        if let Some(example) = schema["examples"].members().next() {
+            return Type::Const(example.clone());
+        }
+    }
+
+    if schema.has_key("const") {
+        return Type::Const(schema["const"].clone());
+    }
+
+    if schema["enum"].is_array() {
+        return Type::Enum(schema["enum"].members().cloned().collect());
+    }
+
+    for key in ["oneOf", "anyOf"].iter() {
+        if schema[*key].is_array() {
+            return Type::OneOf(
+                schema[*key]
+                    .members()
+                    .map(|branch| json_schema_type(branch, random))
+                    .collect(),
+            );
+        }
+    }
+
+    if let Some(first) = schema["allOf"].members().next() {
+        // merging subschemas is out of scope; the first usually carries the shape
+        return json_schema_type(first, random);
+    }
+
+    if schema["type"].is_array() {
+        return Type::OneOf(
+            schema["type"]
+                .members()
+                .map(|ty| {
+                    let mut branch = schema.clone();
+                    branch["type"] = ty.clone();
+                    json_schema_type(&branch, random)
+                })
+                .collect(),
+        );
+    }
+
+    let number = |key: &str| schema[key].as_f64();
+    let size = |key: &str, default: usize| schema[key].as_usize().unwrap_or(default);
+
+    let ty = match schema["type"].as_str() {
+        Some(ty) => ty,
+        None if schema.has_key("properties") => "object",
+        None if schema.has_key("items") => "array",
+        None => return Type::Any,
+    };
+
+    match ty {
+        "null" => Type::Null,
+        "boolean" => Type::Bool,
+        "integer" => {
+            let min = number("minimum")
+                .or_else(|| number("exclusiveMinimum").map(|min| min + 1.0))
+                .map_or(i64::MIN, |min| min.ceil() as i64);
+            let max = number("maximum")
+                .or_else(|| number("exclusiveMaximum").map(|max| max - 1.0))
+                .map_or(i64::MAX, |max| max.floor() as i64);
+
+            int(min, max)
+        }
+        "number" => Type::Float {
+            min: number("minimum")
+                .or_else(|| number("exclusiveMinimum"))
+                .unwrap_or(f64::MIN),
+            max: number("maximum")
+                .or_else(|| number("exclusiveMaximum"))
+                .unwrap_or(f64::MAX),
+        },
+        "string" => Type::String {
+            format: schema["format"].as_str().map(Into::into),
+            min: size("minLength", 0),
+            max: size("maxLength", usize::MAX),
+        },
+        "array" => Type::Array {
+            items: Box::new(json_schema_type(&schema["items"], random)),
+            min: size("minItems", 0),
+            max: size("maxItems", usize::MAX),
+        },
+        "object" => {
+            let required: Vec<&str> = schema["required"]
+                .members()
+                .filter_map(JsonValue::as_str)
+                .collect();
+
+            Type::Object(
+                schema["properties"]
+                    .entries()
+                    .map(|(name, property)| Field {
+                        name: name.into(),
+                        ty: json_schema_type(property, random),
+                        required: required.contains(&name),
+                        oneof: None,
+                    })
+                    .collect(),
+            )
+        }
+        _ => Type::Any,
+    }
+}
+
+fn parse_json_schema(schema: &JsonValue, random: bool) -> HashMap<String, Type> {
+    let mut types = HashMap::new();
+
+    for key in ["definitions", "$defs"].iter() {
+        for (name, definition) in schema[*key].entries() {
+            types.insert(
+                format!("#/{}/{}", key, name),
+                json_schema_type(definition, random),
+            );
+        }
+    }
+
+    types.insert("#".into(), json_schema_type(schema, random));
+
+    types
+}
+
+// values
+
+struct Generator<'a> {
+    types: &'a HashMap<String, Type>,
+    rng: Option<Rng>,
+    depth: usize,
+}
+
+impl Generator<'_> {
+    fn pick<'b, T>(&mut self, options: &'b [T]) -> Option<&'b T> {
+        let i = match self.rng.as_mut() {
+            Some(rng) => rng.below(options.len()),
+            None => 0,
+        };
+
+        options.get(i)
+    }
+
+    fn count(&mut self, min: usize, max: usize) -> usize {
+        let max = max.min(min.max(3));
+
+        match self.rng.as_mut() {
+            _ if self.depth >= MAX_DEPTH => min,
+            Some(rng) => min + rng.below(max - min + 1),
+            None => min.max(1).min(max),
+        }
+    }
+
+    fn word(&mut self, min: usize, max: usize) -> String {
+        const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+        let len = match self.rng.as_mut() {
+            Some(rng) => {
+                let max = max.min(min.max(12));
+                min.max(1).min(max) + rng.below(max - min.max(1).min(max) + 1)
+            }
+            None => min.max(6).min(max),
+        };
+
+        (0..len)
+            .map(|i| match self.rng.as_mut() {
+                Some(rng) => ALPHABET[rng.below(ALPHABET.len())] as char,
+                None => ALPHABET[i % 26] as char,
+            })
+            .collect()
+    }
+
+    fn number(&mut self, min: i64, max: i64) -> i64 {
+        match self.rng.as_mut() {
+            // keep examples readable rather than spanning the whole range
+            Some(rng) if min > 1000 => rng.range(min, max.min(min.saturating_add(1000))),
+            Some(rng) if max < -1000 => rng.range(min.max(max.saturating_sub(1000)), max),
+            Some(rng) => rng.range(min.max(-1000), max.min(1000)),
+            None => 0.max(min).min(max),
+        }
+    }
+
+    fn formatted(&mut self, format: &str) -> Option<String> {
+        let n = |generator: &mut Self, min, max| generator.number(min, max);
+
+        Some(match format {
+            "date-time" => format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                n(self, 2000, 2030),
+                n(self, 1, 12),
+                n(self, 1, 28),
+                n(self, 0, 23),
+                n(self, 0, 59),
+                n(self, 0, 59)
+            ),
+            "date" => format!(
+                "{:04}-{:02}-{:02}",
+                n(self, 2000, 2030),
+                n(self, 1, 12),
+                n(self, 1, 28)
+            ),
+            "time" => format!(
+                "{:02}:{:02}:{:02}",
+                n(self, 0, 23),
+                n(self, 0, 59),
+                n(self, 0, 59)
+            ),
+            "duration" => format!("{}s", n(self, 1, 3600)),
+            "email" => format!("{}@example.com", self.word(3, 12)),
+            "uri" | "url" => format!("https://example.com/{}", self.word(3, 12)),
+            "hostname" => format!("{}.example.com", self.word(3, 12)),
+            "ipv4" => format!(
+                "192.0.2.{}",
+                n(self, 1, 254) // TEST-NET-1
+            ),
+            "uuid" => {
+                let hex: String = (0..32)
+                    .map(|_| std::char::from_digit(n(self, 0, 15) as u32, 16).unwrap_or('0'))
+                    .collect();
+                format!(
+                    "{}-{}-4{}-a{}-{}",
+                    &hex[..8],
+                    &hex[8..12],
+                    &hex[13..16],
+                    &hex[17..20],
+                    &hex[20..]
+                )
+            }
+            // base64 of the word, for protobuf bytes
+            "byte" => {
+                let word = self.word(3, 12);
+                crate::decode::encode_base64(word.as_bytes())
+            }
+            _ => return None,
+        })
+    }
+
+    fn value(&mut self, ty: &Type) -> JsonValue {
+        match ty {
+            Type::Null => JsonValue::Null,
+            Type::Bool => match self.rng.as_mut() {
+                Some(rng) => rng.chance().into(),
+                None => false.into(),
+            },
+            Type::Int { min, max } => self.number(*min, *max).into(),
+            Type::IntString => self.number(0, i64::MAX).to_string().into(),
+            Type::Float { min, max } => {
+                let value = match self.rng.as_mut() {
+                    Some(rng) => rng.range(-100_000, 100_000) as f64 / 100.0,
+                    None => 0.0,
+                };
+                value.max(*min).min(*max).into()
+            }
+            Type::String { format, min, max } => {
+                match format.as_deref().and_then(|format| self.formatted(format)) {
+                    Some(value) => value.into(),
+                    None => self.word(*min, *max).into(),
+                }
+            }
+            Type::Enum(values) => self.pick(values).cloned().unwrap_or(JsonValue::Null),
+            Type::Const(value) => value.clone(),
+            Type::Array { items, min, max } => {
+                let len = self.count(*min, *max);
+                self.depth += 1;
+                let values = (0..len).map(|_| self.value(items)).collect::<Vec<_>>();
+                self.depth -= 1;
+
+                JsonValue::Array(values)
+            }
+            Type::Map(values) => {
+                let len = self.count(0, usize::MAX);
+                let mut object = JsonValue::new_object();
+
+                self.depth += 1;
+                for _ in 0..len {
+                    let key = self.word(3, 8);
+                    object[key.as_str()] = self.value(values);
+                }
+                self.depth -= 1;
+
+                object
+            }
+            Type::Object(fields) => self.object(fields),
+            Type::OneOf(types) => match self.pick(types) {
+                Some(ty) => self.value(ty),
+                None => JsonValue::Null,
+            },
+            Type::Union(branches) => match self.pick(branches) {
+                Some((_, Type::Null)) | None => JsonValue::Null,
+                Some((label, ty)) => {
+                    let mut object = JsonValue::new_object();
+                    object[label.as_str()] = self.value(ty);
+                    object
+                }
+            },
+            Type::Named(name) => match self.types.get(name) {
+                Some(ty) => self.value(ty),
+                None => JsonValue::new_object(),
+            },
+            Type::Any => JsonValue::new_object(),
+        }
+    }
+
+    fn object(&mut self, fields: &[Field]) -> JsonValue {
+        let mut object = JsonValue::new_object();
+        let mut oneofs: Vec<&str> = Vec::new();
+
+        if self.depth >= MAX_DEPTH {
+            return object;
+        }
+
+        self.depth += 1;
+
+        for (i, field) in fields.iter().enumerate() {
+            if let Some(oneof) = &field.oneof {
+                if oneofs.contains(&oneof.as_str()) {
+                    continue;
+                }
+                oneofs.push(oneof);
+
+                let group: Vec<&Field> = fields[i..]
+                    .iter()
+                    .filter(|other| other.oneof.as_ref() == Some(oneof))
+                    .collect();
+                if let Some(chosen) = self.pick(&group) {
+                    object[chosen.name.as_str()] = self.value(&chosen.ty);
+                }
+                continue;
+            }
+
+            // random bodies leave out some optional fields
+            let include = field.required
+                || match self.rng.as_mut() {
+                    Some(rng) => rng.below(4) != 0,
+                    None => true,
+                };
+
+            if include {
+                object[field.name.as_str()] = self.value(&field.ty);
+            }
+        }
+
+        self.depth -= 1;
+
+        object
+    }
+}
+
+/// Reads `SCHEMA[:TYPE]`, generating an example of TYPE (or of the only or
+/// root type) with fixed placeholder values, or random ones when `random`.
+pub fn generate(spec: &str, random: bool) -> Result<JsonValue> {
+    let (path, name) = match spec.rfind(':') {
+        Some(i) if !Path::new(spec).exists() => (&spec[..i], Some(&spec[i + 1..])),
+        _ => (spec, None),
+    };
+
+    let source = std::fs::read_to_string(path)
+        .map_err(|err| GenerateError::ParseError(path.into(), err.to_string()))?;
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+
+    let (types, root) = match extension {
+        "proto" => {
+            let types = parse_proto(path, &source)?;
+            let root = match name {
+                Some(name) => types
+                    .keys()
+                    .find(|key| *key == name || key.ends_with(&format!(".{}", name)))
+                    .cloned()
+                    .ok_or_else(|| GenerateError::UnknownType(path.into(), name.into()))?,
+                None => {
+                    let messages: Vec<&String> = types
+                        .iter()
+                        .filter(|(_, ty)| matches!(ty, Type::Object(_)))
+                        .map(|(key, _)| key)
+                        .collect();
+
+                    match messages.as_slice() {
+                        [only] => (*only).clone(),
+                        _ => return Err(GenerateError::MissingType(path.into()).into()),
+                    }
+                }
+            };
+
+            (types, root)
+        }
+        "avsc" => {
+            let schema = json::parse(&source).map_err(|err| parse_error(path, err))?;
+            let mut types = HashMap::new();
+            let ty = avro_type(&schema, "", &mut types).map_err(|err| parse_error(path, err))?;
+
+            let root = match name {
+                Some(name) => types
+                    .keys()
+                    .find(|key| *key == name || key.ends_with(&format!(".{}", name)))
+                    .cloned()
+                    .ok_or_else(|| GenerateError::UnknownType(path.into(), name.into()))?,
+                None => {
+                    types.insert("".into(), ty);
+                    "".into()
+                }
+            };
+
+            (types, root)
+        }
+        "json" => {
+            let schema = json::parse(&source).map_err(|err| parse_error(path, err))?;
+            let types = parse_json_schema(&schema, random);
+
+            let root = match name {
+                Some(name) => ["definitions", "$defs"]
+                    .iter()
+                    .map(|key| format!("#/{}/{}", key, name))
+                    .find(|key| types.contains_key(key))
+                    .ok_or_else(|| GenerateError::UnknownType(path.into(), name.into()))?,
+                None => "#".into(),
+            };
+
+            (types, root)
+        }
+        _ => return Err(GenerateError::UnknownFormat(path.into()).into()),
+    };
+
+    let mut generator = Generator {
+        types: &types,
+        rng: if random { Some(Rng::seeded()) } else { None },
+        depth: 0,
+    };
+
+    Ok(generator.value(&Type::Named(root)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example(types: &HashMap<String, Type>, root: &str, rng: Option<Rng>) -> String {
+        let mut generator = Generator {
+            types,
+            rng,
+            depth: 0,
+        };
+
+        generator.value(&Type::Named(root.into())).dump()
+    }
+
+    #[test]
+    fn fills_proto_messages_with_placeholders() {
+        let types = parse_proto(
+            "user.proto",
+            r#"
+            syntax = "proto3";
+            package users;
+
+            enum Role { ROLE_UNSPECIFIED = 0; ADMIN = 1; }
+
+            message User {
+              string user_id = 1;
+              int64 created = 2;
+              repeated Role roles = 3;
+              oneof contact {
+                string email = 4;
+                string phone = 5;
+              }
+              map<string, bool> flags = 6;
+            }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            example(&types, "users.User", None),
+            r#"{"userId":"abcdef","created":"0","roles":["ROLE_UNSPECIFIED"],"email":"abcdef","flags":{"abcdef":false}}"#
+        );
+    }
+
+    #[test]
+    fn writes_avro_unions_with_their_branch() {
+        let schema = json::parse(
+            r#"{
+                "type": "record",
+                "name": "Event",
+                "namespace": "app",
+                "fields": [
+                    {"name": "id", "type": "long"},
+                    {"name": "note", "type": ["string", "null"]},
+                    {"name": "kind", "type": {"type": "enum", "name": "Kind", "symbols": ["A", "B"]}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut types = HashMap::new();
+        let ty = avro_type(&schema, "", &mut types).unwrap();
+        types.insert("".into(), ty);
+
+        assert_eq!(
+            example(&types, "", None),
+            r#"{"id":0,"note":{"string":"abcdef"},"kind":"A"}"#
+        );
+    }
+
+    #[test]
+    fn seeded_values_are_repeatable() {
+        let schema = json::parse(
+            r#"{
+                "type": "object",
+                "required": ["id", "email"],
+                "properties": {
+                    "id": {"type": "integer", "minimum": 1, "maximum": 99},
+                    "email": {"type": "string", "format": "email"},
+                    "tags": {"type": "array", "items": {"enum": ["a", "b", "c"]}}
+                }
+            }"#,
+        )
+        .unwrap();
+        let types = parse_json_schema(&schema, true);
+
+        let seeded = example(&types, "#", Some(Rng(42)));
+        assert_eq!(seeded, example(&types, "#", Some(Rng(42))));
+        assert_eq!(
+            seeded,
+            r#"{"id":67,"email":"0to9gxe43dk@example.com","tags":["b","b"]}"#
+        );
+    }
+
+    #[test]
+    fn random_numbers_stay_in_range() {
+        let mut rng = Rng(7);
+
+        for _ in 0..1000 {
+            assert!((-3..=3).contains(&rng.range(-3, 3)));
+            assert!(rng.below(5) < 5);
+        }
+        assert_eq!(rng.range(i64::MIN, i64::MIN), i64::MIN);
+        assert_eq!(rng.below(0), 0);
+    }
+}
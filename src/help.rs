@@ -0,0 +1,136 @@
+pub const REQUEST_ITEMS: &str = "\
+REQUEST ITEMS:
+    Key:Value                    Header                                X-API-Token:123
+    Key:                         Remove a header                       User-Agent:
+    Key;                         Empty header                          X-Empty;
+    key==value                   URL search parameter                  search==rurl
+    key=value                    Data field (string)                   name=ezra
+    key:=json                    Raw JSON field                        count:=3 tags:='[\"a\"]'
+    key=@file                    Data field from file                  bio=@./bio.txt
+    key:=@file                   Raw JSON field from file              config:=@./config.json
+    key@file                     Form file field                       avatar@./avatar.png
+    key@file;type=T;filename=N   File field with its type and name     avatar@./a.bin;type=image/png
+    @file                        Raw request body                      @./payload.json
+    key=@-:path                  Field from piped --json-output        token=@-:$.access_token
+    Key:@prompt                  Header typed at a hidden prompt       X-API-Token:@prompt
+    key==@prompt                 URL parameter typed at a prompt       token==@prompt
+    key=@prompt                  Data field typed at a hidden prompt   password=@prompt
+    key=@clipboard               Data field from the clipboard         note=@clipboard
+    @clipboard                   Raw request body from the clipboard   @clipboard
+
+    An item splits at its first separator, preferring the longest one there
+    (`==` over `=`, `:=` over `:`), so values may contain separators freely.
+    Escape a separator in a key with a backslash: `a\\:b=c`, `x\\=y==1`, `me\\@host=1`.
+    Repeat a header item to send one line per value: `X-Tag:a X-Tag:b`.
+    `key@file` items make `--form` send multipart/form-data, with the file's bytes as they are.
+    Bodies of 1 MiB or more are sent with `Expect: 100-continue`: the body waits for the
+    server's 100, is dropped if it answers with a final status instead, and goes out anyway
+    after a second without an answer. Remove it with `Expect:` to send the body right away.
+    In forms, `key:=` numbers, booleans, and strings send their text, null an empty value,
+    and arrays one field per element; repeat `tags[]=a tags[]=b` for PHP-style lists.
+    `{{name}}` anywhere in the URI or an item takes its value from `--var name=VALUE`
+    or `rurl var set`; `{{env.NAME}}` reads the environment, as do `$NAME` and `${NAME}`
+    with `--expand-env`, even in single-quoted items like `'auth:={\"token\": \"$TOKEN\"}'`.";
+
+pub const EXAMPLES: &str = "\
+EXAMPLES:
+    rurl GET example.com
+    rurl GET example.com/search q==rurl
+    rurl POST example.com/users name=ezra admin:=true
+    rurl POST example.com/users name=ezra --edit
+    rurl GET example.com/token --copy
+    rurl --form POST example.com/login username=ezra password=hunter2
+    rurl PUT example.com/upload @./payload.json
+    rurl POST localhost:8080/users --gen-body users.proto:my.pkg.CreateUser --random
+    rurl --graphql POST example.com/graphql query=@./user.graphql id:=1
+    rurl --yaml POST example.com/config name=ezra replicas:=3
+    rurl --proto users.pb POST api.example.com/twirp/users.Users/Create name=ezra admin:=true
+    rurl --proto users.pb --grpc-web POST api.example.com/users.Users/Get id:=7
+    rurl GET example.com Authorization:'Bearer token'
+    rurl OPTIONS api.example.com/orders --preflight origin=https://app.example.com,method=PUT
+    rurl -a ezra GET example.com/private
+    rurl --fill-form '#login-form' GET example.com/login user=alice pass=secret
+    rurl GET example.com/users --filter '.[0].email'
+    rurl GET example.com/login --wrap
+    rurl GET api.test/health --hosts-file ./test-hosts
+    rurl GET example.com/health --resolve example.com:80:10.0.0.12
+    rurl GET example.com --dns-servers 1.1.1.1,8.8.8.8
+    rurl GET example.com --doh-url https://cloudflare-dns.com/dns-query
+    rurl -6 --interface eth1 GET example.com
+    rurl --auth-type aws4 --aws-profile dev GET my-bucket.s3.us-east-2.amazonaws.com/key
+    rurl --auth-type hmac --hmac-key $KEY --hmac-header 'X-Signature: v1={signature}' POST api.internal/orders sku=a1
+    rurl --auth-type vault --formatter avro GET api.internal/events
+    rurl --oauth2-token-url auth.example.com/token --oauth2-client-id app --oauth2-client-secret s3cret GET api.example.com/me
+    rurl GET example.com/health --repeat 200 --concurrency 8 --no-keepalive
+    rurl GET example.com/health --repeat 600 --concurrency 8 --rate 20/s
+    rurl POST example.com/jobs name=nightly --retries 3 --retry-all-methods
+    rurl --http2-prior-knowledge --trailer Checksum:sha256=abc POST localhost:50051/ingest @./batch.bin
+    rurl GET example.com/users/7 --expect-status 2xx --expect-header 'Content-Type~json' --expect-body-json '$.id == 7'
+    rurl GET example.com/jobs/1 --until '$.status == \"ready\"' --poll-timeout 10m
+    rurl GET example.com/webhook --decode-fields '$.payload,$.items[*].blob'
+    rurl GET example.com/logs.ndjson --filter '$.level'
+    rurl GET example.com/users --table
+    rurl --json GET example.com/users --accept application/vnd.api+json
+    rurl --json POST example.com/jobs/7/cancel --empty-body
+    rurl diff GET https://api.example.com/users/7 https://staging.example.com/users/7 Accept:application/json
+    rurl GET example.com/jobs/1 --watch 2
+    rurl GET example.com/health --watch 10 --expect-status 2xx --notify --bell
+    rurl GET api.github.com/repos/ezracelli/rurl/issues --paginate --max-pages 5
+    rurl GET example.com/logo.png --render-images
+    rurl GET example.com/tool.tar.gz -o tool.tar.gz --checksum sha256:$SHA256 --extract ./tool
+    rurl GET example.com/dump.json Accept-Encoding:gzip -o dump.json.gz --no-decompress
+    rurl GET example.com/export.csv -o export.csv --max-body 100M
+    rurl GET example.com/feed.xml --speed-limit 1k --speed-time 15
+    rurl POST localhost:8080/upload @./big.json --limit-rate 500k
+    rurl var set api https://api.example.com
+    rurl GET '{{var.api}}/users'
+    rurl GET 'example.com/{{tenant}}/users' --var tenant=acme Authorization:'Bearer {{env.TOKEN}}'
+    rurl fragment add tracing X-Trace-Id:{{var.trace}} X-Debug:1
+    rurl --fragment tracing GET example.com/orders
+    rurl GET example.com --all-to-stdout | less -R
+    rurl GET example.com/export.json --highlight-max-bytes none
+    rurl GET example.com/users --unsorted
+    rurl GET https://example.com --follow --retries 2 --trace
+    rurl GET https://example.com --tls-info
+    rurl GET https://example.com --pinnedpubkey sha256//YhKJKSzoTt2b5FP18fvpHo7fJYqQCjAa3HWY3tvRMwE=
+    rurl GET example.com/legacy.txt --hexdump
+    rurl GET example.com/feed Accept-Encoding:gzip --raw-response - | xxd | head
+    rurl GET example.com/static/../../etc/passwd --path-as-is
+    rurl example.com/cache/logo.png -X purge
+    rurl GET example.com/catalog --cache-dir ~/.cache/rurl/responses
+    rurl GET example.com/users --format json | jq .timings.total_ms
+    rurl GET a/session --json-output | rurl POST b/consume token=@-:$.token
+    rurl --dav calendar-query --time-range 20240101T000000Z/20240201T000000Z REPORT dav.example.com/cal/
+    rurl run api.http --var host=https://staging.example.com
+    rurl --history POST example.com/orders sku=a1
+    rurl replay 12 sku=b2
+    rurl doctor
+    rurl completions bash > ~/.local/share/bash-completion/completions/rurl";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_item_columns_line_up() {
+        let rows: Vec<&str> = REQUEST_ITEMS
+            .lines()
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .collect();
+        let column = |row: &str, n: usize| {
+            let mut start = 4;
+            for _ in 0..n {
+                start += row[start..].find("  ").unwrap();
+                start += row[start..].find(|c| c != ' ').unwrap();
+            }
+            start
+        };
+
+        assert!(rows.len() > 10);
+        for row in &rows {
+            assert_eq!(column(row, 1), column(rows[0], 1), "{}", row);
+            assert_eq!(column(row, 2), column(rows[0], 2), "{}", row);
+        }
+    }
+}
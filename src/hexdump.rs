@@ -0,0 +1,81 @@
+//! `--hexdump`: bodies as offset, hex, and ASCII columns, like `xxd` or
+//! `hexdump -C`. Bytes are colored by class, so stray control characters
+//! and high bytes in otherwise readable text stand out.
+
+const WIDTH: usize = 16;
+
+fn color(byte: u8) -> &'static str {
+    match byte {
+        0x00 => "\x1b[2m",
+        b'\t' | b'\n' | b'\r' | b' ' => "\x1b[33m",
+        0x21..=0x7e => "\x1b[32m",
+        0x80..=0xff => "\x1b[35m",
+        _ => "\x1b[31m",
+    }
+}
+
+fn ascii(byte: u8) -> char {
+    match byte {
+        0x20..=0x7e => byte as char,
+        _ => '.',
+    }
+}
+
+/// One line per 16 bytes: the offset, the bytes in hex with a gap after
+/// the eighth, then the bytes as ASCII with `.` for anything unprintable.
+pub fn dump(bytes: &[u8]) -> String {
+    let mut output = String::new();
+
+    for (line, chunk) in bytes.chunks(WIDTH).enumerate() {
+        output += &format!("\x1b[36m{:08x}\x1b[0m  ", line * WIDTH);
+
+        for i in 0..WIDTH {
+            match chunk.get(i) {
+                Some(byte) => output += &format!("{}{:02x}\x1b[0m ", color(*byte), byte),
+                None => output += "   ",
+            }
+
+            if i == WIDTH / 2 - 1 {
+                output.push(' ');
+            }
+        }
+
+        output += " |";
+        for byte in chunk {
+            output += &format!("{}{}\x1b[0m", color(*byte), ascii(*byte));
+        }
+        output += "|\n";
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> String {
+        let mut plain = String::new();
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|c| c.is_ascii_alphabetic());
+            } else {
+                plain.push(c);
+            }
+        }
+
+        plain
+    }
+
+    #[test]
+    fn dumps_offsets_hex_and_ascii() {
+        assert_eq!(
+            plain(&dump(b"Hello, world!\n\x00\xffrurl")),
+            "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |Hello, world!...|\n\
+             00000010  72 75 72 6c                                       |rurl|\n"
+        );
+        assert_eq!(dump(b""), "");
+    }
+}
@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Style, ThemeSet},
+    parsing::{syntax_definition::SyntaxDefinition, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+lazy_static::lazy_static! {
+    static ref SYNTAXES: SyntaxSet = {
+        let mut ps = SyntaxSet::load_defaults_newlines().into_builder();
+        ps.add(
+            SyntaxDefinition::load_from_str(
+                include_str!("../syntaxes/http-response.sublime-syntax",),
+                true,
+                None,
+            )
+            .unwrap(),
+        );
+
+        ps.build()
+    };
+    static ref THEMES: ThemeSet = ThemeSet::load_defaults();
+}
+
+static MAX_BYTES: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// `--highlight-max-bytes`: larger inputs are returned as they are, since
+/// highlighting takes far longer than printing.
+pub fn set_max_bytes(max_bytes: Option<usize>) {
+    MAX_BYTES.store(max_bytes.unwrap_or(usize::MAX), Ordering::SeqCst);
+}
+
+pub fn highlight(input: &str, language: &str) -> String {
+    if input.len() > MAX_BYTES.load(Ordering::SeqCst) {
+        return input.into();
+    }
+
+    let syntax = SYNTAXES
+        .find_syntax_by_extension(language)
+        .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+    let mut higlighter = HighlightLines::new(syntax, &THEMES.themes["base16-ocean.dark"]);
+
+    let lines = LinesWithEndings::from(input);
+
+    lines
+        .map(|line| {
+            let ranges: Vec<(Style, &str)> = higlighter.highlight(line, &SYNTAXES);
+            syntect::util::as_24_bit_terminal_escaped(&ranges[..], false)
+        })
+        .collect::<String>()
+}
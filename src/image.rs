@@ -0,0 +1,192 @@
+//! Inline previews of image responses: the kitty graphics protocol or
+//! iTerm2's inline images where the terminal speaks them, and otherwise
+//! block art from ImageMagick's downscaled pixels.
+
+use crate::decode::encode_base64;
+use std::{
+    io::Write as _,
+    process::{Command, Stdio},
+};
+
+/// kitty takes its base64 payload in chunks of at most this many bytes.
+const KITTY_CHUNK: usize = 4096;
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Kitty,
+    Iterm2,
+    Blocks,
+}
+
+impl Protocol {
+    /// The protocol the terminal is known to speak, from its environment.
+    pub fn detect() -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+
+        if !var("KITTY_WINDOW_ID").is_empty() || var("TERM") == "xterm-kitty" {
+            Protocol::Kitty
+        } else if matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm") {
+            Protocol::Iterm2
+        } else {
+            Protocol::Blocks
+        }
+    }
+}
+
+/// A PNG as kitty graphics commands. kitty decodes PNG itself, but no other
+/// format.
+pub fn kitty(png: &[u8]) -> String {
+    let payload = encode_base64(png);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut output = String::new();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+
+        match i {
+            0 => output += &format!("\x1b_Gf=100,a=T,m={};{}\x1b\\", more, chunk),
+            _ => output += &format!("\x1b_Gm={};{}\x1b\\", more, chunk),
+        }
+    }
+
+    output + "\n"
+}
+
+/// Any image iTerm2 can decode, as an inline file.
+pub fn iterm2(image: &[u8], width: usize) -> String {
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={};preserveAspectRatio=1:{}\x07\n",
+        image.len(),
+        width,
+        encode_base64(image),
+    )
+}
+
+/// Pipes `input` through ImageMagick with `args`, if it is installed.
+fn magick(args: &[&str], input: &[u8]) -> Option<Vec<u8>> {
+    // ImageMagick 7 is `magick`, and 6 is `convert`
+    let mut child = ["magick", "convert"].iter().find_map(|program| {
+        Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()
+    })?;
+
+    let mut stdin = child.stdin.take()?;
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let output = child.wait_with_output().ok()?;
+    writer.join().ok()?.ok()?;
+
+    if output.status.success() {
+        Some(output.stdout)
+    } else {
+        None
+    }
+}
+
+/// The width, height, and RGB pixels of a binary PPM (P6) with 8-bit
+/// samples.
+fn parse_ppm(ppm: &[u8]) -> Option<(usize, usize, &[u8])> {
+    let mut fields = Vec::new();
+    let mut rest = ppm;
+
+    while fields.len() < 4 {
+        let start = rest.iter().position(|byte| !byte.is_ascii_whitespace())?;
+        let end = start + rest[start..].iter().position(u8::is_ascii_whitespace)?;
+        fields.push(std::str::from_utf8(&rest[start..end]).ok()?);
+        // exactly one whitespace byte separates the header from the pixels
+        rest = &rest[end + 1..];
+    }
+
+    let width: usize = fields[1].parse().ok()?;
+    let height: usize = fields[2].parse().ok()?;
+
+    if fields[0] != "P6" || fields[3] != "255" || rest.len() < width * height * 3 {
+        return None;
+    }
+
+    Some((width, height, &rest[..width * height * 3]))
+}
+
+/// Pixels as rows of `▀`, each cell colored with the pixel above in the
+/// foreground and the one below in the background.
+fn half_blocks(width: usize, height: usize, pixels: &[u8]) -> String {
+    let pixel = |x: usize, y: usize| {
+        let i = (y * width + x) * 3;
+        (pixels[i], pixels[i + 1], pixels[i + 2])
+    };
+    let mut output = String::new();
+
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let (r, g, b) = pixel(x, y);
+            output += &format!("\x1b[38;2;{};{};{}m", r, g, b);
+
+            if y + 1 < height {
+                let (r, g, b) = pixel(x, y + 1);
+                output += &format!("\x1b[48;2;{};{};{}m", r, g, b);
+            }
+
+            output += "▀";
+        }
+
+        output += "\x1b[0m\n";
+    }
+
+    output
+}
+
+/// Block art of the image's first frame, at most `columns` wide and `rows`
+/// tall.
+pub fn blocks(image: &[u8], columns: usize, rows: usize) -> Option<String> {
+    let geometry = format!("{}x{}>", columns, rows * 2);
+    let ppm = magick(
+        &["-[0]", "-resize", &geometry, "-depth", "8", "ppm:-"],
+        image,
+    )?;
+    let (width, height, pixels) = parse_ppm(&ppm)?;
+
+    Some(half_blocks(width, height, pixels))
+}
+
+/// The image previewed for `protocol`, at most `columns` wide and `rows`
+/// tall, or `None` when it can't be.
+pub fn preview(protocol: Protocol, image: &[u8], columns: usize, rows: usize) -> Option<String> {
+    match protocol {
+        Protocol::Kitty if image.starts_with(PNG_SIGNATURE) => Some(kitty(image)),
+        Protocol::Iterm2 => Some(iterm2(image, columns)),
+        _ => blocks(image, columns, rows),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_ppm_pixels_as_half_blocks() {
+        let ppm = b"P6\n2 3\n255\n\xff\x00\x00\x00\xff\x00\x00\x00\xff\xff\xff\xff\x10\x20\x30\x00\x00\x00";
+        let (width, height, pixels) = parse_ppm(ppm).unwrap();
+
+        assert_eq!(
+            half_blocks(width, height, pixels),
+            "\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀\x1b[38;2;0;255;0m\x1b[48;2;255;255;255m▀\x1b[0m\n\
+             \x1b[38;2;16;32;48m▀\x1b[38;2;0;0;0m▀\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn chunks_kitty_payloads() {
+        let output = kitty(&[0; 4000]);
+
+        assert!(output.starts_with("\x1b_Gf=100,a=T,m=1;AAAA"));
+        assert!(output.contains("\x1b\\\x1b_Gm=0;AAAA"));
+    }
+}
@@ -0,0 +1,237 @@
+use json::JsonValue;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum JsonPathError {
+    #[error("invalid path {0}: {1}")]
+    ParseError(String, &'static str),
+    #[error("path {0} matched nothing")]
+    NoMatch(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Index(i64),
+    Wildcard,
+}
+
+/// Parses `$.a.b[0]`, `$['a'][*]`, or the jq-style `.a[0]` into segments.
+pub fn parse(path: &str) -> Result<Vec<Segment>, JsonPathError> {
+    let error = |reason| JsonPathError::ParseError(path.into(), reason);
+
+    let mut rest = path.trim();
+    rest = rest.strip_prefix('$').unwrap_or(rest);
+
+    let mut segments = Vec::new();
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(|| error("unclosed ["))?;
+            let inner = after[..end].trim();
+
+            segments.push(match inner {
+                "*" => Segment::Wildcard,
+                _ if inner.starts_with('\'') || inner.starts_with('"') => {
+                    Segment::Key(inner[1..inner.len().max(2) - 1].into())
+                }
+                _ => Segment::Index(inner.parse().map_err(|_| error("invalid index"))?),
+            });
+
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            if after.is_empty() || after.starts_with('[') {
+                rest = after;
+                continue;
+            }
+
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            let key = &after[..end];
+
+            segments.push(match key {
+                "*" => Segment::Wildcard,
+                _ => Segment::Key(key.into()),
+            });
+
+            rest = &after[end..];
+        } else {
+            return Err(error("expected . or ["));
+        }
+    }
+
+    Ok(segments)
+}
+
+fn select_segments<'a>(value: &'a JsonValue, segments: &[Segment], out: &mut Vec<&'a JsonValue>) {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => {
+            out.push(value);
+            return;
+        }
+    };
+
+    match segment {
+        Segment::Key(key) => {
+            if value.has_key(key) {
+                select_segments(&value[key.as_str()], rest, out);
+            }
+        }
+        Segment::Index(index) => {
+            let index = if *index < 0 {
+                value.len() as i64 + index
+            } else {
+                *index
+            };
+
+            if value.is_array() && index >= 0 && (index as usize) < value.len() {
+                select_segments(&value[index as usize], rest, out);
+            }
+        }
+        Segment::Wildcard => {
+            if value.is_array() {
+                for member in value.members() {
+                    select_segments(member, rest, out);
+                }
+            } else {
+                for (_, member) in value.entries() {
+                    select_segments(member, rest, out);
+                }
+            }
+        }
+    }
+}
+
+/// Every value matched by `path`.
+pub fn select<'a>(value: &'a JsonValue, path: &str) -> Result<Vec<&'a JsonValue>, JsonPathError> {
+    let segments = parse(path)?;
+    let mut out = Vec::new();
+
+    select_segments(value, &segments, &mut out);
+
+    Ok(out)
+}
+
+/// The first value matched by `path`, or an error when nothing matches.
+pub fn select_one<'a>(value: &'a JsonValue, path: &str) -> Result<&'a JsonValue, JsonPathError> {
+    select(value, path)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| JsonPathError::NoMatch(path.into()))
+}
+
+/// Applies `path` to a whole document; several matches are collected into an array.
+pub fn filter(value: &JsonValue, path: &str) -> Result<JsonValue, JsonPathError> {
+    let mut matches = select(value, path)?;
+
+    match matches.len() {
+        0 => Err(JsonPathError::NoMatch(path.into())),
+        1 => Ok(matches.remove(0).clone()),
+        _ => Ok(JsonValue::Array(matches.into_iter().cloned().collect())),
+    }
+}
+
+fn update_segments(value: &mut JsonValue, segments: &[Segment], f: &mut dyn FnMut(&mut JsonValue)) {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => {
+            f(value);
+            return;
+        }
+    };
+
+    match segment {
+        Segment::Key(key) => {
+            if value.has_key(key) {
+                update_segments(&mut value[key.as_str()], rest, f);
+            }
+        }
+        Segment::Index(index) => {
+            let index = if *index < 0 {
+                value.len() as i64 + index
+            } else {
+                *index
+            };
+
+            if value.is_array() && index >= 0 && (index as usize) < value.len() {
+                update_segments(&mut value[index as usize], rest, f);
+            }
+        }
+        Segment::Wildcard => {
+            if value.is_array() {
+                for member in value.members_mut() {
+                    update_segments(member, rest, f);
+                }
+            } else {
+                for (_, member) in value.entries_mut() {
+                    update_segments(member, rest, f);
+                }
+            }
+        }
+    }
+}
+
+/// Calls `f` on every value matched by `path`, returning how many matched.
+pub fn update(
+    value: &mut JsonValue,
+    path: &str,
+    mut f: impl FnMut(&mut JsonValue),
+) -> Result<usize, JsonPathError> {
+    let segments = parse(path)?;
+    let mut count = 0;
+
+    update_segments(value, &segments, &mut |value| {
+        count += 1;
+        f(value);
+    });
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_both_syntaxes() {
+        let segments = vec![
+            Segment::Key("users".into()),
+            Segment::Index(-1),
+            Segment::Wildcard,
+        ];
+
+        assert_eq!(parse("$.users[-1].*").unwrap(), segments);
+        assert_eq!(parse("$['users'][-1][*]").unwrap(), segments);
+        assert_eq!(parse(".users[-1][*]").unwrap(), segments);
+        assert_eq!(parse("$").unwrap(), vec![]);
+
+        assert!(parse("$.users[0").is_err());
+        assert!(parse("$.users[x]").is_err());
+        assert!(parse("users").is_err());
+    }
+
+    #[test]
+    fn filters_documents() {
+        let value = json::parse(r#"{"users": [{"id": 1}, {"id": 2}], "total": 2}"#).unwrap();
+
+        assert_eq!(filter(&value, "$.total").unwrap(), 2);
+        assert_eq!(filter(&value, "$.users[-1].id").unwrap(), 2);
+        assert_eq!(filter(&value, "$.users[*].id").unwrap(), json::array![1, 2]);
+        assert_eq!(select(&value, "$.*").unwrap().len(), 2);
+
+        assert!(filter(&value, "$.users[2]").is_err());
+        assert!(select_one(&value, "$.missing").is_err());
+    }
+
+    #[test]
+    fn updates_every_match() {
+        let mut value = json::parse(r#"{"users": [{"id": 1}, {"id": 2}]}"#).unwrap();
+        let count = update(&mut value, "$.users[*].id", |id| {
+            *id = (id.as_i64().unwrap() * 10).into()
+        })
+        .unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(value["users"][1]["id"], 20);
+    }
+}
@@ -0,0 +1,74 @@
+pub mod analyze;
+pub mod auth;
+pub mod aws;
+pub mod bench;
+pub mod binary;
+pub mod body;
+pub mod cache;
+pub mod cancel;
+pub mod charset;
+pub mod client;
+pub mod clipboard;
+pub mod collection;
+pub mod config;
+pub mod cors;
+pub mod curl;
+pub mod dav;
+pub mod decode;
+pub mod deterministic;
+pub mod diagnose;
+pub mod diff;
+pub mod digest;
+pub mod dns;
+pub mod download;
+pub mod editor;
+pub mod encoding;
+pub mod exchange;
+pub mod expect;
+pub mod form;
+pub mod generate;
+pub mod hexdump;
+pub mod highlight;
+pub mod history;
+pub mod hmac;
+pub mod image;
+pub mod jsonpath;
+pub mod limit;
+pub mod locale;
+pub mod msgpack;
+pub mod multi;
+pub mod ndjson;
+pub mod notify;
+pub mod oauth2;
+pub mod output;
+pub mod pager;
+pub mod paginate;
+pub mod pipe;
+pub mod plugin;
+pub mod poll;
+pub mod preconnect;
+pub mod progress;
+pub mod prompt;
+pub mod protobuf;
+pub mod rate;
+pub mod redirect;
+pub mod request_item;
+pub mod resolve;
+pub mod retry;
+pub mod sse;
+pub mod state;
+pub mod table;
+pub mod template;
+pub mod timing;
+pub mod tls;
+pub mod trace;
+pub mod transaction;
+pub mod watch;
+pub mod wire;
+pub mod x509;
+pub mod xml;
+pub mod yaml;
+
+pub use body::Mode;
+pub use client::RurlRequestBuilder;
+pub use request_item::RequestItem;
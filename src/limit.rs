@@ -0,0 +1,206 @@
+//! `--max-body`: a cap on how much of a response is read, so a script can't
+//! pull a multi-gigabyte body into memory or onto disk by accident; and
+//! `--speed-limit`, giving up on a body that arrives too slowly. Bodies are
+//! also read no faster than `--limit-rate`.
+
+use crate::{cancel::CancellationToken, client, rate::Throttle};
+use anyhow::Result;
+use hyper::{
+    body::Bytes,
+    header::{self, HeaderMap},
+    Body, Response,
+};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LimitError {
+    #[error("invalid size {0}, expected bytes like 1048576, 512k, 10M, or 2G")]
+    SizeParseError(String),
+    #[error("the response body is larger than --max-body {0} bytes, stopped reading it")]
+    TooLarge(u64),
+    #[error("the response arrived slower than {0} bytes per second for {1}s, gave up on it")]
+    TooSlow(u64, u64),
+}
+
+impl LimitError {
+    /// curl's exit statuses for `--max-filesize` and timeouts, so scripts
+    /// can tell these apart from other failures.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::SizeParseError(_) => 1,
+            Self::TooLarge(_) => 63,
+            Self::TooSlow(_, _) => 28,
+        }
+    }
+}
+
+/// A byte count, with an optional `k`, `M`, or `G` suffix in powers of 1024.
+#[derive(Clone, Copy, Debug)]
+pub struct Size(pub u64);
+
+impl std::str::FromStr for Size {
+    type Err = LimitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, scale) = match s.char_indices().last() {
+            Some((at, 'k')) | Some((at, 'K')) => (&s[..at], 1 << 10),
+            Some((at, 'm')) | Some((at, 'M')) => (&s[..at], 1 << 20),
+            Some((at, 'g')) | Some((at, 'G')) => (&s[..at], 1 << 30),
+            _ => (s, 1),
+        };
+
+        digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|count| count.checked_mul(scale))
+            .map(Self)
+            .ok_or_else(|| LimitError::SizeParseError(s.into()))
+    }
+}
+
+/// Fail when fewer than `bytes_per_second` arrive over `time`.
+#[derive(Clone, Copy, Debug)]
+pub struct LowSpeed {
+    pub bytes_per_second: u64,
+    pub time: Duration,
+}
+
+/// Counts a response's bytes as they are read, failing once they pass the
+/// limit, or straight away when `Content-Length` already does.
+#[derive(Debug)]
+pub struct BodyLimit {
+    max: Option<u64>,
+    read: u64,
+    low_speed: Option<LowSpeed>,
+    /// When the current low speed window opened, and what it has read.
+    window: (Instant, u64),
+    throttle: Option<Throttle>,
+}
+
+impl BodyLimit {
+    pub fn new(
+        max: Option<Size>,
+        low_speed: Option<LowSpeed>,
+        rate: Option<Size>,
+        headers: &HeaderMap,
+    ) -> Result<Self, LimitError> {
+        let max = max.map(|size| size.0);
+
+        let length = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+
+        match (max, length) {
+            (Some(max), Some(length)) if length > max => Err(LimitError::TooLarge(max)),
+            _ => Ok(Self {
+                max,
+                read: 0,
+                low_speed,
+                window: (Instant::now(), 0),
+                throttle: rate.map(|rate| Throttle::new(rate.0)),
+            }),
+        }
+    }
+
+    fn check(&mut self, chunk: &[u8]) -> Result<(), LimitError> {
+        self.read += chunk.len() as u64;
+        self.window.1 += chunk.len() as u64;
+
+        match self.max {
+            Some(max) if self.read > max => Err(LimitError::TooLarge(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Once a window has passed, fails if it was too slow, and otherwise
+    /// opens the next one.
+    fn check_speed(&mut self, low_speed: LowSpeed) -> Result<(), LimitError> {
+        let (start, bytes) = self.window;
+        let elapsed = start.elapsed();
+
+        if elapsed < low_speed.time {
+            return Ok(());
+        }
+
+        if (bytes as f64) < low_speed.bytes_per_second as f64 * elapsed.as_secs_f64() {
+            return Err(LimitError::TooSlow(
+                low_speed.bytes_per_second,
+                low_speed.time.as_secs(),
+            ));
+        }
+
+        self.window = (Instant::now(), 0);
+
+        Ok(())
+    }
+
+    /// The next chunk of `res`'s body, like `client::next_chunk`, checked
+    /// against the limits. A stalled body fails once its window is up.
+    pub async fn next_chunk(
+        &mut self,
+        res: &mut Response<Body>,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Bytes>> {
+        let chunk = self.checked_chunk(res, cancel).await?;
+
+        if let (Some(throttle), Some(chunk)) = (&mut self.throttle, &chunk) {
+            throttle.pass(chunk.len()).await;
+        }
+
+        Ok(chunk)
+    }
+
+    async fn checked_chunk(
+        &mut self,
+        res: &mut Response<Body>,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Bytes>> {
+        let low_speed = match self.low_speed {
+            Some(low_speed) => low_speed,
+            None => {
+                let chunk = client::next_chunk(res, cancel).await?;
+                if let Some(chunk) = &chunk {
+                    self.check(chunk)?;
+                }
+
+                return Ok(chunk);
+            }
+        };
+
+        loop {
+            let left = low_speed
+                .time
+                .checked_sub(self.window.0.elapsed())
+                .unwrap_or_default();
+
+            match tokio::time::timeout(left, client::next_chunk(res, cancel)).await {
+                Ok(chunk) => {
+                    let chunk = chunk?;
+                    if let Some(chunk) = &chunk {
+                        self.check(chunk)?;
+                        self.check_speed(low_speed)?;
+                    }
+
+                    return Ok(chunk);
+                }
+                Err(_) => self.check_speed(low_speed)?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!("1048576".parse::<Size>().unwrap().0, 1 << 20);
+        assert_eq!("512k".parse::<Size>().unwrap().0, 512 << 10);
+        assert_eq!("2G".parse::<Size>().unwrap().0, 2 << 30);
+        assert!("10MB".parse::<Size>().is_err());
+        assert!("M".parse::<Size>().is_err());
+    }
+}
@@ -0,0 +1,99 @@
+fn normalize_tag(tag: &str) -> Option<String> {
+    // `en_US.UTF-8@euro` -> `en-US`
+    let tag = tag.split(['.', '@']).next()?.replace('_', "-");
+
+    match tag.as_str() {
+        "" | "C" | "POSIX" => None,
+        _ => Some(tag),
+    }
+}
+
+pub fn from_env() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| normalize_tag(&value))
+}
+
+pub fn accept_language(languages: &str) -> String {
+    // explicit q-values mean the list is already a complete header value
+    if languages.contains(';') {
+        return languages
+            .split(',')
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(", ");
+    }
+
+    let mut tags: Vec<String> = Vec::new();
+
+    for tag in languages
+        .split(',')
+        .filter_map(|tag| normalize_tag(tag.trim()))
+    {
+        let primary = tag.split('-').next().unwrap_or_default().to_owned();
+
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+
+        // fall back to the bare language for region-specific tags
+        if !tags.contains(&primary) {
+            tags.push(primary);
+        }
+    }
+
+    tags.iter()
+        .enumerate()
+        .map(|(i, tag)| match i {
+            0 => tag.clone(),
+            // q-values have at most three decimals and must stay above zero
+            _ => format!("{};q={:.1}", tag, (10 - i.min(9)) as f32 / 10.0),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_posix_locales_to_language_tags() {
+        assert_eq!(normalize_tag("en_US.UTF-8@euro").as_deref(), Some("en-US"));
+        assert_eq!(normalize_tag("de_DE").as_deref(), Some("de-DE"));
+        assert_eq!(normalize_tag("fr").as_deref(), Some("fr"));
+        assert_eq!(normalize_tag("C.UTF-8"), None);
+        assert_eq!(normalize_tag("POSIX"), None);
+        assert_eq!(normalize_tag(""), None);
+    }
+
+    #[test]
+    fn falls_back_to_the_bare_language_with_falling_q_values() {
+        assert_eq!(accept_language("fr"), "fr");
+        assert_eq!(accept_language("en_GB.UTF-8"), "en-GB, en;q=0.9");
+        assert_eq!(
+            accept_language("en-US, de-AT,en"),
+            "en-US, en;q=0.9, de-AT;q=0.8, de;q=0.7"
+        );
+        assert_eq!(accept_language("C, nl"), "nl");
+    }
+
+    #[test]
+    fn q_values_stay_above_zero() {
+        let languages = (b'a'..=b'l')
+            .map(|c| format!("{}{}", c as char, c as char))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let header = accept_language(&languages);
+        assert!(header.ends_with("kk;q=0.1, ll;q=0.1"), "{}", header);
+        assert!(!header.contains("q=0.0"));
+    }
+
+    #[test]
+    fn takes_explicit_q_values_as_they_are() {
+        assert_eq!(accept_language("en-GB,fr;q=0.8"), "en-GB, fr;q=0.8");
+    }
+}
@@ -1,420 +1,1405 @@
 use anyhow::Result;
-use hyper::{
-    body::HttpBody as _,
-    header::{HeaderName, HeaderValue},
-    Body, Client, Method, Request, Uri,
+use hyper::{Method, Uri};
+use rurl::{
+    auth::{self, Auth, AuthType},
+    aws::AwsOptions,
+    bench::Bench,
+    body::{self, Mode},
+    cancel::{CancellationToken, CancelledError},
+    client::{self, ConnectOptions, Connector, RurlRequestBuilder},
+    clipboard,
+    config::{Config, ConfigError},
+    cors::Preflight,
+    dav::{self, DavKind},
+    diff,
+    dns::DnsServers,
+    download::Checksum,
+    exchange::{self, Exchange},
+    expect::{ExpectHeader, ExpectStatus, Expectations},
+    form, generate, highlight, history,
+    hmac::{HmacAlgorithm, HmacOptions},
+    limit::{LimitError, LowSpeed, Size},
+    locale,
+    multi::{self, Multi},
+    note,
+    notify::Notifier,
+    oauth2::{self, OAuth2Options},
+    output::{self, Format, HeaderLayout, KeyOrder},
+    pager,
+    paginate::Paginate,
+    pipe,
+    poll::{self, Condition, Poll},
+    progress, prompt,
+    protobuf::{Descriptors, Protobuf},
+    rate::{Rate, RateLimiter},
+    redirect::Redirects,
+    request_item::{self, RequestItem},
+    resolve::{self, Family, Hosts, ResolveOverride},
+    retry::{CircuitBreaker, RetryBudget},
+    template::{self, Template},
+    timing::{Recorder, Timings},
+    tls::{self, TlsOptions},
+    trace,
+    watch::{self, Watch},
+    wire,
 };
-use json::JsonValue;
-use std::str::FromStr as _;
+use std::sync::Arc;
 use structopt::StructOpt;
-use thiserror::Error;
 
-#[derive(Debug, Error)]
-enum ModeError {
-    #[error("Missing mode options")]
-    MissingMode,
+mod completions;
+mod doctor;
+mod fragment;
+mod help;
+mod replay;
+mod run;
+mod var;
+mod version;
+
+lazy_static::lazy_static! {
+    static ref AFTER_HELP: String = format!("{}\n\n{}", help::REQUEST_ITEMS, help::EXAMPLES);
+    static ref SCHEME: regex::Regex = regex::Regex::new("^[A-Za-z][A-Za-z0-9+.-]*://").unwrap();
 }
 
-#[derive(Debug)]
-enum Mode {
-    Form,
-    Json,
-}
+#[derive(Debug, structopt::StructOpt)]
+#[structopt(after_help = AFTER_HELP.as_str())]
+struct Opt {
+    // the body mode flags are only read by clap, to set body_mode's default
+    #[allow(dead_code)]
+    #[structopt(short, long, conflicts_with_all = &["json", "graphql", "xml", "yaml", "msgpack"])]
+    form: bool,
 
-impl Default for Mode {
-    fn default() -> Self {
-        Self::Json
-    }
-}
+    #[allow(dead_code)]
+    #[structopt(short, long, conflicts_with_all = &["form", "graphql", "xml", "yaml", "msgpack"])]
+    json: bool,
 
-impl std::str::FromStr for Mode {
-    type Err = ModeError;
+    /// Ask for this media type instead of the default: application/json with --json, otherwise */*
+    #[structopt(long, value_name = "MEDIA_TYPE")]
+    accept: Option<hyper::header::HeaderValue>,
+
+    /// With --json, send no body when there is no data, instead of {}
+    #[structopt(long)]
+    empty_body: bool,
+
+    /// Send a GraphQL request; data items other than query and operationName become variables,
+    /// merged into a whole `variables:=` or `vars:=` object if one is given
+    #[allow(dead_code)]
+    #[structopt(short, long, conflicts_with_all = &["form", "json", "xml", "yaml", "msgpack"])]
+    graphql: bool,
+
+    /// Send data items as an XML document under a <root> element
+    #[allow(dead_code)]
+    #[structopt(long, conflicts_with_all = &["form", "json", "graphql", "yaml", "msgpack"])]
+    xml: bool,
+
+    /// Send data items as a YAML document
+    #[allow(dead_code)]
+    #[structopt(long, conflicts_with_all = &["form", "json", "graphql", "xml", "msgpack"])]
+    yaml: bool,
+
+    /// Send data items as MessagePack; MessagePack responses are shown as JSON
+    #[allow(dead_code)]
+    #[structopt(long, conflicts_with_all = &["form", "json", "graphql", "xml", "yaml"])]
+    msgpack: bool,
+
+    /// Send data items as a protobuf message from a descriptor set built with
+    /// `protoc --include_imports --descriptor_set_out`; protobuf responses are shown as JSON
+    #[structopt(
+        long,
+        value_name = "DESCRIPTOR",
+        conflicts_with_all = &["form", "json", "graphql", "xml", "yaml", "msgpack"]
+    )]
+    proto: Option<std::path::PathBuf>,
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "form" => Ok(Self::Form),
-            "json" => Ok(Self::Json),
-            _ => Err(Self::Err::MissingMode),
-        }
-    }
-}
+    /// The --proto request message, e.g. users.CreateUser (defaults to the input of the
+    /// method the URI path calls, as in /twirp/users.Users/Create)
+    #[structopt(long, value_name = "MESSAGE", requires = "proto")]
+    proto_message: Option<String>,
 
-#[derive(Debug)]
-enum RequestItem {
-    Data {
-        key: String,
-        value: String,
-    },
-    FormFile {
-        key: String,
-        value: std::path::PathBuf,
-    },
-    Header {
-        key: HeaderName,
-        value: HeaderValue,
-    },
-    JsonData {
-        key: String,
-        value: JsonValue,
-    },
-    SearchParam {
-        key: String,
-        value: String,
-    },
-}
+    /// Send --proto messages in gRPC-web frames rather than bare, as Twirp takes them
+    #[structopt(long, requires = "proto")]
+    grpc_web: bool,
 
-#[derive(Debug, Error)]
-enum RequestItemError {
-    #[error("could not parse request item {0}")]
-    ParseError(String),
-    #[error("unknown request item variant {0}")]
-    VariantParseError(String),
-    #[error("missing file input {0}")]
-    MissingFileInputError(String),
-    #[error("could not read file {0}")]
-    IOError(String),
-}
+    #[structopt(
+        short,
+        long,
+        hidden(true),
+        default_value_if("form", None, "form"),
+        default_value_if("graphql", None, "graphql"),
+        default_value_if("json", None, "json"),
+        default_value_if("xml", None, "xml"),
+        default_value_if("yaml", None, "yaml"),
+        default_value_if("msgpack", None, "msgpack")
+    )]
+    mode: Option<Mode>,
 
-impl std::str::FromStr for RequestItem {
-    type Err = RequestItemError;
+    /// Print DNS, connect, TLS, first-byte, and total timings after the response
+    #[structopt(long)]
+    meta: bool,
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use onig::*;
+    /// Print extra diagnostics; combine with --version for build information
+    #[structopt(short, long)]
+    verbose: bool,
 
-        lazy_static::lazy_static! {
-            static ref RE: Regex = Regex::new(r"(?x)
-                (?<name>.+?)
-                (?<!\\)
-                (?<sep>==|:=@?|=@?|:|@)
-                (?<value>.*)
-            ").unwrap();
-        }
+    /// Hide the upload progress bar shown for large bodies
+    #[structopt(short, long)]
+    quiet: bool,
 
-        match RE.captures(s) {
-            Some(captures) => {
-                let key: String = captures.at(1).unwrap().into();
-                let mut value: String = captures.at(3).unwrap().into();
-
-                let request_item = {
-                    let mut variant: String = captures.at(2).unwrap().into();
-
-                    if variant.len() > 1 && variant.ends_with("@") {
-                        use std::io::Read;
-
-                        if value.len() == 0 {
-                            return Err(Self::Err::MissingFileInputError(value));
-                        }
-
-                        let mut file = std::fs::File::open(value.clone())
-                            .or(Err(Self::Err::IOError(value.clone())))?;
-
-                        let mut buf = String::new();
-                        file.read_to_string(&mut buf)
-                            .or(Err(Self::Err::IOError(value.clone())))?;
-
-                        value = buf;
-                        variant = variant.replace("@", "");
-                    }
-
-                    match variant.as_str() {
-                        "=" => Self::Data { key, value },
-                        "@" => Self::FormFile {
-                            key,
-                            value: value.parse().or(Err(Self::Err::ParseError(s.into())))?,
-                        },
-                        ":" => Self::Header {
-                            key: key.parse().or(Err(Self::Err::ParseError(s.into())))?,
-                            value: value.parse().or(Err(Self::Err::ParseError(s.into())))?,
-                        },
-                        ":=" => Self::JsonData {
-                            key,
-                            value: json::parse(&value).or(Err(Self::Err::ParseError(s.into())))?,
-                        },
-                        "==" => Self::SearchParam { key, value },
-                        _ => return Err(Self::Err::VariantParseError(variant.into())),
-                    }
-                };
-
-                Ok(request_item)
-            }
-            None => Err(Self::Err::ParseError(s.into())),
-        }
-    }
-}
+    /// Print the response as a single JSON document for piping into another rurl
+    #[structopt(long)]
+    json_output: bool,
 
-#[derive(Debug, structopt::StructOpt)]
-struct Opt {
-    #[structopt(short, long, conflicts_with = "json")]
-    form: bool,
+    /// Print request and response bodies as offset, hex, and ASCII columns
+    #[structopt(long, conflicts_with_all = &["json-output", "filter"])]
+    hexdump: bool,
 
-    #[structopt(short, long, conflicts_with = "form")]
-    json: bool,
+    /// Show the response body as the rurl-format-NAME plugin prints it
+    #[structopt(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = &["hexdump", "json-output", "format", "table", "filter"]
+    )]
+    formatter: Option<String>,
+
+    /// Preview image responses in the terminal, with kitty or iTerm2 graphics where available
+    /// and ImageMagick block art otherwise
+    #[structopt(long, conflicts_with_all = &["json-output", "hexdump"])]
+    render_images: bool,
 
+    /// `text` (the default), or `json` to print the request, response, and timings as one
+    /// JSON document
+    #[structopt(
+        long,
+        value_name = "FORMAT",
+        conflicts_with_all = &["json-output", "output"]
+    )]
+    format: Option<Format>,
+
+    /// Print only the parts of a JSON response matched by a JSONPath, e.g. `.[0].email`
+    #[structopt(long, value_name = "EXPR")]
+    filter: Option<String>,
+
+    /// Show a JSON array of objects as a table; CSV responses are tabled whenever stdout is a
+    /// terminal
+    #[structopt(long, conflicts_with_all = &["hexdump", "json-output", "format"])]
+    table: bool,
+
+    /// Base64-decode these comma-separated JSONPaths in the displayed response
+    #[structopt(long, value_name = "PATHS")]
+    decode_fields: Option<String>,
+
+    /// Send the body of a --json-output document as the request body ("-" reads stdin)
+    #[structopt(long)]
+    from_json: Option<String>,
+
+    /// Send an example body generated from SCHEMA[:TYPE], a .proto, .avsc, or JSON Schema file
+    #[structopt(long, value_name = "SCHEMA[:TYPE]")]
+    gen_body: Option<String>,
+
+    /// Fill --gen-body with random values instead of fixed placeholders
+    #[structopt(long, requires = "gen-body")]
+    random: bool,
+
+    /// Print the request, headers, and timings to stdout along with the body, instead of stderr
+    #[structopt(long)]
+    all_to_stdout: bool,
+
+    /// Print DNS answers, connections, TLS sessions, retries, redirect hops, and the protocol used as `*` lines
+    #[structopt(long)]
+    trace: bool,
+
+    /// Sort JSON keys as well as headers
+    #[structopt(long, conflicts_with = "unsorted")]
+    sorted: bool,
+
+    /// Print headers and JSON keys in the order they were sent (HTTP/2 heads group repeated headers)
+    #[structopt(long)]
+    unsorted: bool,
+
+    /// Never pipe long responses through $PAGER
+    #[structopt(long)]
+    no_pager: bool,
+
+    /// Write the response body to a file instead of the terminal
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<std::path::PathBuf>,
+
+    /// Keep a compressed response as sent, e.g. to archive the exact bytes with --output
+    #[structopt(long)]
+    no_decompress: bool,
+
+    /// Verify the --output file against this checksum, deleting it on a mismatch
+    #[structopt(long, value_name = "sha256:HEX", requires = "output")]
+    checksum: Option<Checksum>,
+
+    /// Unpack a tar, tar.gz, or zip --output file into this directory
+    #[structopt(long, value_name = "DIR", requires = "output", parse(from_os_str))]
+    extract: Option<std::path::PathBuf>,
+
+    /// Send a desktop notification when a watched status changes or --expect-* starts failing, or a stream drops
+    #[structopt(long)]
+    notify: bool,
+
+    /// Ring the terminal bell on the same events as --notify
+    #[structopt(long)]
+    bell: bool,
+
+    /// Disconnect from an event stream after this many idle seconds
+    #[structopt(long)]
+    sse_timeout: Option<u64>,
+
+    /// Stop reading a response larger than this, e.g. 10M, and exit with status 63
+    #[structopt(long, value_name = "NBYTES")]
+    max_body: Option<Size>,
+
+    /// Copy the response bytes as received, head and undecoded body, to FILE ("-" for stdout)
+    #[structopt(long, value_name = "FILE", parse(from_os_str))]
+    raw_response: Option<std::path::PathBuf>,
+
+    /// Give up on a response arriving slower than this many bytes per second, e.g. 1k
+    #[structopt(long, value_name = "BYTES")]
+    speed_limit: Option<Size>,
+
+    /// How many seconds a response may stay under --speed-limit (default 30)
+    #[structopt(long, value_name = "SECONDS")]
+    speed_time: Option<u64>,
+
+    /// Send and receive bodies no faster than this many bytes per second, e.g. 500k
+    #[structopt(long, value_name = "BYTES")]
+    limit_rate: Option<Size>,
+
+    /// Print bodies larger than this many bytes unformatted ("none" disables the limit)
+    #[structopt(long, default_value = "1048576")]
+    pretty_max_size: String,
+
+    /// Print bodies larger than this many bytes without colors ("none" disables the limit)
+    #[structopt(long, default_value = "262144")]
+    highlight_max_bytes: String,
+
+    /// Wrap long header values at the terminal width
+    #[structopt(long)]
+    wrap: bool,
+
+    /// Cut header values after this many characters
+    #[structopt(long, value_name = "N", conflicts_with = "wrap")]
+    truncate_headers: Option<usize>,
+
+    /// Print header values in full, overriding --wrap and --truncate-headers
+    #[structopt(long)]
+    full: bool,
+
+    /// Show the request target with scheme and host, as sent to a proxy
+    #[structopt(long)]
+    print_full_url: bool,
+
+    /// Send the path exactly as typed, keeping `.` and `..` segments
+    #[structopt(long)]
+    path_as_is: bool,
+
+    /// Report on compression, cacheability, and framing of the response
+    #[structopt(long)]
+    analyze: bool,
+
+    /// Normalize volatile output (dates, UUIDs, timestamps) for snapshot testing, and send a fixed
+    /// multipart boundary
+    #[structopt(long)]
+    deterministic: bool,
+
+    /// Accept-Language shorthand, e.g. `en-GB,fr;q=0.8` (defaults to the config's "language")
+    #[structopt(long, conflicts_with = "locale")]
+    language: Option<String>,
+
+    /// Send Accept-Language derived from LC_ALL, LC_MESSAGES, or LANG
+    #[structopt(long)]
+    locale: bool,
+
+    /// Send Basic credentials, prompting for the password when it is left out
     #[structopt(
         short,
         long,
-        hidden(true),
-        default_value_if("form", None, "form"),
-        default_value_if("json", None, "json")
+        value_name = "USER[:PASSWORD]",
+        conflicts_with = "oauth2-token-url"
     )]
-    mode: Option<Mode>,
+    auth: Option<String>,
+
+    /// Authenticate the request: aws4 signs it with AWS Signature Version 4, hmac with --hmac-key,
+    /// and any other NAME runs the rurl-auth-NAME plugin
+    #[structopt(long, value_name = "TYPE")]
+    auth_type: Option<AuthType>,
+
+    /// AWS profile from ~/.aws/credentials (defaults to AWS_PROFILE or the environment's keys)
+    #[structopt(long, requires = "auth-type")]
+    aws_profile: Option<String>,
+
+    /// AWS region to sign for (defaults to AWS_REGION or the host name)
+    #[structopt(long, requires = "auth-type")]
+    aws_region: Option<String>,
+
+    /// AWS service to sign for, e.g. s3 (defaults to the host name)
+    #[structopt(long, requires = "auth-type")]
+    aws_service: Option<String>,
+
+    /// Shared key to sign with for --auth-type hmac
+    #[structopt(long, value_name = "KEY", requires = "auth-type")]
+    hmac_key: Option<String>,
+
+    /// HMAC algorithm for --auth-type hmac (sha256)
+    #[structopt(long, value_name = "ALGORITHM", requires = "auth-type")]
+    hmac_algo: Option<HmacAlgorithm>,
+
+    /// Header to send the signature in, with {signature}, {signature_base64}, {date}, and {body_hash}
+    #[structopt(long, value_name = "NAME: VALUE", requires = "auth-type")]
+    hmac_header: Option<String>,
+
+    /// Fetch a client-credentials token from this endpoint and send it as a Bearer token
+    #[structopt(
+        long,
+        value_name = "URL",
+        requires_all = &["oauth2-client-id", "oauth2-client-secret"]
+    )]
+    oauth2_token_url: Option<Uri>,
+
+    /// OAuth2 client ID for --oauth2-token-url
+    #[structopt(long, requires = "oauth2-token-url")]
+    oauth2_client_id: Option<String>,
+
+    /// OAuth2 client secret for --oauth2-token-url
+    #[structopt(long, requires = "oauth2-token-url")]
+    oauth2_client_secret: Option<String>,
+
+    /// Space-separated scopes to request with --oauth2-token-url
+    #[structopt(long, requires = "oauth2-token-url")]
+    oauth2_scope: Option<String>,
+
+    /// Send a CORS preflight with OPTIONS: origin=URL[,method=METHOD][,headers=NAME,...]
+    #[structopt(long, value_name = "origin=URL,...")]
+    preflight: Option<Preflight>,
+
+    /// Keep GET responses that have an ETag or Last-Modified in DIR, and answer a 304 to
+    /// the next identical request with the kept response
+    #[structopt(long, value_name = "DIR", parse(from_os_str))]
+    cache_dir: Option<std::path::PathBuf>,
+
+    /// Send If-Match; `auto` fetches the resource's current ETag first
+    #[structopt(long, value_name = "ETAG|auto")]
+    if_match: Option<String>,
+
+    /// Also print the equivalent curl command before sending
+    #[structopt(long)]
+    curl: bool,
+
+    /// Print the equivalent curl command instead of sending the request
+    #[structopt(long)]
+    curl_only: bool,
+
+    /// Also put the response body on the system clipboard
+    #[structopt(long, conflicts_with_all = &["output", "url", "url-file", "diff", "watch"])]
+    copy: bool,
+
+    /// Expand `$NAME` and `${NAME}` from the environment in every argument, even inside single
+    /// quotes
+    // read from the raw arguments, which are expanded before parsing
+    #[allow(dead_code)]
+    #[structopt(long)]
+    expand_env: bool,
+
+    /// Open the body in $VISUAL or $EDITOR and send what is saved, or nothing if it's emptied
+    #[structopt(long, conflicts_with_all = &["url", "url-file", "diff", "watch"])]
+    edit: bool,
+
+    /// Ask these DNS servers instead of the system resolver, e.g. 1.1.1.1,8.8.8.8
+    #[structopt(long, value_name = "ADDRESS[,ADDRESS...]")]
+    dns_servers: Option<DnsServers>,
+
+    /// Resolve names with this DNS-over-HTTPS server, e.g. https://cloudflare-dns.com/dns-query
+    #[structopt(long, value_name = "URL", conflicts_with = "dns-servers")]
+    doh_url: Option<Uri>,
+
+    /// Resolve names from this /etc/hosts-style file before asking system DNS
+    #[structopt(long, parse(from_os_str))]
+    hosts_file: Option<std::path::PathBuf>,
+
+    /// Connect to ADDRESS for HOST instead of asking DNS, like curl's --resolve
+    #[structopt(long, value_name = "HOST:PORT:ADDRESS", number_of_values = 1)]
+    resolve: Vec<ResolveOverride>,
+
+    /// Only connect to IPv4 addresses
+    #[structopt(short = "4", long, conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Only connect to IPv6 addresses
+    #[structopt(short = "6", long)]
+    ipv6: bool,
+
+    /// Send from this network interface, by device name or address
+    #[structopt(long, value_name = "NAME|ADDRESS", conflicts_with = "local-addr")]
+    interface: Option<String>,
+
+    /// Bind the outgoing connection to this local address
+    #[structopt(long, value_name = "ADDRESS")]
+    local_addr: Option<std::net::IpAddr>,
+
+    /// Resolve and connect before building the request, reporting setup time separately
+    #[structopt(long)]
+    preconnect: bool,
+
+    /// Append TLS session keys to this file for Wireshark (defaults to SSLKEYLOGFILE)
+    #[structopt(long, value_name = "FILE", parse(from_os_str))]
+    keylog_file: Option<std::path::PathBuf>,
+
+    /// Print the negotiated TLS version, cipher, ALPN, and certificate
+    #[structopt(long)]
+    tls_info: bool,
+
+    /// Fail unless the server's public key matches sha256//BASE64[;...] or a PEM or DER key file
+    #[structopt(long, value_name = "HASHES|FILE")]
+    pinnedpubkey: Option<String>,
+
+    /// Run DNS, TCP, TLS, and proxy checks when the connection fails
+    #[structopt(long)]
+    diagnose: bool,
+
+    /// Generate a WebDAV body: propfind, calendar-query, addressbook-query, or sync-collection
+    #[structopt(long, value_name = "KIND")]
+    dav: Option<DavKind>,
+
+    /// Limit a calendar-query to START/END, e.g. 20240101T000000Z/20240201T000000Z
+    #[structopt(long, requires = "dav")]
+    time_range: Option<String>,
+
+    /// Continue a sync-collection from the token of a previous response
+    #[structopt(long, requires = "dav")]
+    sync_token: Option<String>,
+
+    /// GET the page at URI, then submit its form matching #id, .class, index, or name,
+    /// keeping hidden fields and overriding them with data items
+    #[structopt(long, value_name = "SELECTOR")]
+    fill_form: Option<String>,
+
+    /// Send the same request to this URI as well, and show how the two responses differ
+    #[structopt(
+        long,
+        value_name = "URI",
+        conflicts_with_all = &["url", "url-file", "format", "output", "paginate", "until"]
+    )]
+    diff: Option<String>,
+
+    /// Re-send the request at this interval, e.g. 2 or 500ms, showing the latest response and
+    /// what changed since the one before
+    #[structopt(
+        long,
+        value_name = "SECONDS",
+        parse(try_from_str = poll::parse_duration),
+        conflicts_with_all = &["url", "url-file", "format", "output", "paginate", "until", "diff"]
+    )]
+    watch: Option<std::time::Duration>,
+
+    /// Also send the same request to this URI, concurrently (repeatable)
+    #[structopt(long = "url", name = "url", number_of_values = 1)]
+    urls: Vec<String>,
+
+    /// Also send the same request to every URI in this file, one per line
+    #[structopt(long, parse(from_os_str))]
+    url_file: Option<std::path::PathBuf>,
+
+    /// With multiple URIs, write each response body to a file in this directory
+    #[structopt(long, parse(from_os_str))]
+    output_dir: Option<std::path::PathBuf>,
+
+    /// Follow redirects, printing the chain of hops
+    #[structopt(short = "F", long)]
+    follow: bool,
+
+    /// Give up after this many redirects with --follow
+    #[structopt(long, default_value = "30")]
+    max_redirects: usize,
+
+    /// Follow redirects from https to http instead of failing
+    #[structopt(long)]
+    allow_insecure_redirect: bool,
+
+    /// Re-send the request until this JSONPath condition holds, e.g. `$.status == "ready"`
+    #[structopt(long, value_name = "CONDITION")]
+    until: Option<Condition>,
+
+    /// Fetch the following pages, found through `Link: <...>; rel="next"` headers, and show
+    /// them as one body: JSON arrays are joined into one array
+    #[structopt(long, conflicts_with_all = &["output", "until", "format"])]
+    paginate: bool,
+
+    /// Find each next page's URI at this JSONPath in the body instead, e.g. `.next_url`
+    #[structopt(long, value_name = "EXPR", requires = "paginate")]
+    next_jsonpath: Option<String>,
+
+    /// Stop --paginate after this many pages
+    #[structopt(long, default_value = "100")]
+    max_pages: usize,
+
+    /// Time between --until attempts, e.g. 500ms, 2s, 1m
+    #[structopt(long, default_value = "2s", parse(try_from_str = poll::parse_duration))]
+    poll_interval: std::time::Duration,
+
+    /// Give up on --until after this long
+    #[structopt(long, default_value = "5m", parse(try_from_str = poll::parse_duration))]
+    poll_timeout: std::time::Duration,
+
+    /// Exit non-zero unless the status matches, e.g. 200, 2xx, or 200,204
+    #[structopt(long, value_name = "STATUS", number_of_values = 1)]
+    expect_status: Vec<ExpectStatus>,
+
+    /// Exit non-zero unless a header equals `Name:value`, matches `Name~regex`, or is present
+    #[structopt(long, value_name = "HEADER", number_of_values = 1)]
+    expect_header: Vec<ExpectHeader>,
+
+    /// Exit non-zero unless a JSONPath condition holds for the body, e.g. `$.id == 7`
+    #[structopt(long, value_name = "CONDITION", number_of_values = 1)]
+    expect_body_json: Vec<Condition>,
+
+    /// Close idle pooled connections after this long, e.g. 500ms, 30s
+    #[structopt(long, default_value = "90s", parse(try_from_str = poll::parse_duration))]
+    keepalive_timeout: std::time::Duration,
+
+    /// Open a fresh connection for every request instead of reusing pooled ones
+    #[structopt(long)]
+    no_keepalive: bool,
+
+    /// Speak HTTP/2 from the first byte (h2c), to servers known to support it
+    #[structopt(long)]
+    http2_prior_knowledge: bool,
+
+    /// Send a trailer after the body as `Name:value`; trailers only go over HTTP/2
+    #[structopt(
+        long,
+        value_name = "TRAILER",
+        number_of_values = 1,
+        parse(try_from_str = parse_trailer),
+        requires = "http2-prior-knowledge"
+    )]
+    trailer: Vec<(hyper::header::HeaderName, hyper::header::HeaderValue)>,
+
+    /// Record this request for `rurl history` and `rurl replay`, with credentials redacted
+    #[structopt(long)]
+    history: bool,
+
+    /// Send the request this many times and report latency percentiles
+    #[structopt(long, default_value = "1")]
+    repeat: usize,
+
+    /// Number of requests in flight at once with --repeat or multiple URIs
+    #[structopt(long, default_value = "1")]
+    concurrency: usize,
+
+    /// Start at most this many requests with --repeat or multiple URIs, e.g. `10/s` or `300/m`
+    #[structopt(long)]
+    rate: Option<Rate>,
+
+    /// Total number of retries shared by every request in the run, after 5xx responses and
+    /// connection errors, waiting longer each time; only idempotent methods are retried
+    #[structopt(long, default_value = "0")]
+    retries: usize,
+
+    /// Also retry POST, PATCH, and other methods that may not be safe to send twice
+    #[structopt(long, requires = "retries")]
+    retry_all_methods: bool,
+
+    /// Consecutive failures after which a host is no longer contacted (0 disables)
+    #[structopt(long, default_value = "5")]
+    breaker_threshold: usize,
 
-    #[structopt(name = "METHOD")]
+    /// Seconds a host is skipped once its circuit opens
+    #[structopt(long, default_value = "30")]
+    breaker_cooldown: u64,
+
+    /// GET, POST, or any other token, such as PURGE or MKCOL; lowercase is uppercased
+    #[structopt(name = "METHOD", parse(try_from_str = parse_method))]
     method: Method,
 
+    /// The method, given after the URI instead: `rurl example.com -X PUT`
+    // method_first moves it into place before parsing
+    #[allow(dead_code)]
+    #[structopt(short = "X", long = "method", value_name = "METHOD")]
+    method_flag: Option<String>,
+
     #[structopt(name = "URI")]
-    uri: Uri,
+    uri: String,
+
+    /// The scheme for a URI given without one (defaults to the config's "default_scheme",
+    /// then http; https under the rurls name)
+    #[structopt(long, value_name = "SCHEME", possible_values = &["http", "https"])]
+    default_scheme: Option<String>,
+
+    /// Add the request items saved with `rurl fragment add NAME`
+    #[structopt(long, value_name = "NAME", number_of_values = 1)]
+    fragment: Vec<String>,
+
+    /// Fill `{{NAME}}` in the URI and request items; `{{var.NAME}}` and `{{env.NAME}}` need no flag
+    // Template reads it from the raw arguments
+    #[allow(dead_code)]
+    #[structopt(long, value_name = "NAME=VALUE", number_of_values = 1)]
+    var: Vec<String>,
 
     #[structopt(name = "REQUEST_ITEM")]
     request_items: Vec<RequestItem>,
 }
 
-fn highlight(input: &str, language: &str) -> String {
-    use syntect::{
-        easy::HighlightLines,
-        highlighting::{Style, ThemeSet},
-        parsing::{syntax_definition::SyntaxDefinition, SyntaxSet},
-        util::LinesWithEndings,
-    };
-
-    let mut ps = SyntaxSet::load_defaults_newlines().into_builder();
-    ps.add(
-        SyntaxDefinition::load_from_str(
-            include_str!("../syntaxes/http-response.sublime-syntax",),
-            true,
-            None,
-        )
-        .unwrap(),
-    );
+/// Methods are case-sensitive on the wire, but one typed in lowercase is
+/// meant as the standard one, so `get` sends GET.
+fn parse_method(method: &str) -> Result<Method, hyper::http::method::InvalidMethod> {
+    method.to_ascii_uppercase().parse()
+}
 
-    let ps = ps.build();
-    let ts = ThemeSet::load_defaults();
+fn parse_trailer(trailer: &str) -> Result<(hyper::header::HeaderName, hyper::header::HeaderValue)> {
+    match trailer.parse()? {
+        RequestItem::Header { key, value } => Ok((key, value)),
+        _ => anyhow::bail!("invalid --trailer {}, expected Name:value", trailer),
+    }
+}
 
-    let syntax = ps.find_syntax_by_extension(language).unwrap();
-    let mut higlighter = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
+/// Moves the value of `--method`/`-X` to the METHOD position, so
+/// `rurl example.com -X PUT` parses like `rurl PUT example.com`. A METHOD
+/// given as well, as in `rurl GET example.com -X PUT`, is an error: it is a
+/// METHOD when the argument after it is a URI rather than a request item.
+fn method_first(mut args: Vec<String>) -> Result<Vec<String>> {
+    let method = args
+        .iter()
+        .enumerate()
+        .skip(1)
+        .find_map(|(i, arg)| match arg.as_str() {
+            "--method" | "-X" => args.get(i + 1).cloned(),
+            arg => arg
+                .strip_prefix("--method=")
+                .or_else(|| arg.strip_prefix("-X").filter(|method| !method.is_empty()))
+                .map(str::to_owned),
+        });
+
+    let method = match method {
+        Some(method) => method,
+        None => return Ok(args),
+    };
 
-    let lines = LinesWithEndings::from(input);
+    if let Ok(matches) = Opt::clap().get_matches_from_safe(&args) {
+        if let Some(uri) = matches.value_of("URI") {
+            if !uri.starts_with('@')
+                && (SCHEME.is_match(uri) || request_item::tokenize(uri).is_none())
+            {
+                anyhow::bail!(
+                    "--method {} cannot be combined with a METHOD argument",
+                    method
+                );
+            }
+        }
+    }
 
-    lines
-        .map(|line| {
-            let ranges: Vec<(Style, &str)> = higlighter.highlight(line, &ps);
-            syntect::util::as_24_bit_terminal_escaped(&ranges[..], false)
-        })
-        .collect::<String>()
+    args.insert(1, method);
+    Ok(args)
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let opt = Opt::from_args();
+/// Turns `rurl diff METHOD URI [OTHER_URI] [ITEM...]` into `rurl METHOD URI
+/// [ITEM...] --diff OTHER_URI`, diffing URI against itself when no other is
+/// given. The argument after URI is the other one when it has a scheme or
+/// can't be a request item.
+fn diff_args(mut args: Vec<String>) -> Result<Vec<String>> {
+    const USAGE: &str = "usage: rurl diff METHOD URI [OTHER_URI] [ITEM...]";
 
-    println!("{:#?}\n", opt);
+    args.remove(1);
 
-    // build request
+    let uri = match args.get(2) {
+        Some(uri) if !uri.starts_with('-') => uri.clone(),
+        _ => anyhow::bail!(USAGE),
+    };
+    let other = match args.get(3) {
+        Some(other)
+            if !other.starts_with(&['-', '@'][..])
+                && (SCHEME.is_match(other) || request_item::tokenize(other).is_none()) =>
+        {
+            args.remove(3)
+        }
+        _ => uri,
+    };
 
-    let client = Client::new();
+    args.push("--diff".into());
+    args.push(other);
 
-    let mut req = Request::builder()
-        .method(opt.method)
-        .uri({
-            use hyper::http::uri::*;
+    Ok(args)
+}
 
-            let mut parts = opt.uri.into_parts();
+/// The scheme for URIs typed without one when --default-scheme is not given:
+/// `RURL_DEFAULT_SCHEME`, which the rurls binary sets to https, then the
+/// config's "default_scheme", then http.
+fn default_scheme(config: &Config) -> Result<String> {
+    let scheme = match std::env::var("RURL_DEFAULT_SCHEME") {
+        Ok(scheme) => scheme,
+        Err(_) => config
+            .get_str("default_scheme")
+            .unwrap_or("http")
+            .to_owned(),
+    };
 
-            match parts.scheme {
-                Some(_) => {}
-                None => parts.scheme = Some(Scheme::HTTP),
-            }
+    match scheme.as_str() {
+        "http" | "https" => Ok(scheme),
+        scheme => anyhow::bail!("default scheme must be http or https, not {}", scheme),
+    }
+}
 
-            match parts.path_and_query {
-                Some(_) => {}
-                None => parts.path_and_query = Some(PathAndQuery::from_str("/")?),
-            }
+/// A byte count, or "none" for no limit.
+fn parse_max_size(max_size: &str) -> Result<Option<usize>> {
+    match max_size {
+        "none" => Ok(None),
+        size => Ok(Some(size.parse()?)),
+    }
+}
 
-            Uri::from_parts(parts)?
-        })
-        .header("accept", mime::STAR_STAR.to_string())
-        .header(
-            "user-agent",
-            format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
-        );
+/// Connects through --hosts-file, --resolve, --interface, and the DNS and
+/// TLS options.
+fn connect(opt: &Opt, recorder: &Recorder) -> Result<Connector> {
+    let hosts = match &opt.hosts_file {
+        Some(path) => Hosts::load(path)?,
+        None => Hosts::default(),
+    }
+    .with_overrides(&opt.resolve);
+    let family = match (opt.ipv4, opt.ipv6) {
+        (true, _) => Some(Family::V4),
+        (_, true) => Some(Family::V6),
+        _ => None,
+    };
+    let local_address = match &opt.interface {
+        Some(interface) => Some(resolve::interface_address(interface, family)?),
+        None => opt.local_addr,
+    };
+    client::build_connector(
+        recorder,
+        ConnectOptions {
+            hosts,
+            family,
+            local_address,
+            dns_servers: opt.dns_servers.clone().unwrap_or_default().0,
+            doh_url: opt.doh_url.clone(),
+            tls: TlsOptions {
+                keylog_file: opt
+                    .keylog_file
+                    .clone()
+                    .or_else(|| std::env::var_os("SSLKEYLOGFILE").map(Into::into)),
+                http2: opt.http2_prior_knowledge,
+                pins: match &opt.pinnedpubkey {
+                    Some(pin) => tls::pins(pin)?,
+                    None => Vec::new(),
+                },
+            },
+        },
+    )
+}
 
-    // build request headers
+/// The descriptors for --proto, with the message types of the RPC the URI
+/// path names.
+fn load_proto(opt: &Opt, uri: &Uri) -> Result<Option<Arc<Protobuf>>> {
+    Ok(match &opt.proto {
+        Some(path) => {
+            let descriptors = Descriptors::load(path)?;
+            let method = descriptors.method(uri.path()).cloned();
+            let request = match (&opt.proto_message, &method) {
+                (Some(message), _) => message.clone(),
+                (None, Some(method)) => method.input.clone(),
+                (None, None) => anyhow::bail!(
+                    "{} calls no method in {}, name the message with --proto-message",
+                    uri.path(),
+                    path.display()
+                ),
+            };
+
+            Some(Arc::new(Protobuf {
+                descriptors,
+                request,
+                response: method.map(|method| method.output),
+                grpc_web: opt.grpc_web,
+            }))
+        }
+        None => None,
+    })
+}
 
-    for request_item in opt.request_items.iter() {
-        if let RequestItem::Header { key, value } = request_item {
-            req = req.header(key, value);
+/// The request items: those of --fragment first, so items on the command
+/// line override them, then any typed at a prompt, then --from-json and
+/// --gen-body.
+fn request_items(opt: &Opt, config: &Config, template: &Template) -> Result<Vec<RequestItem>> {
+    let mut request_items = Vec::new();
+    for name in &opt.fragment {
+        let items = config
+            .fragment(name)
+            .ok_or_else(|| ConfigError::UnknownFragment(name.clone()))?;
+
+        for item in items {
+            request_items.push(template.render(item)?.parse::<RequestItem>()?);
         }
     }
+    request_items.extend(opt.request_items.iter().cloned());
+    let mut request_items = request_item::resolve(request_items, prompt::secret)?;
+
+    if let Some(path) = &opt.from_json {
+        let document = match path.as_str() {
+            "-" => pipe::stdin_document()?.clone(),
+            path => pipe::read_document(path)?,
+        };
+
+        request_items.push(RequestItem::RawBody {
+            path: "from-json.json".into(),
+            value: pipe::body_of(&document).dump().into_bytes(),
+        });
+    }
 
-    // build request body
+    if let Some(spec) = &opt.gen_body {
+        request_items.push(RequestItem::RawBody {
+            path: "gen-body.json".into(),
+            value: generate::generate(spec, opt.random)?.dump().into_bytes(),
+        });
+    }
 
-    let body = {
-        match opt.mode {
-            Some(Mode::Json) | None => {
-                let body_items_len =
-                    opt.request_items
-                        .iter()
-                        .fold(0, |prev, request_item| match request_item {
-                            RequestItem::Data { key: _, value: _ } => prev + 1,
-                            RequestItem::JsonData { key: _, value: _ } => prev + 1,
-                            _ => prev,
-                        });
-
-                if body_items_len > 0 {
-                    let mut body = JsonValue::new_object();
-
-                    for request_item in opt.request_items {
-                        if let RequestItem::Data { key, value } = request_item {
-                            body[key] = json::parse(&json::stringify(value))?;
-                        } else if let RequestItem::JsonData { key, value } = request_item {
-                            body[key] = value;
-                        }
-                    }
-
-                    let body = body.dump();
-
-                    req = req
-                        .header("content-type", mime::APPLICATION_JSON.to_string())
-                        .header("content-length", body.len());
-
-                    body
-                } else {
-                    "".into()
-                }
-            }
-            Some(Mode::Form) => {
-                let mut body = String::new();
-
-                for request_item in opt.request_items {
-                    if let RequestItem::Data { key, value } = request_item {
-                        if !body.is_empty() {
-                            body.push('&');
-                        }
-
-                        body.push_str(&urlencoding::encode(&key));
-                        body.push('=');
-                        body.push_str(&urlencoding::encode(&value));
-                    } else if let RequestItem::JsonData { key, value } = request_item {
-                        if !body.is_empty() {
-                            body.push('&');
-                        }
-
-                        body.push_str(&urlencoding::encode(&key));
-                        body.push('=');
-                        body.push_str(&urlencoding::encode(&value.dump()));
-                    }
-                }
-
-                if body.len() > 0 {
-                    req = req
-                        .header(
-                            "content-type",
-                            mime::APPLICATION_WWW_FORM_URLENCODED_UTF_8.to_string(),
-                        )
-                        .header("content-length", body.len());
-                }
-
-                body
+    Ok(request_items)
+}
+
+/// Signing for --auth-type.
+fn auth(opt: &Opt) -> Auth {
+    Auth {
+        auth_type: opt.auth_type.clone(),
+        aws: AwsOptions {
+            profile: opt.aws_profile.clone(),
+            region: opt.aws_region.clone(),
+            service: opt.aws_service.clone(),
+        },
+        hmac: HmacOptions {
+            key: opt.hmac_key.clone(),
+            algorithm: opt.hmac_algo,
+            header: opt.hmac_header.clone(),
+        },
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // this is the copy of rurl left behind to serve a --copy
+    if clipboard::serve_if_asked() {
+        return Ok(());
+    }
+
+    match execute().await {
+        // scripts can tell a refused body apart from other failures
+        Err(err) => match err.downcast_ref::<LimitError>() {
+            Some(limit) => {
+                eprintln!("Error: {:?}", err);
+                std::process::exit(limit.exit_code());
             }
+            None => Err(err),
+        },
+        res => res,
+    }
+}
+
+async fn execute() -> Result<()> {
+    // clap exits on --version before any other flag is seen, and METHOD would
+    // otherwise swallow the `doctor`, `var`, `fragment`, `completions`, `run`,
+    // `history`, `replay`, and `diff` subcommands
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--version" || arg == "-V")
+        && args.iter().any(|arg| arg == "--verbose" || arg == "-v")
+    {
+        println!("{}", version::verbose());
+        return Ok(());
+    }
+
+    if args.get(1).map(String::as_str) == Some("doctor") {
+        if !doctor::run() {
+            std::process::exit(1);
         }
+
+        return Ok(());
+    }
+
+    if args.get(1).map(String::as_str) == Some("var") {
+        return var::run(&args[2..]).await;
+    }
+
+    if args.get(1).map(String::as_str) == Some("fragment") {
+        return fragment::run(&args[2..]).await;
+    }
+
+    if args.get(1).map(String::as_str) == Some("completions") {
+        return completions::run(Opt::clap(), &args[2..]);
+    }
+
+    if args.get(1).map(String::as_str) == Some("run") {
+        return run::run(&args[2..]).await;
+    }
+
+    if args.get(1).map(String::as_str) == Some("history") {
+        return replay::history(&args[2..]).await;
+    }
+
+    let args = match args.get(1).map(String::as_str) {
+        Some("replay") => replay::args(&args)?,
+        Some("diff") => diff_args(args)?,
+        _ => args,
     };
 
-    let req = req.body(Body::from(body.clone()))?;
+    // recorded before placeholders are filled, so replays pick up new values
+    let recorded_args = args[1..].to_vec();
+
+    let config = Config::load()?;
+    let template = Template::from_args(&config, &args)?;
+    let args = args
+        .iter()
+        .map(|arg| template.render(arg))
+        .collect::<Result<Vec<_>>>()?;
+
+    // after the placeholders, so values from the environment are taken as they are
+    let args = if args.iter().skip(1).any(|arg| arg == "--expand-env") {
+        args.iter()
+            .enumerate()
+            .map(|(i, arg)| match i {
+                0 => Ok(arg.clone()),
+                _ => template::expand_env(arg),
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        args
+    };
 
-    // print request
+    let opt = Opt::from_iter(method_first(args)?);
 
-    let mut request = format!(
-        "{} {} {:?}\n",
-        req.method(),
-        req.uri().path(),
-        req.version()
-    );
+    let history = if opt.history {
+        Some(recorded_args)
+    } else {
+        None
+    };
 
-    // print request headers
+    request(opt, &config, &template, history).await
+}
 
-    let mut headers: Vec<(&HeaderName, &HeaderValue)> = req.headers().iter().collect();
-    headers.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+/// Sends the request the options describe, once or in whichever mode they
+/// ask for.
+async fn request(
+    opt: Opt,
+    config: &Config,
+    template: &Template<'_>,
+    history: Option<Vec<String>>,
+) -> Result<()> {
+    output::set_all_to_stdout(opt.all_to_stdout);
+    if opt.trace {
+        trace::install();
+    }
+    progress::set_limit_rate(opt.limit_rate.map(|rate| rate.0));
+
+    // "-" stands in for the body on stdout, so only the raw copy is printed
+    let raw_to_stdout = opt.raw_response.as_deref() == Some(std::path::Path::new("-"));
+    match &opt.raw_response {
+        Some(_) if raw_to_stdout => wire::capture_to(Box::new(std::io::stdout())),
+        Some(path) => wire::capture_to(Box::new(std::fs::File::create(path)?)),
+        None => {}
+    }
+    highlight::set_max_bytes(parse_max_size(&opt.highlight_max_bytes)?);
+    output::set_key_order(match (opt.sorted, opt.unsorted) {
+        (true, _) => KeyOrder::Sorted,
+        (_, true) => KeyOrder::Unsorted,
+        _ => KeyOrder::Default,
+    });
+    if opt.unsorted {
+        wire::keep_heads();
+    }
 
-    for (name, value) in headers {
-        request += &format!("{}: {}\n", name, std::str::from_utf8(value.as_ref())?);
+    if opt.verbose {
+        note!("{:#?}\n", opt);
     }
 
-    eprintln!("{}", highlight(&request, "http"));
+    // build request
 
-    // print request body
+    let recorder = Recorder::default();
+    let connector = connect(&opt, &recorder)?;
+    let keepalive = if opt.no_keepalive {
+        None
+    } else {
+        Some(opt.keepalive_timeout)
+    };
+    let http = client::build_client(connector.clone(), keepalive, opt.http2_prior_knowledge);
+    let budget = Arc::new(RetryBudget::new(opt.retries).all_methods(opt.retry_all_methods));
+    let breaker = Arc::new(CircuitBreaker::new(
+        opt.breaker_threshold,
+        std::time::Duration::from_secs(opt.breaker_cooldown),
+    ));
+    let cancel = CancellationToken::new();
+    cancel.cancel_on_ctrl_c();
+    let rate = opt.rate.map(|rate| Arc::new(RateLimiter::new(rate)));
+
+    let default_scheme = match &opt.default_scheme {
+        Some(scheme) => scheme.clone(),
+        None => default_scheme(config)?,
+    };
+    let uri = body::normalize_uri(body::parse_uri(&opt.uri, &default_scheme)?)?;
+    let expectations = Expectations {
+        status: opt.expect_status.clone(),
+        headers: opt.expect_header.clone(),
+        body: opt.expect_body_json.clone(),
+    };
 
-    if body.len() > 0 {
-        eprintln!(
-            "{}\x1b[0m",
-            highlight(
-                &body,
-                match opt.mode {
-                    Some(Mode::Json) | None => "json",
-                    Some(Mode::Form) => "json",
-                }
-            )
+    // connect now, so the request below reuses the warm connection and its
+    // timings show only the request itself
+    if opt.preconnect {
+        tokio::select! {
+            res = connector.preconnect(&uri) => res.map_err(|err| anyhow::anyhow!(err))?,
+            _ = cancel.cancelled() => {
+                return Err(CancelledError(format!("connecting to {}", uri)).into());
+            }
+        };
+
+        note!(
+            "preconnected: {}\n",
+            recorder.timings().format_setup(opt.deterministic)
         );
+        recorder.update(|timings| *timings = Timings::default());
+    }
 
-        if !body.ends_with("\n") {
-            eprintln!("");
-        }
+    let proto = load_proto(&opt, &uri)?;
+    let mode = match &proto {
+        Some(proto) => Some(Mode::Protobuf(proto.clone())),
+        None => opt.mode.clone(),
+    };
+
+    let graphql = matches!(mode, Some(Mode::Graphql));
+    let json_mode = graphql || matches!(mode, Some(Mode::Json));
+    let mut builder = RurlRequestBuilder::new(opt.method.clone(), uri.clone())
+        .mode(mode)
+        .empty_body(opt.empty_body)
+        .path_as_is(opt.path_as_is)
+        .deterministic(opt.deterministic);
+
+    for (name, value) in opt.trailer.clone() {
+        builder = builder.trailer(name, value);
     }
 
-    // make request
+    // build request headers
 
-    let mut res = client.request(req).await?;
+    if let Some(preflight) = &opt.preflight {
+        if opt.method != Method::OPTIONS {
+            anyhow::bail!("--preflight sends an OPTIONS request, not {}", opt.method);
+        }
 
-    // print response
+        for (name, value) in preflight.headers()? {
+            builder = builder.header(name, value);
+        }
+    }
 
-    let mut response = format!(
-        "{:?} {} {}\n",
-        res.version(),
-        res.status().as_u16(),
-        res.status().canonical_reason().unwrap()
-    );
+    let languages = match (&opt.language, opt.locale) {
+        (Some(languages), _) => Some(languages.clone()),
+        (None, true) => locale::from_env(),
+        (None, false) => config.get_str("language").map(String::from),
+    };
 
-    // print response headers
+    if let Some(languages) = languages {
+        if !body::has_header(&opt.request_items, &hyper::header::ACCEPT_LANGUAGE) {
+            builder = builder.header(
+                hyper::header::ACCEPT_LANGUAGE,
+                locale::accept_language(&languages).parse()?,
+            );
+        }
+    }
 
-    let mut headers: Vec<(&HeaderName, &HeaderValue)> = res.headers().iter().collect();
-    headers.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    // build request body
 
-    for (name, value) in headers {
-        response += &format!("{}: {}\n", name, std::str::from_utf8(value.as_ref())?);
+    let mut request_items = request_items(&opt, config, template)?;
+
+    if let Some(kind) = opt.dav {
+        request_items.push(RequestItem::RawBody {
+            path: "dav.xml".into(),
+            value: dav::body(kind, opt.time_range.as_deref(), opt.sync_token.as_deref())?
+                .into_bytes(),
+        });
+
+        if !body::has_header(
+            &request_items,
+            &hyper::header::HeaderName::from_static("depth"),
+        ) {
+            // RFC 6578 requires Depth: 0 for sync-collection
+            let depth = if kind == DavKind::SyncCollection {
+                "0"
+            } else {
+                "1"
+            };
+            builder = builder.header(
+                hyper::header::HeaderName::from_static("depth"),
+                hyper::header::HeaderValue::from_static(depth),
+            );
+        }
     }
 
-    eprintln!("{}", highlight(&response, "http"));
+    let builder = match &opt.fill_form {
+        Some(selector) => form::fill(&http, &uri, selector, request_items, &cancel).await?,
+        None => builder.request_items(request_items),
+    };
 
-    // get response body
+    // cached tokens are reused until they expire, across invocations
+    let builder = match (
+        &opt.oauth2_token_url,
+        &opt.oauth2_client_id,
+        &opt.oauth2_client_secret,
+    ) {
+        (Some(token_url), Some(client_id), Some(client_secret)) => {
+            let options = OAuth2Options {
+                token_url: body::normalize_uri(token_url.clone())?,
+                client_id: client_id.clone(),
+                client_secret: client_secret.clone(),
+                scope: opt.oauth2_scope.clone(),
+            };
+            let token = oauth2::token(&http, &options, &cancel).await?;
+
+            builder.header(
+                hyper::header::AUTHORIZATION,
+                format!("Bearer {}", token).parse()?,
+            )
+        }
+        _ => builder,
+    };
 
-    let mut buf = Vec::new();
-    while let Some(chunk) = res.body_mut().data().await {
-        buf.append(&mut Vec::from(chunk?.as_ref()));
+    let builder = match &opt.accept {
+        Some(accept) => builder.accept(accept.clone()),
+        None => builder,
+    };
+    // unless another type was asked for, a JSON mode expects JSON back
+    let expects_json =
+        json_mode && opt.accept.is_none() && !builder.has_header(&hyper::header::ACCEPT);
+
+    let builder = match &opt.auth {
+        Some(credentials) => builder.header(
+            hyper::header::AUTHORIZATION,
+            auth::basic(credentials)?.parse()?,
+        ),
+        None => builder,
+    };
+
+    let auth = auth(&opt);
+
+    // the pre-fetch carries the same headers and credentials as the request
+    let builder = match opt.if_match.as_deref() {
+        Some("auto") => {
+            let etag = client::fetch_etag(&http, &builder, &auth, &cancel).await?;
+            builder.header(hyper::header::IF_MATCH, etag)
+        }
+        Some(etag) => builder.header(hyper::header::IF_MATCH, etag.parse()?),
+        None => builder,
+    };
+
+    let deterministic = opt.deterministic;
+    let multi = |concurrency| Multi {
+        concurrency,
+        rate: rate.clone(),
+        deterministic,
+        budget: budget.clone(),
+        breaker: breaker.clone(),
+        cancel: cancel.clone(),
+    };
+    let signed = |builder: RurlRequestBuilder| -> Result<_> {
+        let (mut req, body) = builder.build()?;
+        auth.apply(&mut req, &body.content)?;
+        Ok((req, body.content))
+    };
+    let pretty_max_size = parse_max_size(&opt.pretty_max_size)?;
+
+    if let Some(other) = &opt.diff {
+        if !expectations.is_empty() {
+            anyhow::bail!("--expect-* cannot be combined with --diff");
+        }
+
+        let other = body::normalize_uri(body::parse_uri(other, &default_scheme)?)?;
+        let requests = [signed(builder.clone())?, signed(builder.uri(other))?];
+        let differ = diff::run(multi(2), http, requests, proto.as_deref()).await;
+        history::keep(history.as_deref(), None).await;
+
+        // like diff(1), differences are a failure for scripts
+        if differ? {
+            std::process::exit(1);
+        }
+
+        return Ok(());
     }
 
-    let body = std::str::from_utf8(&buf)?;
+    if let Some(interval) = opt.watch {
+        let (req, body) = signed(builder)?;
+        let watch = Watch {
+            interval,
+            multi: multi(1),
+            notifier: Notifier::new(opt.notify, opt.bell),
+            expectations,
+            pretty_max_size,
+        };
 
-    // print response body
+        watch::run(watch, http, &req, &body, proto.as_deref()).await;
+        history::keep(history.as_deref(), None).await;
 
-    let body = match res.headers().get("content-type") {
-        Some(header) => {
-            let mime: mime::MediaType = std::str::from_utf8(header.as_ref())?.parse()?;
+        return Ok(());
+    }
 
-            match (mime.type_(), mime.subtype()) {
-                (mime::TEXT, mime::HTML) => highlight(body, "html"),
-                (mime::APPLICATION, mime::JSON) => highlight(body, "json"),
-                _ => body.into(),
+    let mut uris = opt
+        .urls
+        .iter()
+        .map(|uri| body::parse_uri(uri, &default_scheme))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(path) = &opt.url_file {
+        for line in std::fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+
+            if !line.is_empty() && !line.starts_with('#') {
+                uris.push(body::parse_uri(line, &default_scheme)?);
             }
         }
-        None => body.into(),
-    };
+    }
+
+    if !uris.is_empty() {
+        if opt.if_match.as_deref() == Some("auto") {
+            anyhow::bail!("--if-match auto only supports a single URI");
+        }
+
+        if !expectations.is_empty() {
+            anyhow::bail!("--expect-* only supports a single URI");
+        }
+
+        if opt.format == Some(Format::Json) {
+            anyhow::bail!("--format json only supports a single URI");
+        }
 
-    if body.len() > 0 {
-        println!("{}\x1b[0m", body);
+        let mut requests = vec![signed(builder.clone())?];
+        for uri in uris {
+            requests.push(signed(builder.clone().uri(uri))?);
+        }
+
+        let count = requests.len();
+        let failed = multi::show(
+            multi(opt.concurrency),
+            http,
+            requests,
+            opt.output_dir.as_deref(),
+            proto.as_deref(),
+            pretty_max_size,
+        )
+        .await;
+        history::keep(history.as_deref(), None).await;
+
+        if opt.meta || opt.verbose {
+            note!("{}", recorder.timings().format_reuse(count));
+        }
 
-        if !body.ends_with("\n") {
-            eprintln!("");
+        if failed {
+            std::process::exit(1);
         }
+
+        return Ok(());
     }
 
-    Ok(())
+    let exchange = Exchange {
+        recorder,
+        history,
+        text: opt.format != Some(Format::Json),
+        deterministic: opt.deterministic,
+        meta: opt.meta || opt.verbose,
+        header_layout: match (opt.full, opt.wrap, opt.truncate_headers) {
+            (true, _, _) => HeaderLayout::Full,
+            (_, true, _) => HeaderLayout::Wrap(pager::terminal_width()),
+            (_, _, Some(max)) => HeaderLayout::Truncate(max),
+            _ => HeaderLayout::Full,
+        },
+        print_full_url: opt.print_full_url,
+        edit: opt.edit,
+        cache_dir: opt.cache_dir,
+        curl: opt.curl,
+        curl_only: opt.curl_only,
+        hexdump: opt.hexdump,
+        bench: if opt.repeat > 1 {
+            Some(Bench {
+                repeat: opt.repeat,
+                concurrency: opt.concurrency,
+                rate: rate.clone(),
+                budget: budget.clone(),
+                breaker: breaker.clone(),
+                cancel: cancel.clone(),
+            })
+        } else {
+            None
+        },
+        progress: {
+            use std::io::IsTerminal as _;
+
+            !opt.quiet && std::io::stderr().is_terminal()
+        },
+        poll: match opt.until {
+            Some(condition) => Some(Poll {
+                condition,
+                interval: opt.poll_interval,
+                timeout: opt.poll_timeout,
+                budget: budget.clone(),
+                breaker: breaker.clone(),
+                cancel: cancel.clone(),
+            }),
+            None => None,
+        },
+        redirects: if opt.follow {
+            Some(Redirects {
+                max: opt.max_redirects,
+                allow_insecure: opt.allow_insecure_redirect,
+                auth: auth.clone(),
+                budget: budget.clone(),
+                breaker: breaker.clone(),
+                cancel: cancel.clone(),
+            })
+        } else {
+            None
+        },
+        budget: budget.clone(),
+        breaker: breaker.clone(),
+        cancel: cancel.clone(),
+        auth: auth.clone(),
+        diagnose: opt.diagnose,
+        tls_info: opt.tls_info,
+        if_match: opt.if_match.is_some(),
+        preflight: opt.preflight,
+        expectations,
+        max_body: opt.max_body,
+        // like curl, either flag alone turns the check on
+        low_speed: match (opt.speed_limit, opt.speed_time) {
+            (None, None) => None,
+            (limit, time) => Some(LowSpeed {
+                bytes_per_second: limit.map_or(1, |limit| limit.0),
+                time: std::time::Duration::from_secs(time.unwrap_or(30)),
+            }),
+        },
+        limit_rate: opt.limit_rate,
+        raw_to_stdout,
+        output: opt.output,
+        no_decompress: opt.no_decompress,
+        checksum: opt.checksum,
+        extract: opt.extract,
+        sse_timeout: opt.sse_timeout.map(std::time::Duration::from_secs),
+        notifier: Notifier::new(opt.notify, opt.bell),
+        json_output: opt.json_output,
+        decode_fields: opt.decode_fields,
+        paginate: if opt.paginate {
+            Some(Paginate {
+                max_pages: opt.max_pages,
+                next_jsonpath: opt.next_jsonpath,
+                auth: auth.clone(),
+                budget: budget.clone(),
+                breaker: breaker.clone(),
+                cancel: cancel.clone(),
+            })
+        } else {
+            None
+        },
+        filter: opt.filter,
+        copy: opt.copy,
+        formatter: opt.formatter,
+        pager: !opt.no_pager,
+        analyze: opt.analyze,
+        render_images: opt.render_images,
+        table: opt.table,
+        dav: opt.dav.is_some(),
+        graphql,
+        pretty_max_size,
+        expects_json,
+        proto,
+    };
+
+    exchange::run(exchange, http, builder).await
 }
@@ -0,0 +1,259 @@
+//! MessagePack for `--msgpack` request bodies and `application/msgpack`
+//! responses, converting to and from JSON values. Binary values decode to
+//! base64 strings, since JSON has no bytes type.
+
+use crate::decode::encode_base64;
+use json::JsonValue;
+use std::convert::TryInto as _;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MsgpackError {
+    #[error("MessagePack body ends in the middle of a value")]
+    UnexpectedEnd,
+    #[error("unsupported MessagePack type 0x{0:02x}")]
+    UnsupportedType(u8),
+    #[error("MessagePack string is not valid UTF-8")]
+    InvalidString,
+    #[error("{0} bytes left over after the MessagePack value")]
+    TrailingBytes(usize),
+}
+
+/// The header of a string, array, or map: the fix marker with the length
+/// in its low bits when it fits under `fix_max`, else the first of
+/// `markers` (for 8, 16, and 32-bit lengths, or just 16 and 32) whose
+/// length field holds it.
+fn push_header(output: &mut Vec<u8>, len: usize, fix: u8, fix_max: usize, markers: &[u8]) {
+    if len < fix_max {
+        output.push(fix | len as u8);
+        return;
+    }
+
+    let sizes: &[usize] = if markers.len() == 3 {
+        &[1, 2, 4]
+    } else {
+        &[2, 4]
+    };
+
+    for (marker, size) in markers.iter().zip(sizes) {
+        if *size == 4 || len < 1 << (8 * size) {
+            output.push(*marker);
+            output.extend_from_slice(&(len as u32).to_be_bytes()[4 - size..]);
+            return;
+        }
+    }
+}
+
+fn push_integer(output: &mut Vec<u8>, n: i64) {
+    if (-32..=0x7f).contains(&n) {
+        // positive and negative fixints are the byte itself
+        output.push(n as i8 as u8);
+    } else if n > 0 {
+        let (marker, size) = match n {
+            _ if n <= 0xff => (0xcc, 1),
+            _ if n <= 0xffff => (0xcd, 2),
+            _ if n <= 0xffff_ffff => (0xce, 4),
+            _ => (0xcf, 8),
+        };
+
+        output.push(marker);
+        output.extend_from_slice(&(n as u64).to_be_bytes()[8 - size..]);
+    } else {
+        let (marker, size) = match n {
+            _ if n >= -0x80 => (0xd0, 1),
+            _ if n >= -0x8000 => (0xd1, 2),
+            _ if n >= -0x8000_0000 => (0xd2, 4),
+            _ => (0xd3, 8),
+        };
+
+        output.push(marker);
+        output.extend_from_slice(&n.to_be_bytes()[8 - size..]);
+    }
+}
+
+fn push_value(output: &mut Vec<u8>, value: &JsonValue) {
+    match value {
+        JsonValue::Null => output.push(0xc0),
+        JsonValue::Boolean(false) => output.push(0xc2),
+        JsonValue::Boolean(true) => output.push(0xc3),
+        JsonValue::Number(_) => {
+            let n = value.as_f64().unwrap_or_default();
+
+            // whole numbers JSON can carry exactly go out as integers
+            if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 {
+                push_integer(output, n as i64);
+            } else {
+                output.push(0xcb);
+                output.extend_from_slice(&n.to_be_bytes());
+            }
+        }
+        JsonValue::Short(_) | JsonValue::String(_) => {
+            let text = value.as_str().unwrap_or_default();
+            push_header(output, text.len(), 0xa0, 32, &[0xd9, 0xda, 0xdb]);
+            output.extend_from_slice(text.as_bytes());
+        }
+        JsonValue::Array(values) => {
+            push_header(output, values.len(), 0x90, 16, &[0xdc, 0xdd]);
+            for value in values {
+                push_value(output, value);
+            }
+        }
+        JsonValue::Object(object) => {
+            push_header(output, object.len(), 0x80, 16, &[0xde, 0xdf]);
+            for (key, value) in object.iter() {
+                push_value(output, &key.into());
+                push_value(output, value);
+            }
+        }
+    }
+}
+
+pub fn encode(value: &JsonValue) -> Vec<u8> {
+    let mut output = Vec::new();
+    push_value(&mut output, value);
+
+    output
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MsgpackError> {
+        if self.input.len() < len {
+            return Err(MsgpackError::UnexpectedEnd);
+        }
+
+        let (taken, rest) = self.input.split_at(len);
+        self.input = rest;
+
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], MsgpackError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn length(&mut self, size: usize) -> Result<usize, MsgpackError> {
+        Ok(match size {
+            1 => self.array::<1>()?[0] as usize,
+            2 => u16::from_be_bytes(self.array()?) as usize,
+            _ => u32::from_be_bytes(self.array()?) as usize,
+        })
+    }
+
+    fn string(&mut self, len: usize) -> Result<JsonValue, MsgpackError> {
+        let bytes = self.take(len)?;
+        let text = std::str::from_utf8(bytes).map_err(|_| MsgpackError::InvalidString)?;
+
+        Ok(text.into())
+    }
+
+    fn values(&mut self, len: usize) -> Result<JsonValue, MsgpackError> {
+        let mut values = JsonValue::new_array();
+        for _ in 0..len {
+            values.push(self.value()?).unwrap();
+        }
+
+        Ok(values)
+    }
+
+    fn map(&mut self, len: usize) -> Result<JsonValue, MsgpackError> {
+        let mut object = JsonValue::new_object();
+        for _ in 0..len {
+            let key = self.value()?;
+            let key = key.as_str().map_or_else(|| key.dump(), str::to_owned);
+            object[key.as_str()] = self.value()?;
+        }
+
+        Ok(object)
+    }
+
+    fn value(&mut self) -> Result<JsonValue, MsgpackError> {
+        let marker = self.array::<1>()?[0];
+
+        Ok(match marker {
+            0x00..=0x7f => marker.into(),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize)?,
+            0x90..=0x9f => self.values((marker & 0x0f) as usize)?,
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize)?,
+            0xc0 => JsonValue::Null,
+            0xc2 => false.into(),
+            0xc3 => true.into(),
+            0xc4..=0xc6 => {
+                let len = self.length(1 << (marker - 0xc4))?;
+                encode_base64(self.take(len)?).into()
+            }
+            0xca => f32::from_be_bytes(self.array()?).into(),
+            0xcb => f64::from_be_bytes(self.array()?).into(),
+            0xcc => self.array::<1>()?[0].into(),
+            0xcd => u16::from_be_bytes(self.array()?).into(),
+            0xce => u32::from_be_bytes(self.array()?).into(),
+            0xcf => u64::from_be_bytes(self.array()?).into(),
+            0xd0 => (self.array::<1>()?[0] as i8).into(),
+            0xd1 => i16::from_be_bytes(self.array()?).into(),
+            0xd2 => i32::from_be_bytes(self.array()?).into(),
+            0xd3 => i64::from_be_bytes(self.array()?).into(),
+            0xd9..=0xdb => {
+                let len = self.length(1 << (marker - 0xd9))?;
+                self.string(len)?
+            }
+            0xdc | 0xdd => {
+                let len = self.length(2 << (marker - 0xdc))?;
+                self.values(len)?
+            }
+            0xde | 0xdf => {
+                let len = self.length(2 << (marker - 0xde))?;
+                self.map(len)?
+            }
+            0xe0..=0xff => (marker as i8).into(),
+            marker => return Err(MsgpackError::UnsupportedType(marker)),
+        })
+    }
+}
+
+/// Whether a response is MessagePack, under any of the names it is served as.
+pub fn is_msgpack(content_type: &mime::Mime) -> bool {
+    matches!(
+        (content_type.type_(), content_type.subtype().as_str()),
+        (mime::APPLICATION, "msgpack" | "x-msgpack" | "vnd.msgpack")
+    )
+}
+
+/// Decodes one MessagePack value, rejecting anything after it.
+pub fn decode(input: &[u8]) -> Result<JsonValue, MsgpackError> {
+    let mut reader = Reader { input };
+    let value = reader.value()?;
+
+    match reader.input.len() {
+        0 => Ok(value),
+        len => Err(MsgpackError::TrailingBytes(len)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_json() {
+        let value = json::parse(
+            r#"{"id": 7, "name": "ezra", "tags": ["a", "b"], "score": -1.5,
+                "big": 70000, "neg": -200, "nothing": null, "ok": true}"#,
+        )
+        .unwrap();
+        let bytes = encode(&value);
+
+        assert_eq!(&bytes[..5], b"\x88\xa2id\x07");
+        assert_eq!(decode(&bytes).unwrap(), value);
+        assert!(matches!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(MsgpackError::UnexpectedEnd)
+        ));
+        assert!(matches!(
+            decode(b"\xc0\xc0"),
+            Err(MsgpackError::TrailingBytes(1))
+        ));
+    }
+}
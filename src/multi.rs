@@ -0,0 +1,287 @@
+use crate::{
+    binary,
+    cancel::CancellationToken,
+    charset,
+    client::{self, HttpClient},
+    deterministic,
+    highlight::highlight,
+    note,
+    output::{self, HeaderLayout},
+    protobuf::Protobuf,
+    rate::RateLimiter,
+    retry::{CircuitBreaker, RetryBudget},
+};
+use anyhow::Result;
+use hyper::{Body, HeaderMap, Request, StatusCode, Uri};
+use std::{path::Path, sync::Arc};
+use tokio::sync::{mpsc, Semaphore};
+
+#[derive(Debug)]
+pub struct MultiResponse {
+    pub index: usize,
+    pub uri: Uri,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub head: String,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl MultiResponse {
+    /// The body as text, with the type to render it as: JSON when it was
+    /// decoded from MessagePack or protobuf. `None` when it is binary.
+    pub fn text(
+        &self,
+        proto: Option<&Protobuf>,
+        deterministic: bool,
+    ) -> Option<(Option<mime::Mime>, String)> {
+        let content_type: Option<mime::Mime> = self
+            .content_type
+            .as_deref()
+            .and_then(|content_type| content_type.parse().ok());
+
+        let decoded = output::decoded_json(content_type.as_ref(), &self.body, proto);
+        let content_type = match decoded {
+            Some(_) => "application/json".parse().ok(),
+            None => content_type,
+        };
+
+        let declared = charset::declared(self.content_type.as_deref(), &self.body);
+
+        if decoded.is_none()
+            && declared.is_none()
+            && binary::is_binary(content_type.as_ref(), &self.body)
+        {
+            return None;
+        }
+
+        let (body, warning) = match decoded {
+            Some(json) => (json, None),
+            None => charset::decode(self.content_type.as_deref(), &self.body),
+        };
+        if let Some(warning) = warning {
+            eprintln!("warning: {}", warning);
+        }
+
+        let body = if deterministic {
+            deterministic::normalize(&body)
+        } else {
+            body
+        };
+
+        Some((content_type, body))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Multi {
+    pub concurrency: usize,
+    pub rate: Option<Arc<RateLimiter>>,
+    pub deterministic: bool,
+    pub budget: Arc<RetryBudget>,
+    pub breaker: Arc<CircuitBreaker>,
+    pub cancel: CancellationToken,
+}
+
+/// A file name unique to the request's position and target, e.g.
+/// `0-example.com_8080_users.txt`.
+pub fn result_file_name(index: usize, uri: &Uri) -> String {
+    let target = format!(
+        "{}_{}{}",
+        uri.host().unwrap_or("unknown"),
+        uri.port_u16()
+            .map(|port| port.to_string())
+            .unwrap_or_default(),
+        uri.path()
+    );
+    let target: String = target
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+
+    format!("{}-{}.txt", index, target.trim_end_matches('_'))
+}
+
+async fn fetch(
+    multi: &Multi,
+    client: &HttpClient,
+    index: usize,
+    req: &Request<Body>,
+    body: &[u8],
+) -> Result<MultiResponse> {
+    let mut res = client::send(
+        client,
+        req,
+        body,
+        &multi.budget,
+        &multi.breaker,
+        &multi.cancel,
+    )
+    .await?;
+
+    let head = output::response_head(&res, multi.deterministic, HeaderLayout::Full)?;
+    let content_type = res
+        .headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|header| header.to_str().ok())
+        .map(String::from);
+
+    let mut buf = Vec::new();
+    while let Some(chunk) = client::next_chunk(&mut res, &multi.cancel).await? {
+        buf.extend_from_slice(&chunk);
+    }
+
+    Ok(MultiResponse {
+        index,
+        uri: req.uri().clone(),
+        status: res.status(),
+        headers: res.headers().clone(),
+        head,
+        content_type,
+        body: buf,
+    })
+}
+
+/// Sends every request with at most `multi.concurrency` in flight and no
+/// faster than `multi.rate`, handing
+/// each response to `on_response` as soon as it completes.
+pub async fn run(
+    multi: Multi,
+    client: HttpClient,
+    requests: Vec<(Request<Body>, Vec<u8>)>,
+    mut on_response: impl FnMut(Result<MultiResponse, (Uri, anyhow::Error)>),
+) {
+    let semaphore = Arc::new(Semaphore::new(multi.concurrency.max(1)));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    for (index, (req, body)) in requests.into_iter().enumerate() {
+        let multi = multi.clone();
+        let client = client.clone();
+        let semaphore = semaphore.clone();
+        let tx = tx.clone();
+
+        tokio::spawn(async move {
+            let _permit = semaphore.acquire().await;
+            if let Some(rate) = &multi.rate {
+                rate.acquire().await;
+            }
+            let response = fetch(&multi, &client, index, &req, &body)
+                .await
+                .map_err(|err| (req.uri().clone(), err));
+
+            let _ = tx.send(response);
+        });
+    }
+
+    drop(tx);
+
+    while let Some(response) = rx.recv().await {
+        on_response(response);
+    }
+}
+
+/// Sends the requests as [`run`] does and shows each response as it
+/// completes, under a `==> URI <==` line, or writes its body into
+/// `output_dir`. Returns whether any request failed.
+pub async fn show(
+    multi: Multi,
+    client: HttpClient,
+    requests: Vec<(Request<Body>, Vec<u8>)>,
+    output_dir: Option<&Path>,
+    proto: Option<&Protobuf>,
+    pretty_max_size: Option<usize>,
+) -> bool {
+    let deterministic = multi.deterministic;
+    let mut failed = false;
+
+    run(multi, client, requests, |response| match response {
+        Ok(response) => {
+            if let Some(dir) = output_dir {
+                let path = dir.join(result_file_name(response.index, &response.uri));
+
+                if let Err(err) = std::fs::write(&path, &response.body) {
+                    note!(
+                        "==> {} <==\ncould not write {}: {}\n",
+                        response.uri,
+                        path.display(),
+                        err
+                    );
+                    failed = true;
+                } else {
+                    note!("==> {} <== {}", response.uri, path.display());
+                }
+
+                return;
+            }
+
+            note!("==> {} <==", response.uri);
+            note!("{}", highlight(&response.head, "http"));
+
+            match response.text(proto, deterministic) {
+                Some((content_type, body)) => println!(
+                    "{}\x1b[0m\n",
+                    output::render_body(content_type.as_ref(), &body, pretty_max_size)
+                ),
+                None => note!("{}\n", binary::NOTE),
+            }
+        }
+        Err((uri, err)) => {
+            note!("==> {} <==\nerror: {}\n", uri, err);
+            failed = true;
+        }
+    })
+    .await;
+
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content_type: Option<&str>, head: &str, body: &[u8]) -> MultiResponse {
+        MultiResponse {
+            index: 0,
+            uri: Uri::from_static("http://example.com/"),
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            head: head.into(),
+            content_type: content_type.map(Into::into),
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn names_result_files_by_position_and_target() {
+        let uri = Uri::from_static("http://example.com:8080/users/7?page=2");
+        assert_eq!(result_file_name(0, &uri), "0-example.com_8080_users_7.txt");
+
+        let uri = Uri::from_static("https://api.example.com/");
+        assert_eq!(result_file_name(12, &uri), "12-api.example.com.txt");
+
+        let uri = Uri::from_static("/relative");
+        assert_eq!(result_file_name(1, &uri), "1-unknown__relative.txt");
+    }
+
+    #[test]
+    fn bodies_are_text_unless_binary() {
+        let json = response(Some("application/json"), "", b"{\"id\": 1}");
+        let (content_type, body) = json.text(None, false).unwrap();
+        assert_eq!(content_type.unwrap().essence_str(), "application/json");
+        assert_eq!(body, "{\"id\": 1}");
+
+        let png = response(Some("image/png"), "", b"\x89PNG\r\n\x1a\n\0\0");
+        assert!(png.text(None, false).is_none());
+    }
+
+    #[test]
+    fn normalizes_text_bodies_when_deterministic() {
+        let response = response(Some("text/plain"), "", b"at 2021-05-06T07:08:09Z");
+
+        let (_, body) = response.text(None, true).unwrap();
+        assert_eq!(body, "at 1970-01-01T00:00:00Z");
+    }
+}
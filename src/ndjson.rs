@@ -0,0 +1,127 @@
+//! Newline-delimited JSON (NDJSON, JSON Lines) responses, shown one
+//! document per line as they arrive, each pretty-printed on its own.
+
+use crate::{
+    highlight::highlight,
+    jsonpath::{self, JsonPathError},
+    output::pretty_json,
+};
+use anyhow::Result;
+
+pub fn is_ndjson(content_type: &mime::Mime) -> bool {
+    matches!(
+        (content_type.type_(), content_type.subtype().as_str()),
+        (
+            mime::APPLICATION,
+            "x-ndjson" | "ndjson" | "jsonl" | "x-jsonlines" | "jsonlines"
+        )
+    )
+}
+
+/// Whether an undeclared body is NDJSON: more than one line, each a JSON
+/// document, that aren't one document together.
+pub fn sniff(body: &str) -> bool {
+    let mut lines = body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+
+    // fail fast on the first line of HTML and other text
+    match lines.peek() {
+        Some(line) if line.trim_start().starts_with(&['{', '['][..]) => {}
+        _ => return false,
+    }
+
+    let mut count = 0;
+    for line in lines {
+        if json::parse(line).is_err() {
+            return false;
+        }
+        count += 1;
+    }
+
+    count > 1 && json::parse(body).is_err()
+}
+
+/// Splits a body into lines as chunks arrive.
+#[derive(Debug, Default)]
+pub struct LineParser {
+    buf: Vec<u8>,
+}
+
+impl LineParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The lines completed by `chunk`, without their line endings.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(chunk);
+
+        let mut lines = Vec::new();
+
+        while let Some(end) = self.buf.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            lines.push(
+                String::from_utf8_lossy(&line)
+                    .trim_end_matches(['\n', '\r'])
+                    .to_owned(),
+            );
+        }
+
+        lines
+    }
+
+    /// The last line, when the body doesn't end with a newline.
+    pub fn finish(self) -> Option<String> {
+        if self.buf.is_empty() {
+            None
+        } else {
+            Some(String::from_utf8_lossy(&self.buf).into_owned())
+        }
+    }
+}
+
+/// One line pretty-printed and highlighted, after `filter` if given. Blank
+/// lines and lines the filter doesn't match are skipped, and lines that
+/// aren't JSON are shown as they are.
+pub fn render(line: &str, filter: Option<&str>) -> Result<Option<String>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let document = match json::parse(line) {
+        Ok(document) => document,
+        Err(_) => return Ok(Some(line.to_owned())),
+    };
+    let document = match filter.map(|path| jsonpath::filter(&document, path)) {
+        Some(Ok(filtered)) => filtered,
+        Some(Err(JsonPathError::NoMatch(_))) => return Ok(None),
+        Some(Err(err)) => return Err(err.into()),
+        None => document,
+    };
+
+    Ok(Some(highlight(&pretty_json(&document), "json")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_lines_across_chunks() {
+        let mut parser = LineParser::new();
+
+        assert!(parser.feed(b"{\"id\": 1}\r\n{\"id\"").len() == 1);
+        assert_eq!(parser.feed(b": 2}\n{\"id\": 3}"), ["{\"id\": 2}"]);
+        assert_eq!(parser.finish().as_deref(), Some("{\"id\": 3}"));
+    }
+
+    #[test]
+    fn sniffs_ndjson_bodies() {
+        assert!(sniff("{\"id\": 1}\n{\"id\": 2}\n"));
+        assert!(!sniff("{\"id\": 1}\n"));
+        assert!(!sniff("{\n  \"id\": 1\n}\n"));
+        assert!(!sniff("<html>\n</html>\n"));
+    }
+}
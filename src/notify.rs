@@ -0,0 +1,105 @@
+use hyper::StatusCode;
+
+#[derive(Debug, Default)]
+pub struct Notifier {
+    desktop: bool,
+    bell: bool,
+    last_status: Option<StatusCode>,
+    failing: bool,
+}
+
+impl Notifier {
+    pub fn new(desktop: bool, bell: bool) -> Self {
+        Self {
+            desktop,
+            bell,
+            ..Default::default()
+        }
+    }
+
+    /// Notifies when the status differs from the previously observed one.
+    pub fn observe_status(&mut self, uri: &hyper::Uri, status: StatusCode) {
+        if let Some(message) = self.status_change(uri, status) {
+            self.notify(&message);
+        }
+    }
+
+    fn status_change(&mut self, uri: &hyper::Uri, status: StatusCode) -> Option<String> {
+        match self.last_status.replace(status) {
+            Some(last_status) if last_status != status => Some(format!(
+                "{} changed from {} to {}",
+                uri, last_status, status
+            )),
+            _ => None,
+        }
+    }
+
+    /// Notifies when assertions go from passing to failing.
+    pub fn observe_assertions(&mut self, uri: &hyper::Uri, passed: bool) {
+        if let Some(message) = self.assertions_change(uri, passed) {
+            self.notify(&message);
+        }
+    }
+
+    fn assertions_change(&mut self, uri: &hyper::Uri, passed: bool) -> Option<String> {
+        let started_failing = !passed && !self.failing;
+        self.failing = !passed;
+
+        if started_failing {
+            Some(format!("assertions started failing for {}", uri))
+        } else {
+            None
+        }
+    }
+
+    pub fn notify(&self, message: &str) {
+        if self.bell {
+            eprint!("\x07");
+        }
+
+        if self.desktop {
+            let result = if cfg!(target_os = "macos") {
+                std::process::Command::new("osascript")
+                    .arg("-e")
+                    .arg(format!(
+                        "display notification {:?} with title \"rurl\"",
+                        message
+                    ))
+                    .status()
+            } else {
+                std::process::Command::new("notify-send")
+                    .arg("rurl")
+                    .arg(message)
+                    .status()
+            };
+
+            if result.is_err() {
+                eprintln!("could not send desktop notification: {}", message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notices_status_changes_and_new_failures() {
+        let uri: hyper::Uri = "http://example.com/health".parse().unwrap();
+        let mut notifier = Notifier::new(false, false);
+
+        assert_eq!(notifier.status_change(&uri, StatusCode::OK), None);
+        assert_eq!(notifier.status_change(&uri, StatusCode::OK), None);
+        assert_eq!(
+            notifier.status_change(&uri, StatusCode::SERVICE_UNAVAILABLE),
+            Some("http://example.com/health changed from 200 OK to 503 Service Unavailable".into())
+        );
+
+        assert_eq!(notifier.assertions_change(&uri, true), None);
+        assert!(notifier.assertions_change(&uri, false).is_some());
+        assert_eq!(notifier.assertions_change(&uri, false), None);
+        assert_eq!(notifier.assertions_change(&uri, true), None);
+        assert!(notifier.assertions_change(&uri, false).is_some());
+    }
+}
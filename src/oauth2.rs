@@ -0,0 +1,217 @@
+use crate::{
+    cancel::{CancellationToken, CancelledError},
+    client::{self, HttpClient, Upload},
+    digest::{hex, sha256},
+    state::{self, Lock},
+};
+use anyhow::Result;
+use hyper::{header, Body, Method, Request, StatusCode, Uri};
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// Tokens this close to expiry are refreshed rather than sent.
+const EXPIRY_MARGIN: u64 = 30;
+
+#[derive(Debug, Error)]
+pub enum OAuth2Error {
+    #[error("token request to {0} failed with {1}: {2}")]
+    TokenStatus(Uri, StatusCode, String),
+    #[error("token response from {0} has no access_token")]
+    MissingToken(Uri),
+}
+
+#[derive(Clone, Debug)]
+pub struct OAuth2Options {
+    pub token_url: Uri,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Where tokens are cached.
+pub fn cache_dir() -> Option<std::path::PathBuf> {
+    state::cache_dir().map(|dir| dir.join("oauth2"))
+}
+
+/// One cache file per token endpoint, client, secret, and scope, so a token
+/// fetched with one secret is never handed out for another. The secret goes
+/// in hashed, and the whole key is hashed again for the file name.
+fn cache_key(options: &OAuth2Options) -> String {
+    let key = format!(
+        "{}\n{}\n{}\n{}",
+        options.token_url,
+        options.client_id,
+        hex(&sha256(options.client_secret.as_bytes())),
+        options.scope.as_deref().unwrap_or_default()
+    );
+
+    hex(&sha256(key.as_bytes()))
+}
+
+fn cache_path(options: &OAuth2Options) -> Option<std::path::PathBuf> {
+    cache_dir().map(|dir| dir.join(format!("{}.json", cache_key(options))))
+}
+
+fn read_cached(path: &std::path::Path) -> Option<String> {
+    let cached = json::parse(&std::fs::read_to_string(path).ok()?).ok()?;
+
+    if cached["expires_at"].as_u64()? > now() + EXPIRY_MARGIN {
+        cached["access_token"].as_str().map(Into::into)
+    } else {
+        None
+    }
+}
+
+fn write_cached(path: &std::path::Path, token: &str, expires_in: u64) -> Result<()> {
+    let mut cached = json::JsonValue::new_object();
+    cached["access_token"] = token.into();
+    cached["expires_at"] = (now() + expires_in).into();
+
+    // the token is as good as a password until it expires
+    state::write_atomic(path, cached.dump().as_bytes(), 0o600)?;
+
+    Ok(())
+}
+
+async fn fetch(
+    client: &HttpClient,
+    options: &OAuth2Options,
+    cancel: &CancellationToken,
+) -> Result<(String, Option<u64>)> {
+    let mut form = format!(
+        "grant_type=client_credentials&client_id={}&client_secret={}",
+        urlencoding::encode(&options.client_id),
+        urlencoding::encode(&options.client_secret)
+    );
+
+    if let Some(scope) = &options.scope {
+        form += &format!("&scope={}", urlencoding::encode(scope));
+    }
+
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(options.token_url.clone())
+        .header(header::ACCEPT, mime::APPLICATION_JSON.to_string())
+        .header(
+            header::CONTENT_TYPE,
+            mime::APPLICATION_WWW_FORM_URLENCODED.to_string(),
+        )
+        .body(Body::from(form))?;
+
+    let mut res = tokio::select! {
+        res = client.request(req.map(Upload::from)) => res?,
+        _ = cancel.cancelled() => {
+            return Err(CancelledError(format!("POST {}", options.token_url)).into());
+        }
+    };
+
+    let mut buf = Vec::new();
+    while let Some(chunk) = client::next_chunk(&mut res, cancel).await? {
+        buf.extend_from_slice(&chunk);
+    }
+
+    let body = String::from_utf8_lossy(&buf);
+
+    if !res.status().is_success() {
+        return Err(
+            OAuth2Error::TokenStatus(options.token_url.clone(), res.status(), body.into()).into(),
+        );
+    }
+
+    let response =
+        json::parse(&body).map_err(|_| OAuth2Error::MissingToken(options.token_url.clone()))?;
+    let token = response["access_token"]
+        .as_str()
+        .ok_or_else(|| OAuth2Error::MissingToken(options.token_url.clone()))?;
+
+    Ok((token.into(), response["expires_in"].as_u64()))
+}
+
+/// A client-credentials access token, from the cache while it is still valid
+/// and otherwise from the token endpoint. The cache is locked while fetching,
+/// so parallel invocations share a single new token.
+pub async fn token(
+    client: &HttpClient,
+    options: &OAuth2Options,
+    cancel: &CancellationToken,
+) -> Result<String> {
+    let path = match cache_path(options) {
+        Some(path) => path,
+        None => return Ok(fetch(client, options, cancel).await?.0),
+    };
+
+    if let Some(token) = read_cached(&path) {
+        return Ok(token);
+    }
+
+    let _lock = Lock::acquire(&path).await?;
+
+    // another process may have refreshed it while this one waited
+    if let Some(token) = read_cached(&path) {
+        return Ok(token);
+    }
+
+    let (token, expires_in) = fetch(client, options, cancel).await?;
+
+    // without expires_in there is no telling how long the token lasts
+    if let Some(expires_in) = expires_in {
+        write_cached(&path, &token, expires_in)?;
+    }
+
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_tokens_until_shortly_before_they_expire() {
+        let dir = std::env::temp_dir().join(format!("rurl-oauth2-{}", std::process::id()));
+        let path = dir.join("token.json");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(read_cached(&path), None);
+
+        write_cached(&path, "fresh", 3600).unwrap();
+        assert_eq!(read_cached(&path).as_deref(), Some("fresh"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        write_cached(&path, "stale", EXPIRY_MARGIN).unwrap();
+        assert_eq!(read_cached(&path), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn keys_the_cache_by_client_secret() {
+        let options = OAuth2Options {
+            token_url: "https://auth.example.com/token".parse().unwrap(),
+            client_id: "rurl".into(),
+            client_secret: "s3cret".into(),
+            scope: None,
+        };
+        let rotated = OAuth2Options {
+            client_secret: "rotated".into(),
+            ..options.clone()
+        };
+
+        assert_eq!(cache_key(&options), cache_key(&options.clone()));
+        assert_ne!(cache_key(&options), cache_key(&rotated));
+        assert!(!cache_key(&options).contains("s3cret"));
+    }
+}
@@ -0,0 +1,560 @@
+use crate::{
+    deterministic,
+    highlight::highlight,
+    msgpack, ndjson,
+    protobuf::{self, Protobuf},
+    sse, wire, xml,
+};
+use anyhow::Result;
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    Body, HeaderMap, Request, Response,
+};
+use json::JsonValue;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use thiserror::Error;
+
+static ALL_TO_STDOUT: AtomicBool = AtomicBool::new(false);
+static KEY_ORDER: AtomicU8 = AtomicU8::new(KeyOrder::Default as u8);
+
+/// `--all-to-stdout`: print what [`note!`] prints to stdout as well.
+pub fn set_all_to_stdout(enabled: bool) {
+    ALL_TO_STDOUT.store(enabled, Ordering::SeqCst);
+}
+
+pub fn all_to_stdout() -> bool {
+    ALL_TO_STDOUT.load(Ordering::SeqCst)
+}
+
+/// The order headers and JSON object keys print in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyOrder {
+    /// Headers sorted by name, JSON keys as the server sent them.
+    Default,
+    /// `--sorted`: both sorted.
+    Sorted,
+    /// `--unsorted`: both as sent, for diffing against raw traffic. HTTP/2
+    /// heads are decoded into a map, so their repeated headers print
+    /// grouped by name.
+    Unsorted,
+}
+
+pub fn set_key_order(order: KeyOrder) {
+    KEY_ORDER.store(order as u8, Ordering::SeqCst);
+}
+
+fn key_order() -> KeyOrder {
+    match KEY_ORDER.load(Ordering::SeqCst) {
+        order if order == KeyOrder::Sorted as u8 => KeyOrder::Sorted,
+        order if order == KeyOrder::Unsorted as u8 => KeyOrder::Unsorted,
+        _ => KeyOrder::Default,
+    }
+}
+
+fn sort_keys(value: &JsonValue) -> JsonValue {
+    match value {
+        JsonValue::Object(object) => {
+            let mut entries: Vec<(&str, &JsonValue)> = object.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+
+            let mut sorted = JsonValue::new_object();
+            for (key, value) in entries {
+                sorted[key] = sort_keys(value);
+            }
+
+            sorted
+        }
+        JsonValue::Array(values) => JsonValue::Array(values.iter().map(sort_keys).collect()),
+        value => value.clone(),
+    }
+}
+
+/// Pretty-prints `value`, with its keys sorted under `--sorted`.
+pub fn pretty_json(value: &JsonValue) -> String {
+    if key_order() == KeyOrder::Sorted {
+        sort_keys(value).pretty(2)
+    } else {
+        value.pretty(2)
+    }
+}
+
+/// Prints everything but the response body: the request and response
+/// heads, timings, and notes about the exchange. These go to stderr, so
+/// stdout carries only the body, unless `--all-to-stdout` is given.
+/// Errors and warnings about the request always go to stderr.
+#[macro_export]
+macro_rules! note {
+    ($($arg:tt)*) => {
+        if $crate::output::all_to_stdout() {
+            println!($($arg)*);
+        } else {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("unknown format {0}, expected text or json")]
+    ParseError(String),
+}
+
+/// `--format`: colored text for people, or one JSON document for tools.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(FormatError::ParseError(s.into())),
+        }
+    }
+}
+
+/// Shows non-UTF-8 header bytes as `\xNN` escapes instead of failing.
+pub fn display_header_value(value: &HeaderValue) -> String {
+    match std::str::from_utf8(value.as_bytes()) {
+        Ok(value) => value.into(),
+        Err(_) => value
+            .as_bytes()
+            .iter()
+            .map(|byte| match byte {
+                0x20..=0x7e => (*byte as char).to_string(),
+                _ => format!("\\x{:02x}", byte),
+            })
+            .collect(),
+    }
+}
+
+/// How header values longer than the terminal are printed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeaderLayout {
+    Full,
+    /// Wrap values at this many columns, indenting continuation lines.
+    Wrap(usize),
+    /// Cut values after this many characters.
+    Truncate(usize),
+}
+
+/// Breaks `value` into a line of at most `first` characters and then lines
+/// of at most `width`, preferring to break after a space so cookies and
+/// policies split between directives.
+fn wrap(value: &str, first: usize, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut rest: Vec<char> = value.chars().collect();
+    let mut limit = first;
+
+    while rest.len() > limit {
+        let at = rest[..limit]
+            .iter()
+            .rposition(|c| *c == ' ')
+            .filter(|&at| at > 0)
+            .map_or(limit, |at| at + 1);
+
+        lines.push(rest[..at].iter().collect::<String>().trim_end().to_owned());
+        rest.drain(..at);
+        limit = width;
+    }
+    lines.push(rest.into_iter().collect());
+
+    lines
+}
+
+fn layout_header(name: &HeaderName, value: &str, layout: HeaderLayout) -> String {
+    match layout {
+        HeaderLayout::Truncate(max) if value.chars().count() > max => {
+            let kept: String = value.chars().take(max).collect();
+            format!("{}: {}… (+{} bytes)", name, kept, value.len() - kept.len())
+        }
+        HeaderLayout::Wrap(columns) => {
+            // continuation lines line up under the value, unless the name is
+            // so long that would leave too little room
+            let indent = if name.as_str().len() + 2 + 20 <= columns {
+                name.as_str().len() + 2
+            } else {
+                4
+            };
+            let lines = wrap(
+                value,
+                columns.saturating_sub(name.as_str().len() + 2).max(20),
+                columns.saturating_sub(indent).max(20),
+            );
+
+            format!(
+                "{}: {}",
+                name,
+                lines.join(&format!("\n{}", " ".repeat(indent)))
+            )
+        }
+        _ => format!("{}: {}", name, value),
+    }
+}
+
+/// `headers` in the order `received` lists them, then any it doesn't, since
+/// a `HeaderMap` keeps the values of a repeated header together.
+fn in_received_order<'a>(
+    headers: &'a HeaderMap,
+    received: &[(String, Vec<u8>)],
+) -> Vec<(&'a HeaderName, &'a HeaderValue)> {
+    let mut remaining: Vec<_> = headers.iter().collect();
+    let mut ordered = Vec::with_capacity(remaining.len());
+
+    for (name, value) in received {
+        let found = remaining
+            .iter()
+            .position(|(n, v)| n.as_str() == name && v.as_bytes() == &value[..]);
+
+        if let Some(at) = found {
+            ordered.push(remaining.remove(at));
+        }
+    }
+
+    ordered.extend(remaining);
+    ordered
+}
+
+fn format_headers(
+    headers: &HeaderMap,
+    received: Option<&[(String, Vec<u8>)]>,
+    deterministic: bool,
+    layout: HeaderLayout,
+) -> Result<String> {
+    let mut output = String::new();
+
+    // the sort is stable, so a repeated header keeps one line per value in
+    // the order it was sent or received
+    let mut headers = match received {
+        Some(received) => in_received_order(headers, received),
+        None => headers.iter().collect(),
+    };
+    if key_order() != KeyOrder::Unsorted {
+        headers.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    }
+
+    for (name, value) in headers {
+        let value = if deterministic && deterministic::is_volatile_header(name.as_str()) {
+            deterministic::PLACEHOLDER.into()
+        } else {
+            display_header_value(value)
+        };
+
+        output += &layout_header(name, &value, layout);
+        output += "\n";
+    }
+
+    Ok(output)
+}
+
+/// The request as it goes on the wire: origin-form target with the query,
+/// and the `host` line hyper adds. `full_url` prints the absolute-form
+/// target a proxy would receive instead.
+pub fn request_head(
+    req: &Request<Body>,
+    deterministic: bool,
+    full_url: bool,
+    layout: HeaderLayout,
+) -> Result<String> {
+    let target = if full_url {
+        req.uri().to_string()
+    } else {
+        req.uri()
+            .path_and_query()
+            .map(|path_and_query| path_and_query.as_str())
+            .unwrap_or("/")
+            .to_owned()
+    };
+
+    let mut request = format!("{} {} {:?}\n", req.method(), target, req.version());
+    let mut headers = req.headers().clone();
+
+    let host = match headers.remove(hyper::header::HOST) {
+        Some(host) => Some(display_header_value(&host)),
+        None => req.uri().authority().map(ToString::to_string),
+    };
+
+    if let Some(host) = host {
+        request += &format!("host: {}\n", host);
+    }
+
+    Ok(request + &format_headers(&headers, None, deterministic, layout)?)
+}
+
+pub fn response_head(
+    res: &Response<Body>,
+    deterministic: bool,
+    layout: HeaderLayout,
+) -> Result<String> {
+    let response = format!(
+        "{:?} {} {}\n",
+        res.version(),
+        res.status().as_u16(),
+        res.status().canonical_reason().unwrap()
+    );
+
+    // only the wire has the order repeated headers were received in
+    let received = match key_order() {
+        KeyOrder::Unsorted => wire::last_head(),
+        _ => None,
+    };
+
+    Ok(response + &format_headers(res.headers(), received.as_deref(), deterministic, layout)?)
+}
+
+/// Trailers received after the body, laid out like headers.
+pub fn trailers(trailers: &HeaderMap, deterministic: bool, layout: HeaderLayout) -> Result<String> {
+    format_headers(trailers, None, deterministic, layout)
+}
+
+/// Formats and highlights `body` by content type. Bodies larger than
+/// `pretty_max_size` bytes are returned untouched to stay responsive.
+pub fn render_body(
+    content_type: Option<&mime::Mime>,
+    body: &str,
+    pretty_max_size: Option<usize>,
+) -> String {
+    if let Some(pretty_max_size) = pretty_max_size {
+        if body.len() > pretty_max_size {
+            note!(
+                "body is {} bytes, over --pretty-max-size {}; printing it unformatted \
+                 (pass a larger --pretty-max-size or --pretty-max-size none to format it)\n",
+                body.len(),
+                pretty_max_size
+            );
+
+            return body.into();
+        }
+    }
+
+    match content_type {
+        Some(mime) => match (mime.type_(), mime.subtype(), mime.suffix()) {
+            (mime::TEXT, mime::HTML, _) => highlight(body, "html"),
+            (mime::APPLICATION, mime::JSON, _) | (_, _, Some(mime::JSON)) => {
+                match json::parse(body) {
+                    Ok(json) => highlight(&pretty_json(&json), "json"),
+                    Err(_) => highlight(body, "json"),
+                }
+            }
+            (mime::APPLICATION, mime::XML, _)
+            | (mime::TEXT, mime::XML, _)
+            | (_, _, Some(mime::XML)) => highlight(&xml::pretty_print(body), "xml"),
+            (mime::APPLICATION, subtype, _) | (mime::TEXT, subtype, _)
+                if matches!(subtype.as_str(), "yaml" | "x-yaml") =>
+            {
+                highlight(body, "yaml")
+            }
+            (_, _, Some(suffix)) if suffix.as_str() == "yaml" => highlight(body, "yaml"),
+            _ => body.into(),
+        },
+        None => body.into(),
+    }
+}
+
+/// `application/json`, or a `+json` type like `application/problem+json`.
+pub fn is_json(content_type: &mime::Mime) -> bool {
+    content_type.subtype() == mime::JSON || content_type.suffix() == Some(mime::JSON)
+}
+
+/// A MessagePack or `--proto` response as the JSON it decodes to, which is
+/// shown, filtered, and checked in its place.
+pub fn decoded_json(
+    content_type: Option<&mime::Mime>,
+    body: &[u8],
+    proto: Option<&Protobuf>,
+) -> Option<String> {
+    let content_type = content_type?;
+
+    let decoded = if msgpack::is_msgpack(content_type) {
+        msgpack::decode(body).map_err(anyhow::Error::from)
+    } else {
+        match proto
+            .filter(|_| protobuf::is_protobuf(content_type))?
+            .decode(content_type, body)
+        {
+            Ok(Some((value, trailers))) => {
+                // gRPC-web sends its trailers in the body, after the messages
+                if let Some(trailers) = trailers {
+                    note!("{}\n", highlight(&trailers, "http"));
+                }
+
+                Ok(value)
+            }
+            Ok(None) => return None,
+            Err(err) => Err(err.into()),
+        }
+    };
+
+    match decoded {
+        Ok(value) => Some(value.dump()),
+        Err(err) => {
+            eprintln!("warning: {}", err);
+            None
+        }
+    }
+}
+
+/// Parses a response body for `flag`, which only works on JSON.
+pub fn parse_json_body(
+    body: &str,
+    flag: &str,
+    content_type: Option<&mime::Mime>,
+) -> Result<JsonValue> {
+    json::parse(body).map_err(|_| {
+        anyhow::anyhow!(
+            "{} only applies to JSON bodies, but the response is {}",
+            flag,
+            content_type
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "untyped".into())
+        )
+    })
+}
+
+/// Splits a GraphQL response into separately labelled `errors` and `data` sections.
+pub fn render_graphql(body: &str) -> Option<String> {
+    let response = json::parse(body).ok()?;
+    let mut output = String::new();
+
+    if !response["errors"].is_null() {
+        output += "\x1b[1;31merrors\x1b[0m\n";
+        output += &highlight(&pretty_json(&response["errors"]), "json");
+        output += "\x1b[0m\n";
+    }
+
+    if !response["data"].is_null() {
+        output += "\x1b[1;32mdata\x1b[0m\n";
+        output += &highlight(&pretty_json(&response["data"]), "json");
+        output += "\x1b[0m\n";
+    }
+
+    if output.is_empty() {
+        None
+    } else {
+        Some(output)
+    }
+}
+
+pub fn is_event_stream(res: &Response<Body>) -> bool {
+    res.headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|header| header.to_str().ok())
+        .map(|header| header.trim_start().starts_with("text/event-stream"))
+        .unwrap_or(false)
+}
+
+pub fn is_ndjson(res: &Response<Body>) -> bool {
+    res.headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|header| header.to_str().ok()?.parse::<mime::Mime>().ok())
+        .is_some_and(|content_type| ndjson::is_ndjson(&content_type))
+}
+
+pub fn print_event(event: &sse::Event) {
+    let mut meta = String::new();
+
+    for (name, value) in [
+        ("event", &event.event),
+        ("id", &event.id),
+        ("retry", &event.retry),
+    ]
+    .iter()
+    {
+        if let Some(value) = value {
+            meta += &format!("{}: {}\n", name, value);
+        }
+    }
+
+    if !meta.is_empty() {
+        note!("{}\x1b[0m", highlight(meta.trim_end(), "yaml"));
+    }
+
+    match json::parse(&event.data) {
+        Ok(data) => println!("{}\x1b[0m", highlight(&pretty_json(&data), "json")),
+        Err(_) => println!("{}", event.data),
+    }
+
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_headers_print_on_separate_lines_in_order() {
+        let mut headers = HeaderMap::new();
+        headers.append("x-tag", HeaderValue::from_static("b"));
+        headers.append("accept", HeaderValue::from_static("*/*"));
+        headers.append("x-tag", HeaderValue::from_static("a"));
+        headers.append("x-tag", HeaderValue::from_static("c"));
+
+        assert_eq!(
+            format_headers(&headers, None, false, HeaderLayout::Full).unwrap(),
+            "accept: */*\nx-tag: b\nx-tag: a\nx-tag: c\n"
+        );
+    }
+
+    #[test]
+    fn sorted_sorts_nested_keys() {
+        let value = json::parse(r#"{"b": [{"d": 1, "c": 2}], "a": null}"#).unwrap();
+
+        assert_eq!(
+            sort_keys(&value).dump(),
+            r#"{"a":null,"b":[{"c":2,"d":1}]}"#
+        );
+    }
+
+    #[test]
+    fn request_head_shows_the_query_and_host() {
+        let req = Request::get("http://example.com:8080/users?page=2")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(
+            request_head(&req, false, false, HeaderLayout::Full).unwrap(),
+            "GET /users?page=2 HTTP/1.1\nhost: example.com:8080\n"
+        );
+        assert_eq!(
+            request_head(&req, false, true, HeaderLayout::Full).unwrap(),
+            "GET http://example.com:8080/users?page=2 HTTP/1.1\nhost: example.com:8080\n"
+        );
+    }
+
+    #[test]
+    fn long_header_values_wrap_under_the_value() {
+        let name = HeaderName::from_static("set-cookie");
+
+        assert_eq!(
+            layout_header(
+                &name,
+                "session=abcdefghij; Path=/; HttpOnly; SameSite=Lax",
+                HeaderLayout::Wrap(40)
+            ),
+            "set-cookie: session=abcdefghij; Path=/;\n            HttpOnly; SameSite=Lax"
+        );
+        assert_eq!(
+            layout_header(&name, "a=1", HeaderLayout::Wrap(40)),
+            "set-cookie: a=1"
+        );
+    }
+
+    #[test]
+    fn long_header_values_truncate_with_the_cut_length() {
+        let name = HeaderName::from_static("x-long");
+
+        assert_eq!(
+            layout_header(&name, "abcdefghij", HeaderLayout::Truncate(4)),
+            "x-long: abcd… (+6 bytes)"
+        );
+        assert_eq!(
+            layout_header(&name, "abcd", HeaderLayout::Truncate(4)),
+            "x-long: abcd"
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::{IsTerminal as _, Write as _};
+use std::process::{Command, Stdio};
+
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Rows and columns of the terminal, as reported by `stty size`.
+fn stty_size() -> Option<(usize, usize)> {
+    // `stty` reports the size of whichever terminal is its stdin
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut size = output.split_whitespace().map(|n| n.parse().ok());
+
+    Some((size.next()??, size.next()??))
+}
+
+fn from_env(name: &str) -> Option<usize> {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+}
+
+/// The terminal's height in rows, or 24 when it cannot be told.
+pub fn terminal_height() -> usize {
+    from_env("LINES")
+        .or_else(|| stty_size().map(|(rows, _)| rows))
+        .unwrap_or(24)
+}
+
+/// The terminal's width in columns, or 80 when it cannot be told.
+pub fn terminal_width() -> usize {
+    from_env("COLUMNS")
+        .or_else(|| stty_size().map(|(_, columns)| columns))
+        .unwrap_or(80)
+}
+
+fn page(output: &str) -> std::io::Result<bool> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.into());
+
+    if pager.trim().is_empty() {
+        return Ok(false);
+    }
+
+    let mut child = match Command::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(_) => return Ok(false),
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // the pager closing its input early (e.g. quitting less) is not an error
+        match stdin.write_all(output.as_bytes()) {
+            Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => return Err(err),
+            _ => {}
+        }
+    }
+
+    child.wait()?;
+
+    Ok(true)
+}
+
+/// Prints `output` to stdout, through `$PAGER` when it would not fit on the terminal.
+pub fn print(output: &str, enabled: bool) -> std::io::Result<()> {
+    let long = || output.lines().count() >= terminal_height();
+
+    if enabled && std::io::stdout().is_terminal() && long() && page(output)? {
+        return Ok(());
+    }
+
+    let mut stdout = std::io::stdout();
+    stdout.write_all(output.as_bytes())?;
+    stdout.flush()?;
+
+    Ok(())
+}
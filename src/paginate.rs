@@ -0,0 +1,277 @@
+//! `--paginate`: fetching the pages after the first, found through RFC 8288
+//! `Link: <...>; rel="next"` headers or a JSONPath into each body, and
+//! combining them into one body.
+
+use crate::{
+    auth::Auth,
+    cancel::CancellationToken,
+    client::{self, HttpClient},
+    encoding, jsonpath, note, redirect,
+    retry::{CircuitBreaker, RetryBudget},
+};
+use anyhow::Result;
+use hyper::{header, Body, HeaderMap, Request, StatusCode, Uri};
+use json::JsonValue;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PaginateError {
+    #[error("page {0} failed: {1} returned {2}")]
+    PageFailed(usize, Uri, StatusCode),
+}
+
+#[derive(Clone, Debug)]
+pub struct Paginate {
+    pub max_pages: usize,
+    pub next_jsonpath: Option<String>,
+    /// Signs each page's request, as a signature covers its URI.
+    pub auth: Auth,
+    pub budget: Arc<RetryBudget>,
+    pub breaker: Arc<CircuitBreaker>,
+    pub cancel: CancellationToken,
+}
+
+/// Each link in a Link header value, as its target and its parameters with
+/// quoted values unescaped. Commas inside `<...>` or quotes don't split
+/// links.
+fn parse_links(value: &str) -> Vec<(&str, Vec<(String, String)>)> {
+    let mut links = Vec::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let target = &rest[start + 1..end];
+        let mut params = Vec::new();
+        rest = &rest[end + 1..];
+
+        while let Some(param) = rest.trim_start().strip_prefix(';') {
+            let param = param.trim_start();
+            let name_end = param.find(&['=', ';', ','][..]).unwrap_or(param.len());
+            let name = param[..name_end].trim().to_owned();
+            let mut value = String::new();
+            rest = &param[name_end..];
+
+            if let Some(param) = rest.strip_prefix('=') {
+                let param = param.trim_start();
+
+                if let Some(quoted) = param.strip_prefix('"') {
+                    let mut chars = quoted.char_indices();
+                    let mut end = quoted.len();
+
+                    while let Some((i, c)) = chars.next() {
+                        match c {
+                            '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                            '"' => {
+                                end = i + 1;
+                                break;
+                            }
+                            _ => value.push(c),
+                        }
+                    }
+
+                    rest = &quoted[end..];
+                } else {
+                    let end = param.find(&[';', ','][..]).unwrap_or(param.len());
+                    value = param[..end].trim().to_owned();
+                    rest = &param[end..];
+                }
+            }
+
+            params.push((name, value));
+        }
+
+        links.push((target, params));
+    }
+
+    links
+}
+
+/// The `rel="next"` target of the Link headers, resolved against `base`.
+pub fn next_link(headers: &HeaderMap, base: &Uri) -> Option<Uri> {
+    headers
+        .get_all(header::LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(parse_links)
+        .find(|(_, params)| {
+            params.iter().any(|(name, value)| {
+                name.eq_ignore_ascii_case("rel")
+                    && value
+                        .split_whitespace()
+                        .any(|rel| rel.eq_ignore_ascii_case("next"))
+            })
+        })
+        .and_then(|(target, _)| redirect::resolve(base, target))
+}
+
+/// The URI at `path` in a JSON body, resolved against `base`. A `null`,
+/// empty, or missing value means there is no next page.
+pub fn next_in_body(body: &[u8], path: &str, base: &Uri) -> Option<Uri> {
+    let document = std::str::from_utf8(body)
+        .ok()
+        .and_then(|body| json::parse(body).ok())?;
+    let matches = jsonpath::select(&document, path).ok()?;
+    let next = matches.first()?.as_str().filter(|next| !next.is_empty())?;
+
+    redirect::resolve(base, next)
+}
+
+/// Pages as one body: JSON arrays concatenated into one array, other JSON
+/// collected into an array of pages, and anything else one after another.
+pub fn combine(mut pages: Vec<Vec<u8>>) -> Vec<u8> {
+    if pages.len() == 1 {
+        return pages.remove(0);
+    }
+
+    let documents: Option<Vec<JsonValue>> = pages
+        .iter()
+        .map(|page| {
+            std::str::from_utf8(page)
+                .ok()
+                .and_then(|page| json::parse(page).ok())
+        })
+        .collect();
+
+    match documents {
+        Some(documents) if documents.iter().all(JsonValue::is_array) => {
+            let items = documents
+                .into_iter()
+                .flat_map(|mut document| {
+                    document
+                        .members_mut()
+                        .map(JsonValue::take)
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+
+            JsonValue::Array(items).dump().into_bytes()
+        }
+        Some(documents) => JsonValue::Array(documents).dump().into_bytes(),
+        None => {
+            let mut combined = Vec::new();
+
+            for page in pages {
+                if !combined.is_empty() && !combined.ends_with(b"\n") {
+                    combined.push(b'\n');
+                }
+
+                combined.extend(page);
+            }
+
+            combined
+        }
+    }
+}
+
+/// Fetches the pages after `first`, the body of `req`'s response with
+/// `headers`, until one has no next page or `max_pages` are in. Returns
+/// every page's body, combined, or an error when any page fails, rather
+/// than passing off some of the pages as all of them.
+pub async fn rest(
+    paginate: Paginate,
+    client: &HttpClient,
+    req: &Request<Body>,
+    body: &[u8],
+    headers: &HeaderMap,
+    first: Vec<u8>,
+) -> Result<Vec<u8>> {
+    let next = |uri: &Uri, headers: &HeaderMap, page: &[u8]| match &paginate.next_jsonpath {
+        Some(path) => next_in_body(page, path, uri),
+        None => next_link(headers, uri),
+    };
+
+    let mut visited = vec![req.uri().to_string()];
+    let mut uri = next(req.uri(), headers, &first);
+    let mut pages = vec![first];
+
+    while let Some(page_uri) = uri.take() {
+        if pages.len() >= paginate.max_pages {
+            note!(
+                "stopped after {} pages (raise --max-pages to fetch more)",
+                pages.len()
+            );
+            break;
+        }
+
+        if visited.contains(&page_uri.to_string()) {
+            note!("stopped paginating: {} was already fetched", page_uri);
+            break;
+        }
+        visited.push(page_uri.to_string());
+
+        let mut page_req = client::clone_request(req, body);
+        let same_host = page_uri.authority() == req.uri().authority();
+        *page_req.uri_mut() = page_uri.clone();
+
+        // as with redirects, credentials stay with the host they were meant for
+        if same_host {
+            paginate.auth.apply(&mut page_req, body)?;
+        } else {
+            redirect::strip_credentials(&mut page_req);
+        }
+
+        let mut res = client::send(
+            client,
+            &page_req,
+            body,
+            &paginate.budget,
+            &paginate.breaker,
+            &paginate.cancel,
+        )
+        .await?;
+
+        let mut raw = Vec::new();
+        while let Some(chunk) = client::next_chunk(&mut res, &paginate.cancel).await? {
+            raw.extend_from_slice(&chunk);
+        }
+
+        if !res.status().is_success() {
+            return Err(PaginateError::PageFailed(pages.len() + 1, page_uri, res.status()).into());
+        }
+
+        note!("page {}: {} {}", pages.len() + 1, res.status(), page_uri);
+
+        let page = encoding::decode(res.headers(), &raw).unwrap_or(raw);
+        uri = next(&page_uri, res.headers(), &page);
+        pages.push(page);
+    }
+
+    Ok(combine(pages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_next_link() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::LINK,
+            r#"<https://api.example.com/items?page=1&a=b,c>; rel="prev first", </items?page=3>; title="a; \"b\", c"; rel="next""#
+                .parse()
+                .unwrap(),
+        );
+        let base: Uri = "https://api.example.com/items?page=2".parse().unwrap();
+
+        assert_eq!(
+            next_link(&headers, &base),
+            Some("https://api.example.com/items?page=3".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn combines_json_arrays() {
+        let pages = vec![b"[1, 2]".to_vec(), b"[3]".to_vec(), b"[]".to_vec()];
+
+        assert_eq!(combine(pages), b"[1,2,3]");
+        assert_eq!(
+            combine(vec![b"{\"a\": 1}".to_vec(), b"{\"a\": 2}".to_vec()]),
+            b"[{\"a\":1},{\"a\":2}]"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! The `--json-output` document one rurl invocation prints and another reads
+//! with `--from-json` or `key=@-:$.path` items:
+//!
+//! ```json
+//! {"rurl": 1, "status": 200, "headers": {"content-type": "..."}, "body": ...}
+//! ```
+//!
+//! `body` is the parsed JSON body when it is JSON, and a string otherwise.
+//! A header received more than once, like `set-cookie`, is an array of its
+//! values in order, since joining them with commas is lossy.
+//!
+//! `--format json` prints the whole exchange in the same style, see
+//! [`exchange`].
+
+use crate::{decode, timing::Timings};
+use anyhow::Result;
+use hyper::{Body, HeaderMap, Request, Response};
+use json::JsonValue;
+use std::time::Duration;
+
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Headers as an object; a header received more than once is an array.
+pub(crate) fn headers_json(headers: &HeaderMap) -> JsonValue {
+    let mut object = JsonValue::new_object();
+
+    for name in headers.keys() {
+        let values: Vec<String> = headers
+            .get_all(name)
+            .iter()
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+            .collect();
+
+        object[name.as_str()] = if values.len() == 1 {
+            values[0].as_str().into()
+        } else {
+            values.into()
+        };
+    }
+
+    object
+}
+
+fn body_json(body: &[u8]) -> JsonValue {
+    let body = String::from_utf8_lossy(body);
+    json::parse(&body).unwrap_or_else(|_| body.into_owned().into())
+}
+
+pub fn envelope(status: u16, headers: &HeaderMap, body: &[u8]) -> JsonValue {
+    let mut document = JsonValue::new_object();
+    document["rurl"] = FORMAT_VERSION.into();
+    document["status"] = status.into();
+    document["headers"] = headers_json(headers);
+    document["body"] = body_json(body);
+
+    document
+}
+
+/// Sets `body`, or `body_base64` when it is not UTF-8.
+fn set_body(object: &mut JsonValue, body: &[u8]) {
+    if std::str::from_utf8(body).is_ok() {
+        object["body"] = body_json(body);
+    } else {
+        object["body_base64"] = decode::encode_base64(body).into();
+    }
+}
+
+fn milliseconds(duration: Option<Duration>) -> JsonValue {
+    duration.map_or(JsonValue::Null, |duration| {
+        (duration.as_secs_f64() * 1000.0).into()
+    })
+}
+
+/// The whole exchange for `--format json`:
+///
+/// ```json
+/// {"rurl": 1,
+///  "request": {"method": "GET", "uri": "...", "headers": {...}, "body": ...},
+///  "response": {"status": 200, "reason": "OK", "headers": {...}, "body": ...},
+///  "timings": {"dns_ms": 1.2, ..., "total_ms": 80.4, "bytes": 512}}
+/// ```
+///
+/// The response body is decoded; bodies that are not UTF-8 are given as
+/// `body_base64` instead. Trailers received after it are added to the
+/// response as `"trailers": {...}`.
+pub fn exchange(
+    req: &Request<Body>,
+    req_body: &[u8],
+    res: &Response<Body>,
+    res_body: &[u8],
+    trailers: Option<&HeaderMap>,
+    timings: &Timings,
+) -> JsonValue {
+    let mut request = JsonValue::new_object();
+    request["method"] = req.method().as_str().into();
+    request["uri"] = req.uri().to_string().into();
+    request["headers"] = headers_json(req.headers());
+    set_body(&mut request, req_body);
+
+    let mut response = JsonValue::new_object();
+    response["status"] = res.status().as_u16().into();
+    response["reason"] = res.status().canonical_reason().into();
+    response["headers"] = headers_json(res.headers());
+    set_body(&mut response, res_body);
+
+    if let Some(trailers) = trailers {
+        response["trailers"] = headers_json(trailers);
+    }
+
+    let mut times = JsonValue::new_object();
+    times["dns_ms"] = milliseconds(timings.dns);
+    times["connect_ms"] = milliseconds(timings.connect);
+    times["tls_ms"] = milliseconds(timings.tls);
+    times["ttfb_ms"] = milliseconds(timings.ttfb);
+    times["total_ms"] = milliseconds(timings.total);
+    times["bytes"] = timings.bytes.into();
+
+    let mut document = JsonValue::new_object();
+    document["rurl"] = FORMAT_VERSION.into();
+    document["request"] = request;
+    document["response"] = response;
+    document["timings"] = times;
+
+    document
+}
+
+/// The body of an envelope, or the whole document when it is plain JSON.
+pub fn body_of(document: &JsonValue) -> &JsonValue {
+    if document["rurl"].is_number() && document.has_key("body") {
+        &document["body"]
+    } else {
+        document
+    }
+}
+
+/// Reads a document from `path`, or from stdin when `path` is `-`.
+pub fn read_document(path: &str) -> Result<JsonValue> {
+    use std::io::Read as _;
+
+    let mut contents = String::new();
+
+    match path {
+        "-" => {
+            std::io::stdin().read_to_string(&mut contents)?;
+        }
+        path => contents = std::fs::read_to_string(path)?,
+    }
+
+    Ok(json::parse(&contents)?)
+}
+
+/// Stdin can only be read once, so every `@-` item shares this document.
+pub fn stdin_document() -> Result<&'static JsonValue> {
+    lazy_static::lazy_static! {
+        static ref STDIN: Result<JsonValue, String> =
+            read_document("-").map_err(|err| format!("could not read JSON from stdin: {}", err));
+    }
+
+    STDIN.as_ref().map_err(|err| anyhow::anyhow!("{}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::{HeaderValue, CONTENT_TYPE, SET_COOKIE};
+
+    #[test]
+    fn headers_received_twice_are_arrays() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("a=1"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("b=2"));
+
+        assert_eq!(
+            headers_json(&headers),
+            json::object! { "content-type" => "text/plain", "set-cookie" => json::array!["a=1", "b=2"] }
+        );
+    }
+
+    #[test]
+    fn envelopes_parse_json_bodies_and_keep_others_as_strings() {
+        let document = envelope(201, &HeaderMap::new(), b"{\"id\": 7}");
+        assert_eq!(document["rurl"], FORMAT_VERSION);
+        assert_eq!(document["status"], 201);
+        assert_eq!(document["body"]["id"], 7);
+        assert_eq!(body_of(&document), &json::object! { "id" => 7 });
+
+        let document = envelope(200, &HeaderMap::new(), b"plain text");
+        assert_eq!(document["body"], "plain text");
+    }
+
+    #[test]
+    fn plain_json_is_its_own_body() {
+        let document = json::object! { "status" => 200, "body" => "not an envelope" };
+        assert_eq!(body_of(&document), &document);
+    }
+
+    #[test]
+    fn bodies_that_are_not_utf8_are_base64() {
+        let mut object = JsonValue::new_object();
+        set_body(&mut object, b"\xff\xfe");
+        assert!(!object.has_key("body"));
+        assert_eq!(object["body_base64"], "//4=");
+
+        let mut object = JsonValue::new_object();
+        set_body(&mut object, b"[1, 2]");
+        assert_eq!(object["body"], json::array![1, 2]);
+    }
+
+    #[test]
+    fn durations_are_milliseconds_or_null() {
+        assert_eq!(milliseconds(Some(Duration::from_micros(1500))), 1.5);
+        assert!(milliseconds(None).is_null());
+    }
+}
@@ -0,0 +1,318 @@
+//! Plugins: executables on `PATH` that add auth schemes and body formatters
+//! without patching rurl. `--auth-type NAME` runs `rurl-auth-NAME` and
+//! `--formatter NAME` runs `rurl-format-NAME`; each is given one JSON
+//! document on stdin, as described in the README.
+
+use crate::{
+    decode,
+    pipe::{headers_json, FORMAT_VERSION},
+};
+use anyhow::Result;
+use hyper::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Body, Request, StatusCode,
+};
+use json::JsonValue;
+use std::{
+    io::Write as _,
+    process::{Command, Stdio},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PluginError {
+    #[error("invalid plugin name {0}, expected letters, digits, - and _")]
+    InvalidName(String),
+    #[error("no {0} on PATH")]
+    NotFound(String),
+    #[error("could not run {0}: {1}")]
+    Spawn(String, std::io::Error),
+    #[error("{0} failed{1}")]
+    Failed(String, String),
+    #[error("{0} printed invalid JSON: {1}")]
+    InvalidJson(String, json::Error),
+    #[error("{0} set an invalid header {1}")]
+    InvalidHeader(String, String),
+}
+
+/// Plugin names become part of a program name, so only plain ones are taken.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The auth and format plugins on `PATH`, each by its program name, first
+/// on `PATH` winning as it does when run.
+pub fn discover() -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    let dirs = std::env::var_os("PATH").unwrap_or_default();
+
+    for dir in std::env::split_paths(&dirs) {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let plugin = ["rurl-auth-", "rurl-format-"]
+                .iter()
+                .any(|prefix| name.strip_prefix(prefix).is_some_and(is_valid_name));
+
+            if plugin && is_executable(&entry.path()) && !found.contains(&name) {
+                found.push(name);
+            }
+        }
+    }
+
+    found.sort();
+    found
+}
+
+#[cfg(unix)]
+fn is_executable(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    std::fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &std::path::Path) -> bool {
+    path.is_file()
+}
+
+/// Bodies go as they are, as text when they are UTF-8, so a signature
+/// covers the exact bytes sent.
+fn set_body(object: &mut JsonValue, body: &[u8]) {
+    match std::str::from_utf8(body) {
+        Ok(body) => object["body"] = body.into(),
+        Err(_) => object["body_base64"] = decode::encode_base64(body).into(),
+    }
+}
+
+fn run(program: &str, input: &JsonValue) -> Result<Vec<u8>, PluginError> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => PluginError::NotFound(program.into()),
+            _ => PluginError::Spawn(program.into(), err),
+        })?;
+
+    // written from a thread, as the plugin may print before it has read it all
+    let input = input.dump();
+    let stdin = child.stdin.take();
+    let writer = std::thread::spawn(move || {
+        if let Some(mut stdin) = stdin {
+            let _ = stdin.write_all(input.as_bytes());
+        }
+    });
+
+    let output = child
+        .wait_with_output()
+        .map_err(|err| PluginError::Spawn(program.into(), err))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let detail = match stderr.trim() {
+            "" => format!(" with {}", output.status),
+            stderr => format!(": {}", stderr),
+        };
+
+        return Err(PluginError::Failed(program.into(), detail));
+    }
+
+    Ok(output.stdout)
+}
+
+/// What an auth plugin is given: the request as it will be sent.
+fn auth_input(req: &Request<Body>, body: &[u8]) -> JsonValue {
+    let mut request = JsonValue::new_object();
+    request["method"] = req.method().as_str().into();
+    request["uri"] = req.uri().to_string().into();
+    request["headers"] = headers_json(req.headers());
+    set_body(&mut request, body);
+
+    let mut input = JsonValue::new_object();
+    input["rurl"] = FORMAT_VERSION.into();
+    input["request"] = request;
+
+    input
+}
+
+/// Applies an auth plugin's answer to `headers`, checking all of it before
+/// changing anything.
+fn apply_headers(program: &str, output: &[u8], headers: &mut HeaderMap) -> Result<(), PluginError> {
+    let output = json::parse(&String::from_utf8_lossy(output))
+        .map_err(|err| PluginError::InvalidJson(program.into(), err))?;
+
+    let mut changes = Vec::new();
+    for (name, value) in output["headers"].entries() {
+        let invalid = || PluginError::InvalidHeader(program.into(), name.into());
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?;
+
+        let values: Vec<&JsonValue> = match value {
+            JsonValue::Null => Vec::new(),
+            JsonValue::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+
+        let values = values
+            .into_iter()
+            .map(|value| {
+                value
+                    .as_str()
+                    .and_then(|value| HeaderValue::from_str(value).ok())
+                    .ok_or_else(invalid)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        changes.push((name, values));
+    }
+
+    for (name, values) in changes {
+        headers.remove(&name);
+        for value in values {
+            headers.append(&name, value);
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs `rurl-auth-NAME` on the built request, and applies the headers it
+/// answers with: a string sets one, an array repeats it, and null removes it.
+pub fn authenticate(name: &str, req: &mut Request<Body>, body: &[u8]) -> Result<()> {
+    let program = format!("rurl-auth-{}", name);
+
+    let output = run(&program, &auth_input(req, body))?;
+    apply_headers(&program, &output, req.headers_mut())?;
+
+    Ok(())
+}
+
+/// What `rurl-format-NAME` prints for the response, shown in place of the
+/// body.
+pub fn format(name: &str, status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Result<String> {
+    if !is_valid_name(name) {
+        return Err(PluginError::InvalidName(name.into()).into());
+    }
+
+    let program = format!("rurl-format-{}", name);
+
+    let mut response = JsonValue::new_object();
+    response["status"] = status.as_u16().into();
+    response["reason"] = status.canonical_reason().into();
+    response["headers"] = headers_json(headers);
+    set_body(&mut response, body);
+
+    let mut input = JsonValue::new_object();
+    input["rurl"] = FORMAT_VERSION.into();
+    input["response"] = response;
+
+    let output = run(&program, &input)?;
+
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::{AUTHORIZATION, CONTENT_TYPE, DATE};
+
+    fn apply(output: &str) -> Result<HeaderMap, PluginError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(DATE, HeaderValue::from_static("yesterday"));
+
+        apply_headers("rurl-auth-test", output.as_bytes(), &mut headers).map(|_| headers)
+    }
+
+    #[test]
+    fn encodes_the_request_for_auth_plugins() {
+        let req = Request::post("https://example.com/things?page=2")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::empty())
+            .unwrap();
+
+        let input = auth_input(&req, b"{\"id\": 1}");
+        assert_eq!(input["rurl"], FORMAT_VERSION);
+        assert_eq!(input["request"]["method"], "POST");
+        assert_eq!(input["request"]["uri"], "https://example.com/things?page=2");
+        assert_eq!(
+            input["request"]["headers"]["content-type"],
+            "application/json"
+        );
+        // the exact bytes, not parsed, so a signature over them holds
+        assert_eq!(input["request"]["body"], "{\"id\": 1}");
+
+        let input = auth_input(&req, b"\xff\x00");
+        assert!(!input["request"].has_key("body"));
+        assert_eq!(input["request"]["body_base64"], "/wA=");
+    }
+
+    #[test]
+    fn decodes_headers_to_set_repeat_and_remove() {
+        let headers = apply(
+            r#"{"headers": {"Authorization": "Signed abc", "X-Sig": ["1", "2"], "Date": null}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(headers[AUTHORIZATION], "Signed abc");
+        assert_eq!(
+            headers.get_all("x-sig").iter().collect::<Vec<_>>(),
+            ["1", "2"]
+        );
+        assert!(!headers.contains_key(DATE));
+        assert_eq!(headers[CONTENT_TYPE], "application/json");
+
+        assert_eq!(apply("{}").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn malformed_replies_are_errors() {
+        assert!(matches!(
+            apply("Authorization: Signed abc"),
+            Err(PluginError::InvalidJson(..))
+        ));
+        assert!(matches!(apply(""), Err(PluginError::InvalidJson(..))));
+
+        for reply in &[
+            r#"{"headers": {"Bad Name": "x"}}"#,
+            r#"{"headers": {"X-Sig": 7}}"#,
+            r#"{"headers": {"X-Sig": ["1", {}]}}"#,
+            r#"{"headers": {"X-Sig": "line\nbreak"}}"#,
+        ] {
+            assert!(
+                matches!(apply(reply), Err(PluginError::InvalidHeader(_, _))),
+                "{}",
+                reply
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_replies_change_nothing() {
+        let mut headers = HeaderMap::new();
+        headers.insert(DATE, HeaderValue::from_static("yesterday"));
+
+        let reply = br#"{"headers": {"Date": null, "X-Sig": 7}}"#;
+        assert!(apply_headers("rurl-auth-test", reply, &mut headers).is_err());
+        assert_eq!(headers[DATE], "yesterday");
+    }
+
+    #[test]
+    fn plugin_names_are_plain() {
+        assert!(is_valid_name("hmac-v2_beta"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("../evil"));
+        assert!(!is_valid_name("a b"));
+        assert!(format("../evil", StatusCode::OK, &HeaderMap::new(), b"").is_err());
+    }
+}
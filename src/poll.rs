@@ -0,0 +1,252 @@
+use crate::{
+    cancel::{CancellationToken, CancelledError},
+    client::{self, HttpClient},
+    jsonpath, note,
+    retry::{CircuitBreaker, RetryBudget},
+};
+use anyhow::Result;
+use hyper::{Body, Request, Response};
+use json::JsonValue;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// Comparison operators, longest first so `<=` is not read as `<`.
+const OPERATORS: &[&str] = &["==", "!=", "<=", ">=", "<", ">"];
+
+#[derive(Debug, Error)]
+pub enum PollError {
+    #[error("invalid duration {0}, expected e.g. 500ms, 2s, 5m, or 1h")]
+    DurationParseError(String),
+    #[error("invalid condition {0}, expected e.g. `$.status == \"ready\"`")]
+    ConditionParseError(String),
+    #[error("{0} did not hold within {1:?}")]
+    Timeout(String, Duration),
+}
+
+/// Parses `500ms`, `2s`, `5m`, `1h`, or a bare number of seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, PollError> {
+    let error = || PollError::DurationParseError(s.into());
+
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let value: f64 = s[..split].parse().map_err(|_| error())?;
+
+    let seconds = match &s[split..] {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 60.0 * 60.0,
+        _ => return Err(error()),
+    };
+
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+#[derive(Clone, Debug)]
+pub struct Condition {
+    source: String,
+    path: String,
+    comparison: Option<(&'static str, JsonValue)>,
+}
+
+impl std::str::FromStr for Condition {
+    type Err = PollError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let operator = OPERATORS
+            .iter()
+            .filter_map(|operator| s.find(operator).map(|i| (i, *operator)))
+            .min_by_key(|&(i, operator)| (i, std::cmp::Reverse(operator.len())));
+
+        let (path, comparison) = match operator {
+            Some((i, operator)) => {
+                let expected = s[i + operator.len()..].trim();
+
+                // allow `$.status == ready` as well as `$.status == "ready"`
+                let expected = json::parse(expected).unwrap_or_else(|_| expected.into());
+
+                (&s[..i], Some((operator, expected)))
+            }
+            None => (s, None),
+        };
+
+        let path = path.trim();
+        jsonpath::parse(path).map_err(|_| Self::Err::ConditionParseError(s.into()))?;
+
+        Ok(Self {
+            source: s.into(),
+            path: path.into(),
+            comparison,
+        })
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn compare(actual: &JsonValue, operator: &str, expected: &JsonValue) -> bool {
+    match operator {
+        "==" => actual == expected,
+        "!=" => actual != expected,
+        _ => match (actual.as_f64(), expected.as_f64()) {
+            (Some(actual), Some(expected)) => match operator {
+                "<" => actual < expected,
+                "<=" => actual <= expected,
+                ">" => actual > expected,
+                _ => actual >= expected,
+            },
+            _ => false,
+        },
+    }
+}
+
+impl Condition {
+    /// Whether any value matched by the path satisfies the condition; a bare
+    /// path holds when it matches anything other than `null` or `false`.
+    /// Otherwise, describes what was found instead.
+    pub fn check(&self, body: &[u8]) -> Result<(), String> {
+        let document = std::str::from_utf8(body)
+            .ok()
+            .and_then(|body| json::parse(body).ok())
+            .ok_or_else(|| "the body is not JSON".to_owned())?;
+
+        let matches = jsonpath::select(&document, &self.path).map_err(|err| err.to_string())?;
+
+        let holds = matches.iter().any(|actual| match &self.comparison {
+            Some((operator, expected)) => compare(actual, operator, expected),
+            None => !actual.is_null() && actual.as_bool() != Some(false),
+        });
+
+        match (holds, matches.first()) {
+            (true, _) => Ok(()),
+            (false, Some(actual)) => Err(format!("{} is {}", self.path, actual.dump())),
+            (false, None) => Err(format!("{} matched nothing", self.path)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Poll {
+    pub condition: Condition,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub budget: Arc<RetryBudget>,
+    pub breaker: Arc<CircuitBreaker>,
+    pub cancel: CancellationToken,
+}
+
+/// Re-sends `req` every `poll.interval` until `poll.condition` holds for its
+/// body, returning that response with the body buffered.
+pub async fn run(
+    poll: Poll,
+    client: &HttpClient,
+    req: &Request<Body>,
+    body: &[u8],
+) -> Result<Response<Body>> {
+    let start = Instant::now();
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let mut res =
+            client::send(client, req, body, &poll.budget, &poll.breaker, &poll.cancel).await?;
+
+        let mut buf = Vec::new();
+        while let Some(chunk) = client::next_chunk(&mut res, &poll.cancel).await? {
+            buf.extend_from_slice(&chunk);
+        }
+
+        let (parts, _) = res.into_parts();
+
+        let found = match poll.condition.check(&buf) {
+            Ok(()) => {
+                note!("{} held after {} attempts\n", poll.condition, attempt);
+                return Ok(Response::from_parts(parts, Body::from(buf)));
+            }
+            Err(found) => found,
+        };
+
+        if start.elapsed() + poll.interval > poll.timeout {
+            return Err(PollError::Timeout(poll.condition.to_string(), poll.timeout).into());
+        }
+
+        note!(
+            "attempt {}: {} ({}), retrying in {:?}",
+            attempt,
+            found,
+            parts.status,
+            poll.interval
+        );
+
+        tokio::select! {
+            _ = tokio::time::delay_for(poll.interval) => {}
+            _ = poll.cancel.cancelled() => {
+                return Err(CancelledError(format!("waiting for {}", poll.condition)).into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("1.5").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_duration("2s").unwrap(), Duration::from_secs(2));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("2d").is_err());
+    }
+
+    #[test]
+    fn checks_conditions() {
+        let check = |condition: &str, body: &str| {
+            condition
+                .parse::<Condition>()
+                .unwrap()
+                .check(body.as_bytes())
+        };
+
+        assert!(check(r#"$.status == "ready""#, r#"{"status": "ready"}"#).is_ok());
+        assert!(check("$.status == ready", r#"{"status": "ready"}"#).is_ok());
+        assert!(check(
+            "$.jobs[*].done <= 3",
+            r#"{"jobs": [{"done": 9}, {"done": 3}]}"#
+        )
+        .is_ok());
+        assert!(check("$.done", r#"{"done": true}"#).is_ok());
+
+        assert_eq!(
+            check("$.status != pending", r#"{"status": "pending"}"#),
+            Err(r#"$.status is "pending""#.into())
+        );
+        assert_eq!(
+            check("$.done", r#"{"done": false}"#),
+            Err("$.done is false".into())
+        );
+        assert_eq!(
+            check("$.count > 1", "{}"),
+            Err("$.count matched nothing".into())
+        );
+        assert_eq!(
+            check("$.count > 1", "<html>"),
+            Err("the body is not JSON".into())
+        );
+
+        assert!("status == ready".parse::<Condition>().is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use hyper::{service::Service, Uri};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+fn origin(uri: &Uri) -> String {
+    format!(
+        "{}://{}",
+        uri.scheme_str().unwrap_or("http"),
+        uri.authority()
+            .map(|authority| authority.as_str())
+            .unwrap_or_default()
+    )
+}
+
+/// A connector that can open connections ahead of time. Each warm connection
+/// is handed to the next request for its origin instead of dialing again;
+/// after that the client's own pool keeps it alive as usual.
+#[derive(Debug)]
+pub struct PrimedConnector<C, T> {
+    inner: C,
+    warm: Arc<Mutex<HashMap<String, Vec<T>>>>,
+}
+
+impl<C: Clone, T> Clone for PrimedConnector<C, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            warm: self.warm.clone(),
+        }
+    }
+}
+
+impl<C, T> PrimedConnector<C, T>
+where
+    C: Service<Uri, Response = T> + Clone,
+{
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            warm: Arc::default(),
+        }
+    }
+
+    /// Resolves and connects to the origin of `uri` now, keeping the
+    /// connection for the next request there. Returns how long it took.
+    pub async fn preconnect(&self, uri: &Uri) -> Result<Duration, C::Error> {
+        let start = Instant::now();
+        let mut inner = self.inner.clone();
+
+        std::future::poll_fn(|cx| inner.poll_ready(cx)).await?;
+        let connection = inner.call(uri.clone()).await?;
+        let elapsed = start.elapsed();
+
+        self.warm
+            .lock()
+            .unwrap()
+            .entry(origin(uri))
+            .or_default()
+            .push(connection);
+
+        Ok(elapsed)
+    }
+
+    /// Number of warm connections not yet used.
+    pub fn warm(&self) -> usize {
+        self.warm.lock().unwrap().values().map(Vec::len).sum()
+    }
+}
+
+impl<C, T> Service<Uri> for PrimedConnector<C, T>
+where
+    C: Service<Uri, Response = T>,
+    C::Future: Send + 'static,
+    T: Send + 'static,
+{
+    type Response = T;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<T, C::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connection = self
+            .warm
+            .lock()
+            .unwrap()
+            .get_mut(&origin(&uri))
+            .and_then(Vec::pop);
+
+        if let Some(connection) = connection {
+            return Box::pin(async move { Ok(connection) });
+        }
+
+        Box::pin(self.inner.call(uri))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Numbers each connection it opens, from 1.
+    #[derive(Clone, Default)]
+    struct Dialer(Arc<AtomicUsize>);
+
+    impl Service<Uri> for Dialer {
+        type Response = usize;
+        type Error = std::io::Error;
+        type Future = std::future::Ready<Result<usize, std::io::Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: Uri) -> Self::Future {
+            std::future::ready(Ok(self.0.fetch_add(1, Ordering::SeqCst) + 1))
+        }
+    }
+
+    #[test]
+    fn origins_ignore_the_path() {
+        let uri = |uri: &str| origin(&uri.parse().unwrap());
+
+        assert_eq!(
+            uri("https://example.com:8443/a?b"),
+            "https://example.com:8443"
+        );
+        assert_eq!(uri("https://example.com/"), "https://example.com");
+        assert_ne!(uri("http://example.com/"), uri("https://example.com/"));
+    }
+
+    #[tokio::test]
+    async fn the_next_request_to_the_origin_takes_the_warm_connection() {
+        let dialer = Dialer::default();
+        let mut connector = PrimedConnector::new(dialer.clone());
+
+        connector
+            .preconnect(&"https://example.com/".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(connector.warm(), 1);
+        assert_eq!(dialer.0.load(Ordering::SeqCst), 1);
+
+        let other = connector.call("https://other.test/".parse().unwrap());
+        assert_eq!(other.await.unwrap(), 2);
+        assert_eq!(connector.warm(), 1);
+
+        let warm = connector.call("https://example.com/users".parse().unwrap());
+        assert_eq!(warm.await.unwrap(), 1);
+        assert_eq!(connector.warm(), 0);
+
+        // once taken, the client's pool keeps it, so the next dials again
+        let again = connector.call("https://example.com/".parse().unwrap());
+        assert_eq!(again.await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn clones_share_warm_connections() {
+        let connector = PrimedConnector::new(Dialer::default());
+        let mut clone = connector.clone();
+
+        connector
+            .preconnect(&"http://example.com/".parse().unwrap())
+            .await
+            .unwrap();
+
+        let warm = clone.call("http://example.com/".parse().unwrap());
+        assert_eq!(warm.await.unwrap(), 1);
+        assert_eq!(connector.warm(), 0);
+    }
+}
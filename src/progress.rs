@@ -0,0 +1,171 @@
+//! The upload progress bar: bytes sent, the percentage of the body, and
+//! throughput, redrawn in place on stderr. It only shows for bodies of at
+//! least [`MIN_BYTES`], and only once `set_enabled` turns it on, which the
+//! binary does for single requests when stderr is a terminal. Bodies are
+//! also sent at no more than the rate `set_limit_rate` gives.
+
+use crate::rate::Throttle;
+use hyper::{body::Bytes, Body};
+use std::{
+    io::Write as _,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+/// Smaller bodies go out before a bar would be readable.
+pub const MIN_BYTES: usize = 1 << 20;
+
+const CHUNK: usize = 64 * 1024;
+const REDRAW: Duration = Duration::from_millis(100);
+const WIDTH: usize = 30;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Bytes per second for `--limit-rate`, or 0 for no limit.
+static LIMIT_RATE: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+pub fn set_limit_rate(bytes_per_second: Option<u64>) {
+    LIMIT_RATE.store(bytes_per_second.unwrap_or(0), Ordering::SeqCst);
+}
+
+/// `1536` as `1.5 KiB`.
+pub fn format_bytes(bytes: f64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", value),
+        _ => format!("{:.1} {}", value, units[unit]),
+    }
+}
+
+struct Bar {
+    total: usize,
+    sent: usize,
+    start: Instant,
+    drawn: Option<Instant>,
+}
+
+impl Bar {
+    fn line(&self) -> String {
+        let fraction = self.sent as f64 / self.total.max(1) as f64;
+        let filled = (fraction * WIDTH as f64) as usize;
+        let elapsed = self.start.elapsed().as_secs_f64().max(1e-9);
+
+        format!(
+            "upload [{}{}] {} / {}  {:>3.0}%  {}/s",
+            "#".repeat(filled),
+            " ".repeat(WIDTH - filled),
+            format_bytes(self.sent as f64),
+            format_bytes(self.total as f64),
+            fraction * 100.0,
+            format_bytes(self.sent as f64 / elapsed),
+        )
+    }
+
+    fn advance(&mut self, bytes: usize) {
+        self.sent += bytes;
+
+        let due = self.drawn.is_none_or(|drawn| drawn.elapsed() >= REDRAW);
+        if due || self.sent == self.total {
+            eprint!("\r\x1b[2K{}", self.line());
+            let _ = std::io::stderr().flush();
+            self.drawn = Some(Instant::now());
+        }
+    }
+
+    fn finish(&self) {
+        if self.sent == self.total {
+            eprintln!();
+        } else {
+            // the request failed partway, so don't leave a stale bar behind
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
+
+/// The request body, streamed in chunks behind a progress bar when the bar
+/// is enabled and the body is large enough to want one.
+///
+/// With `hold`, nothing is sent until the other end of `hold` sends or `wait`
+/// passes, and nothing at all if it is dropped first, as it is once a final
+/// response is in.
+pub fn upload(body: &[u8], hold: Option<(oneshot::Receiver<()>, Duration)>) -> Body {
+    let bar = ENABLED.load(Ordering::SeqCst) && body.len() >= MIN_BYTES;
+    let mut throttle = match LIMIT_RATE.load(Ordering::SeqCst) {
+        0 => None,
+        rate => Some(Throttle::new(rate)),
+    };
+
+    if !bar && hold.is_none() && throttle.is_none() {
+        return Body::from(body.to_vec());
+    }
+
+    let body = Bytes::from(body.to_vec());
+    let (mut sender, stream) = Body::channel();
+
+    tokio::spawn(async move {
+        if let Some((answered, wait)) = hold {
+            if let Ok(Err(_)) = tokio::time::timeout(wait, answered).await {
+                return;
+            }
+        }
+
+        let mut bar = Some(Bar {
+            total: body.len(),
+            sent: 0,
+            start: Instant::now(),
+            drawn: None,
+        })
+        .filter(|_| bar);
+
+        let size = throttle
+            .as_ref()
+            .map_or(CHUNK, |throttle| throttle.chunk_size(CHUNK));
+
+        for offset in (0..body.len()).step_by(size) {
+            let chunk = body.slice(offset..(offset + size).min(body.len()));
+            let len = chunk.len();
+
+            if sender.send_data(chunk).await.is_err() {
+                break;
+            }
+
+            if let Some(bar) = &mut bar {
+                bar.advance(len);
+            }
+
+            if let Some(throttle) = &mut throttle {
+                throttle.pass(len).await;
+            }
+        }
+
+        if let Some(bar) = &bar {
+            bar.finish();
+        }
+    });
+
+    stream
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(format_bytes(512.0), "512 B");
+        assert_eq!(format_bytes(1536.0), "1.5 KiB");
+        assert_eq!(format_bytes(40.0 * 1024.0 * 1024.0), "40.0 MiB");
+    }
+}
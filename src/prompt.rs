@@ -0,0 +1,53 @@
+//! Reads secrets from the terminal without echoing them, so passwords and
+//! tokens stay out of `ps` output and shell history.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead as _, BufReader, Write as _},
+    process::{Command, Stdio},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PromptError {
+    #[error("cannot prompt for {0} without a terminal")]
+    NoTerminal(String),
+    #[error("could not read {0}: {1}")]
+    IOError(String, std::io::Error),
+}
+
+/// Turns echo on the terminal off or back on; `stty` acts on its stdin.
+fn set_echo(tty: &File, echo: bool) {
+    let _ = Command::new("stty")
+        .arg(if echo { "echo" } else { "-echo" })
+        .stdin(tty.try_clone().map_or_else(|_| Stdio::null(), Stdio::from))
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// Prompts for `name` on the controlling terminal, even when stdin and
+/// stdout are redirected, and reads one line with echo turned off.
+pub fn secret(name: &str) -> Result<String, PromptError> {
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|_| PromptError::NoTerminal(name.into()))?;
+
+    write!(tty, "{}: ", name)
+        .and_then(|_| tty.flush())
+        .map_err(|err| PromptError::IOError(name.into(), err))?;
+
+    set_echo(&tty, false);
+    let mut line = String::new();
+    let read = tty
+        .try_clone()
+        .and_then(|input| BufReader::new(input).read_line(&mut line));
+    set_echo(&tty, true);
+
+    // the user's Enter was not echoed either
+    let _ = writeln!(tty);
+    read.map_err(|err| PromptError::IOError(name.into(), err))?;
+
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).into())
+}
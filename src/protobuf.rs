@@ -0,0 +1,1016 @@
+//! Protobuf bodies for `--proto`. Data items are encoded as a message from a
+//! compiled descriptor set (`protoc --include_imports --descriptor_set_out`),
+//! and protobuf responses are decoded back to JSON, both in the proto3 JSON
+//! mapping: lowerCamel field names, 64-bit integers as strings, enums by
+//! name, and bytes as base64. `--grpc-web` wraps messages in gRPC-web's
+//! length-prefixed frames; Twirp takes them bare.
+
+use crate::{
+    decode::{base64, encode_base64},
+    generate::lower_camel_case,
+};
+use anyhow::Result;
+use json::JsonValue;
+use std::{collections::HashMap, convert::TryInto as _, path::Path};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ProtobufError {
+    #[error("protobuf data ends in the middle of a field")]
+    UnexpectedEnd,
+    #[error("protobuf varint is longer than 10 bytes")]
+    LongVarint,
+    #[error("unsupported protobuf wire type {0}")]
+    UnsupportedWireType(u64),
+    #[error("protobuf field {0} has the wrong wire type for its type")]
+    WireTypeMismatch(String),
+    #[error("protobuf string field {0} is not valid UTF-8")]
+    InvalidString(String),
+    #[error("no message {0} in the descriptor set")]
+    UnknownMessage(String),
+    #[error("{0} has no field {1}")]
+    UnknownField(String, String),
+    #[error("invalid value for protobuf field {0}: {1}")]
+    InvalidValue(String, String),
+    #[error("compressed gRPC-web frames are not supported")]
+    CompressedFrame,
+}
+
+// field types, as numbered in descriptor.proto
+const DOUBLE: u64 = 1;
+const FLOAT: u64 = 2;
+const INT64: u64 = 3;
+const UINT64: u64 = 4;
+const INT32: u64 = 5;
+const FIXED64: u64 = 6;
+const FIXED32: u64 = 7;
+const BOOL: u64 = 8;
+const STRING: u64 = 9;
+const GROUP: u64 = 10;
+const MESSAGE: u64 = 11;
+const BYTES: u64 = 12;
+const UINT32: u64 = 13;
+const ENUM: u64 = 14;
+const SFIXED32: u64 = 15;
+const SFIXED64: u64 = 16;
+const SINT32: u64 = 17;
+const SINT64: u64 = 18;
+
+// wire types
+const VARINT: u64 = 0;
+const I64: u64 = 1;
+const LEN: u64 = 2;
+const I32: u64 = 5;
+
+#[derive(Clone, Copy, Debug)]
+enum Wire<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> Wire<'a> {
+    fn varint(self) -> u64 {
+        match self {
+            Wire::Varint(value) => value,
+            _ => 0,
+        }
+    }
+
+    fn bytes(self) -> &'a [u8] {
+        match self {
+            Wire::Bytes(bytes) => bytes,
+            _ => &[],
+        }
+    }
+
+    fn text(self) -> String {
+        String::from_utf8_lossy(self.bytes()).into_owned()
+    }
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ProtobufError> {
+        if self.input.len() < len {
+            return Err(ProtobufError::UnexpectedEnd);
+        }
+
+        let (taken, rest) = self.input.split_at(len);
+        self.input = rest;
+
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64, ProtobufError> {
+        let mut value = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(ProtobufError::LongVarint)
+    }
+
+    fn fixed64(&mut self) -> Result<u64, ProtobufError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn fixed32(&mut self) -> Result<u32, ProtobufError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn field(&mut self) -> Result<Option<(u64, Wire<'a>)>, ProtobufError> {
+        if self.input.is_empty() {
+            return Ok(None);
+        }
+
+        let key = self.varint()?;
+        let wire = match key & 7 {
+            VARINT => Wire::Varint(self.varint()?),
+            I64 => Wire::Fixed64(self.fixed64()?),
+            LEN => {
+                let len = self.varint()? as usize;
+                Wire::Bytes(self.take(len)?)
+            }
+            I32 => Wire::Fixed32(self.fixed32()?),
+            other => return Err(ProtobufError::UnsupportedWireType(other)),
+        };
+
+        Ok(Some((key >> 3, wire)))
+    }
+
+    /// One element of a packed repeated field of type `kind`.
+    fn packed(&mut self, kind: u64) -> Result<Wire<'a>, ProtobufError> {
+        Ok(match kind {
+            DOUBLE | FIXED64 | SFIXED64 => Wire::Fixed64(self.fixed64()?),
+            FLOAT | FIXED32 | SFIXED32 => Wire::Fixed32(self.fixed32()?),
+            _ => Wire::Varint(self.varint()?),
+        })
+    }
+}
+
+fn parse(input: &[u8]) -> Result<Vec<(u64, Wire<'_>)>, ProtobufError> {
+    let mut reader = Reader { input };
+    let mut fields = Vec::new();
+
+    while let Some(field) = reader.field()? {
+        fields.push(field);
+    }
+
+    Ok(fields)
+}
+
+fn push_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    output.push(value as u8);
+}
+
+fn push_len(output: &mut Vec<u8>, number: u64, bytes: &[u8]) {
+    push_varint(output, number << 3 | LEN);
+    push_varint(output, bytes.len() as u64);
+    output.extend_from_slice(bytes);
+}
+
+fn zigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Scalars that repeat as one packed run rather than a field each.
+fn packable(kind: u64) -> bool {
+    !matches!(kind, STRING | BYTES | MESSAGE | GROUP)
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.into()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Field {
+    name: String,
+    json_name: String,
+    number: u64,
+    kind: u64,
+    /// The message or enum type, fully qualified without the leading dot.
+    type_name: String,
+    repeated: bool,
+    packed: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Message {
+    fields: Vec<Field>,
+    /// The generated entry type of a `map<K, V>` field.
+    map_entry: bool,
+}
+
+/// The request and response types of an RPC.
+#[derive(Clone, Debug)]
+pub struct Method {
+    pub input: String,
+    pub output: String,
+}
+
+/// The messages, enums, and services of a `FileDescriptorSet`.
+#[derive(Clone, Debug, Default)]
+pub struct Descriptors {
+    messages: HashMap<String, Message>,
+    enums: HashMap<String, Vec<(String, i64)>>,
+    /// Keyed by `pkg.Service/Method`, the end of the path gRPC-web and
+    /// Twirp call it on.
+    methods: HashMap<String, Method>,
+}
+
+impl Descriptors {
+    pub fn load(path: &Path) -> Result<Self> {
+        let input = std::fs::read(path)
+            .map_err(|err| anyhow::anyhow!("could not read {}: {}", path.display(), err))?;
+
+        Ok(Self::parse(&input)?)
+    }
+
+    pub fn parse(input: &[u8]) -> Result<Self, ProtobufError> {
+        let mut descriptors = Self::default();
+
+        for (number, file) in parse(input)? {
+            if number == 1 {
+                descriptors.add_file(file.bytes())?;
+            }
+        }
+
+        Ok(descriptors)
+    }
+
+    fn add_file(&mut self, input: &[u8]) -> Result<(), ProtobufError> {
+        let parts = parse(input)?;
+        let text = |number| {
+            parts
+                .iter()
+                .find(|(n, _)| *n == number)
+                .map(|(_, wire)| wire.text())
+                .unwrap_or_default()
+        };
+        let package = text(2);
+        let proto3 = text(12) == "proto3";
+
+        for (number, wire) in &parts {
+            match number {
+                4 => self.add_message(&package, wire.bytes(), proto3)?,
+                5 => self.add_enum(&package, wire.bytes())?,
+                6 => self.add_service(&package, wire.bytes())?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_message(
+        &mut self,
+        scope: &str,
+        input: &[u8],
+        proto3: bool,
+    ) -> Result<(), ProtobufError> {
+        let parts = parse(input)?;
+        let name = parts
+            .iter()
+            .find(|(number, _)| *number == 1)
+            .map(|(_, wire)| qualify(scope, &wire.text()))
+            .unwrap_or_default();
+        let mut message = Message::default();
+
+        for (number, wire) in parts {
+            match number {
+                2 => message.fields.push(field(wire.bytes(), proto3)?),
+                3 => self.add_message(&name, wire.bytes(), proto3)?,
+                4 => self.add_enum(&name, wire.bytes())?,
+                7 => {
+                    message.map_entry = parse(wire.bytes())?
+                        .iter()
+                        .any(|(number, option)| *number == 7 && option.varint() != 0)
+                }
+                _ => {}
+            }
+        }
+
+        self.messages.insert(name, message);
+
+        Ok(())
+    }
+
+    fn add_enum(&mut self, scope: &str, input: &[u8]) -> Result<(), ProtobufError> {
+        let mut name = String::new();
+        let mut values = Vec::new();
+
+        for (number, wire) in parse(input)? {
+            match number {
+                1 => name = qualify(scope, &wire.text()),
+                2 => {
+                    let mut value = (String::new(), 0);
+
+                    for (number, wire) in parse(wire.bytes())? {
+                        match number {
+                            1 => value.0 = wire.text(),
+                            2 => value.1 = wire.varint() as i32 as i64,
+                            _ => {}
+                        }
+                    }
+
+                    values.push(value);
+                }
+                _ => {}
+            }
+        }
+
+        self.enums.insert(name, values);
+
+        Ok(())
+    }
+
+    fn add_service(&mut self, scope: &str, input: &[u8]) -> Result<(), ProtobufError> {
+        let parts = parse(input)?;
+        let service = parts
+            .iter()
+            .find(|(number, _)| *number == 1)
+            .map(|(_, wire)| qualify(scope, &wire.text()))
+            .unwrap_or_default();
+
+        for (_, method) in parts.iter().filter(|(number, _)| *number == 2) {
+            let (mut name, mut input, mut output) = (String::new(), String::new(), String::new());
+
+            for (number, wire) in parse(method.bytes())? {
+                match number {
+                    1 => name = wire.text(),
+                    2 => input = wire.text().trim_start_matches('.').to_owned(),
+                    3 => output = wire.text().trim_start_matches('.').to_owned(),
+                    _ => {}
+                }
+            }
+
+            self.methods
+                .insert(format!("{}/{}", service, name), Method { input, output });
+        }
+
+        Ok(())
+    }
+
+    /// The method a gRPC-web or Twirp URI path calls, e.g.
+    /// `/twirp/users.Users/Get`.
+    pub fn method(&self, path: &str) -> Option<&Method> {
+        let mut segments = path.trim_end_matches('/').rsplit('/');
+        let method = segments.next()?;
+        let service = segments.next()?;
+
+        self.methods.get(&format!("{}/{}", service, method))
+    }
+
+    fn message(&self, name: &str) -> Result<&Message, ProtobufError> {
+        self.messages
+            .get(name.trim_start_matches('.'))
+            .ok_or_else(|| ProtobufError::UnknownMessage(name.into()))
+    }
+
+    fn is_map(&self, field: &Field) -> bool {
+        field.kind == MESSAGE
+            && self
+                .messages
+                .get(&field.type_name)
+                .is_some_and(|message| message.map_entry)
+    }
+
+    /// `value`, a JSON object, as message `name`. Fields may be given by
+    /// their JSON or their original name; nulls are left out.
+    pub fn encode(&self, name: &str, value: &JsonValue) -> Result<Vec<u8>, ProtobufError> {
+        let message = self.message(name)?;
+        let mut output = Vec::new();
+
+        if !value.is_object() {
+            return Err(ProtobufError::InvalidValue(name.into(), value.dump()));
+        }
+
+        for (key, value) in value.entries() {
+            let field = message
+                .fields
+                .iter()
+                .find(|field| field.json_name == key || field.name == key)
+                .ok_or_else(|| ProtobufError::UnknownField(name.into(), key.into()))?;
+
+            self.push_field(&mut output, field, value)?;
+        }
+
+        Ok(output)
+    }
+
+    fn push_field(
+        &self,
+        output: &mut Vec<u8>,
+        field: &Field,
+        value: &JsonValue,
+    ) -> Result<(), ProtobufError> {
+        if value.is_null() {
+            return Ok(());
+        }
+
+        if !field.repeated {
+            return self.push_single(output, field, value);
+        }
+
+        // a map is a repeated entry message with `key` and `value` fields
+        if self.is_map(field) {
+            if !value.is_object() {
+                return Err(ProtobufError::InvalidValue(
+                    field.name.clone(),
+                    value.dump(),
+                ));
+            }
+
+            for (key, value) in value.entries() {
+                let mut entry = JsonValue::new_object();
+                entry["key"] = key.into();
+                entry["value"] = value.clone();
+
+                push_len(
+                    output,
+                    field.number,
+                    &self.encode(&field.type_name, &entry)?,
+                );
+            }
+
+            return Ok(());
+        }
+
+        let values: Vec<&JsonValue> = match value {
+            JsonValue::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+
+        if field.packed {
+            let mut packed = Vec::new();
+            for value in values {
+                self.push_scalar(&mut packed, field, value)?;
+            }
+
+            push_len(output, field.number, &packed);
+        } else {
+            for value in values {
+                self.push_single(output, field, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push_single(
+        &self,
+        output: &mut Vec<u8>,
+        field: &Field,
+        value: &JsonValue,
+    ) -> Result<(), ProtobufError> {
+        let invalid = || ProtobufError::InvalidValue(field.name.clone(), value.dump());
+
+        match field.kind {
+            MESSAGE => push_len(output, field.number, &self.encode(&field.type_name, value)?),
+            STRING => push_len(
+                output,
+                field.number,
+                value.as_str().ok_or_else(invalid)?.as_bytes(),
+            ),
+            BYTES => push_len(
+                output,
+                field.number,
+                &value.as_str().and_then(base64).ok_or_else(invalid)?,
+            ),
+            GROUP => {
+                return Err(ProtobufError::InvalidValue(
+                    field.name.clone(),
+                    "groups are not supported".into(),
+                ))
+            }
+            _ => {
+                let mut scalar = Vec::new();
+                let wire = self.push_scalar(&mut scalar, field, value)?;
+
+                push_varint(output, field.number << 3 | wire);
+                output.extend_from_slice(&scalar);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A number, bool, or enum value without its tag, returning its wire
+    /// type. Integers may be strings, as proto3 JSON writes 64-bit ones.
+    fn push_scalar(
+        &self,
+        output: &mut Vec<u8>,
+        field: &Field,
+        value: &JsonValue,
+    ) -> Result<u64, ProtobufError> {
+        let invalid = || ProtobufError::InvalidValue(field.name.clone(), value.dump());
+        let signed = || {
+            value
+                .as_i64()
+                .or_else(|| value.as_str()?.parse().ok())
+                .ok_or_else(invalid)
+        };
+        let unsigned = || {
+            value
+                .as_u64()
+                .or_else(|| value.as_str()?.parse().ok())
+                .ok_or_else(invalid)
+        };
+        let signed32 = || signed()?.try_into().map_err(|_| invalid());
+        let unsigned32 = || unsigned()?.try_into().map_err(|_| invalid());
+        let float = || match value.as_str() {
+            Some("NaN") => Ok(f64::NAN),
+            Some("Infinity") => Ok(f64::INFINITY),
+            Some("-Infinity") => Ok(f64::NEG_INFINITY),
+            Some(text) => text.parse().map_err(|_| invalid()),
+            None => value.as_f64().ok_or_else(invalid),
+        };
+
+        Ok(match field.kind {
+            DOUBLE => {
+                output.extend_from_slice(&float()?.to_le_bytes());
+                I64
+            }
+            FLOAT => {
+                output.extend_from_slice(&(float()? as f32).to_le_bytes());
+                I32
+            }
+            INT64 => {
+                push_varint(output, signed()? as u64);
+                VARINT
+            }
+            INT32 => {
+                let value: i32 = signed32()?;
+                push_varint(output, value as i64 as u64);
+                VARINT
+            }
+            UINT64 => {
+                push_varint(output, unsigned()?);
+                VARINT
+            }
+            UINT32 => {
+                let value: u32 = unsigned32()?;
+                push_varint(output, value as u64);
+                VARINT
+            }
+            SINT64 => {
+                let value = signed()?;
+                push_varint(output, ((value << 1) ^ (value >> 63)) as u64);
+                VARINT
+            }
+            SINT32 => {
+                let value: i32 = signed32()?;
+                push_varint(output, ((value << 1) ^ (value >> 31)) as u32 as u64);
+                VARINT
+            }
+            BOOL => {
+                let value = match (value.as_bool(), value.as_str()) {
+                    (Some(value), _) => value,
+                    (_, Some("true")) => true,
+                    (_, Some("false")) => false,
+                    _ => return Err(invalid()),
+                };
+
+                push_varint(output, value as u64);
+                VARINT
+            }
+            ENUM => {
+                let number = match value.as_str() {
+                    Some(name) => self
+                        .enums
+                        .get(&field.type_name)
+                        .and_then(|values| values.iter().find(|(value, _)| value == name))
+                        .map(|(_, number)| *number)
+                        .ok_or_else(invalid)?,
+                    None => signed()?,
+                };
+
+                push_varint(output, number as u64);
+                VARINT
+            }
+            FIXED64 => {
+                output.extend_from_slice(&unsigned()?.to_le_bytes());
+                I64
+            }
+            SFIXED64 => {
+                output.extend_from_slice(&signed()?.to_le_bytes());
+                I64
+            }
+            FIXED32 => {
+                let value: u32 = unsigned32()?;
+                output.extend_from_slice(&value.to_le_bytes());
+                I32
+            }
+            SFIXED32 => {
+                let value: i32 = signed32()?;
+                output.extend_from_slice(&value.to_le_bytes());
+                I32
+            }
+            _ => return Err(invalid()),
+        })
+    }
+
+    /// Message `name` as JSON. Fields the descriptor set doesn't know are
+    /// left out, as are fields left at their default, which aren't sent.
+    pub fn decode(&self, name: &str, input: &[u8]) -> Result<JsonValue, ProtobufError> {
+        let message = self.message(name)?;
+        let mut object = JsonValue::new_object();
+
+        for (number, wire) in parse(input)? {
+            let field = match message.fields.iter().find(|field| field.number == number) {
+                Some(field) => field,
+                None => continue,
+            };
+            let key = field.json_name.as_str();
+
+            if field.repeated && self.is_map(field) {
+                let mut entry = self.single(field, wire)?;
+                let map_key = match entry["key"].take() {
+                    JsonValue::Null => self.default_key(&field.type_name),
+                    key => key.as_str().map_or_else(|| key.dump(), str::to_owned),
+                };
+
+                if !object[key].is_object() {
+                    object[key] = JsonValue::new_object();
+                }
+                object[key][map_key.as_str()] = entry["value"].take();
+            } else if field.repeated {
+                let mut values = Vec::new();
+
+                match wire {
+                    Wire::Bytes(bytes) if packable(field.kind) => {
+                        let mut reader = Reader { input: bytes };
+
+                        while !reader.input.is_empty() {
+                            values.push(self.single(field, reader.packed(field.kind)?)?);
+                        }
+                    }
+                    wire => values.push(self.single(field, wire)?),
+                }
+
+                if !object[key].is_array() {
+                    object[key] = JsonValue::new_array();
+                }
+                if let JsonValue::Array(array) = &mut object[key] {
+                    array.extend(values);
+                }
+            } else {
+                object[key] = self.single(field, wire)?;
+            }
+        }
+
+        Ok(object)
+    }
+
+    /// The key of a map entry that left it at its default.
+    fn default_key(&self, entry: &str) -> String {
+        let kind = self
+            .messages
+            .get(entry)
+            .and_then(|entry| entry.fields.iter().find(|field| field.number == 1))
+            .map_or(STRING, |field| field.kind);
+
+        match kind {
+            STRING => String::new(),
+            BOOL => "false".into(),
+            _ => "0".into(),
+        }
+    }
+
+    fn single(&self, field: &Field, wire: Wire) -> Result<JsonValue, ProtobufError> {
+        let float = |value: f64| -> JsonValue {
+            match value {
+                _ if value.is_nan() => "NaN".into(),
+                _ if value == f64::INFINITY => "Infinity".into(),
+                _ if value == f64::NEG_INFINITY => "-Infinity".into(),
+                _ => value.into(),
+            }
+        };
+
+        Ok(match (field.kind, wire) {
+            (DOUBLE, Wire::Fixed64(bits)) => float(f64::from_bits(bits)),
+            (FLOAT, Wire::Fixed32(bits)) => float(f32::from_bits(bits) as f64),
+            (INT64, Wire::Varint(value)) => (value as i64).to_string().into(),
+            (UINT64, Wire::Varint(value)) => value.to_string().into(),
+            (INT32, Wire::Varint(value)) => (value as i32).into(),
+            (UINT32, Wire::Varint(value)) => (value as u32).into(),
+            (SINT64, Wire::Varint(value)) => zigzag(value).to_string().into(),
+            (SINT32, Wire::Varint(value)) => (zigzag(value) as i32).into(),
+            (BOOL, Wire::Varint(value)) => (value != 0).into(),
+            (ENUM, Wire::Varint(value)) => {
+                let number = value as i32 as i64;
+
+                self.enums
+                    .get(&field.type_name)
+                    .and_then(|values| values.iter().find(|(_, n)| *n == number))
+                    .map_or_else(|| number.into(), |(name, _)| name.as_str().into())
+            }
+            (FIXED64, Wire::Fixed64(value)) => value.to_string().into(),
+            (SFIXED64, Wire::Fixed64(value)) => (value as i64).to_string().into(),
+            (FIXED32, Wire::Fixed32(value)) => value.into(),
+            (SFIXED32, Wire::Fixed32(value)) => (value as i32).into(),
+            (STRING, Wire::Bytes(bytes)) => std::str::from_utf8(bytes)
+                .map_err(|_| ProtobufError::InvalidString(field.name.clone()))?
+                .into(),
+            (BYTES, Wire::Bytes(bytes)) => encode_base64(bytes).into(),
+            (MESSAGE, Wire::Bytes(bytes)) => self.decode(&field.type_name, bytes)?,
+            _ => return Err(ProtobufError::WireTypeMismatch(field.name.clone())),
+        })
+    }
+}
+
+fn field(input: &[u8], proto3: bool) -> Result<Field, ProtobufError> {
+    let mut field = Field::default();
+    let mut packed = None;
+
+    for (number, wire) in parse(input)? {
+        match number {
+            1 => field.name = wire.text(),
+            3 => field.number = wire.varint(),
+            4 => field.repeated = wire.varint() == 3,
+            5 => field.kind = wire.varint(),
+            6 => field.type_name = wire.text().trim_start_matches('.').to_owned(),
+            8 => {
+                packed = parse(wire.bytes())?
+                    .iter()
+                    .find(|(number, _)| *number == 2)
+                    .map(|(_, option)| option.varint() != 0)
+            }
+            10 => field.json_name = wire.text(),
+            _ => {}
+        }
+    }
+
+    if field.json_name.is_empty() {
+        field.json_name = lower_camel_case(&field.name);
+    }
+
+    // proto3 packs repeated scalars unless told not to; proto2 only if told
+    field.packed = field.repeated && packable(field.kind) && packed.unwrap_or(proto3);
+
+    Ok(field)
+}
+
+/// A message as a gRPC-web data frame: a zero flags byte, then its length.
+pub fn frame(message: &[u8]) -> Vec<u8> {
+    let mut output = vec![0];
+    output.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    output.extend_from_slice(message);
+
+    output
+}
+
+/// The messages of a gRPC-web body, and the text of its trailer frame,
+/// header lines like `grpc-status: 0`.
+pub fn unframe(mut input: &[u8]) -> Result<(Vec<&[u8]>, Option<String>), ProtobufError> {
+    let mut messages = Vec::new();
+    let mut trailers = None;
+
+    while !input.is_empty() {
+        if input.len() < 5 {
+            return Err(ProtobufError::UnexpectedEnd);
+        }
+
+        let flags = input[0];
+        let len = u32::from_be_bytes(input[1..5].try_into().unwrap()) as usize;
+        let frame = input.get(5..5 + len).ok_or(ProtobufError::UnexpectedEnd)?;
+
+        if flags & 0x01 != 0 {
+            return Err(ProtobufError::CompressedFrame);
+        }
+
+        if flags & 0x80 != 0 {
+            trailers = Some(String::from_utf8_lossy(frame).trim_end().to_owned());
+        } else {
+            messages.push(frame);
+        }
+
+        input = &input[5 + len..];
+    }
+
+    Ok((messages, trailers))
+}
+
+pub fn is_protobuf(content_type: &mime::Mime) -> bool {
+    matches!(
+        (content_type.type_(), content_type.subtype().as_str()),
+        (
+            mime::APPLICATION,
+            "protobuf" | "x-protobuf" | "grpc-web" | "grpc-web+proto"
+        )
+    )
+}
+
+/// The `--proto` body mode: the messages each side of the call sends, and
+/// whether they go in gRPC-web frames.
+#[derive(Debug)]
+pub struct Protobuf {
+    pub descriptors: Descriptors,
+    pub request: String,
+    /// Known when the URI path names a method in the descriptor set.
+    pub response: Option<String>,
+    pub grpc_web: bool,
+}
+
+impl Protobuf {
+    pub fn content_type(&self) -> &'static str {
+        if self.grpc_web {
+            "application/grpc-web+proto"
+        } else {
+            "application/protobuf"
+        }
+    }
+
+    pub fn encode(&self, value: &JsonValue) -> Result<Vec<u8>, ProtobufError> {
+        let message = self.descriptors.encode(&self.request, value)?;
+
+        Ok(if self.grpc_web {
+            frame(&message)
+        } else {
+            message
+        })
+    }
+
+    /// A response body as JSON, and the text of its gRPC-web trailer frame.
+    /// A server stream of several messages is an array of them.
+    pub fn decode(
+        &self,
+        content_type: &mime::Mime,
+        body: &[u8],
+    ) -> Result<Option<(JsonValue, Option<String>)>, ProtobufError> {
+        let response = match &self.response {
+            Some(response) => response,
+            None => return Ok(None),
+        };
+
+        if content_type.subtype().as_str() != "grpc-web" {
+            return Ok(Some((self.descriptors.decode(response, body)?, None)));
+        }
+
+        let (messages, trailers) = unframe(body)?;
+        let mut values = messages
+            .into_iter()
+            .map(|message| self.descriptors.decode(response, message))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let value = match values.len() {
+            0 => JsonValue::Null,
+            1 => values.remove(0),
+            _ => values.into(),
+        };
+
+        Ok(Some((value, trailers)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn len(number: u64, bytes: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        push_len(&mut output, number, bytes);
+        output
+    }
+
+    fn varint(number: u64, value: u64) -> Vec<u8> {
+        let mut output = Vec::new();
+        push_varint(&mut output, number << 3);
+        push_varint(&mut output, value);
+        output
+    }
+
+    fn field(name: &str, number: u64, kind: u64, type_name: &str, label: u64) -> Vec<u8> {
+        len(
+            2,
+            &[
+                len(1, name.as_bytes()),
+                varint(3, number),
+                varint(4, label),
+                varint(5, kind),
+                len(6, type_name.as_bytes()),
+            ]
+            .concat(),
+        )
+    }
+
+    /// users.proto: `message User` with a nested map entry, an enum, and a
+    /// service, as protoc would compile it.
+    fn descriptors() -> Descriptors {
+        let entry = [
+            len(1, b"CountsEntry"),
+            field("key", 1, STRING, "", 1),
+            field("value", 2, INT32, "", 1),
+            len(7, &varint(7, 1)),
+        ]
+        .concat();
+        let user = [
+            len(1, b"User"),
+            field("user_id", 1, STRING, "", 1),
+            field("id", 2, INT64, "", 1),
+            field("scores", 3, INT32, "", 3),
+            field("role", 4, ENUM, ".users.Role", 1),
+            field("counts", 5, MESSAGE, ".users.User.CountsEntry", 3),
+            field("avatar", 6, BYTES, "", 1),
+            field("delta", 7, SINT32, "", 1),
+            field("ratio", 8, DOUBLE, "", 1),
+            len(3, &entry),
+        ]
+        .concat();
+        let role = [
+            len(1, b"Role"),
+            len(2, &[len(1, b"UNKNOWN"), varint(2, 0)].concat()),
+            len(2, &[len(1, b"ADMIN"), varint(2, 1)].concat()),
+        ]
+        .concat();
+        let service = [
+            len(1, b"Users"),
+            len(
+                2,
+                &[
+                    len(1, b"Get"),
+                    len(2, b".users.User"),
+                    len(3, b".users.User"),
+                ]
+                .concat(),
+            ),
+        ]
+        .concat();
+        let file = [
+            len(1, b"users.proto"),
+            len(2, b"users"),
+            len(4, &user),
+            len(5, &role),
+            len(6, &service),
+            len(12, b"proto3"),
+        ]
+        .concat();
+
+        Descriptors::parse(&len(1, &file)).unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let descriptors = descriptors();
+        let value = json::parse(
+            r#"{"userId": "u1", "id": "9007199254740993", "scores": [1, -2, 300],
+                "role": "ADMIN", "counts": {"a": 1, "b": 2}, "avatar": "AAH/",
+                "delta": -3, "ratio": 0.5}"#,
+        )
+        .unwrap();
+        let bytes = descriptors.encode("users.User", &value).unwrap();
+
+        assert_eq!(&bytes[..4], b"\x0a\x02u1");
+        assert_eq!(descriptors.decode("users.User", &bytes).unwrap(), value);
+
+        // original names and numbers as strings are accepted too
+        let value = json::parse(r#"{"user_id": "u1", "id": 7, "role": 1}"#).unwrap();
+        assert_eq!(
+            descriptors.encode("users.User", &value).unwrap(),
+            b"\x0a\x02u1\x10\x07\x20\x01"
+        );
+
+        assert!(matches!(
+            descriptors.encode("users.User", &json::parse(r#"{"nope": 1}"#).unwrap()),
+            Err(ProtobufError::UnknownField(_, _))
+        ));
+    }
+
+    #[test]
+    fn finds_methods_and_frames_grpc_web() {
+        let descriptors = descriptors();
+
+        assert_eq!(
+            descriptors.method("/twirp/users.Users/Get").unwrap().output,
+            "users.User"
+        );
+        assert!(descriptors.method("/users.Users/Delete").is_none());
+
+        let mut body = frame(b"\x0a\x02u1");
+        body.extend_from_slice(b"\x80\x00\x00\x00\x10grpc-status: 0\r\n");
+        let (messages, trailers) = unframe(&body).unwrap();
+
+        assert_eq!(messages, [b"\x0a\x02u1"]);
+        assert_eq!(trailers.as_deref(), Some("grpc-status: 0"));
+    }
+}
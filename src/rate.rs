@@ -0,0 +1,133 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RateError {
+    #[error("invalid rate {0}, expected e.g. 10/s, 300/m, 1000/h, or a bare number per second")]
+    ParseError(String),
+}
+
+/// Requests per period for `--rate`, e.g. `10/s`, `300/m`, or `1000/h`.
+#[derive(Clone, Copy, Debug)]
+pub struct Rate {
+    interval: Duration,
+}
+
+impl std::str::FromStr for Rate {
+    type Err = RateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || RateError::ParseError(s.into());
+
+        let (count, period) = match s.trim().split_once('/') {
+            Some((count, period)) => (count, period),
+            None => (s.trim(), "s"),
+        };
+        let count: f64 = count.parse().map_err(|_| error())?;
+
+        let seconds = match period {
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 60.0 * 60.0,
+            _ => return Err(error()),
+        };
+
+        if !(count > 0.0 && count.is_finite()) {
+            return Err(error());
+        }
+
+        Ok(Self {
+            interval: Duration::from_secs_f64(seconds / count),
+        })
+    }
+}
+
+/// A token bucket that holds one token and refills at the rate: each request
+/// waits for the next token, so starts are evenly spaced across every worker
+/// and never bunch up after a slow stretch.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            interval: rate.interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let slot = (*next).max(now);
+            *next = slot + self.interval;
+
+            slot - now
+        };
+
+        if wait > Duration::default() {
+            tokio::time::delay_for(wait).await;
+        }
+    }
+}
+
+/// Holds a transfer to `--limit-rate` bytes per second: after each chunk,
+/// waits until the transfer is no longer ahead of that pace.
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_second: u64,
+    start: Instant,
+    sent: u64,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            start: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// A chunk size that keeps the pace smooth, about a tenth of a second's
+    /// worth of bytes, and at most `max`.
+    pub fn chunk_size(&self, max: usize) -> usize {
+        ((self.bytes_per_second / 10) as usize).clamp(1, max)
+    }
+
+    pub async fn pass(&mut self, bytes: usize) {
+        self.sent += bytes as u64;
+
+        let due = Duration::from_secs_f64(self.sent as f64 / self.bytes_per_second as f64);
+        let elapsed = self.start.elapsed();
+
+        if due > elapsed {
+            tokio::time::delay_for(due - elapsed).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rates() {
+        let interval = |s: &str| s.parse::<Rate>().map(|rate| rate.interval).ok();
+
+        assert_eq!(interval("10/s"), Some(Duration::from_millis(100)));
+        assert_eq!(interval("120/m"), Some(Duration::from_millis(500)));
+        assert_eq!(interval("3600/h"), Some(Duration::from_secs(1)));
+        assert_eq!(interval("4"), Some(Duration::from_millis(250)));
+        assert_eq!(interval("0/s"), None);
+        assert_eq!(interval("10/d"), None);
+        assert_eq!(interval("fast"), None);
+    }
+}
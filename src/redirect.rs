@@ -0,0 +1,367 @@
+use crate::{
+    auth::Auth,
+    cancel::CancellationToken,
+    client::{self, HttpClient},
+    deterministic, note,
+    retry::{CircuitBreaker, RetryBudget},
+};
+use anyhow::Result;
+use hyper::{header, Body, Method, Request, Response, StatusCode, Uri};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RedirectError {
+    #[error("redirect loop: {0} {1} was already visited")]
+    Loop(Method, Uri),
+    #[error("insecure redirect from {0} to {1}; pass --allow-insecure-redirect to follow it")]
+    Downgrade(Uri, Uri),
+    #[error("stopped after {0} redirects (raise --max-redirects to follow more)")]
+    TooMany(usize),
+    #[error("invalid Location {1} in redirect from {0}")]
+    InvalidLocation(Uri, String),
+}
+
+#[derive(Debug)]
+pub struct Hop {
+    pub status: StatusCode,
+    pub uri: Uri,
+    pub location: Option<Uri>,
+    pub latency: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct Redirects {
+    pub max: usize,
+    pub allow_insecure: bool,
+    /// Signs each hop's request, as a signature covers its method and URI.
+    pub auth: Auth,
+    pub budget: Arc<RetryBudget>,
+    pub breaker: Arc<CircuitBreaker>,
+    pub cancel: CancellationToken,
+}
+
+/// Resolves a Location header against the URI it was received from.
+pub(crate) fn resolve(base: &Uri, location: &str) -> Option<Uri> {
+    if location.contains("://") {
+        return location.parse().ok();
+    }
+
+    let scheme = base.scheme_str().unwrap_or("http");
+
+    if let Some(rest) = location.strip_prefix("//") {
+        return format!("{}://{}", scheme, rest).parse().ok();
+    }
+
+    let authority = base.authority()?;
+    let path = if location.starts_with('/') {
+        location.to_owned()
+    } else {
+        let directory = &base.path()[..base.path().rfind('/').map_or(0, |i| i + 1)];
+        format!("{}{}", directory, location)
+    };
+    let path = match path.split_once('?') {
+        Some((path, query)) => format!("{}?{}", crate::body::remove_dot_segments(path), query),
+        None => crate::body::remove_dot_segments(&path),
+    };
+
+    format!("{}://{}{}", scheme, authority, path).parse().ok()
+}
+
+/// Removes what authenticates a request before it goes to another host:
+/// credentials, and the Host it was signed for.
+pub(crate) fn strip_credentials(req: &mut Request<Body>) {
+    let headers = req.headers_mut();
+    headers.remove(header::AUTHORIZATION);
+    headers.remove(header::COOKIE);
+    headers.remove(header::HOST);
+}
+
+/// The request for the hop to `next`. Like browsers, 301/302 turn a POST
+/// into a GET and 303 always does, clearing `body`, while 307/308 resend the
+/// same method and body. On the same host it is signed again, and on another
+/// its credentials are dropped.
+fn redirect_request(
+    req: &Request<Body>,
+    status: StatusCode,
+    next: Uri,
+    body: &mut Vec<u8>,
+    auth: &Auth,
+) -> Result<Request<Body>> {
+    let change_to_get = status == StatusCode::SEE_OTHER
+        || (matches!(status.as_u16(), 301 | 302) && req.method() == Method::POST);
+
+    if change_to_get {
+        body.clear();
+    }
+
+    let mut next_req = client::clone_request(req, body);
+
+    if change_to_get {
+        if req.method() != Method::HEAD {
+            *next_req.method_mut() = Method::GET;
+        }
+
+        let headers = next_req.headers_mut();
+        headers.remove(header::CONTENT_TYPE);
+        headers.remove(header::CONTENT_LENGTH);
+    }
+
+    let same_host = next.authority() == req.uri().authority();
+    *next_req.uri_mut() = next;
+
+    if same_host {
+        auth.apply(&mut next_req, body)?;
+    } else {
+        strip_credentials(&mut next_req);
+    }
+
+    Ok(next_req)
+}
+
+fn is_redirect(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 301 | 302 | 303 | 307 | 308)
+}
+
+/// Sends `req`, following up to `redirects.max` redirects, see
+/// `redirect_request` for what each hop sends. A loop is a method and URI
+/// that was already requested.
+pub async fn follow(
+    redirects: Redirects,
+    client: &HttpClient,
+    req: &Request<Body>,
+    body: &[u8],
+) -> Result<(Response<Body>, Vec<Hop>)> {
+    let mut hops = Vec::new();
+    let mut visited = vec![(req.method().clone(), req.uri().to_string())];
+    let mut req = client::clone_request(req, body);
+    let mut body = body.to_vec();
+
+    loop {
+        let start = Instant::now();
+        let res = client::send(
+            client,
+            &req,
+            &body,
+            &redirects.budget,
+            &redirects.breaker,
+            &redirects.cancel,
+        )
+        .await?;
+        let latency = start.elapsed();
+
+        let status = res.status();
+        let location = res
+            .headers()
+            .get(header::LOCATION)
+            .map(|location| String::from_utf8_lossy(location.as_bytes()).into_owned());
+
+        let location = match location {
+            Some(location) if is_redirect(status) => location,
+            _ => {
+                hops.push(Hop {
+                    status,
+                    uri: req.uri().clone(),
+                    location: None,
+                    latency,
+                });
+
+                return Ok((res, hops));
+            }
+        };
+
+        let next = resolve(req.uri(), &location)
+            .ok_or_else(|| RedirectError::InvalidLocation(req.uri().clone(), location))?;
+
+        crate::trace!("{} redirects to {}", status, next);
+
+        hops.push(Hop {
+            status,
+            uri: req.uri().clone(),
+            location: Some(next.clone()),
+            latency,
+        });
+
+        if hops.len() > redirects.max {
+            return Err(RedirectError::TooMany(redirects.max).into());
+        }
+
+        if req.uri().scheme_str() == Some("https")
+            && next.scheme_str() == Some("http")
+            && !redirects.allow_insecure
+        {
+            note!("{}", format_chain(&hops, false));
+            return Err(RedirectError::Downgrade(req.uri().clone(), next).into());
+        }
+
+        let next_req = redirect_request(&req, status, next.clone(), &mut body, &redirects.auth)?;
+
+        // a POST answered with 303 to its own URI is not a loop: the GET is new
+        let key = (next_req.method().clone(), next.to_string());
+        if visited.contains(&key) {
+            note!("{}", format_chain(&hops, false));
+            return Err(RedirectError::Loop(next_req.method().clone(), next).into());
+        }
+        visited.push(key);
+
+        req = next_req;
+    }
+}
+
+/// A table of every hop: status, latency, and where it pointed next.
+pub fn format_chain(hops: &[Hop], deterministic: bool) -> String {
+    let mut output = String::new();
+
+    for (i, hop) in hops.iter().enumerate() {
+        let latency = if deterministic {
+            deterministic::PLACEHOLDER.to_owned()
+        } else {
+            format!("{:.1}ms", hop.latency.as_secs_f64() * 1000.0)
+        };
+
+        let downgrade = match &hop.location {
+            Some(location)
+                if hop.uri.scheme_str() == Some("https")
+                    && location.scheme_str() == Some("http") =>
+            {
+                "  \x1b[1;31mINSECURE https → http\x1b[0m"
+            }
+            _ => "",
+        };
+
+        output += &format!(
+            "{:>2}. {} {:>8}  {}",
+            i + 1,
+            hop.status.as_u16(),
+            latency,
+            hop.uri
+        );
+
+        if let Some(location) = &hop.location {
+            output += &format!("\n              → {}{}", location, downgrade);
+        }
+
+        output += "\n";
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth::AuthType, hmac::HmacOptions};
+
+    fn uri(uri: &str) -> Uri {
+        uri.parse().unwrap()
+    }
+
+    fn post(uri: &str) -> Request<Body> {
+        Request::post(uri)
+            .header(header::AUTHORIZATION, "Basic ZXpyYTpwdw==")
+            .header(header::COOKIE, "session=1")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, "2")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn resolves_locations() {
+        let base = uri("https://example.com/a/b/c?q=1");
+
+        assert_eq!(
+            resolve(&base, "http://other.test/x"),
+            Some(uri("http://other.test/x"))
+        );
+        assert_eq!(
+            resolve(&base, "//cdn.test/x"),
+            Some(uri("https://cdn.test/x"))
+        );
+        assert_eq!(
+            resolve(&base, "/root?page=2"),
+            Some(uri("https://example.com/root?page=2"))
+        );
+        assert_eq!(resolve(&base, "../d"), Some(uri("https://example.com/a/d")));
+        assert_eq!(resolve(&base, "d"), Some(uri("https://example.com/a/b/d")));
+        assert_eq!(resolve(&base, "http://[bad"), None);
+    }
+
+    #[test]
+    fn rewrites_the_method_like_browsers() {
+        let auth = Auth::default();
+        let next = || uri("http://example.com/done");
+
+        for (status, method) in &[
+            (301, Method::GET),
+            (302, Method::GET),
+            (303, Method::GET),
+            (307, Method::POST),
+            (308, Method::POST),
+        ] {
+            let status = StatusCode::from_u16(*status).unwrap();
+            let mut body = b"{}".to_vec();
+            let req = redirect_request(
+                &post("http://example.com/form"),
+                status,
+                next(),
+                &mut body,
+                &auth,
+            )
+            .unwrap();
+
+            assert_eq!(req.method(), method, "{}", status);
+            assert_eq!(body.is_empty(), method == Method::GET, "{}", status);
+            assert_eq!(
+                req.headers().contains_key(header::CONTENT_TYPE),
+                method == Method::POST
+            );
+        }
+
+        let head = Request::head("http://example.com/")
+            .body(Body::empty())
+            .unwrap();
+        let req =
+            redirect_request(&head, StatusCode::SEE_OTHER, next(), &mut Vec::new(), &auth).unwrap();
+        assert_eq!(req.method(), Method::HEAD);
+    }
+
+    #[test]
+    fn strips_credentials_on_another_host_and_signs_on_the_same() {
+        let auth = Auth {
+            auth_type: Some(AuthType::Hmac),
+            hmac: HmacOptions {
+                key: Some("secret".into()),
+                ..HmacOptions::default()
+            },
+            ..Auth::default()
+        };
+        let req = post("http://example.com/a");
+
+        let other = redirect_request(
+            &req,
+            StatusCode::TEMPORARY_REDIRECT,
+            uri("http://other.test/a"),
+            &mut b"{}".to_vec(),
+            &auth,
+        )
+        .unwrap();
+        assert!(!other.headers().contains_key(header::AUTHORIZATION));
+        assert!(!other.headers().contains_key(header::COOKIE));
+
+        let same = redirect_request(
+            &req,
+            StatusCode::TEMPORARY_REDIRECT,
+            uri("http://example.com/b"),
+            &mut b"{}".to_vec(),
+            &auth,
+        )
+        .unwrap();
+        let signed = same.headers()[header::AUTHORIZATION].to_str().unwrap();
+        assert!(signed.starts_with("HMAC-SHA256 "), "{}", signed);
+        assert_eq!(same.headers()[header::COOKIE], "session=1");
+    }
+}
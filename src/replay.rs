@@ -0,0 +1,54 @@
+use anyhow::Result;
+use rurl::{history, note, output};
+
+const HISTORY_USAGE: &str = "usage: rurl history [COUNT] | show ID | clear";
+const REPLAY_USAGE: &str = "usage: rurl replay ID [ITEM...]";
+
+fn list(count: usize) -> Result<()> {
+    let entries = history::load()?;
+
+    for entry in &entries[entries.len().saturating_sub(count)..] {
+        println!("{}", entry.summary());
+    }
+
+    Ok(())
+}
+
+/// Lists the last requests recorded with `--history`, 20 unless given a count.
+pub async fn history(args: &[String]) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] => list(20)?,
+        ["clear"] => history::clear().await?,
+        [count] => list(count.parse().map_err(|_| anyhow::anyhow!(HISTORY_USAGE))?)?,
+        ["show", id] => {
+            let entry = history::find(id.parse().map_err(|_| anyhow::anyhow!(HISTORY_USAGE))?)?;
+            println!("{}", entry.command());
+        }
+        _ => anyhow::bail!(HISTORY_USAGE),
+    }
+
+    Ok(())
+}
+
+/// Turns `rurl replay ID [ITEM...]` into the recorded command line, with
+/// the given items replacing the recorded ones with the same key.
+pub fn args(args: &[String]) -> Result<Vec<String>> {
+    let (id, items) = match args.get(2).map(|id| id.parse::<u64>()) {
+        Some(Ok(id)) => (id, &args[3..]),
+        _ => anyhow::bail!(REPLAY_USAGE),
+    };
+
+    let entry = history::find(id)?;
+    let entry = history::Entry {
+        args: entry.edit(items)?,
+        ..entry
+    };
+
+    // before the options are parsed, so as they will be
+    output::set_all_to_stdout(entry.args.iter().any(|arg| arg == "--all-to-stdout"));
+    note!("replaying {}\n", entry.command());
+
+    Ok(std::iter::once(args[0].clone()).chain(entry.args).collect())
+}
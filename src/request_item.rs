@@ -0,0 +1,792 @@
+use hyper::header::{HeaderName, HeaderValue};
+use json::JsonValue;
+use thiserror::Error;
+
+#[derive(Clone, Debug)]
+pub enum RequestItem {
+    Data {
+        key: String,
+        value: String,
+    },
+    /// `key@path`, a multipart file field, with `;type=` and `;filename=`
+    /// overriding the part's Content-Type and filename.
+    FormFile {
+        key: String,
+        value: std::path::PathBuf,
+        content: Vec<u8>,
+        content_type: Option<String>,
+        filename: Option<String>,
+    },
+    Header {
+        key: HeaderName,
+        value: HeaderValue,
+    },
+    /// `Name;` sends the header with an empty value.
+    EmptyHeader {
+        key: HeaderName,
+    },
+    /// `Name:` removes the header, including rurl's defaults.
+    UnsetHeader {
+        key: HeaderName,
+    },
+    JsonData {
+        key: String,
+        value: JsonValue,
+    },
+    RawBody {
+        path: std::path::PathBuf,
+        value: Vec<u8>,
+    },
+    SearchParam {
+        key: String,
+        value: String,
+    },
+    /// An item whose value is still to be read from a file, stdin, the
+    /// clipboard, or the terminal; see [`resolve`]. `separator` is that of
+    /// the item it stands for, `@` for a form file and empty for a raw body.
+    Unresolved {
+        key: String,
+        separator: &'static str,
+        source: Source,
+    },
+}
+
+/// Where the value of an [`RequestItem::Unresolved`] item is read from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    /// `@prompt`, typed at a hidden prompt.
+    Prompt,
+    /// `@clipboard`, the system clipboard.
+    Clipboard,
+    /// `@-` or `@-:$.path`, a piped --json-output document.
+    Stdin(Option<String>),
+    /// `@path`, a file.
+    File(String),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Prompt => write!(f, "@prompt"),
+            Self::Clipboard => write!(f, "@clipboard"),
+            Self::Stdin(None) => write!(f, "@-"),
+            Self::Stdin(Some(path)) => write!(f, "@-:{}", path),
+            Self::File(path) => write!(f, "@{}", path),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RequestItemError {
+    #[error("could not parse request item {0}")]
+    ParseError(String),
+    #[error("unknown request item variant {0}")]
+    VariantParseError(String),
+    #[error("missing file input {0}")]
+    MissingFileInputError(String),
+    #[error("could not read file {0}")]
+    IOError(String),
+    #[error("{0}")]
+    StdinError(String),
+    #[error("{0}")]
+    PromptError(String),
+    #[error("could not read the clipboard: {0}")]
+    ClipboardError(String),
+}
+
+/// Parses a header value, folding line breaks and encoding characters up
+/// to U+00FF as latin-1 bytes, with a warning for either.
+fn header_value(key: &str, value: &str) -> Result<HeaderValue, hyper::header::InvalidHeaderValue> {
+    let mut value = value.to_owned();
+
+    if value.contains(['\r', '\n']) {
+        eprintln!("warning: folding line breaks in header {}", key);
+        value = value
+            .split(['\r', '\n'])
+            .filter(|line| !line.trim().is_empty())
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+
+    if value.is_ascii() {
+        return HeaderValue::from_str(&value);
+    }
+
+    if value.chars().all(|c| (c as u32) <= 0xff) {
+        eprintln!("warning: sending header {} as latin-1", key);
+
+        let bytes: Vec<u8> = value.chars().map(|c| c as u8).collect();
+        return HeaderValue::from_bytes(&bytes);
+    }
+
+    eprintln!("warning: sending header {} as raw UTF-8 bytes", key);
+    HeaderValue::from_bytes(value.as_bytes())
+}
+
+/// Item separators. Scanning left to right, the first unescaped position
+/// where any separator begins splits the item, and at that position the
+/// longest separator wins, so `==` beats `=` and `:=@` beats `:=` and `:`.
+/// A trailing `;` (an empty header) is only a separator at the very end.
+const SEPARATORS: &[&str] = &["==", "=@", "=", ":=@", ":=", ":", "@"];
+
+/// Characters a backslash escapes in keys: `\=`, `\:`, `\@`, and `\\`.
+const ESCAPABLE: &[char] = &['=', ':', '@', '\\'];
+
+/// Splits an item into its unescaped key, separator, and verbatim value.
+///
+/// A backslash before any other character is kept as-is, and the value
+/// is never unescaped, so `Authorization:Basic a=b` keeps its `=`.
+pub fn tokenize(s: &str) -> Option<(String, &'static str, &str)> {
+    let mut key = String::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            match chars.peek() {
+                Some(&(_, next)) if ESCAPABLE.contains(&next) => {
+                    key.push(next);
+                    chars.next();
+                }
+                _ => key.push(c),
+            }
+
+            continue;
+        }
+
+        if let Some(separator) = SEPARATORS
+            .iter()
+            .find(|separator| s[i..].starts_with(*separator))
+        {
+            if key.is_empty() {
+                return None;
+            }
+
+            return Some((key, separator, &s[i + separator.len()..]));
+        }
+
+        if c == ';' && i + 1 == s.len() && !key.is_empty() {
+            return Some((key, ";", ""));
+        }
+
+        key.push(c);
+    }
+
+    None
+}
+
+/// Splits `path;type=image/png;filename=a.png` into the path, the type,
+/// and the filename. Segments that are neither option stay in the path.
+fn file_options(value: &str) -> (String, Option<String>, Option<String>) {
+    let mut segments = value.split(';');
+    let mut path = segments.next().unwrap_or_default().to_owned();
+    let mut content_type = None;
+    let mut filename = None;
+
+    for segment in segments {
+        if let Some(value) = segment.strip_prefix("type=") {
+            content_type = Some(value.to_owned());
+        } else if let Some(value) = segment.strip_prefix("filename=") {
+            filename = Some(value.to_owned());
+        } else {
+            path.push(';');
+            path.push_str(segment);
+        }
+    }
+
+    (path, content_type, filename)
+}
+
+impl std::str::FromStr for RequestItem {
+    type Err = RequestItemError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix('@') {
+            if path.is_empty() {
+                return Err(Self::Err::MissingFileInputError(path.into()));
+            }
+
+            return Ok(Self::Unresolved {
+                key: String::new(),
+                separator: "",
+                source: match path {
+                    "clipboard" => Source::Clipboard,
+                    path => Source::File(path.into()),
+                },
+            });
+        }
+
+        match tokenize(s) {
+            Some((key, variant, value)) => {
+                // `@prompt`, `@clipboard`, `@-`, and `@file` values are read by
+                // `resolve`, once the arguments are parsed
+                let unresolved = |separator, source| {
+                    Ok(Self::Unresolved {
+                        key: key.clone(),
+                        separator,
+                        source,
+                    })
+                };
+
+                match (variant, value) {
+                    ("=@", "prompt") => return unresolved("=", Source::Prompt),
+                    ("==", "@prompt") => return unresolved("==", Source::Prompt),
+                    (":", "@prompt") => {
+                        key.parse::<HeaderName>()
+                            .or(Err(Self::Err::ParseError(s.into())))?;
+                        return unresolved(":", Source::Prompt);
+                    }
+                    ("=@" | ":=@", "") => {
+                        return Err(Self::Err::MissingFileInputError(value.into()));
+                    }
+                    ("=@" | ":=@", value) => {
+                        let source = match value {
+                            "clipboard" => Source::Clipboard,
+                            "-" => Source::Stdin(None),
+                            value => match value.strip_prefix("-:") {
+                                Some(path) => Source::Stdin(Some(path.into())),
+                                None => Source::File(value.into()),
+                            },
+                        };
+
+                        return unresolved(&variant[..variant.len() - 1], source);
+                    }
+                    ("@", value) => return unresolved("@", Source::File(value.into())),
+                    _ => {}
+                }
+
+                let value: String = value.into();
+
+                let request_item = match variant {
+                    "=" => Self::Data { key, value },
+                    ":" if value.is_empty() => Self::UnsetHeader {
+                        key: key.parse().or(Err(Self::Err::ParseError(s.into())))?,
+                    },
+                    ";" => Self::EmptyHeader {
+                        key: key.parse().or(Err(Self::Err::ParseError(s.into())))?,
+                    },
+                    ":" => Self::Header {
+                        key: key.parse().or(Err(Self::Err::ParseError(s.into())))?,
+                        value: header_value(&key, &value)
+                            .or(Err(Self::Err::ParseError(s.into())))?,
+                    },
+                    ":=" => Self::JsonData {
+                        key,
+                        value: json::parse(&value).or(Err(Self::Err::ParseError(s.into())))?,
+                    },
+                    "==" => Self::SearchParam { key, value },
+                    _ => return Err(Self::Err::VariantParseError(variant.into())),
+                };
+
+                Ok(request_item)
+            }
+            None => Err(Self::Err::ParseError(s.into())),
+        }
+    }
+}
+
+/// Reads the value of an unresolved item: the text typed at the prompt with
+/// `prompt`, what is on the clipboard, what a piped document selects, or a
+/// file's contents.
+fn read<F, E>(
+    key: &str,
+    separator: &str,
+    source: &Source,
+    prompt: &mut F,
+) -> Result<Vec<u8>, RequestItemError>
+where
+    F: FnMut(&str) -> Result<String, E>,
+    E: std::fmt::Display,
+{
+    Ok(match source {
+        Source::Prompt => prompt(key)
+            .map_err(|err| RequestItemError::PromptError(err.to_string()))?
+            .into_bytes(),
+        Source::Clipboard => crate::clipboard::paste()
+            .map_err(|err| RequestItemError::ClipboardError(err.to_string()))?,
+        Source::Stdin(path) => {
+            let document = crate::pipe::stdin_document()
+                .map_err(|err| RequestItemError::StdinError(err.to_string()))?;
+            let document = crate::pipe::body_of(document);
+
+            let selected = match path {
+                Some(path) => crate::jsonpath::select_one(document, path)
+                    .map_err(|err| RequestItemError::StdinError(err.to_string()))?,
+                None => document,
+            };
+
+            match (separator, selected.as_str()) {
+                ("=", Some(selected)) => selected.into(),
+                _ => selected.dump().into_bytes(),
+            }
+        }
+        Source::File(path) => {
+            std::fs::read(path).or(Err(RequestItemError::IOError(path.clone())))?
+        }
+    })
+}
+
+/// The item an unresolved one stands for, with its value read.
+fn resolve_item<F, E>(
+    key: String,
+    separator: &'static str,
+    source: Source,
+    prompt: &mut F,
+) -> Result<RequestItem, RequestItemError>
+where
+    F: FnMut(&str) -> Result<String, E>,
+    E: std::fmt::Display,
+{
+    if separator == "@" {
+        let path = match source {
+            Source::File(path) => path,
+            source => source.to_string(),
+        };
+        let (path, content_type, filename) = file_options(&path);
+        let content = std::fs::read(&path).or(Err(RequestItemError::IOError(path.clone())))?;
+
+        return Ok(RequestItem::FormFile {
+            key,
+            value: path.into(),
+            content,
+            content_type,
+            filename,
+        });
+    }
+
+    let value = read(&key, separator, &source, prompt)?;
+
+    // `@clipboard` is sent typed as JSON if it parses
+    if separator.is_empty() {
+        let path = match source {
+            Source::File(path) => path,
+            _ if std::str::from_utf8(&value).is_ok_and(|value| json::parse(value).is_ok()) => {
+                "clipboard.json".into()
+            }
+            _ => "clipboard.txt".into(),
+        };
+
+        return Ok(RequestItem::RawBody {
+            path: path.into(),
+            value,
+        });
+    }
+
+    let value = match &source {
+        Source::File(path) => {
+            String::from_utf8(value).or(Err(RequestItemError::IOError(path.clone())))?
+        }
+        _ => String::from_utf8_lossy(&value).into_owned(),
+    };
+    let item = || RequestItemError::ParseError(format!("{}{}{}", key, separator, source));
+
+    Ok(match separator {
+        "=" => RequestItem::Data { key, value },
+        "==" => RequestItem::SearchParam { key, value },
+        ":=" => RequestItem::JsonData {
+            value: json::parse(&value).map_err(|_| item())?,
+            key,
+        },
+        _ => RequestItem::Header {
+            value: header_value(&key, &value).map_err(|_| item())?,
+            key: key.parse().map_err(|_| item())?,
+        },
+    })
+}
+
+/// Replaces each [`RequestItem::Unresolved`] item with the item it stands
+/// for, reading prompts with `prompt`, which is [`crate::prompt::secret`]
+/// outside of tests. Items are parsed more than once while the arguments
+/// are, so files, stdin, the clipboard, and the terminal are read here,
+/// once, after them.
+pub fn resolve<F, E>(
+    request_items: Vec<RequestItem>,
+    mut prompt: F,
+) -> Result<Vec<RequestItem>, RequestItemError>
+where
+    F: FnMut(&str) -> Result<String, E>,
+    E: std::fmt::Display,
+{
+    request_items
+        .into_iter()
+        .map(|request_item| match request_item {
+            RequestItem::Unresolved {
+                key,
+                separator,
+                source,
+            } => resolve_item(key, separator, source, &mut prompt),
+            request_item => Ok(request_item),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(s: &str) -> Option<(String, &'static str, String)> {
+        tokenize(s).map(|(key, separator, value)| (key, separator, value.to_owned()))
+    }
+
+    fn token(
+        key: &str,
+        separator: &'static str,
+        value: &str,
+    ) -> Option<(String, &'static str, String)> {
+        Some((key.into(), separator, value.into()))
+    }
+
+    #[test]
+    fn tokenizes_every_separator() {
+        assert_eq!(tokens("a==b"), token("a", "==", "b"));
+        assert_eq!(tokens("a=@b"), token("a", "=@", "b"));
+        assert_eq!(tokens("a=b"), token("a", "=", "b"));
+        assert_eq!(tokens("a:=@b"), token("a", ":=@", "b"));
+        assert_eq!(tokens("a:=b"), token("a", ":=", "b"));
+        assert_eq!(tokens("a:b"), token("a", ":", "b"));
+        assert_eq!(tokens("a@b"), token("a", "@", "b"));
+    }
+
+    #[test]
+    fn first_separator_wins() {
+        assert_eq!(tokens("a:b=c"), token("a", ":", "b=c"));
+        assert_eq!(tokens("a=b:c"), token("a", "=", "b:c"));
+        assert_eq!(tokens("a@b=c"), token("a", "@", "b=c"));
+        assert_eq!(tokens("a=b==c"), token("a", "=", "b==c"));
+        assert_eq!(
+            tokens("url==http://x.test/?q=1"),
+            token("url", "==", "http://x.test/?q=1")
+        );
+        assert_eq!(
+            tokens("Authorization:Basic dXNlcjpwYXNz=="),
+            token("Authorization", ":", "Basic dXNlcjpwYXNz==")
+        );
+    }
+
+    #[test]
+    fn longest_separator_wins_at_a_position() {
+        assert_eq!(tokens("a===b"), token("a", "==", "=b"));
+        assert_eq!(tokens("a:==b"), token("a", ":=", "=b"));
+        assert_eq!(tokens("a:=@@b"), token("a", ":=@", "@b"));
+        assert_eq!(tokens("a=@@b"), token("a", "=@", "@b"));
+        assert_eq!(tokens("a:@b"), token("a", ":", "@b"));
+    }
+
+    #[test]
+    fn trailing_semicolons_are_empty_headers() {
+        assert_eq!(tokens("X-Empty;"), token("X-Empty", ";", ""));
+        assert_eq!(tokens("a;b"), None);
+        assert_eq!(tokens("a;b=c"), token("a;b", "=", "c"));
+        assert_eq!(tokens("Cookie:a=1;"), token("Cookie", ":", "a=1;"));
+        assert_eq!(tokens(";"), None);
+    }
+
+    #[test]
+    fn empty_values_are_allowed() {
+        assert_eq!(tokens("a="), token("a", "=", ""));
+        assert_eq!(tokens("a:"), token("a", ":", ""));
+        assert_eq!(tokens("a=="), token("a", "==", ""));
+        assert_eq!(tokens("a:="), token("a", ":=", ""));
+    }
+
+    #[test]
+    fn escapes_are_stripped_from_keys() {
+        assert_eq!(tokens(r"a\=b=c"), token("a=b", "=", "c"));
+        assert_eq!(tokens(r"a\:b=c"), token("a:b", "=", "c"));
+        assert_eq!(tokens(r"a\@b=c"), token("a@b", "=", "c"));
+        assert_eq!(tokens(r"a\\=c"), token(r"a\", "=", "c"));
+        assert_eq!(tokens(r"a\=\=b==c"), token("a==b", "==", "c"));
+        assert_eq!(tokens(r"\:a:b"), token(":a", ":", "b"));
+    }
+
+    #[test]
+    fn other_backslashes_are_kept() {
+        assert_eq!(tokens(r"a\nb=c"), token(r"a\nb", "=", "c"));
+        assert_eq!(tokens(r"a\=c"), None);
+        assert_eq!(tokens(r"a\"), None);
+    }
+
+    #[test]
+    fn values_are_not_unescaped() {
+        assert_eq!(tokens(r"a=b\=c"), token("a", "=", r"b\=c"));
+        assert_eq!(tokens(r"a:b\:c"), token("a", ":", r"b\:c"));
+    }
+
+    #[test]
+    fn keys_may_contain_unicode() {
+        assert_eq!(tokens("naïve=é"), token("naïve", "=", "é"));
+    }
+
+    #[test]
+    fn items_without_a_key_or_separator_are_rejected() {
+        assert_eq!(tokens(""), None);
+        assert_eq!(tokens("abc"), None);
+        assert_eq!(tokens("=b"), None);
+        assert_eq!(tokens(":b"), None);
+        assert_eq!(tokens("==b"), None);
+        assert_eq!(tokens(r"a\:b"), None);
+    }
+
+    #[test]
+    fn parses_data() {
+        match "name=ezra".parse() {
+            Ok(RequestItem::Data { key, value }) => {
+                assert_eq!(key, "name");
+                assert_eq!(value, "ezra");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_escaped_data_keys() {
+        match r"a\:b=c".parse() {
+            Ok(RequestItem::Data { key, value }) => {
+                assert_eq!(key, "a:b");
+                assert_eq!(value, "c");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_search_params() {
+        match "q==a=b".parse() {
+            Ok(RequestItem::SearchParam { key, value }) => {
+                assert_eq!(key, "q");
+                assert_eq!(value, "a=b");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_headers() {
+        match "X-API-Token:a:b".parse() {
+            Ok(RequestItem::Header { key, value }) => {
+                assert_eq!(key, "x-api-token");
+                assert_eq!(value, "a:b");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_unset_headers() {
+        match "User-Agent:".parse() {
+            Ok(RequestItem::UnsetHeader { key }) => assert_eq!(key, "user-agent"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_empty_headers() {
+        match "X-Empty;".parse() {
+            Ok(RequestItem::EmptyHeader { key }) => assert_eq!(key, "x-empty"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_json_data() {
+        match "tags:=[1, 2]".parse() {
+            Ok(RequestItem::JsonData { key, value }) => {
+                assert_eq!(key, "tags");
+                assert_eq!(value, json::array![1, 2]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_form_files() {
+        let item = "manifest@./Cargo.toml".parse().unwrap();
+
+        match resolve(vec![item], no_prompt).unwrap().pop() {
+            Some(RequestItem::FormFile {
+                key,
+                value,
+                content_type,
+                filename,
+                ..
+            }) => {
+                assert_eq!(key, "manifest");
+                assert_eq!(value, std::path::PathBuf::from("./Cargo.toml"));
+                assert_eq!(content_type, None);
+                assert_eq!(filename, None);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn splits_file_options_from_the_path() {
+        assert_eq!(
+            file_options("a.png;type=image/png;filename=b.png"),
+            (
+                "a.png".into(),
+                Some("image/png".into()),
+                Some("b.png".into())
+            )
+        );
+        assert_eq!(file_options("a;b.txt"), ("a;b.txt".into(), None, None));
+    }
+
+    #[test]
+    fn rejects_invalid_items() {
+        assert!(matches!(
+            "abc".parse::<RequestItem>(),
+            Err(RequestItemError::ParseError(_))
+        ));
+        assert!(matches!(
+            "count:=nope".parse::<RequestItem>(),
+            Err(RequestItemError::ParseError(_))
+        ));
+        assert!(matches!(
+            "bad header:x".parse::<RequestItem>(),
+            Err(RequestItemError::ParseError(_))
+        ));
+        assert!(matches!(
+            "a=@".parse::<RequestItem>(),
+            Err(RequestItemError::MissingFileInputError(_))
+        ));
+        assert!(matches!(
+            "@".parse::<RequestItem>(),
+            Err(RequestItemError::MissingFileInputError(_))
+        ));
+    }
+
+    fn no_prompt(_: &str) -> Result<String, &'static str> {
+        Err("no terminal")
+    }
+
+    #[test]
+    fn reads_files_only_when_resolved() {
+        let item: RequestItem = "a=@/nonexistent/rurl".parse().unwrap();
+        assert!(
+            matches!(&item, RequestItem::Unresolved { separator: "=", source, .. }
+            if *source == Source::File("/nonexistent/rurl".into()))
+        );
+        assert!(matches!(
+            resolve(vec![item], no_prompt),
+            Err(RequestItemError::IOError(_))
+        ));
+
+        let items = [
+            "bio=@Cargo.toml",
+            "manifest@Cargo.toml;type=text/x-toml",
+            "@Cargo.toml",
+        ]
+        .iter()
+        .map(|item| item.parse().unwrap())
+        .collect();
+        let manifest = std::fs::read("Cargo.toml").unwrap();
+
+        match resolve(items, no_prompt).unwrap().as_slice() {
+            [RequestItem::Data { key, value }, RequestItem::FormFile {
+                key: field,
+                content,
+                content_type,
+                ..
+            }, RequestItem::RawBody { path, value: raw }] => {
+                assert_eq!(key, "bio");
+                assert_eq!(value.as_bytes(), &manifest[..]);
+                assert_eq!(field, "manifest");
+                assert_eq!(content, &manifest);
+                assert_eq!(content_type.as_deref(), Some("text/x-toml"));
+                assert_eq!(path, std::path::Path::new("Cargo.toml"));
+                assert_eq!(raw, &manifest);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_stdin_and_clipboard_items_without_reading_them() {
+        for (item, separator, source) in &[
+            (
+                "token=@-:$.access_token",
+                "=",
+                Source::Stdin(Some("$.access_token".into())),
+            ),
+            ("user:=@-", ":=", Source::Stdin(None)),
+            ("note=@clipboard", "=", Source::Clipboard),
+            ("@clipboard", "", Source::Clipboard),
+        ] {
+            match item.parse() {
+                Ok(RequestItem::Unresolved {
+                    separator: parsed,
+                    source: parsed_source,
+                    ..
+                }) => {
+                    assert_eq!(parsed, *separator, "{}", item);
+                    assert_eq!(&parsed_source, source, "{}", item);
+                }
+                other => panic!("unexpected {:?} for {}", other, item),
+            }
+        }
+    }
+
+    #[test]
+    fn prompts_are_parsed_without_reading_the_terminal() {
+        for (item, expected) in &[
+            ("password=@prompt", "="),
+            ("token==@prompt", "=="),
+            ("X-API-Token:@prompt", ":"),
+        ] {
+            match item.parse() {
+                Ok(RequestItem::Unresolved {
+                    separator,
+                    source: Source::Prompt,
+                    ..
+                }) => assert_eq!(separator, *expected),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+
+        assert!(matches!(
+            "bad header:@prompt".parse::<RequestItem>(),
+            Err(RequestItemError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn resolves_prompts_once_each() {
+        let items = [
+            "password=@prompt",
+            "token==@prompt",
+            "X-API-Token:@prompt",
+            "a=b",
+        ]
+        .iter()
+        .map(|item| item.parse().unwrap())
+        .collect();
+
+        let mut asked = Vec::new();
+        let items = resolve(items, |name| {
+            asked.push(name.to_owned());
+            Ok::<_, String>(format!("{}-secret", name))
+        })
+        .unwrap();
+
+        assert_eq!(asked, ["password", "token", "X-API-Token"]);
+        assert!(matches!(&items[0], RequestItem::Data { key, value }
+            if key == "password" && value == "password-secret"));
+        assert!(matches!(&items[1], RequestItem::SearchParam { key, value }
+            if key == "token" && value == "token-secret"));
+        assert!(matches!(&items[2], RequestItem::Header { key, value }
+            if key == "x-api-token" && value == "X-API-Token-secret"));
+        assert!(matches!(&items[3], RequestItem::Data { key, value }
+            if key == "a" && value == "b"));
+    }
+
+    #[test]
+    fn prompt_errors_are_reported() {
+        let items = vec!["token==@prompt".parse().unwrap()];
+
+        assert!(matches!(
+            resolve(items, |_| Err("no terminal")),
+            Err(RequestItemError::PromptError(err)) if err == "no terminal"
+        ));
+    }
+}
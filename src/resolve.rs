@@ -0,0 +1,433 @@
+use anyhow::Result;
+use hyper::{client::connect::dns::Name, service::Service};
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::IpAddr,
+    pin::Pin,
+    process::{Command, Stdio},
+    sync::Arc,
+    task::{Context, Poll},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum HostsError {
+    #[error("{0}:{1}: invalid address {2}")]
+    AddressParseError(String, usize, String),
+    #[error("invalid --resolve {0}, expected HOST:PORT:ADDRESS[,ADDRESS...]")]
+    OverrideParseError(String),
+    #[error("no {1} address on interface {0}")]
+    NoInterfaceAddress(String, String),
+}
+
+/// Restricts connections to IPv4 (`-4`) or IPv6 (`-6`) addresses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    pub fn of(address: &IpAddr) -> Self {
+        match address {
+            IpAddr::V4(_) => Self::V4,
+            IpAddr::V6(_) => Self::V6,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::V4 => "IPv4",
+            Self::V6 => "IPv6",
+        }
+    }
+}
+
+/// The address to bind for `--interface`: the value itself when it is an
+/// address, otherwise the first global address `ip addr` lists for the
+/// device, of `family` if given.
+pub fn interface_address(interface: &str, family: Option<Family>) -> Result<IpAddr> {
+    if let Ok(address) = interface.parse() {
+        return Ok(address);
+    }
+
+    let err = || {
+        let family = family.map_or("IP", Family::name);
+        HostsError::NoInterfaceAddress(interface.into(), family.into())
+    };
+
+    let output = Command::new("ip")
+        .args(["-o", "addr", "show", "dev", interface])
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|_| err())?;
+
+    // `2: eth0    inet 192.0.2.7/24 brd 192.0.2.255 scope global eth0 ...`
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.contains("scope link"))
+        .filter_map(|line| {
+            let mut fields = line
+                .split_whitespace()
+                .skip_while(|field| !field.starts_with("inet"));
+            fields.next()?;
+            fields.next()?.split('/').next()?.parse().ok()
+        })
+        .find(|address| family.is_none_or(|family| family == Family::of(address)))
+        .ok_or_else(|| err().into())
+}
+
+/// A `--resolve HOST:PORT:ADDRESS` entry, like curl's. Addresses may be
+/// comma-separated, and IPv6 ones bracketed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolveOverride {
+    pub host: String,
+    pub port: u16,
+    pub addresses: Vec<IpAddr>,
+}
+
+impl std::str::FromStr for ResolveOverride {
+    type Err = HostsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || HostsError::OverrideParseError(s.into());
+        let mut fields = s.splitn(3, ':');
+
+        let host = fields
+            .next()
+            .filter(|host| !host.is_empty())
+            .ok_or_else(err)?;
+        let port = fields
+            .next()
+            .and_then(|port| port.parse().ok())
+            .ok_or_else(err)?;
+        let addresses = fields
+            .next()
+            .ok_or_else(err)?
+            .split(',')
+            .map(|address| {
+                address
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .parse()
+                    .map_err(|_| err())
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            host: host.to_ascii_lowercase(),
+            port,
+            addresses,
+        })
+    }
+}
+
+/// Host name overrides consulted before system DNS.
+#[derive(Clone, Debug, Default)]
+pub struct Hosts(Arc<HashMap<String, Vec<IpAddr>>>);
+
+impl Hosts {
+    /// Parses an `/etc/hosts`-style file: an address followed by one or more
+    /// names per line, with `#` comments.
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        let mut hosts: HashMap<String, Vec<IpAddr>> = HashMap::new();
+
+        for (i, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+
+            let address = match fields.next() {
+                Some(address) => address.parse().map_err(|_| {
+                    HostsError::AddressParseError(path.display().to_string(), i + 1, address.into())
+                })?,
+                None => continue,
+            };
+
+            for name in fields {
+                hosts
+                    .entry(name.to_ascii_lowercase())
+                    .or_default()
+                    .push(address);
+            }
+        }
+
+        Ok(Self(Arc::new(hosts)))
+    }
+
+    /// Adds `--resolve` entries, which replace any file entries for a host.
+    ///
+    /// The resolver only sees host names, so an entry applies to its host
+    /// on every port rather than just the one given.
+    pub fn with_overrides(mut self, overrides: &[ResolveOverride]) -> Self {
+        let hosts = Arc::make_mut(&mut self.0);
+
+        for entry in overrides {
+            hosts.insert(entry.host.clone(), entry.addresses.clone());
+        }
+
+        self
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&[IpAddr]> {
+        self.0.get(&name.to_ascii_lowercase()).map(Vec::as_slice)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OverrideResolver<R> {
+    inner: R,
+    hosts: Hosts,
+    family: Option<Family>,
+}
+
+impl<R> OverrideResolver<R> {
+    pub fn new(inner: R, hosts: Hosts, family: Option<Family>) -> Self {
+        Self {
+            inner,
+            hosts,
+            family,
+        }
+    }
+}
+
+fn format_ips(addresses: &[IpAddr]) -> String {
+    addresses
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Alternates the families, IPv6 first, as RFC 8305 orders addresses for
+/// racing, so a family that doesn't connect isn't tried twice in a row.
+fn interleave(addresses: Vec<IpAddr>) -> Vec<IpAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addresses.into_iter().partition(IpAddr::is_ipv6);
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    let mut ordered = Vec::new();
+
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return ordered,
+            (v6, v4) => ordered.extend(v6.into_iter().chain(v4)),
+        }
+    }
+}
+
+/// Keeps the addresses of `family`, failing when none are left.
+fn only_family(
+    name: &str,
+    addresses: Vec<IpAddr>,
+    family: Option<Family>,
+) -> Result<std::vec::IntoIter<IpAddr>, std::io::Error> {
+    let family = match family {
+        Some(family) => family,
+        None => return Ok(interleave(addresses).into_iter()),
+    };
+
+    let addresses: Vec<_> = addresses
+        .into_iter()
+        .filter(|address| Family::of(address) == family)
+        .collect();
+
+    if addresses.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} has no {} address", name, family.name()),
+        ));
+    }
+
+    Ok(addresses.into_iter())
+}
+
+impl<R> Service<Name> for OverrideResolver<R>
+where
+    R: Service<Name>,
+    R::Response: Iterator<Item = IpAddr>,
+    R::Future: Send + 'static,
+    R::Error: From<std::io::Error>,
+{
+    type Response = std::vec::IntoIter<IpAddr>;
+    type Error = R::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, R::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let family = self.family;
+
+        // the connector adds the port after resolving
+        if let Some(addresses) = self.hosts.lookup(name.as_str()) {
+            let addresses = only_family(name.as_str(), addresses.to_vec(), family);
+            if let Ok(addresses) = &addresses {
+                crate::trace!(
+                    "{} is {} by override",
+                    name,
+                    format_ips(addresses.as_slice())
+                );
+            }
+
+            return Box::pin(async move { Ok(addresses?) });
+        }
+
+        let resolving = self.inner.call(name.clone());
+
+        Box::pin(async move {
+            let addresses = only_family(name.as_str(), resolving.await?.collect(), family)?;
+            crate::trace!("{} resolved to {}", name, format_ips(addresses.as_slice()));
+
+            Ok(addresses)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(addresses: &[&str]) -> Vec<IpAddr> {
+        addresses
+            .iter()
+            .map(|address| address.parse().unwrap())
+            .collect()
+    }
+
+    fn hosts_file(contents: &str) -> Result<Hosts> {
+        let path = std::env::temp_dir().join(format!(
+            "rurl-hosts-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        let hosts = Hosts::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        hosts
+    }
+
+    /// Answers every name with 192.0.2.1, as system DNS would.
+    struct Upstream;
+
+    impl Service<Name> for Upstream {
+        type Response = std::vec::IntoIter<IpAddr>;
+        type Error = std::io::Error;
+        type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: Name) -> Self::Future {
+            std::future::ready(Ok(ips(&["192.0.2.1"]).into_iter()))
+        }
+    }
+
+    async fn resolve(hosts: &Hosts, family: Option<Family>, name: &str) -> std::io::Result<String> {
+        let mut resolver = OverrideResolver::new(Upstream, hosts.clone(), family);
+        let addresses = resolver.call(name.parse().unwrap()).await?;
+
+        Ok(format_ips(addresses.as_slice()))
+    }
+
+    #[test]
+    fn loads_hosts_files() {
+        let hosts = hosts_file(
+            "# local names\n\
+             127.0.0.1\tapi.test  API.example.test # both\n\
+             \n\
+             ::1 api.test\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            hosts.lookup("api.test").unwrap(),
+            &ips(&["127.0.0.1", "::1"])[..]
+        );
+        assert_eq!(
+            hosts.lookup("Api.Example.Test").unwrap(),
+            &ips(&["127.0.0.1"])[..]
+        );
+        assert_eq!(hosts.lookup("other.test"), None);
+
+        let err = hosts_file("127.0.0.1 ok.test\n300.0.0.1 bad.test\n").unwrap_err();
+        assert!(
+            err.to_string().ends_with(":2: invalid address 300.0.0.1"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn resolve_entries_replace_the_hosts_file() {
+        let entry: ResolveOverride = "API.test:443:10.0.0.1,[::2]".parse().unwrap();
+        assert_eq!(
+            entry,
+            ResolveOverride {
+                host: "api.test".into(),
+                port: 443,
+                addresses: ips(&["10.0.0.1", "::2"]),
+            }
+        );
+        for bad in &[
+            "api.test",
+            "api.test:x:10.0.0.1",
+            ":443:10.0.0.1",
+            "api.test:443:nope",
+        ] {
+            assert!(bad.parse::<ResolveOverride>().is_err(), "{}", bad);
+        }
+
+        let hosts = hosts_file("127.0.0.1 api.test web.test\n")
+            .unwrap()
+            .with_overrides(&[entry]);
+        assert_eq!(
+            hosts.lookup("api.test").unwrap(),
+            &ips(&["10.0.0.1", "::2"])[..]
+        );
+        assert_eq!(hosts.lookup("web.test").unwrap(), &ips(&["127.0.0.1"])[..]);
+    }
+
+    #[tokio::test]
+    async fn resolves_from_the_hosts_file_before_dns() {
+        let hosts = hosts_file("127.0.0.1 api.test\n::1 api.test\n").unwrap();
+
+        assert_eq!(
+            resolve(&hosts, None, "api.test").await.unwrap(),
+            "::1, 127.0.0.1"
+        );
+        assert_eq!(
+            resolve(&hosts, None, "API.TEST").await.unwrap(),
+            "::1, 127.0.0.1"
+        );
+        assert_eq!(
+            resolve(&hosts, None, "other.test").await.unwrap(),
+            "192.0.2.1"
+        );
+        assert_eq!(
+            resolve(&hosts, Some(Family::V4), "api.test").await.unwrap(),
+            "127.0.0.1"
+        );
+
+        let v4_only = hosts_file("127.0.0.1 api.test\n").unwrap();
+        let err = resolve(&v4_only, Some(Family::V6), "api.test")
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "api.test has no IPv6 address");
+    }
+
+    #[test]
+    fn interleaves_families_ipv6_first() {
+        let addresses = ["10.0.0.1", "10.0.0.2", "10.0.0.3", "::1", "::2"]
+            .iter()
+            .map(|address| address.parse().unwrap())
+            .collect();
+
+        assert_eq!(
+            format_ips(&interleave(addresses)),
+            "::1, 10.0.0.1, ::2, 10.0.0.2, 10.0.0.3"
+        );
+    }
+}
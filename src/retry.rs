@@ -0,0 +1,234 @@
+use hyper::Method;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RetryError {
+    #[error("circuit open for host {0}, skipping request")]
+    CircuitOpen(String),
+}
+
+/// The longest wait before the first retry, doubled for each one after it.
+const BACKOFF_BASE: Duration = Duration::from_millis(100);
+const BACKOFF_MAX: Duration = Duration::from_secs(10);
+
+/// Whether sending a request twice has the same effect as sending it once,
+/// per RFC 7231: retrying anything else could, say, place an order twice.
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// How long to wait before retry `attempt`, counting from zero: "full
+/// jitter", a `random` fraction of an exponentially growing cap, so clients
+/// retrying together spread out.
+pub fn backoff(attempt: u32, random: u64) -> Duration {
+    let cap = BACKOFF_BASE
+        .checked_mul(2u32.saturating_pow(attempt))
+        .map_or(BACKOFF_MAX, |cap| cap.min(BACKOFF_MAX));
+
+    cap.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+/// A random number for `backoff`, from the clock; good enough for jitter.
+pub fn jitter() -> u64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or_default();
+
+    // splitmix64's finalizer, so close instants land far apart
+    let mut x = u64::from(nanos).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[derive(Debug)]
+pub struct RetryBudget {
+    remaining: AtomicUsize,
+    all_methods: bool,
+}
+
+impl RetryBudget {
+    pub fn new(retries: usize) -> Self {
+        Self {
+            remaining: AtomicUsize::new(retries),
+            all_methods: false,
+        }
+    }
+
+    /// Retries non-idempotent methods too, such as POST and PATCH.
+    pub fn all_methods(mut self, all_methods: bool) -> Self {
+        self.all_methods = all_methods;
+        self
+    }
+
+    /// Whether a request with `method` may be retried at all.
+    pub fn allows(&self, method: &Method) -> bool {
+        self.all_methods || is_idempotent(method)
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        let mut remaining = self.remaining.load(Ordering::SeqCst);
+
+        while remaining > 0 {
+            match self.remaining.compare_exchange(
+                remaining,
+                remaining - 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return true,
+                Err(current) => remaining = current,
+            }
+        }
+
+        false
+    }
+}
+
+#[derive(Debug, Default)]
+struct HostState {
+    failures: usize,
+    open_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: usize,
+    cooldown: Duration,
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: usize, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn check(&self, host: &str) -> Result<(), RetryError> {
+        if self.threshold == 0 {
+            return Ok(());
+        }
+
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = hosts.entry(host.into()).or_default();
+
+        match state.open_until {
+            Some(open_until) if Instant::now() < open_until => {
+                Err(RetryError::CircuitOpen(host.into()))
+            }
+            Some(_) => {
+                // half-open: let one request through, a single failure re-opens
+                state.open_until = None;
+                state.failures = self.threshold - 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_success(&self, host: &str) {
+        if let Some(state) = self.hosts.lock().unwrap().get_mut(host) {
+            state.failures = 0;
+            state.open_until = None;
+        }
+    }
+
+    pub fn record_failure(&self, host: &str) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let mut hosts = self.hosts.lock().unwrap();
+        let state = hosts.entry(host.into()).or_default();
+
+        state.failures += 1;
+
+        if state.failures >= self.threshold {
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budgets_are_shared_and_run_out() {
+        let budget = RetryBudget::new(2);
+
+        assert!(budget.try_acquire());
+        assert!(budget.try_acquire());
+        assert!(!budget.try_acquire());
+        assert!(!RetryBudget::new(0).try_acquire());
+    }
+
+    #[test]
+    fn only_idempotent_methods_are_retried_unless_all_are() {
+        let budget = RetryBudget::new(1);
+        assert!(budget.allows(&Method::GET));
+        assert!(budget.allows(&Method::PUT));
+        assert!(!budget.allows(&Method::POST));
+        assert!(!budget.allows(&Method::PATCH));
+
+        let budget = budget.all_methods(true);
+        assert!(budget.allows(&Method::POST));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_cap() {
+        assert_eq!(backoff(0, 0), Duration::from_millis(0));
+        assert_eq!(backoff(0, u64::MAX), BACKOFF_BASE);
+        assert_eq!(backoff(3, u64::MAX), BACKOFF_BASE * 8);
+        assert_eq!(backoff(40, u64::MAX), BACKOFF_MAX);
+        assert!(backoff(3, u64::MAX / 2) < BACKOFF_BASE * 5);
+    }
+
+    #[test]
+    fn breakers_open_after_the_threshold_and_half_open_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(20));
+
+        breaker.record_failure("a.test");
+        assert!(breaker.check("a.test").is_ok());
+        breaker.record_failure("a.test");
+        assert!(breaker.check("a.test").is_err());
+        assert!(breaker.check("b.test").is_ok());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.check("a.test").is_ok());
+
+        // one failure while half-open opens it again
+        breaker.record_failure("a.test");
+        assert!(breaker.check("a.test").is_err());
+    }
+
+    #[test]
+    fn successes_reset_breakers_and_zero_disables_them() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(30));
+        breaker.record_failure("a.test");
+        breaker.record_success("a.test");
+        breaker.record_failure("a.test");
+        assert!(breaker.check("a.test").is_ok());
+
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(30));
+        for _ in 0..10 {
+            breaker.record_failure("a.test");
+        }
+        assert!(breaker.check("a.test").is_ok());
+    }
+}
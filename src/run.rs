@@ -0,0 +1,285 @@
+use anyhow::Result;
+use hyper::{Method, StatusCode, Uri};
+use rurl::{
+    cancel::{CancellationToken, CancelledError},
+    client::{self, ConnectOptions, HttpClient, RurlRequestBuilder},
+    collection::{self, Captured, Collection, Request},
+    config::Config,
+    encoding,
+    request_item::RequestItem,
+    retry::{CircuitBreaker, RetryBudget},
+    template::Template,
+    timing::Recorder,
+    transaction::{self, Step},
+};
+use std::{
+    cell::{Cell, RefCell},
+    time::{Duration, Instant},
+};
+
+const USAGE: &str = "usage: rurl run FILE [--var NAME=VALUE]...";
+
+struct Runner<'a> {
+    http: HttpClient,
+    budget: RetryBudget,
+    breaker: CircuitBreaker,
+    cancel: CancellationToken,
+    template: Template<'a>,
+    vars: Vec<(String, String)>,
+    captured: RefCell<Vec<Captured>>,
+    /// The width of the name column.
+    width: usize,
+    succeeded: Cell<usize>,
+}
+
+impl Runner<'_> {
+    fn render(&self, input: &str) -> Result<String> {
+        collection::render(input, &self.vars, &self.captured.borrow(), &self.template)
+    }
+
+    /// Sends a request and prints its status line, keeping the response
+    /// for later requests to refer to.
+    async fn step(&self, request: Request, rollback: bool) -> Result<StatusCode> {
+        let start = Instant::now();
+
+        match self.send(&request, rollback).await {
+            Ok((line, captured)) => {
+                let status = StatusCode::from_u16(captured.status)?;
+
+                println!(
+                    "{:<width$}  {}  {}  {}ms",
+                    request.name,
+                    line,
+                    status,
+                    start.elapsed().as_millis(),
+                    width = self.width
+                );
+
+                if !rollback && !status.is_client_error() && !status.is_server_error() {
+                    self.succeeded.set(self.succeeded.get() + 1);
+                }
+
+                self.captured.borrow_mut().push(captured);
+
+                Ok(status)
+            }
+            Err(err) => {
+                if !err.is::<CancelledError>() {
+                    println!(
+                        "{:<width$}  error: {}",
+                        request.name,
+                        err,
+                        width = self.width
+                    );
+                }
+
+                Err(err)
+            }
+        }
+    }
+
+    async fn send(&self, request: &Request, rollback: bool) -> Result<(String, Captured)> {
+        let method: Method = self.render(&request.method)?.parse()?;
+        let uri: Uri = self.render(&request.uri)?.parse()?;
+        let mut builder = RurlRequestBuilder::new(method, uri);
+
+        for (name, value) in &request.headers {
+            builder = builder.request_item(RequestItem::Header {
+                key: name.parse()?,
+                value: self.render(value)?.parse()?,
+            });
+        }
+
+        if !request.body.is_empty() {
+            let value = self.render(&request.body)?;
+
+            // the path only picks the default Content-Type
+            let path = if json::parse(&value).is_ok() {
+                "body.json"
+            } else {
+                "body.txt"
+            };
+
+            builder = builder.request_item(RequestItem::RawBody {
+                path: path.into(),
+                value: value.into_bytes(),
+            });
+        }
+
+        let (req, body) = builder.build()?;
+        let line = format!("{} {}", req.method(), req.uri());
+
+        // rollbacks still go out after the run was cancelled
+        let cancel = if rollback {
+            CancellationToken::new()
+        } else {
+            self.cancel.clone()
+        };
+
+        let mut res = client::send(
+            &self.http,
+            &req,
+            &body.content,
+            &self.budget,
+            &self.breaker,
+            &cancel,
+        )
+        .await?;
+
+        let mut buf = Vec::new();
+        while let Some(chunk) = client::next_chunk(&mut res, &cancel).await? {
+            buf.extend_from_slice(&chunk);
+        }
+
+        let buf = encoding::decode(res.headers(), &buf).unwrap_or(buf);
+
+        Ok((
+            line,
+            Captured {
+                name: request.name.clone(),
+                status: res.status().as_u16(),
+                headers: res.headers().clone(),
+                body: String::from_utf8_lossy(&buf).into_owned(),
+            },
+        ))
+    }
+}
+
+/// The `.http` file to run, checking that the rest are `--var` options.
+fn file_of(args: &[String]) -> Result<&str> {
+    let path = match args.first() {
+        Some(path) if !path.starts_with('-') => path,
+        _ => anyhow::bail!(USAGE),
+    };
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--var" => {
+                rest.next();
+            }
+            arg if arg.starts_with("--var=") => {}
+            _ => anyhow::bail!(USAGE),
+        }
+    }
+
+    Ok(path)
+}
+
+/// The width of the name column, which rollbacks are printed in too.
+fn name_width(requests: &[Request]) -> usize {
+    requests
+        .iter()
+        .flat_map(|request| std::iter::once(request).chain(request.rollback.as_deref()))
+        .map(|request| request.name.len())
+        .max()
+        .unwrap_or_default()
+}
+
+/// Runs the requests in an `.http` file in order, one status line each,
+/// and fails at the first that errors or gets a 4xx or 5xx response, after
+/// sending the rollbacks of the requests before it.
+pub async fn run(args: &[String]) -> Result<()> {
+    let path = file_of(args)?;
+
+    let config = Config::load()?;
+    let collection: Collection = std::fs::read_to_string(path)?.parse()?;
+
+    let recorder = Recorder::default();
+    let connector = client::build_connector(&recorder, ConnectOptions::default())?;
+    let cancel = CancellationToken::new();
+    cancel.cancel_on_ctrl_c();
+
+    let mut runner = Runner {
+        http: client::build_client(connector, Some(Duration::from_secs(90)), false),
+        budget: RetryBudget::new(0),
+        breaker: CircuitBreaker::new(5, Duration::from_secs(30)),
+        cancel,
+        template: Template::from_args(&config, &args[1..])?,
+        vars: Vec::new(),
+        captured: RefCell::new(Vec::new()),
+        width: name_width(&collection.requests),
+        succeeded: Cell::new(0),
+    };
+
+    // each file variable may use the ones before it
+    for (name, value) in &collection.vars {
+        let value = runner.render(value)?;
+        runner.vars.push((name.clone(), value));
+    }
+
+    let steps = collection
+        .requests
+        .iter()
+        .map(|request| Step {
+            name: request.name.clone(),
+            request: request.clone(),
+            rollback: request.rollback.as_deref().cloned(),
+        })
+        .collect();
+
+    let runner = &runner;
+    let result = transaction::run(steps, move |request, rollback| {
+        runner.step(request, rollback)
+    })
+    .await;
+
+    if matches!(&result, Err(err) if err.is::<CancelledError>()) {
+        return result;
+    }
+
+    println!(
+        "\n{} of {} requests succeeded",
+        runner.succeeded.get(),
+        collection.requests.len()
+    );
+
+    if result.is_err() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn takes_a_file_and_variables() {
+        assert_eq!(file_of(&args(&["api.http"])).unwrap(), "api.http");
+        assert_eq!(
+            file_of(&args(&["api.http", "--var", "host=a", "--var=user=b"])).unwrap(),
+            "api.http"
+        );
+
+        for bad in &[&[][..], &["--var", "host=a"], &["api.http", "--verbose"]] {
+            assert_eq!(file_of(&args(bad)).unwrap_err().to_string(), USAGE);
+        }
+    }
+
+    #[test]
+    fn names_are_padded_to_the_longest_including_rollbacks() {
+        let collection: Collection = "\
+### user
+POST /users
+
+### rollback:
+DELETE /users/1
+
+### me
+GET /me
+"
+        .parse()
+        .unwrap();
+
+        let rollback = collection.requests[0].rollback.as_ref().unwrap();
+        assert_eq!(name_width(&collection.requests), rollback.name.len());
+        assert_eq!(name_width(&collection.requests[1..]), "me".len());
+        assert_eq!(name_width(&[]), 0);
+    }
+}
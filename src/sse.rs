@@ -0,0 +1,134 @@
+#[derive(Debug, Default)]
+pub struct Event {
+    pub event: Option<String>,
+    pub id: Option<String>,
+    pub retry: Option<String>,
+    pub data: String,
+}
+
+#[derive(Debug, Default)]
+pub struct EventParser {
+    buf: Vec<u8>,
+    event: Event,
+    has_data: bool,
+}
+
+impl EventParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<Event> {
+        self.buf.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+
+        while let Some(end) = self.buf.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if let Some(event) = self.line(line) {
+                events.push(event);
+            }
+        }
+
+        events
+    }
+
+    fn line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            // a blank line dispatches the event, but only if it carried data
+            let event = std::mem::take(&mut self.event);
+            let has_data = std::mem::replace(&mut self.has_data, false);
+
+            return if has_data { Some(event) } else { None };
+        }
+
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.find(':') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => (line, ""),
+        };
+        let value = value.strip_prefix(' ').unwrap_or(value);
+
+        match field {
+            "event" => self.event.event = Some(value.into()),
+            "id" => self.event.id = Some(value.into()),
+            "retry" => self.event.retry = Some(value.into()),
+            "data" => {
+                if self.has_data {
+                    self.event.data.push('\n');
+                }
+
+                self.event.data.push_str(value);
+                self.has_data = true;
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(events: &[Event]) -> Vec<&str> {
+        events.iter().map(|event| event.data.as_str()).collect()
+    }
+
+    #[test]
+    fn joins_data_lines_with_newlines() {
+        let mut parser = EventParser::new();
+        let events = parser.feed(b"data: a\ndata:b\ndata\n\ndata: c\n\n");
+
+        assert_eq!(data(&events), vec!["a\nb\n", "c"]);
+    }
+
+    #[test]
+    fn reads_crlf_lines_split_across_chunks() {
+        let mut parser = EventParser::new();
+
+        assert!(parser.feed(b"event: tick\r").is_empty());
+        assert!(parser.feed(b"\ndata: 1\r\n\r").is_empty());
+
+        let events = parser.feed(b"\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event.as_deref(), Some("tick"));
+        assert_eq!(events[0].data, "1");
+    }
+
+    #[test]
+    fn keeps_id_and_retry_per_event() {
+        let mut parser = EventParser::new();
+        let events = parser.feed(b"id: 7\nretry: 3000\ndata: a\n\ndata: b\n\n");
+
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+        assert_eq!(events[0].retry.as_deref(), Some("3000"));
+        assert_eq!(events[1].id, None);
+        assert_eq!(events[1].retry, None);
+    }
+
+    #[test]
+    fn skips_comments_and_events_without_data() {
+        let mut parser = EventParser::new();
+        let events = parser.feed(b": keep-alive\n\nevent: ping\n\n:x\ndata: a\n\n");
+
+        assert_eq!(data(&events), vec!["a"]);
+        assert_eq!(events[0].event, None);
+    }
+
+    #[test]
+    fn holds_events_until_a_blank_line() {
+        let mut parser = EventParser::new();
+
+        assert!(parser.feed(b"data: a\n").is_empty());
+        assert!(parser.feed(b"data: b").is_empty());
+        assert_eq!(data(&parser.feed(b"\n\n")), vec!["a\nb"]);
+    }
+}
@@ -0,0 +1,240 @@
+//! Where rurl keeps state between invocations:
+//!
+//! - data (`$RURL_DATA_DIR`, `$XDG_DATA_HOME/rurl`, or `~/.local/share/rurl`)
+//!   - `history/`: past requests
+//! - cache (`$RURL_CACHE_DIR`, `$XDG_CACHE_HOME/rurl`, or `~/.cache/rurl`)
+//!   - `oauth2/`: client-credentials tokens, until they expire
+//!
+//! Parallel CI jobs may share these directories, so shared files are only
+//! written while holding a [`Lock`] and replaced with [`write_atomic`].
+
+use std::{
+    io::Write as _,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// A lock file naming no process, untouched for this long, was left by a
+/// process that crashed before writing its PID.
+#[cfg(not(unix))]
+const STALE_AFTER: Duration = Duration::from_secs(30);
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+const LOCK_RETRY: Duration = Duration::from_millis(25);
+
+fn dir(env: &str, xdg_env: &str, home_default: &[&str]) -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os(env) {
+        return Some(dir.into());
+    }
+
+    if let Some(dir) = std::env::var_os(xdg_env) {
+        return Some(PathBuf::from(dir).join("rurl"));
+    }
+
+    std::env::var_os("HOME").map(|home| {
+        home_default
+            .iter()
+            .fold(PathBuf::from(home), |path, part| path.join(part))
+            .join("rurl")
+    })
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    dir("RURL_DATA_DIR", "XDG_DATA_HOME", &[".local", "share"])
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    dir("RURL_CACHE_DIR", "XDG_CACHE_HOME", &[".cache"])
+}
+
+pub fn history_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("history"))
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(format!("{}{}", name, suffix))
+}
+
+/// Whether a lock was left behind: it names no process and hasn't been
+/// touched for [`STALE_AFTER`]. A live holder keeps its lock however long it
+/// takes, e.g. fetching a token over a slow link.
+#[cfg(not(unix))]
+fn is_stale(path: &Path) -> bool {
+    let holder = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok());
+
+    holder.is_none()
+        && std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| std::time::SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > STALE_AFTER)
+}
+
+/// An exclusive lock on a path, held on `<path>.lock` and released on drop.
+///
+/// On unix it is an advisory `flock` on that file, which the kernel lets go
+/// of when the holder exits, even by crashing, so there is nothing stale to
+/// recover and no window between finding a lock stale and taking it over.
+/// The file itself stays, as removing it would let a waiter that already
+/// opened it lock a file nobody else can see.
+#[derive(Debug)]
+pub struct Lock {
+    #[cfg(unix)]
+    _file: std::fs::File,
+    #[cfg(not(unix))]
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Waits for other rurl processes to release `path`, without blocking
+    /// the runtime in the meantime.
+    pub async fn acquire(path: &Path) -> std::io::Result<Self> {
+        let lock = sibling(path, ".lock");
+        let start = Instant::now();
+
+        if let Some(dir) = lock.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        loop {
+            match Self::try_acquire(&lock)? {
+                Some(held) => return Ok(held),
+                None if start.elapsed() > LOCK_TIMEOUT => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("{} is locked by another rurl process", path.display()),
+                    ));
+                }
+                None => tokio::time::delay_for(LOCK_RETRY).await,
+            }
+        }
+    }
+
+    /// Takes the lock on `lock` if no other process holds it.
+    #[cfg(unix)]
+    fn try_acquire(lock: &Path) -> std::io::Result<Option<Self>> {
+        use std::os::unix::{fs::OpenOptionsExt as _, io::AsRawFd as _};
+
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(lock)?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            return Ok(Some(Self { _file: file }));
+        }
+
+        let err = std::io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EWOULDBLOCK) | Some(libc::EINTR) => Ok(None),
+            _ => Err(err),
+        }
+    }
+
+    /// Takes the lock by creating `lock` with this process's PID in it,
+    /// removing it first if it was left behind.
+    #[cfg(not(unix))]
+    fn try_acquire(lock: &Path) -> std::io::Result<Option<Self>> {
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(lock)
+        {
+            Ok(mut file) => {
+                let _ = write!(file, "{}", std::process::id());
+                Ok(Some(Self { path: lock.into() }))
+            }
+            Err(err) if err.kind() != std::io::ErrorKind::AlreadyExists => Err(err),
+            Err(_) => {
+                if is_stale(lock) {
+                    let _ = std::fs::remove_file(lock);
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Replaces `path` in one step, so readers never see a partially written
+/// file. On unix the file is created with `mode`, less the umask, so a
+/// private file is never readable by others, not even before the rename.
+pub fn write_atomic(path: &Path, contents: &[u8], mode: u32) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let temp = sibling(path, &format!(".{}.tmp", std::process::id()));
+
+    // a leftover from a crashed process would keep its old mode
+    let _ = std::fs::remove_file(&temp);
+
+    let mut open = std::fs::OpenOptions::new();
+    open.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+
+        open.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    open.open(&temp)
+        .and_then(|mut file| file.write_all(contents))
+        .and_then(|_| std::fs::rename(&temp, path))
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp);
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rurl-{}-{}", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn locks_are_exclusive_until_dropped() {
+        let path = lock_path("exclusive");
+        let lock = sibling(&path, ".lock");
+
+        let held = Lock::acquire(&path).await.unwrap();
+        assert!(Lock::try_acquire(&lock).unwrap().is_none());
+
+        drop(held);
+        assert!(Lock::try_acquire(&lock).unwrap().is_some());
+
+        let _ = std::fs::remove_file(&lock);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn lock_files_left_behind_are_taken_over() {
+        // a crashed holder leaves its file, but not its flock
+        let path = lock_path("left");
+        let lock = sibling(&path, ".lock");
+        std::fs::write(&lock, "12345").unwrap();
+
+        assert!(Lock::acquire(&path).await.is_ok());
+
+        let _ = std::fs::remove_file(&lock);
+    }
+}
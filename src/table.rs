@@ -0,0 +1,198 @@
+//! Tabular responses as aligned terminal tables: CSV and TSV bodies, and
+//! with `--table`, JSON arrays of objects.
+
+use json::JsonValue;
+use std::iter;
+
+/// Columns wider than this are truncated, however wide the terminal.
+const MAX_COLUMN: usize = 40;
+/// Columns aren't truncated below this to fit the terminal.
+const MIN_COLUMN: usize = 6;
+const GAP: &str = "  ";
+
+/// The delimiter of a CSV or TSV content type.
+pub fn delimiter(content_type: &mime::Mime) -> Option<char> {
+    match (content_type.type_(), content_type.subtype().as_str()) {
+        (mime::TEXT, "csv") | (mime::APPLICATION, "csv") => Some(','),
+        (mime::TEXT, "tab-separated-values") => Some('\t'),
+        _ => None,
+    }
+}
+
+/// Rows as RFC 4180 reads them: a quoted field may hold the delimiter,
+/// line breaks, and `""` for a quote.
+pub fn parse_delimited(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            _ if quoted => field.push(c),
+            _ if c == delimiter => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
+
+/// A JSON array of objects as rows under a header of every key, in the
+/// order they're first seen. Values other than strings are shown as JSON,
+/// and missing ones are left empty.
+pub fn from_json(value: &JsonValue) -> Option<Vec<Vec<String>>> {
+    if !value.is_array() || value.is_empty() || !value.members().all(JsonValue::is_object) {
+        return None;
+    }
+
+    let mut header: Vec<String> = Vec::new();
+    for object in value.members() {
+        for (key, _) in object.entries() {
+            if !header.iter().any(|name| name == key) {
+                header.push(key.into());
+            }
+        }
+    }
+
+    let mut rows = vec![header.clone()];
+    for object in value.members() {
+        rows.push(
+            header
+                .iter()
+                .map(|key| match &object[key.as_str()] {
+                    JsonValue::Null if !object.has_key(key) => String::new(),
+                    value => value.as_str().map_or_else(|| value.dump(), str::to_owned),
+                })
+                .collect(),
+        );
+    }
+
+    Some(rows)
+}
+
+/// A cell on one line: control characters become spaces, and text wider
+/// than `width` is cut short with `…`.
+fn fit(text: &str, width: usize) -> String {
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+
+    if text.chars().count() <= width {
+        text
+    } else {
+        text.chars()
+            .take(width.saturating_sub(1))
+            .chain(iter::once('…'))
+            .collect()
+    }
+}
+
+/// The first row as a bold header over the rest, in aligned columns.
+/// Numeric columns are right-aligned, and the widest columns are truncated
+/// until the table fits in `width`.
+pub fn render(rows: &[Vec<String>], width: usize) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let cell = |row: &[String], i: usize| row.get(i).map_or("", String::as_str).to_owned();
+
+    let mut widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .map(|row| cell(row, i).chars().count())
+                .max()
+                .unwrap_or(0)
+                .min(MAX_COLUMN)
+        })
+        .collect();
+
+    let gaps = GAP.len() * columns.saturating_sub(1);
+    while widths.iter().sum::<usize>() + gaps > width {
+        let (widest, &most) = match widths.iter().enumerate().max_by_key(|(_, width)| **width) {
+            Some(widest) => widest,
+            None => break,
+        };
+
+        if most <= MIN_COLUMN {
+            break;
+        }
+
+        widths[widest] -= 1;
+    }
+
+    let numeric: Vec<bool> = (0..columns)
+        .map(|i| {
+            let mut values = rows.iter().skip(1).map(|row| cell(row, i));
+            rows.len() > 1
+                && values.all(|value| value.is_empty() || value.trim().parse::<f64>().is_ok())
+        })
+        .collect();
+
+    let mut output = String::new();
+
+    for (n, row) in rows.iter().enumerate() {
+        let line: Vec<String> = (0..columns)
+            .map(|i| {
+                let text = fit(&cell(row, i), widths[i]);
+                let padding = " ".repeat(widths[i] - text.chars().count());
+
+                match (n, numeric[i]) {
+                    (0, _) => format!("\x1b[1m{}\x1b[0m{}", text, padding),
+                    (_, true) => format!("{}{}", padding, text),
+                    (_, false) => format!("{}{}", text, padding),
+                }
+            })
+            .collect();
+
+        output += line.join(GAP).trim_end();
+        output += "\n";
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quoted_csv() {
+        let rows = parse_delimited("id,note\r\n1,\"a, \"\"b\"\"\nc\"\n2,\n", ',');
+
+        assert_eq!(rows, [["id", "note"], ["1", "a, \"b\"\nc"], ["2", ""]]);
+    }
+
+    #[test]
+    fn aligns_and_truncates_columns() {
+        let rows = from_json(
+            &json::parse(r#"[{"name": "ezra", "n": 7}, {"n": 12, "note": "a long note here"}]"#)
+                .unwrap(),
+        )
+        .unwrap();
+        let table = render(&rows, 20);
+
+        assert_eq!(
+            table,
+            "\x1b[1mname\x1b[0m  \x1b[1mn\x1b[0m   \x1b[1mnote\x1b[0m\n\
+             ezra   7\n\
+             \x20     12  a long no…\n"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! `{{NAME}}` placeholders in the URI, headers, and data values, filled
+//! from `--var NAME=VALUE`, `{{var.NAME}}` from the saved variables, and
+//! `{{env.NAME}}` from the environment; and with `--expand-env`, shell-style
+//! `$NAME` and `${NAME}`.
+
+use crate::config::{Config, ConfigError};
+use anyhow::Result;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("invalid --var {0}, expected NAME=VALUE")]
+    ParseError(String),
+    #[error(
+        "no value for {{{{{0}}}}}, pass --var {0}=VALUE or save it with `rurl var set {0} VALUE`"
+    )]
+    UnknownVar(String),
+    #[error("environment variable {0} is not set")]
+    UnknownEnv(String),
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+pub struct Template<'a> {
+    config: &'a Config,
+    vars: Vec<(String, String)>,
+}
+
+impl<'a> Template<'a> {
+    /// Collects the `--var` values from the raw command line, since
+    /// placeholders are filled in before the arguments are parsed.
+    pub fn from_args(config: &'a Config, args: &[String]) -> Result<Self, TemplateError> {
+        let mut vars = Vec::new();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            // a missing value is left for clap to report
+            let var = match arg.strip_prefix("--var") {
+                Some("") => match args.next() {
+                    Some(var) => var.as_str(),
+                    None => break,
+                },
+                Some(rest) if rest.starts_with('=') => &rest[1..],
+                _ => continue,
+            };
+
+            match var.find('=') {
+                Some(i) if is_name(&var[..i]) => {
+                    vars.push((var[..i].to_owned(), var[i + 1..].to_owned()))
+                }
+                _ => return Err(TemplateError::ParseError(var.into())),
+            }
+        }
+
+        Ok(Self { config, vars })
+    }
+
+    fn value(&self, name: &str) -> Result<Option<String>> {
+        if let Some(name) = name.strip_prefix("var.") {
+            return match self.config.var(name) {
+                Some(value) => Ok(Some(value.to_owned())),
+                None => Err(ConfigError::UnknownVar(name.into()).into()),
+            };
+        }
+
+        if let Some(name) = name.strip_prefix("env.") {
+            return match std::env::var(name) {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(TemplateError::UnknownEnv(name.into()).into()),
+            };
+        }
+
+        // anything else in braces, like a JSON body, is left alone
+        if !is_name(name) {
+            return Ok(None);
+        }
+
+        // the last --var wins, then the saved variables
+        match self.vars.iter().rev().find(|(var, _)| var == name) {
+            Some((_, value)) => Ok(Some(value.clone())),
+            None => match self.config.var(name) {
+                Some(value) => Ok(Some(value.to_owned())),
+                None => Err(TemplateError::UnknownVar(name.into()).into()),
+            },
+        }
+    }
+
+    /// Replaces every placeholder in `input` with its value.
+    pub fn render(&self, input: &str) -> Result<String> {
+        let mut output = String::new();
+        let mut rest = input;
+
+        while let Some(start) = rest.find("{{") {
+            let end = match rest[start..].find("}}") {
+                Some(end) => start + end,
+                None => break,
+            };
+
+            output += &rest[..start];
+            match self.value(rest[start + "{{".len()..end].trim())? {
+                Some(value) => output += &value,
+                None => output += &rest[start..end + "}}".len()],
+            }
+            rest = &rest[end + "}}".len()..];
+        }
+
+        Ok(output + rest)
+    }
+}
+
+/// Replaces `$NAME` and `${NAME}` in `input` with environment variables, as
+/// a shell would inside double quotes, but in items the shell never sees
+/// expanded, like single-quoted JSON. `$$` is a literal `$`, and a `$` not
+/// followed by a name, as in the JSONPath `$.id`, is left alone.
+pub fn expand_env(input: &str) -> Result<String, TemplateError> {
+    let mut output = String::new();
+    let mut rest = input;
+
+    while let Some(start) = rest.find('$') {
+        output += &rest[..start];
+        let after = &rest[start + 1..];
+
+        let (name, len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else if let Some(escaped) = after.strip_prefix('$') {
+            output.push('$');
+            rest = escaped;
+            continue;
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end)
+        };
+
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            output.push('$');
+            rest = after;
+            continue;
+        }
+
+        match std::env::var(name) {
+            Ok(value) => output += &value,
+            Err(_) => return Err(TemplateError::UnknownEnv(name.into())),
+        }
+        rest = &after[len..];
+    }
+
+    Ok(output + rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn collects_vars_from_the_raw_arguments() {
+        let config = Config::default();
+        let template = Template::from_args(
+            &config,
+            &args(&["--var", "a=1", "GET", "--var=b=x=y", "--var", "a=2"]),
+        )
+        .unwrap();
+
+        assert_eq!(template.render("{{a}} {{ b }}").unwrap(), "2 x=y");
+
+        for bad in &["--var=noequals", "--var==1", "--var=a b=1"] {
+            assert!(matches!(
+                Template::from_args(&config, &args(&[bad])),
+                Err(TemplateError::ParseError(_))
+            ));
+        }
+        assert!(Template::from_args(&config, &args(&["--var"])).is_ok());
+    }
+
+    #[test]
+    fn fills_placeholders_from_vars_then_saved_variables() {
+        let mut config = Config::default();
+        config.set_var("host", "saved.test");
+        config.set_var("user", "ezra");
+        std::env::set_var("RURL_TEST_TEMPLATE", "from-env");
+
+        let template = Template::from_args(&config, &args(&["--var", "host=cli.test"])).unwrap();
+
+        assert_eq!(
+            template
+                .render("{{host}}/{{user}}/{{var.host}}/{{env.RURL_TEST_TEMPLATE}}")
+                .unwrap(),
+            "cli.test/ezra/saved.test/from-env"
+        );
+        // JSON and unclosed braces are not placeholders
+        assert_eq!(
+            template.render(r#"{{"a": 1}} {{host"#).unwrap(),
+            r#"{{"a": 1}} {{host"#
+        );
+
+        let err = template.render("{{missing}}").unwrap_err();
+        assert!(err.to_string().starts_with("no value for {{missing}}"));
+        assert!(template.render("{{var.missing}}").is_err());
+        assert!(template.render("{{env.RURL_TEST_UNSET}}").is_err());
+    }
+
+    #[test]
+    fn expands_environment_variables() {
+        std::env::set_var("RURL_TEST_TOKEN", "abc");
+
+        assert_eq!(
+            expand_env(r#"token:={"a": "$RURL_TEST_TOKEN", "b": "${RURL_TEST_TOKEN}x"}"#).unwrap(),
+            r#"token:={"a": "abc", "b": "abcx"}"#
+        );
+        assert_eq!(
+            expand_env("$.items[0] costs $$5").unwrap(),
+            "$.items[0] costs $5"
+        );
+        assert!(expand_env("$RURL_TEST_UNSET").is_err());
+    }
+}
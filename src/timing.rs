@@ -0,0 +1,217 @@
+use hyper::{client::connect::dns::Name, service::Service, Uri};
+use std::{
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::net::TcpStream;
+
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    pub tls: Option<Duration>,
+    pub ttfb: Option<Duration>,
+    pub total: Option<Duration>,
+    pub bytes: usize,
+    /// Connections opened, as opposed to reused from the pool.
+    pub connections: usize,
+    /// The address the last connection opened went to, which with both
+    /// families resolved is whichever answered first.
+    pub address: Option<SocketAddr>,
+    /// What the last TLS handshake negotiated.
+    pub session: Option<crate::tls::Session>,
+}
+
+fn family(address: &SocketAddr) -> &'static str {
+    if address.is_ipv6() {
+        "IPv6"
+    } else {
+        "IPv4"
+    }
+}
+
+fn format_duration(duration: Option<Duration>, deterministic: bool) -> String {
+    match duration {
+        _ if deterministic => crate::deterministic::PLACEHOLDER.to_owned(),
+        Some(duration) => format!("{:.1}ms", duration.as_secs_f64() * 1000.0),
+        None => "-".into(),
+    }
+}
+
+impl Timings {
+    pub fn format(&self, deterministic: bool) -> String {
+        let duration = |duration| format_duration(duration, deterministic);
+
+        let mut line = format!(
+            "dns: {}  connect: {}  tls: {}  ttfb: {}  total: {}  downloaded: {} bytes",
+            duration(self.dns),
+            duration(self.connect),
+            duration(self.tls),
+            duration(self.ttfb),
+            duration(self.total),
+            self.bytes,
+        );
+
+        if let Some(address) = &self.address {
+            let shown = if deterministic {
+                crate::deterministic::PLACEHOLDER.to_owned()
+            } else {
+                address.to_string()
+            };
+            line += &format!("  address: {} ({})", shown, family(address));
+        }
+
+        line
+    }
+
+    /// How many of `requests` went over a connection an earlier one opened.
+    pub fn format_reuse(&self, requests: usize) -> String {
+        format!(
+            "connections: {} opened, {} of {} requests reused one",
+            self.connections,
+            requests.saturating_sub(self.connections),
+            requests
+        )
+    }
+
+    /// Just the connection setup phases, for --preconnect.
+    pub fn format_setup(&self, deterministic: bool) -> String {
+        let duration = |duration| format_duration(duration, deterministic);
+
+        format!(
+            "dns: {}  connect: {}  tls: {}",
+            duration(self.dns),
+            duration(self.connect),
+            duration(self.tls),
+        )
+    }
+}
+
+/// Shared handle the instrumented resolver and connector record into.
+#[derive(Clone, Debug, Default)]
+pub struct Recorder(Arc<Mutex<Timings>>);
+
+impl Recorder {
+    pub fn update(&self, f: impl FnOnce(&mut Timings)) {
+        f(&mut self.0.lock().unwrap());
+    }
+
+    pub fn timings(&self) -> Timings {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TimedResolver<R> {
+    inner: R,
+    recorder: Recorder,
+}
+
+impl<R> TimedResolver<R> {
+    pub fn new(inner: R, recorder: Recorder) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl<R> Service<Name> for TimedResolver<R>
+where
+    R: Service<Name>,
+    R::Future: Send + 'static,
+{
+    type Response = R::Response;
+    type Error = R::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<R::Response, R::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let start = Instant::now();
+        let recorder = self.recorder.clone();
+        let resolving = self.inner.call(name);
+
+        Box::pin(async move {
+            let res = resolving.await;
+            recorder.update(|timings| timings.dns = Some(start.elapsed()));
+            res
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TimedConnector<C> {
+    inner: C,
+    recorder: Recorder,
+}
+
+impl<C> TimedConnector<C> {
+    pub fn new(inner: C, recorder: Recorder) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl<C> Service<Uri> for TimedConnector<C>
+where
+    C: Service<Uri, Response = TcpStream>,
+    C::Error: std::fmt::Display,
+    C::Future: Send + 'static,
+{
+    type Response = C::Response;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<C::Response, C::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let default_port = match uri.scheme_str() {
+            Some("https") => 443,
+            _ => 80,
+        };
+        crate::trace!(
+            "connecting to {}:{}",
+            uri.host().unwrap_or_default(),
+            uri.port_u16().unwrap_or(default_port)
+        );
+
+        let start = Instant::now();
+        let recorder = self.recorder.clone();
+        let connecting = self.inner.call(uri);
+
+        Box::pin(async move {
+            let res = connecting.await;
+
+            match &res {
+                Ok(stream) => match (stream.peer_addr(), stream.local_addr()) {
+                    (Ok(peer), Ok(local)) => crate::trace!(
+                        "connected to {} over {} from {}",
+                        peer,
+                        family(&peer),
+                        local
+                    ),
+                    _ => crate::trace!("connected"),
+                },
+                Err(err) => crate::trace!("connection failed: {}", err),
+            }
+
+            // the inner connector resolves first, so connect time excludes dns
+            recorder.update(|timings| {
+                let elapsed = start.elapsed();
+                timings.connect = Some(elapsed - timings.dns.unwrap_or_default().min(elapsed));
+
+                if let Ok(stream) = &res {
+                    timings.connections += 1;
+                    timings.address = stream.peer_addr().ok();
+                }
+            });
+
+            res
+        })
+    }
+}
@@ -0,0 +1,385 @@
+//! HTTPS over rustls: the client configuration, with the system's CA
+//! certificates (or SSL_CERT_FILE), and a connector that times handshakes
+//! and keeps what they negotiated.
+
+use crate::timing::Recorder;
+use anyhow::Result;
+use hyper::{client::connect::Connection, service::Service, Uri};
+use hyper_rustls::{HttpsConnector, MaybeHttpsStream};
+use rustls::{
+    Certificate, ClientConfig, ClientSession, KeyLog, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, Session as _, TLSError, WebPKIVerifier,
+};
+use std::{
+    fs::File,
+    future::Future,
+    io::Write as _,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Instant, SystemTime},
+};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("invalid --pinnedpubkey {0}, expected sha256//BASE64[;sha256//BASE64...] or a public key file")]
+    PinParseError(String),
+}
+
+/// How HTTPS connections are set up.
+#[derive(Clone, Debug, Default)]
+pub struct TlsOptions {
+    /// Append session secrets here, in the NSS key log format.
+    pub keylog_file: Option<PathBuf>,
+    /// Offer only HTTP/2 in ALPN, for `--http2-prior-knowledge`.
+    pub http2: bool,
+    /// SHA-256 hashes of the public keys the server may present.
+    pub pins: Vec<[u8; 32]>,
+}
+
+/// Reads `--pinnedpubkey`: `sha256//BASE64` hashes separated by `;`, or a
+/// file holding the public key, in PEM or DER.
+pub fn pins(arg: &str) -> Result<Vec<[u8; 32]>> {
+    let invalid = || TlsError::PinParseError(arg.into());
+
+    if !arg.starts_with("sha256//") {
+        let key = std::fs::read(arg)?;
+        let key = match std::str::from_utf8(&key) {
+            Ok(text) if text.contains("-----BEGIN") => crate::x509::pem(text, "PUBLIC KEY")
+                .into_iter()
+                .next()
+                .ok_or_else(invalid)?,
+            _ => key,
+        };
+
+        return Ok(vec![crate::digest::sha256(&key)]);
+    }
+
+    arg.split(';')
+        .map(|pin| {
+            let hash = pin
+                .strip_prefix("sha256//")
+                .and_then(crate::decode::base64)
+                .ok_or_else(invalid)?;
+            let mut pin = [0; 32];
+            if hash.len() != pin.len() {
+                return Err(invalid().into());
+            }
+
+            pin.copy_from_slice(&hash);
+            Ok(pin)
+        })
+        .collect()
+}
+
+/// Checks the chain as usual, then that the server's own key is pinned.
+struct PinnedVerifier {
+    inner: WebPKIVerifier,
+    pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        dns_name: webpki::DNSNameRef,
+        ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        let verified =
+            self.inner
+                .verify_server_cert(roots, presented_certs, dns_name, ocsp_response)?;
+
+        let certificate = presented_certs
+            .first()
+            .ok_or(TLSError::NoCertificatesPresented)?;
+        let public_key = crate::x509::parse(&certificate.0)
+            .map_err(|err| TLSError::General(err.to_string()))?
+            .public_key;
+        let hash = crate::digest::sha256(&public_key);
+
+        if !self.pins.contains(&hash) {
+            return Err(TLSError::General(format!(
+                "the server's public key sha256//{} is not pinned",
+                crate::decode::encode_base64(&hash)
+            )));
+        }
+
+        Ok(verified)
+    }
+}
+
+/// Session secrets for Wireshark, one `LABEL CLIENT_RANDOM SECRET` line each.
+struct KeyLogFile(Mutex<File>);
+
+impl KeyLog for KeyLogFile {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let line = format!(
+            "{} {} {}\n",
+            label,
+            crate::digest::hex(client_random),
+            crate::digest::hex(secret)
+        );
+
+        // like the trace, a line that can't be written doesn't fail the request
+        let _ = self.0.lock().unwrap().write_all(line.as_bytes());
+    }
+}
+
+pub fn client_config(options: &TlsOptions) -> Result<ClientConfig> {
+    let mut config = ClientConfig::new();
+
+    // without any CA certificates, only https requests fail
+    config.root_store = match rustls_native_certs::load_native_certs() {
+        Ok(store) | Err((Some(store), _)) => store,
+        Err((None, err)) => {
+            crate::trace!("no CA certificates: {}", err);
+            rustls::RootCertStore::empty()
+        }
+    };
+
+    config.alpn_protocols = if options.http2 {
+        vec![b"h2".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    };
+
+    if let Some(path) = &options.keylog_file {
+        let mut open = std::fs::OpenOptions::new();
+        open.create(true).append(true);
+
+        // the log decrypts every session written to it
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt as _;
+
+            open.mode(0o600);
+        }
+
+        let file = open.open(path)?;
+
+        config.key_log = Arc::new(KeyLogFile(Mutex::new(file)));
+    }
+
+    if !options.pins.is_empty() {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(PinnedVerifier {
+                inner: WebPKIVerifier::new(),
+                pins: options.pins.clone(),
+            }));
+    }
+
+    Ok(config)
+}
+
+/// Certificates expiring sooner than this are warned about.
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+/// What a handshake negotiated, for `--tls-info`.
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    pub version: String,
+    pub cipher: String,
+    pub alpn: Option<String>,
+    /// The server's certificate chain, DER-encoded, its own first.
+    pub certificates: Vec<Vec<u8>>,
+}
+
+impl Session {
+    fn new(session: &ClientSession) -> Self {
+        Self {
+            version: session
+                .get_protocol_version()
+                .map(|version| format!("{:?}", version))
+                .unwrap_or_default(),
+            cipher: session
+                .get_negotiated_ciphersuite()
+                .map(|suite| format!("{:?}", suite.suite))
+                .unwrap_or_default(),
+            alpn: session
+                .get_alpn_protocol()
+                .map(|alpn| String::from_utf8_lossy(alpn).into_owned()),
+            certificates: session
+                .get_peer_certificates()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|certificate| certificate.0)
+                .collect(),
+        }
+    }
+}
+
+/// Lists the session and the server's certificate, warning when it
+/// expires within 30 days of `now`.
+pub fn describe(session: &Session, now: SystemTime) -> String {
+    let mut output = String::new();
+    let mut line = |name: &str, value: &str| {
+        if !value.is_empty() {
+            output += &format!("{:<10} {}\n", name, value);
+        }
+    };
+
+    line("tls", &session.version);
+    line("cipher", &session.cipher);
+    line("alpn", session.alpn.as_deref().unwrap_or_default());
+
+    let certificate = match session
+        .certificates
+        .first()
+        .map(|der| crate::x509::parse(der))
+    {
+        Some(Ok(certificate)) => certificate,
+        Some(Err(err)) => {
+            line("cert", &err.to_string());
+            return output;
+        }
+        None => return output,
+    };
+
+    line("subject", &certificate.subject);
+    line("issuer", &certificate.issuer);
+    line("names", &certificate.names.join(", "));
+    line("expires", &certificate.not_after.to_string());
+
+    let now = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let days = (certificate.not_after.unix() - now).div_euclid(86_400);
+    if days < 0 {
+        output += "warning: the certificate has expired\n";
+    } else if days < EXPIRY_WARNING_DAYS {
+        output += &format!("warning: the certificate expires in {} days\n", days);
+    }
+
+    output
+}
+
+/// Connects over TLS for https URIs, recording how long the handshake took
+/// and what it negotiated.
+#[derive(Clone, Debug)]
+pub struct TlsConnector<C> {
+    inner: HttpsConnector<C>,
+    recorder: Recorder,
+}
+
+impl<C> TlsConnector<C> {
+    pub fn new(inner: C, config: ClientConfig, recorder: Recorder) -> Self {
+        Self {
+            inner: HttpsConnector::from((inner, config)),
+            recorder,
+        }
+    }
+}
+
+impl<C> Service<Uri> for TlsConnector<C>
+where
+    C: Service<Uri>,
+    C::Response: Connection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    C::Future: Send + 'static,
+    C::Error: Into<BoxError>,
+{
+    type Response = MaybeHttpsStream<C::Response>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let start = Instant::now();
+        let recorder = self.recorder.clone();
+        let connecting = self.inner.call(uri);
+
+        Box::pin(async move {
+            let res = connecting.await;
+
+            if let Ok(MaybeHttpsStream::Https(stream)) = &res {
+                let session = Session::new(stream.get_ref().1);
+
+                crate::trace!(
+                    "TLS connection using {} / {}",
+                    session.version,
+                    session.cipher
+                );
+                match &session.alpn {
+                    Some(alpn) => crate::trace!("ALPN: server accepted {}", alpn),
+                    None => crate::trace!("ALPN: server did not agree on a protocol"),
+                }
+
+                // the inner connector resolves and connects first
+                recorder.update(|timings| {
+                    let elapsed = start.elapsed();
+                    let before =
+                        timings.dns.unwrap_or_default() + timings.connect.unwrap_or_default();
+                    timings.tls = Some(elapsed - before.min(elapsed));
+                    timings.session = Some(session);
+                });
+            }
+
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn reads_pins() {
+        let pin = "sha256//x0zdUa1BCNXvSnXz/X/5cGHJEiTHdwlAfH0XHPYOoDY=";
+        let hash = pins(pin).unwrap();
+        assert_eq!(hash.len(), 1);
+        assert_eq!(crate::decode::encode_base64(&hash[0]), &pin[8..]);
+
+        let both = format!("{};sha256//{}", pin, crate::decode::encode_base64(&[7; 32]));
+        assert_eq!(pins(&both).unwrap()[1], [7; 32]);
+
+        let file = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/localhost.pub.pem");
+        assert_eq!(pins(file).unwrap(), hash);
+
+        assert!(pins("sha256//c2hvcnQ=").is_err());
+        assert!(pins("sha256//x0zdUa1BCNXvSnXz;md5//AAAA").is_err());
+    }
+
+    #[test]
+    fn warns_about_certificates_expiring_soon() {
+        let der = crate::x509::pem(include_str!("../tests/tls/localhost.pem"), "CERTIFICATE");
+        let session = Session {
+            version: "TLSv1_3".into(),
+            cipher: "TLS13_AES_128_GCM_SHA256".into(),
+            alpn: Some("h2".into()),
+            certificates: der,
+        };
+        let expires = crate::x509::parse(&session.certificates[0])
+            .unwrap()
+            .not_after
+            .unix() as u64;
+        let at = |days_left: u64| {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(expires - days_left * 86_400)
+        };
+
+        let described = describe(&session, at(400));
+        assert!(described.starts_with(
+            "tls        TLSv1_3\n\
+             cipher     TLS13_AES_128_GCM_SHA256\n\
+             alpn       h2\n\
+             subject    O=rurl tests, CN=localhost\n"
+        ));
+        assert!(described.contains("expires    2126-09-22 09:48:52 UTC\n"));
+        assert!(!described.contains("warning"));
+
+        assert!(describe(&session, at(10)).ends_with("the certificate expires in 10 days\n"));
+        assert!(describe(&session, at(0) + Duration::from_secs(60))
+            .ends_with("the certificate has expired\n"));
+    }
+}
@@ -0,0 +1,75 @@
+//! `--trace`: what happens below the request, printed as it happens in the
+//! style of curl's `*` lines. DNS answers, the address each connection
+//! went to, the TLS session, retries, redirect hops, and the protocol the
+//! response came back over.
+//!
+//! The lines are `log` records, so a logger installed with [`install`]
+//! prints them along with warnings from hyper and rustls, and without one
+//! they cost a level check.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+#[doc(hidden)]
+pub use log;
+
+/// Prints this crate's records, and other crates' warnings and errors, as
+/// `* ` lines with [`note!`](crate::note).
+struct Tracer;
+
+impl Log for Tracer {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        is_ours(metadata.target()) || metadata.level() <= Level::Warn
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if is_ours(record.target()) {
+            crate::note!("* {}", record.args());
+        } else {
+            crate::note!("* {}: {}", record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static TRACER: Tracer = Tracer;
+
+fn is_ours(target: &str) -> bool {
+    target == env!("CARGO_PKG_NAME") || target.starts_with(concat!(env!("CARGO_PKG_NAME"), "::"))
+}
+
+/// Prints trace lines from now on. Only the first call installs the logger.
+pub fn install() {
+    if log::set_logger(&TRACER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
+
+/// Records a `* ` line for `--trace`, which is printed only once [`install`]
+/// has been called.
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::trace::log::info!($($arg)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prints_our_records_and_only_warnings_from_others() {
+        let record = |target, level| Metadata::builder().target(target).level(level).build();
+
+        assert!(Tracer.enabled(&record("rurl::dns", Level::Info)));
+        assert!(Tracer.enabled(&record("rurl", Level::Info)));
+        assert!(!Tracer.enabled(&record("rurlish", Level::Info)));
+        assert!(!Tracer.enabled(&record("hyper::proto", Level::Debug)));
+        assert!(Tracer.enabled(&record("rustls::session", Level::Warn)));
+    }
+}
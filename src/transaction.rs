@@ -0,0 +1,187 @@
+//! Rollbacks for request sequences: each step may name a request that undoes
+//! it, and when a later step fails, the rollbacks of the steps that went
+//! through run in reverse order, so a smoke test against a shared
+//! environment cleans up after itself.
+
+use crate::note;
+use anyhow::Result;
+use hyper::StatusCode;
+use std::future::Future;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TransactionError {
+    #[error("step {0} failed with {1}")]
+    StepStatus(String, StatusCode),
+}
+
+/// A request in a sequence, with the request that undoes it.
+#[derive(Clone, Debug)]
+pub struct Step<R> {
+    pub name: String,
+    pub request: R,
+    pub rollback: Option<R>,
+}
+
+fn failed(status: StatusCode) -> bool {
+    status.is_client_error() || status.is_server_error()
+}
+
+/// Sends the steps in order with `send`, stopping at the first error or 4xx
+/// or 5xx status. On failure, the rollbacks of the steps that went through
+/// are sent in reverse order before the failure is returned. `send` is told
+/// whether a request is a rollback, which should go out even once the run
+/// has been cancelled.
+pub async fn run<R, F, Fut>(steps: Vec<Step<R>>, mut send: F) -> Result<()>
+where
+    F: FnMut(R, bool) -> Fut,
+    Fut: Future<Output = Result<StatusCode>>,
+{
+    let mut completed = Vec::new();
+
+    for step in steps {
+        let failure = match send(step.request, false).await {
+            Ok(status) if !failed(status) => {
+                completed.push((step.name, step.rollback));
+                continue;
+            }
+            Ok(status) => TransactionError::StepStatus(step.name, status).into(),
+            Err(err) => err,
+        };
+
+        if completed.iter().any(|(_, rollback)| rollback.is_some()) {
+            note!("{}, rolling back", failure);
+        }
+
+        for (name, rollback) in completed.into_iter().rev() {
+            let rollback = match rollback {
+                Some(rollback) => rollback,
+                None => continue,
+            };
+
+            // roll back as much as possible, reporting what could not be
+            match send(rollback, true).await {
+                Ok(status) if !failed(status) => {}
+                Ok(status) => note!("rollback of {} failed with {}", name, status),
+                Err(err) => note!("rollback of {} failed: {}", name, err),
+            }
+        }
+
+        return Err(failure);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn step(name: &'static str, rollback: Option<&'static str>) -> Step<&'static str> {
+        Step {
+            name: name.into(),
+            request: name,
+            rollback,
+        }
+    }
+
+    /// Runs `steps`, answering each request with its status in `statuses`
+    /// (200 otherwise), and returns what was sent and the result.
+    async fn run_with(
+        steps: Vec<Step<&'static str>>,
+        statuses: &[(&str, u16)],
+    ) -> (Vec<String>, Result<()>) {
+        let sent = RefCell::new(Vec::new());
+
+        let result = run(steps, |request, rollback| {
+            sent.borrow_mut().push(match rollback {
+                true => format!("rollback {}", request),
+                false => request.to_owned(),
+            });
+            let status = statuses
+                .iter()
+                .find(|(name, _)| *name == request)
+                .map_or(200, |(_, status)| *status);
+
+            async move {
+                match status {
+                    0 => anyhow::bail!("connection refused"),
+                    status => Ok(StatusCode::from_u16(status).unwrap()),
+                }
+            }
+        })
+        .await;
+
+        (sent.into_inner(), result)
+    }
+
+    #[tokio::test]
+    async fn sends_every_step_when_all_succeed() {
+        let steps = vec![step("create", Some("delete")), step("update", None)];
+        let (sent, result) = run_with(steps, &[("create", 201), ("update", 304)]).await;
+
+        assert!(result.is_ok());
+        assert_eq!(sent, ["create", "update"]);
+    }
+
+    #[tokio::test]
+    async fn rolls_back_completed_steps_in_reverse_order() {
+        let steps = vec![
+            step("user", Some("delete user")),
+            step("login", None),
+            step("team", Some("delete team")),
+            step("invite", Some("delete invite")),
+            step("never sent", Some("never rolled back")),
+        ];
+        let (sent, result) = run_with(steps, &[("invite", 409)]).await;
+
+        assert_eq!(
+            sent,
+            [
+                "user",
+                "login",
+                "team",
+                "invite",
+                "rollback delete team",
+                "rollback delete user",
+            ]
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "step invite failed with 409 Conflict"
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_rolling_back_past_a_failed_rollback() {
+        let steps = vec![
+            step("first", Some("undo first")),
+            step("second", Some("undo second")),
+            step("third", None),
+        ];
+        let (sent, result) = run_with(steps, &[("third", 0), ("undo second", 500)]).await;
+
+        assert_eq!(
+            sent,
+            [
+                "first",
+                "second",
+                "third",
+                "rollback undo second",
+                "rollback undo first",
+            ]
+        );
+        // the step's failure is reported, not the rollback's
+        assert_eq!(result.unwrap_err().to_string(), "connection refused");
+    }
+
+    #[tokio::test]
+    async fn a_failed_first_step_has_nothing_to_roll_back() {
+        let steps = vec![step("first", Some("undo first")), step("second", None)];
+        let (sent, result) = run_with(steps, &[("first", 503)]).await;
+
+        assert_eq!(sent, ["first"]);
+        assert!(result.is_err());
+    }
+}
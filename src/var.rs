@@ -0,0 +1,36 @@
+use anyhow::Result;
+use rurl::config::Config;
+
+const USAGE: &str = "usage: rurl var set NAME VALUE | get NAME | unset NAME | list";
+
+pub async fn run(args: &[String]) -> Result<()> {
+    let _lock = Config::lock().await?;
+    let mut config = Config::load()?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["set", name, value] => {
+            config.set_var(name, value);
+            config.save()?;
+        }
+        ["get", name] => match config.var(name) {
+            Some(value) => println!("{}", value),
+            None => anyhow::bail!("unknown variable {}", name),
+        },
+        ["unset", name] => {
+            if !config.unset_var(name) {
+                anyhow::bail!("unknown variable {}", name);
+            }
+
+            config.save()?;
+        }
+        ["list"] => {
+            for (name, value) in config.vars() {
+                println!("{}={}", name, value);
+            }
+        }
+        _ => anyhow::bail!(USAGE),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use rurl::{config::Config, plugin, state};
+
+fn display(path: Option<std::path::PathBuf>) -> String {
+    match path {
+        Some(path) => path.display().to_string(),
+        None => "none".into(),
+    }
+}
+
+pub fn verbose() -> String {
+    let config = display(Config::path());
+    let data = display(state::data_dir());
+    let cache = display(state::cache_dir());
+
+    let plugins = match plugin::discover() {
+        plugins if plugins.is_empty() => "none".into(),
+        plugins => plugins.join(", "),
+    };
+
+    let features = match env!("RURL_FEATURES") {
+        "" => "none",
+        features => features,
+    };
+
+    format!(
+        "{name} {version} ({hash})
+target:   {target} ({profile})
+rustc:    {rustc}
+features: {features}
+tls:      rustls {rustls}
+http/2:   prior knowledge only
+http/3:   unsupported
+plugins:  {plugins}
+config:   {config}
+data:     {data}
+cache:    {cache}
+hyper:    {hyper}
+tokio:    {tokio}
+syntect:  {syntect}",
+        name = env!("CARGO_PKG_NAME"),
+        version = env!("CARGO_PKG_VERSION"),
+        hash = env!("RURL_GIT_HASH"),
+        target = env!("RURL_TARGET"),
+        profile = env!("RURL_PROFILE"),
+        rustc = env!("RURL_RUSTC_VERSION"),
+        features = features,
+        plugins = plugins,
+        config = config,
+        data = data,
+        cache = cache,
+        rustls = env!("RURL_RUSTLS_VERSION"),
+        hyper = env!("RURL_HYPER_VERSION"),
+        tokio = env!("RURL_TOKIO_VERSION"),
+        syntect = env!("RURL_SYNTECT_VERSION"),
+    )
+}
@@ -0,0 +1,200 @@
+//! `--watch`: the same request again every interval, shown like watch(1)
+//! does, with what changed since the previous response.
+
+use crate::{
+    binary,
+    client::{self, HttpClient},
+    diff,
+    expect::Expectations,
+    highlight::highlight,
+    multi::{self, Multi, MultiResponse},
+    notify::Notifier,
+    output,
+    protobuf::Protobuf,
+};
+use hyper::{Body, Request};
+use std::time::Duration;
+
+pub struct Watch {
+    pub interval: Duration,
+    pub multi: Multi,
+    pub notifier: Notifier,
+    pub expectations: Expectations,
+    pub pretty_max_size: Option<usize>,
+}
+
+/// The lines that changed from `previous` to `response`, leaving out Date,
+/// which changes every time.
+fn changes(
+    previous: &MultiResponse,
+    response: &MultiResponse,
+    text: Option<&str>,
+    proto: Option<&Protobuf>,
+    deterministic: bool,
+) -> Vec<String> {
+    let head = |head: &str| {
+        head.lines()
+            .filter(|line| !line.to_ascii_lowercase().starts_with("date:"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let mut changes = diff::lines(&head(&previous.head), &head(&response.head));
+
+    match (previous.text(proto, deterministic), text) {
+        (Some((_, before)), Some(after)) => changes.extend(diff::bodies(&before, after)),
+        _ if previous.body != response.body => changes.push("\x1b[33m~ binary body\x1b[0m".into()),
+        _ => {}
+    }
+
+    changes
+}
+
+/// Sends the request every `watch.interval` until ctrl-c, clearing the
+/// screen for each response. Expectations are reported each time rather
+/// than ending the watch.
+pub async fn run(
+    mut watch: Watch,
+    client: HttpClient,
+    req: &Request<Body>,
+    body: &[u8],
+    proto: Option<&Protobuf>,
+) {
+    let deterministic = watch.multi.deterministic;
+    let cancel = watch.multi.cancel.clone();
+    let mut previous: Option<MultiResponse> = None;
+
+    for count in 1.. {
+        let mut response = None;
+        let requests = vec![(client::clone_request(req, body), body.to_vec())];
+
+        multi::run(watch.multi.clone(), client.clone(), requests, |sent| {
+            response = Some(sent)
+        })
+        .await;
+
+        // clear the screen, as watch(1) does
+        print!("\x1b[2J\x1b[H");
+        println!(
+            "\x1b[1mEvery {:?}: {} {}\x1b[0m  (#{}, ctrl-c to stop)\n",
+            watch.interval,
+            req.method(),
+            req.uri(),
+            count
+        );
+
+        match response {
+            Some(Ok(response)) => {
+                watch.notifier.observe_status(req.uri(), response.status);
+
+                let text = response.text(proto, deterministic);
+
+                println!("{}", highlight(&response.head, "http"));
+                match &text {
+                    Some((content_type, body)) => println!(
+                        "{}\x1b[0m\n",
+                        output::render_body(content_type.as_ref(), body, watch.pretty_max_size)
+                    ),
+                    None => println!("{}\n", binary::NOTE),
+                }
+
+                if let Some(previous) = &previous {
+                    let text = text.as_ref().map(|(_, body)| body.as_str());
+                    let changes = changes(previous, &response, text, proto, deterministic);
+
+                    if changes.is_empty() {
+                        println!("\x1b[2mno changes since the last request\x1b[0m");
+                    } else {
+                        println!("\x1b[1mchanged since the last request:\x1b[0m");
+                        for change in changes {
+                            println!("{}", change);
+                        }
+                    }
+                }
+
+                if !watch.expectations.is_empty() {
+                    let checked = watch.expectations.check(
+                        response.status,
+                        &response.headers,
+                        &response.body,
+                    );
+                    if let Err(err) = &checked {
+                        println!("\n\x1b[31m{}\x1b[0m", err);
+                    }
+
+                    watch
+                        .notifier
+                        .observe_assertions(req.uri(), checked.is_ok());
+                }
+
+                previous = Some(response);
+            }
+            Some(Err((_, err))) => println!("\x1b[31merror: {}\x1b[0m", err),
+            None => {}
+        }
+
+        tokio::select! {
+            _ = tokio::time::delay_for(watch.interval) => {}
+            _ = cancel.cancelled() => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{HeaderMap, StatusCode, Uri};
+
+    fn response(head: &str, body: &[u8]) -> MultiResponse {
+        MultiResponse {
+            index: 0,
+            uri: Uri::from_static("http://example.com/"),
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            head: head.into(),
+            content_type: Some("text/plain".into()),
+            body: body.to_vec(),
+        }
+    }
+
+    fn changes_between(previous: &MultiResponse, response: &MultiResponse) -> Vec<String> {
+        let text = response.text(None, false).map(|(_, body)| body);
+        changes(previous, response, text.as_deref(), None, false)
+    }
+
+    #[test]
+    fn the_date_is_not_a_change() {
+        let previous = response(
+            "HTTP/1.1 200 OK\nDate: Mon, 01 Jan 2024 00:00:00 GMT",
+            b"up",
+        );
+        let next = response(
+            "HTTP/1.1 200 OK\ndate: Mon, 01 Jan 2024 00:00:05 GMT",
+            b"up",
+        );
+
+        assert!(changes_between(&previous, &next).is_empty());
+    }
+
+    #[test]
+    fn reports_changed_heads_and_bodies() {
+        let previous = response("HTTP/1.1 200 OK\nETag: \"1\"", b"up");
+        let next = response("HTTP/1.1 200 OK\nETag: \"2\"", b"down");
+
+        let changes = changes_between(&previous, &next);
+        assert!(changes.iter().any(|line| line.contains("- ETag: \"1\"")));
+        assert!(changes.iter().any(|line| line.contains("+ ETag: \"2\"")));
+        assert!(changes.iter().any(|line| line.contains("down")));
+    }
+
+    #[test]
+    fn binary_bodies_are_compared_whole() {
+        let binary = |body: &[u8]| MultiResponse {
+            content_type: Some("application/octet-stream".into()),
+            ..response("HTTP/1.1 200 OK", body)
+        };
+
+        let changes = changes_between(&binary(b"\0\x01"), &binary(b"\0\x02"));
+        assert_eq!(changes, ["\x1b[33m~ binary body\x1b[0m"]);
+        assert!(changes_between(&binary(b"\0\x01"), &binary(b"\0\x01")).is_empty());
+    }
+}
@@ -0,0 +1,300 @@
+//! `--raw-response`: a copy of every byte read from the server, exactly as
+//! it came off the wire, before hyper parses it. Heads, chunked framing,
+//! and compressed bodies are all left as they are, for protocol debugging
+//! or for tools that want the response unprocessed.
+//!
+//! `--unsorted` also reads response heads here, since a `HeaderMap` groups
+//! repeated headers by name and so loses how they were interleaved, and so
+//! does `Expect: 100-continue`, since hyper drops the interim 100 it waits on.
+
+use hyper::{
+    client::connect::{Connected, Connection},
+    service::Service,
+    Uri,
+};
+use std::{
+    future::Future,
+    io::{self, Write},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::oneshot,
+};
+
+/// A response head's header lines, in the order they were received.
+pub type HeadLines = Vec<(String, Vec<u8>)>;
+
+/// Heads longer than this are left alone, like hyper's own limit.
+const MAX_HEAD: usize = 64 * 1024;
+
+static KEEP_HEADS: AtomicBool = AtomicBool::new(false);
+static WAITERS: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static::lazy_static! {
+    static ref SINK: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+    static ref LAST_HEAD: Mutex<Option<HeadLines>> = Mutex::new(None);
+    static ref CONTINUE: Mutex<Option<(usize, oneshot::Sender<()>)>> = Mutex::new(None);
+}
+
+/// Copies what every connection reads into `sink` from now on.
+pub fn capture_to(sink: Box<dyn Write + Send>) {
+    *SINK.lock().unwrap() = Some(sink);
+}
+
+fn capture(bytes: &[u8]) {
+    if let Some(sink) = SINK.lock().unwrap().as_mut() {
+        // like the trace, a copy that can't be written doesn't fail the request
+        let _ = sink.write_all(bytes).and_then(|_| sink.flush());
+    }
+}
+
+/// Keeps the header lines of every HTTP/1 response head read from now on.
+pub fn keep_heads() {
+    KEEP_HEADS.store(true, Ordering::SeqCst);
+}
+
+/// The header lines of the last response head read, if heads are kept.
+pub fn last_head() -> Option<HeadLines> {
+    LAST_HEAD.lock().unwrap().clone()
+}
+
+/// Sends on `answered` when the next response head read is a 100, or drops
+/// it when a final head comes first, until the returned guard is dropped.
+/// Only one request waits at a time, so `answered` comes back if another
+/// already is.
+pub fn await_continue(answered: oneshot::Sender<()>) -> Result<Waiting, oneshot::Sender<()>> {
+    let mut waiting = CONTINUE.lock().unwrap();
+    if waiting.is_some() {
+        return Err(answered);
+    }
+
+    let id = WAITERS.fetch_add(1, Ordering::SeqCst);
+    *waiting = Some((id, answered));
+    Ok(Waiting(id))
+}
+
+/// Stops waiting for a 100 when dropped.
+#[derive(Debug)]
+pub struct Waiting(usize);
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        let mut waiting = CONTINUE.lock().unwrap();
+        if waiting.as_ref().map(|(id, _)| *id) == Some(self.0) {
+            waiting.take();
+        }
+    }
+}
+
+/// The status code of a head, from its status line.
+fn status(head: &[u8]) -> Option<&[u8]> {
+    let line = head.split(|&b| b == b'\n').next()?;
+    let status = line.splitn(3, |&b| b == b' ').nth(1)?;
+    Some(status.strip_suffix(b"\r").unwrap_or(status))
+}
+
+/// The header lines of a head ending in a blank line, or `None` for an
+/// interim 1xx head, after which the final head follows.
+fn parse_head(head: &[u8]) -> Option<HeadLines> {
+    if status(head)?.starts_with(b"1") {
+        return None;
+    }
+
+    let lines = head.split(|&b| b == b'\n').skip(1).map(|line| match line {
+        [line @ .., b'\r'] => line,
+        line => line,
+    });
+
+    Some(
+        lines
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| {
+                let colon = line.iter().position(|&b| b == b':')?;
+                let name = String::from_utf8_lossy(&line[..colon]);
+                let value = &line[colon + 1..];
+                let start = value.iter().position(|b| !b.is_ascii_whitespace());
+                let end = value.iter().rposition(|b| !b.is_ascii_whitespace());
+                let value = match (start, end) {
+                    (Some(start), Some(end)) => value[start..=end].to_vec(),
+                    _ => Vec::new(),
+                };
+
+                Some((name.trim().to_ascii_lowercase(), value))
+            })
+            .collect(),
+    )
+}
+
+/// A connection whose reads are copied to the sink, if there is one.
+#[derive(Debug)]
+pub struct Tapped<T> {
+    inner: T,
+    /// The response head being read, from the request written last until
+    /// its blank line.
+    head: Option<Vec<u8>>,
+}
+
+/// Whether response heads are read at all: for `--unsorted`, or while a
+/// request waits for a 100.
+fn reading_heads() -> bool {
+    KEEP_HEADS.load(Ordering::SeqCst) || CONTINUE.lock().unwrap().is_some()
+}
+
+impl<T> Tapped<T> {
+    fn read_head(&mut self, bytes: &[u8]) {
+        let head = match &mut self.head {
+            Some(head) => head,
+            None => return,
+        };
+        head.extend_from_slice(bytes);
+
+        // a head ends with a blank line, which may be split across reads
+        while let Some(end) = head.windows(4).position(|window| window == b"\r\n\r\n") {
+            let waiting = || {
+                CONTINUE
+                    .lock()
+                    .unwrap()
+                    .take()
+                    .map(|(_, answered)| answered)
+            };
+
+            match parse_head(&head[..end + 4]) {
+                Some(lines) => {
+                    // answered before the body went out, so it never will
+                    waiting();
+                    if KEEP_HEADS.load(Ordering::SeqCst) {
+                        *LAST_HEAD.lock().unwrap() = Some(lines);
+                    }
+                    self.head = None;
+                    return;
+                }
+                None => {
+                    if status(head) == Some(&b"100"[..]) {
+                        if let Some(answered) = waiting() {
+                            let _ = answered.send(());
+                        }
+                    }
+                    head.drain(..end + 4);
+                }
+            }
+        }
+
+        if head.len() > MAX_HEAD {
+            self.head = None;
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Tapped<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(len)) = &read {
+            capture(&buf[..*len]);
+            self.read_head(&buf[..*len]);
+        }
+
+        read
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Tapped<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        // what's read after a request is written starts with its response
+        if self.head.is_none() && reading_heads() {
+            self.head = Some(Vec::new());
+        }
+
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl<T: Connection> Connection for Tapped<T> {
+    fn connected(&self) -> Connected {
+        self.inner.connected()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TappedConnector<C>(C);
+
+impl<C> TappedConnector<C> {
+    pub fn new(inner: C) -> Self {
+        Self(inner)
+    }
+}
+
+impl<C> Service<Uri> for TappedConnector<C>
+where
+    C: Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = Tapped<C::Response>;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, C::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connecting = self.0.call(uri);
+
+        Box::pin(async move {
+            Ok(Tapped {
+                inner: connecting.await?,
+                head: None,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_heads_in_order() {
+        let head = b"HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\nLink: </a>\r\n\
+                     set-cookie:b=2 \r\nX-Empty:\r\n\r\n";
+
+        assert_eq!(
+            parse_head(head).unwrap(),
+            [
+                ("set-cookie".to_owned(), b"a=1".to_vec()),
+                ("link".to_owned(), b"</a>".to_vec()),
+                ("set-cookie".to_owned(), b"b=2".to_vec()),
+                ("x-empty".to_owned(), Vec::new()),
+            ]
+        );
+        assert_eq!(parse_head(b"HTTP/1.1 100 Continue\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn reads_status_codes() {
+        assert_eq!(status(b"HTTP/1.1 100 Continue\r\n\r\n"), Some(&b"100"[..]));
+        assert_eq!(status(b"HTTP/1.1 417\r\n\r\n"), Some(&b"417"[..]));
+    }
+}
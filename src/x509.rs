@@ -0,0 +1,341 @@
+//! Just enough DER to describe a server's certificate for `--tls-info` and
+//! to pin its public key: the names, when it expires, and the key.
+
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+use thiserror::Error;
+
+const BOOLEAN: u8 = 0x01;
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const OID: u8 = 0x06;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const VERSION: u8 = 0xa0;
+const EXTENSIONS: u8 = 0xa3;
+const DNS_NAME: u8 = 0x82;
+const IP_ADDRESS: u8 = 0x87;
+
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// Attribute types shown in names, by the last byte of `2.5.4.x`.
+const ATTRIBUTES: &[(u8, &str)] = &[
+    (3, "CN"),
+    (6, "C"),
+    (7, "L"),
+    (8, "ST"),
+    (10, "O"),
+    (11, "OU"),
+];
+
+#[derive(Debug, Error)]
+pub enum X509Error {
+    #[error("malformed certificate: {0}")]
+    Malformed(String),
+}
+
+fn malformed(what: &str) -> X509Error {
+    X509Error::Malformed(what.into())
+}
+
+/// A tag, its contents, and the whole encoding including the header.
+struct Tlv<'a> {
+    tag: u8,
+    content: &'a [u8],
+    raw: &'a [u8],
+}
+
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.0.first().copied()
+    }
+
+    fn read(&mut self) -> Result<Tlv<'a>, X509Error> {
+        let input = self.0;
+        let tag = *input.first().ok_or_else(|| malformed("truncated"))?;
+        let first = *input.get(1).ok_or_else(|| malformed("truncated"))? as usize;
+
+        let (length, header) = if first < 0x80 {
+            (first, 2)
+        } else {
+            // long form, the low bits count the length bytes that follow
+            let count = first & 0x7f;
+            if count == 0 || count > 4 {
+                return Err(malformed("unsupported length"));
+            }
+
+            let bytes = input
+                .get(2..2 + count)
+                .ok_or_else(|| malformed("truncated"))?;
+            let length = bytes
+                .iter()
+                .fold(0, |length, &b| (length << 8) | b as usize);
+            (length, 2 + count)
+        };
+
+        let end = header + length;
+        if end > input.len() {
+            return Err(malformed("truncated"));
+        }
+
+        self.0 = &input[end..];
+        Ok(Tlv {
+            tag,
+            content: &input[header..end],
+            raw: &input[..end],
+        })
+    }
+
+    fn expect(&mut self, tag: u8) -> Result<Tlv<'a>, X509Error> {
+        let tlv = self.read()?;
+        if tlv.tag != tag {
+            return Err(X509Error::Malformed(format!(
+                "expected tag {:#04x}, found {:#04x}",
+                tag, tlv.tag
+            )));
+        }
+
+        Ok(tlv)
+    }
+}
+
+/// A UTC time as written in the certificate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Time {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl Time {
+    fn parse(tlv: &Tlv) -> Result<Self, X509Error> {
+        let text = std::str::from_utf8(tlv.content).map_err(|_| malformed("time"))?;
+        let text = text.strip_suffix('Z').ok_or_else(|| malformed("time"))?;
+        let number = |range: std::ops::Range<usize>| {
+            text.get(range)
+                .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|digits| digits.parse().ok())
+                .ok_or_else(|| malformed("time"))
+        };
+
+        // UTCTime has two year digits, 50 and over meaning the 1900s
+        let (year, rest) = match (tlv.tag, text.len()) {
+            (UTC_TIME, 12) => {
+                let year = number(0..2)? as i64;
+                (if year >= 50 { 1900 + year } else { 2000 + year }, 2)
+            }
+            (GENERALIZED_TIME, 14) => (number(0..4)? as i64, 4),
+            _ => return Err(malformed("time")),
+        };
+
+        Ok(Time {
+            year,
+            month: number(rest..rest + 2)?,
+            day: number(rest + 2..rest + 4)?,
+            hour: number(rest + 4..rest + 6)?,
+            minute: number(rest + 6..rest + 8)?,
+            second: number(rest + 8..rest + 10)?,
+        })
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn unix(&self) -> i64 {
+        // days from civil, counting years from March so leap days come last
+        let year = if self.month <= 2 {
+            self.year - 1
+        } else {
+            self.year
+        };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = (self.month as i64 + 9) % 12;
+        let day_of_year = (153 * month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+
+        days * 86_400 + (self.hour * 3600 + self.minute * 60 + self.second) as i64
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    /// The DNS names and IP addresses from the subject alternative names.
+    pub names: Vec<String>,
+    pub not_after: Time,
+    /// The DER SubjectPublicKeyInfo, which public key pins hash.
+    pub public_key: Vec<u8>,
+}
+
+/// Formats a distinguished name like `O=rurl tests, CN=localhost`, leaving
+/// out attributes other than the common ones.
+fn name(content: &[u8]) -> Result<String, X509Error> {
+    let mut parts = Vec::new();
+    let mut rdns = Der(content);
+
+    while rdns.peek().is_some() {
+        let mut set = Der(rdns.expect(SET)?.content);
+
+        while set.peek().is_some() {
+            let mut attribute = Der(set.expect(SEQUENCE)?.content);
+            let oid = attribute.expect(OID)?.content;
+            let value = attribute.read()?.content;
+
+            let label = match oid {
+                [0x55, 0x04, last] => ATTRIBUTES.iter().find(|(id, _)| id == last),
+                _ => None,
+            };
+            if let Some((_, label)) = label {
+                parts.push(format!("{}={}", label, String::from_utf8_lossy(value)));
+            }
+        }
+    }
+
+    Ok(parts.join(", "))
+}
+
+fn alt_names(content: &[u8]) -> Result<Vec<String>, X509Error> {
+    let mut names = Vec::new();
+    let mut general = Der(Der(content).expect(SEQUENCE)?.content);
+
+    while general.peek().is_some() {
+        let tlv = general.read()?;
+        match (tlv.tag, tlv.content.len()) {
+            (DNS_NAME, _) => names.push(String::from_utf8_lossy(tlv.content).into_owned()),
+            (IP_ADDRESS, 4) => {
+                let mut octets = [0; 4];
+                octets.copy_from_slice(tlv.content);
+                names.push(Ipv4Addr::from(octets).to_string());
+            }
+            (IP_ADDRESS, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(tlv.content);
+                names.push(Ipv6Addr::from(octets).to_string());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(names)
+}
+
+/// Parses a DER-encoded X.509 certificate.
+pub fn parse(der: &[u8]) -> Result<Certificate, X509Error> {
+    let mut certificate = Der(Der(der).expect(SEQUENCE)?.content);
+    let mut tbs = Der(certificate.expect(SEQUENCE)?.content);
+
+    if tbs.peek() == Some(VERSION) {
+        tbs.read()?;
+    }
+    tbs.expect(INTEGER)?;
+    tbs.expect(SEQUENCE)?;
+
+    let issuer = name(tbs.expect(SEQUENCE)?.content)?;
+    let mut validity = Der(tbs.expect(SEQUENCE)?.content);
+    validity.read()?;
+    let not_after = Time::parse(&validity.read()?)?;
+    let subject = name(tbs.expect(SEQUENCE)?.content)?;
+    let public_key = tbs.expect(SEQUENCE)?.raw.to_vec();
+
+    let mut names = Vec::new();
+    while tbs.peek().is_some() {
+        let tlv = tbs.read()?;
+        if tlv.tag != EXTENSIONS {
+            continue;
+        }
+
+        let mut extensions = Der(Der(tlv.content).expect(SEQUENCE)?.content);
+        while extensions.peek().is_some() {
+            let mut extension = Der(extensions.expect(SEQUENCE)?.content);
+            let oid = extension.expect(OID)?.content;
+            if extension.peek() == Some(BOOLEAN) {
+                extension.read()?;
+            }
+            let value = extension.expect(OCTET_STRING)?.content;
+
+            if oid == SUBJECT_ALT_NAME {
+                names = alt_names(value)?;
+            }
+        }
+    }
+
+    Ok(Certificate {
+        subject,
+        issuer,
+        names,
+        not_after,
+        public_key,
+    })
+}
+
+/// The base64 bodies of the PEM blocks labeled `label`, decoded.
+pub fn pem(text: &str, label: &str) -> Vec<Vec<u8>> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+
+    text.split(begin.as_str())
+        .skip(1)
+        .filter_map(|block| block.split(end.as_str()).next())
+        .filter_map(crate::decode::base64)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_the_test_certificate() {
+        let der = pem(include_str!("../tests/tls/localhost.pem"), "CERTIFICATE");
+        let certificate = parse(&der[0]).unwrap();
+
+        assert_eq!(certificate.subject, "O=rurl tests, CN=localhost");
+        assert_eq!(certificate.issuer, "O=rurl tests, CN=rurl test CA");
+        assert_eq!(certificate.names, ["localhost", "127.0.0.1"]);
+        assert_eq!(certificate.not_after.year, 2126);
+        assert_eq!(certificate.not_after.month, 9);
+        assert_eq!(certificate.not_after.day, 22);
+        assert_eq!(certificate.public_key[0], SEQUENCE);
+
+        assert!(parse(&der[0][..40]).is_err());
+    }
+
+    #[test]
+    fn reads_both_time_encodings() {
+        let utc = Tlv {
+            tag: UTC_TIME,
+            content: b"700101000000Z",
+            raw: &[],
+        };
+        assert_eq!(Time::parse(&utc).unwrap().unix(), 0);
+
+        let generalized = Tlv {
+            tag: GENERALIZED_TIME,
+            content: b"20240229123456Z",
+            raw: &[],
+        };
+        let time = Time::parse(&generalized).unwrap();
+        assert_eq!(time.unix(), 1_709_210_096);
+        assert_eq!(time.to_string(), "2024-02-29 12:34:56 UTC");
+    }
+}
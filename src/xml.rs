@@ -0,0 +1,197 @@
+use json::JsonValue;
+
+const INDENT: &str = "  ";
+
+fn tag_end(input: &str) -> usize {
+    let terminator = if input.starts_with("<!--") {
+        "-->"
+    } else if input.starts_with("<![CDATA[") {
+        "]]>"
+    } else {
+        let mut quote = None;
+
+        for (i, c) in input.char_indices() {
+            match (quote, c) {
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (Some(q), c) if q == c => quote = None,
+                (None, '>') => return i + 1,
+                _ => {}
+            }
+        }
+
+        return input.len();
+    };
+
+    input
+        .find(terminator)
+        .map(|i| i + terminator.len())
+        .unwrap_or_else(|| input.len())
+}
+
+fn push_line(output: &mut String, depth: usize, line: &str) {
+    for _ in 0..depth {
+        output.push_str(INDENT);
+    }
+
+    output.push_str(line);
+    output.push('\n');
+}
+
+pub fn pretty_print(input: &str) -> String {
+    let mut output = String::new();
+    let mut depth = 0;
+    let mut rest = input.trim();
+
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = rest[..end].trim();
+
+            if !text.is_empty() {
+                push_line(&mut output, depth, text);
+            }
+
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = tag_end(rest);
+        let tag = &rest[..end];
+        rest = &rest[end..];
+
+        if tag.starts_with("</") {
+            depth = depth.saturating_sub(1);
+            push_line(&mut output, depth, tag);
+        } else if tag.starts_with("<?") || tag.starts_with("<!") || tag.ends_with("/>") {
+            push_line(&mut output, depth, tag);
+        } else {
+            // keep `<a>text</a>` on a single line
+            let text_end = rest.find('<').unwrap_or(rest.len());
+
+            if rest[text_end..].starts_with("</") {
+                let close_end = text_end + tag_end(&rest[text_end..]);
+                let line = format!(
+                    "{}{}{}",
+                    tag,
+                    rest[..text_end].trim(),
+                    &rest[text_end..close_end]
+                );
+
+                push_line(&mut output, depth, &line);
+                rest = &rest[close_end..];
+            } else {
+                push_line(&mut output, depth, tag);
+                depth += 1;
+            }
+        }
+    }
+
+    output
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+        && !name.to_ascii_lowercase().starts_with("xml")
+}
+
+fn push_element(output: &mut String, depth: usize, name: &str, value: &JsonValue) {
+    // keys that are not element names keep their spelling in an attribute
+    let (open, close) = if is_name(name) {
+        (name.to_owned(), name)
+    } else {
+        (format!("entry name=\"{}\"", escape(name)), "entry")
+    };
+
+    match value {
+        JsonValue::Array(values) => {
+            for value in values {
+                push_element(output, depth, name, value);
+            }
+        }
+        JsonValue::Object(object) if !object.is_empty() => {
+            push_line(output, depth, &format!("<{}>", open));
+            for (key, value) in object.iter() {
+                push_element(output, depth + 1, key, value);
+            }
+            push_line(output, depth, &format!("</{}>", close));
+        }
+        JsonValue::Object(_) | JsonValue::Null => {
+            push_line(output, depth, &format!("<{}/>", open));
+        }
+        value => {
+            let text = value.as_str().map_or_else(|| value.dump(), str::to_owned);
+            push_line(
+                output,
+                depth,
+                &format!("<{}>{}</{}>", open, escape(&text), close),
+            );
+        }
+    }
+}
+
+/// Data items as an XML document for `--xml`: each key is an element
+/// under `<root>`, an array repeats its element once per value, and null
+/// is an empty element.
+pub fn from_json(value: &JsonValue) -> String {
+    let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    push_element(&mut output, 0, "root", value);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indents_nested_elements() {
+        let input = r#"<?xml version="1.0"?><feed a="x>y"><!-- a > b --><title>News</title><entry><id>1</id><link href="/1"/></entry></feed>"#;
+
+        assert_eq!(
+            pretty_print(input),
+            r#"<?xml version="1.0"?>
+<feed a="x>y">
+  <!-- a > b -->
+  <title>News</title>
+  <entry>
+    <id>1</id>
+    <link href="/1"/>
+  </entry>
+</feed>
+"#
+        );
+    }
+
+    #[test]
+    fn writes_json_as_elements() {
+        let value = json::object! {
+            "name": "a & b",
+            "tags": ["x", "y"],
+            "2fa": true,
+            "owner": null,
+        };
+
+        assert_eq!(
+            from_json(&value),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<root>
+  <name>a &amp; b</name>
+  <tags>x</tags>
+  <tags>y</tags>
+  <entry name="2fa">true</entry>
+  <owner/>
+</root>
+"#
+        );
+    }
+}
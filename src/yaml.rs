@@ -0,0 +1,115 @@
+//! Data items as a YAML document for `--yaml`, in block style. Strings
+//! that YAML would read as something else, or that need escapes, are
+//! double-quoted; JSON's string syntax is valid YAML for that.
+
+use json::JsonValue;
+
+const INDENT: usize = 2;
+
+fn is_plain(text: &str) -> bool {
+    let reserved = [
+        "", "~", "null", "true", "false", "yes", "no", "on", "off", "y", "n",
+    ];
+
+    !reserved.contains(&text.to_ascii_lowercase().as_str())
+        && text.parse::<f64>().is_err()
+        && text.starts_with(|c: char| c.is_alphabetic() || c == '/' || c == '_')
+        && !text.ends_with(' ')
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || " _-./@".contains(c))
+}
+
+fn scalar(value: &JsonValue) -> String {
+    match value {
+        JsonValue::Object(_) => "{}".into(),
+        JsonValue::Array(_) => "[]".into(),
+        value => match value.as_str() {
+            Some(text) if is_plain(text) => text.into(),
+            _ => value.dump(),
+        },
+    }
+}
+
+fn is_block(value: &JsonValue) -> bool {
+    match value {
+        JsonValue::Object(object) => !object.is_empty(),
+        JsonValue::Array(values) => !values.is_empty(),
+        _ => false,
+    }
+}
+
+fn push(output: &mut String, indent: usize, value: &JsonValue) {
+    match value {
+        JsonValue::Object(object) if !object.is_empty() => {
+            for (key, value) in object.iter() {
+                output.push_str(&" ".repeat(indent));
+                output.push_str(&scalar(&key.into()));
+                output.push(':');
+
+                if is_block(value) {
+                    output.push('\n');
+                    push(output, indent + INDENT, value);
+                } else {
+                    output.push(' ');
+                    output.push_str(&scalar(value));
+                    output.push('\n');
+                }
+            }
+        }
+        JsonValue::Array(values) if !values.is_empty() => {
+            for value in values {
+                output.push_str(&" ".repeat(indent));
+                output.push_str("- ");
+
+                if is_block(value) {
+                    // the first line of the nested block follows the dash
+                    let mut nested = String::new();
+                    push(&mut nested, indent + INDENT, value);
+                    output.push_str(&nested[indent + INDENT..]);
+                } else {
+                    output.push_str(&scalar(value));
+                    output.push('\n');
+                }
+            }
+        }
+        value => {
+            output.push_str(&scalar(value));
+            output.push('\n');
+        }
+    }
+}
+
+pub fn from_json(value: &JsonValue) -> String {
+    let mut output = String::new();
+    push(&mut output, 0, value);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_block_style() {
+        let value = json::parse(
+            r#"{"name": "ezra", "id": "7", "tags": ["a", {"b": true, "c": []}],
+                "on": "yes", "note": "a: b", "empty": {}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            from_json(&value),
+            "name: ezra\n\
+             id: \"7\"\n\
+             tags:\n  \
+               - a\n  \
+               - b: true\n    \
+                 c: []\n\
+             \"on\": \"yes\"\n\
+             note: \"a: b\"\n\
+             empty: {}\n"
+        );
+    }
+}
@@ -0,0 +1,1845 @@
+//! The binary against a mock server: what it sends for each body mode and
+//! request item, and what it prints and exits with.
+
+mod common;
+
+use common::{rurl, rurl_env, rurl_for, rurls, scratch_dir, scratch_file, MockServer, Reply};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn data_items_default_to_a_json_body() {
+    let server = MockServer::reply(Reply::json("{}"));
+    let run = rurl(&["POST", &server.url("/users"), "name=ezra", "admin:=true"]);
+    let request = server.single();
+
+    assert!(run.success());
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.target, "/users");
+    assert_eq!(request.header("content-type"), Some("application/json"));
+    assert_eq!(
+        json::parse(&request.body_text()).unwrap(),
+        json::parse(r#"{"name": "ezra", "admin": true}"#).unwrap()
+    );
+}
+
+#[test]
+fn form_sends_urlencoded_data() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let run = rurl(&["POST", &server.url("/"), "--form", "name=ezra c", "n:=1"]);
+    let request = server.single();
+
+    assert!(run.success());
+    assert!(request
+        .header("content-type")
+        .unwrap()
+        .starts_with("application/x-www-form-urlencoded"));
+    assert_eq!(request.body_text(), "name=ezra%20c&n=1");
+}
+
+#[test]
+fn form_fields_coerce_json_values() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let url = server.url("/");
+    let run = rurl(&[
+        "POST",
+        &url,
+        "--form",
+        "tags[]=a",
+        "tags[]=b",
+        "ids:=[1, 2]",
+        "ok:=true",
+        "none:=null",
+    ]);
+
+    assert!(run.success());
+    assert_eq!(
+        server.single().body_text(),
+        "tags%5B%5D=a&tags%5B%5D=b&ids=1&ids=2&ok=true&none="
+    );
+
+    let path = scratch_file("note.txt", b"hi");
+    let file = format!("note@{}", path.display());
+    let run = rurl(&["POST", &url, "--form", "ids:=[1, 2]", &file]);
+    let body = server.received()[1].body_text();
+
+    assert!(run.success());
+    assert!(body.contains("name=\"ids\"\r\n\r\n1\r\n"));
+    assert!(body.contains("name=\"ids\"\r\n\r\n2\r\n"));
+}
+
+#[test]
+fn form_files_are_sent_as_multipart() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let path = scratch_file("pixel.bin", b"\x89PNG\r\n\x00\xff");
+    let file = format!("avatar@{};type=image/png;filename=me.png", path.display());
+    let run = rurl(&["POST", &server.url("/"), "--form", "name=ezra", &file]);
+    let request = server.single();
+
+    assert!(run.success());
+
+    let content_type = request.header("content-type").unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/form-data; boundary=")
+        .unwrap();
+    let mut expected = format!(
+        "--{0}\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nezra\r\n\
+         --{0}\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"me.png\"\r\n\
+         Content-Type: image/png\r\n\r\n",
+        boundary
+    )
+    .into_bytes();
+    expected.extend_from_slice(b"\x89PNG\r\n\x00\xff");
+    expected.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+    assert_eq!(request.body, expected);
+}
+
+#[test]
+fn deterministic_multipart_bodies_use_a_fixed_boundary() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let path = scratch_file("hello.txt", b"hello");
+    let file = format!("note@{}", path.display());
+
+    for _ in 0..2 {
+        let run = rurl(&["POST", &server.url("/"), "--form", "--deterministic", &file]);
+        assert!(run.success(), "{:?}", run);
+    }
+
+    for request in server.received() {
+        assert_eq!(
+            request.header("content-type"),
+            Some("multipart/form-data; boundary=rurl-0000000000000000")
+        );
+    }
+}
+
+#[test]
+fn graphql_splits_the_query_from_its_variables() {
+    let server = MockServer::reply(Reply::json(r#"{"data": {"user": null}}"#));
+    let run = rurl(&[
+        "POST",
+        &server.url("/graphql"),
+        "--graphql",
+        "query=query($id: ID!) { user(id: $id) { name } }",
+        "id:=7",
+    ]);
+    let request = server.single();
+
+    assert!(run.success());
+    assert_eq!(request.header("content-type"), Some("application/json"));
+    assert_eq!(
+        json::parse(&request.body_text()).unwrap(),
+        json::parse(
+            r#"{"query": "query($id: ID!) { user(id: $id) { name } }", "variables": {"id": 7}}"#
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn header_items() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let run = rurl(&[
+        "GET",
+        &server.url("/search?q=rust"),
+        "X-Request-Id:abc123",
+        "Accept:text/plain",
+    ]);
+    let request = server.single();
+
+    assert!(run.success());
+    assert_eq!(request.target, "/search?q=rust");
+    assert_eq!(request.header("x-request-id"), Some("abc123"));
+    assert_eq!(request.header("accept"), Some("text/plain"));
+    assert!(request.body.is_empty());
+}
+
+#[test]
+fn variables_fill_placeholders_and_stay_out_of_the_history() {
+    let server = MockServer::reply(Reply::json("{}"));
+    let dir = scratch_dir();
+    let (config, data) = (dir.join("config"), dir.join("data"));
+    let env = [
+        ("RURL_CONFIG_DIR", config.to_str().unwrap()),
+        ("RURL_DATA_DIR", data.to_str().unwrap()),
+    ];
+    let var = |args: &[&str]| rurl_env(&[&["var"][..], args].concat(), &env);
+
+    assert!(var(&["set", "token", "saved-s3cret"]).success());
+    assert!(var(&["set", "user", "ezra"]).success());
+    assert_eq!(var(&["get", "token"]).stdout, "saved-s3cret\n");
+    assert_eq!(var(&["list"]).stdout, "token=saved-s3cret\nuser=ezra\n");
+
+    let url = server.url("/users/{{user}}");
+    let run = rurl_env(
+        &[
+            "--history",
+            "--var",
+            "user=cli-user",
+            "GET",
+            &url,
+            "Authorization:Bearer {{token}}",
+            "note=from {{var.user}}",
+        ],
+        &env,
+    );
+    let request = server.single();
+
+    assert!(run.success(), "{:?}", run);
+    // --var wins over the saved variable, which {{var.NAME}} still reads
+    assert_eq!(request.target, "/users/cli-user");
+    assert_eq!(request.header("authorization"), Some("Bearer saved-s3cret"));
+    assert_eq!(
+        json::parse(&request.body_text()).unwrap()["note"],
+        "from ezra"
+    );
+
+    let history = std::fs::read_to_string(data.join("history").join("history.jsonl")).unwrap();
+    assert!(history.contains("user=REDACTED"), "{}", history);
+    assert!(history.contains("/users/{{user}}"), "{}", history);
+    assert!(!history.contains("cli-user") && !history.contains("saved-s3cret"));
+
+    assert!(var(&["unset", "token"]).success());
+    assert!(!var(&["get", "token"]).success());
+    assert!(!var(&["unset", "token"]).success());
+
+    let run = rurl_env(&["GET", &url, "Authorization:Bearer {{token}}"], &env);
+    assert!(!run.success());
+    assert!(
+        run.stderr.contains("no value for {{token}}"),
+        "{}",
+        run.stderr
+    );
+
+    let run = rurl_env(&["--var", "not a var", "GET", &url], &env);
+    assert!(!run.success());
+    assert!(
+        run.stderr.contains("invalid --var not a var"),
+        "{}",
+        run.stderr
+    );
+    assert_eq!(server.received().len(), 1);
+}
+
+#[test]
+fn fragments_are_saved_and_added_to_requests() {
+    let server = MockServer::reply(Reply::json("{}"));
+    let config = scratch_dir();
+    let env = [("RURL_CONFIG_DIR", config.to_str().unwrap())];
+    let fragment = |args: &[&str]| rurl_env(&[&["fragment"][..], args].concat(), &env);
+
+    assert!(fragment(&["add", "team", "X-Team:core", "page==1", "role=admin"]).success());
+    assert_eq!(
+        fragment(&["show", "team"]).stdout,
+        "X-Team:core\npage==1\nrole=admin\n"
+    );
+    assert_eq!(
+        fragment(&["list"]).stdout,
+        "team: X-Team:core page==1 role=admin\n"
+    );
+
+    let run = rurl_env(
+        &[
+            "--fragment",
+            "team",
+            "POST",
+            &server.url("/users"),
+            "role=owner",
+        ],
+        &env,
+    );
+    let request = server.single();
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(request.header("x-team"), Some("core"));
+    assert_eq!(request.target, "/users?page=1");
+    // items on the command line come after, so they win
+    assert_eq!(json::parse(&request.body_text()).unwrap()["role"], "owner");
+
+    assert!(fragment(&["remove", "team"]).success());
+    assert_eq!(fragment(&["list"]).stdout, "");
+
+    let run = rurl_env(&["--fragment", "team", "GET", &server.url("/")], &env);
+    assert!(!run.success());
+    assert!(run.stderr.contains("team"), "{}", run.stderr);
+
+    let run = fragment(&["add", "bad", "no separator"]);
+    assert!(!run.success());
+    assert!(run
+        .stderr
+        .contains("could not parse request item no separator"));
+    assert!(!fragment(&["remove", "bad"]).success());
+    assert!(!fragment(&["add", "empty"]).success());
+}
+
+#[test]
+fn language_sends_accept_language() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let url = server.url("/");
+
+    assert!(rurl(&["GET", &url, "--language", "de_AT,fr"]).success());
+    assert!(rurl_env(&["GET", &url, "--locale"], &[("LC_ALL", "pt_BR.UTF-8")]).success());
+    assert!(rurl(&["GET", &url, "--language", "en", "Accept-Language:nl"]).success());
+
+    let received = server.received();
+    assert_eq!(
+        received[0].header("accept-language"),
+        Some("de-AT, de;q=0.9, fr;q=0.8")
+    );
+    assert_eq!(
+        received[1].header("accept-language"),
+        Some("pt-BR, pt;q=0.9")
+    );
+    assert_eq!(received[2].header("accept-language"), Some("nl"));
+}
+
+#[test]
+fn unsorted_prints_response_headers_as_received() {
+    let server = MockServer::reply(
+        Reply::text("ok")
+            .header("Set-Cookie", "a=1")
+            .header("Link", "</next>; rel=next")
+            .header("Set-Cookie", "b=2"),
+    );
+    let run = rurl(&["GET", &server.url("/"), "--unsorted"]);
+
+    assert!(run.success());
+    let at = |text: &str| run.stderr.find(text).unwrap();
+    assert!(at("a=1") < at("rel=next"));
+    assert!(at("rel=next") < at("b=2"));
+}
+
+#[test]
+fn expectations_set_the_exit_code() {
+    let server = MockServer::reply(
+        Reply::json(r#"{"id": 8}"#)
+            .header("ETag", "\"v1\"")
+            .header("Cache-Control", "no-cache"),
+    );
+    let url = server.url("/");
+
+    let run = rurl(&[
+        "GET",
+        &url,
+        "--expect-status",
+        "2xx",
+        "--expect-header",
+        "ETag",
+    ]);
+    assert!(run.success(), "{:?}", run);
+
+    let run = rurl(&[
+        "GET",
+        &url,
+        "--expect-status",
+        "404",
+        "--expect-body-json",
+        "$.id == 7",
+    ]);
+    assert_eq!(run.code, Some(1));
+    assert!(run.stderr.contains("2 expectations failed"));
+    assert!(run.stderr.contains("status is 200 OK, expected 404"));
+    assert!(run.stderr.contains("$.id == 7: $.id is 8"));
+}
+
+#[test]
+fn only_idempotent_methods_are_retried_unless_asked() {
+    let server = MockServer::reply(Reply::new(503));
+
+    assert!(rurl(&["GET", &server.url("/"), "--retries", "2"]).success());
+    assert_eq!(server.received().len(), 3);
+
+    assert!(rurl(&["POST", &server.url("/"), "--retries", "2"]).success());
+    assert_eq!(server.received().len(), 4);
+
+    let run = rurl(&[
+        "POST",
+        &server.url("/"),
+        "--retries",
+        "2",
+        "--retry-all-methods",
+    ]);
+    assert!(run.success());
+    assert_eq!(server.received().len(), 7);
+    assert_eq!(run.stderr.matches("retrying after 503").count(), 2);
+}
+
+#[test]
+fn malformed_content_types_still_show_the_body() {
+    let server = MockServer::reply(
+        Reply::new(200)
+            .header("Content-Type", "text/;;")
+            .body(b"plain enough"),
+    );
+    let run = rurl(&["GET", &server.url("/")]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout.trim_end(), "plain enough");
+}
+
+#[test]
+fn server_errors_still_exit_zero_without_expectations() {
+    let server = MockServer::reply(Reply::new(500).body(b"boom"));
+    let run = rurl(&["GET", &server.url("/")]);
+
+    assert!(run.success());
+    assert!(run.stderr.contains("HTTP/1.1 500 Internal Server Error"));
+    assert_eq!(run.stdout.trim_end(), "boom");
+}
+
+#[test]
+fn format_json_prints_the_exchange() {
+    let server = MockServer::reply(Reply::json(r#"{"ok": true}"#));
+    let run = rurl(&[
+        "POST",
+        &server.url("/things"),
+        "--format",
+        "json",
+        "name=ezra",
+    ]);
+    let document = json::parse(&run.stdout).unwrap();
+
+    assert!(run.success());
+    assert_eq!(document["rurl"], 1);
+    assert_eq!(document["request"]["method"], "POST");
+    assert_eq!(document["request"]["body"]["name"], "ezra");
+    assert_eq!(document["response"]["status"], 200);
+    assert_eq!(document["response"]["body"]["ok"], true);
+    assert!(document["timings"]["total_ms"].is_number());
+}
+
+#[test]
+fn json_output_prints_the_envelope() {
+    let server = MockServer::reply(Reply::json("[1, 2]").header("X-Total", "2"));
+    let run = rurl(&["GET", &server.url("/"), "--json-output"]);
+    let document = json::parse(&run.stdout).unwrap();
+
+    assert!(run.success());
+    assert_eq!(document["status"], 200);
+    assert_eq!(document["headers"]["x-total"], "2");
+    assert_eq!(document["body"][1], 2);
+}
+
+#[test]
+fn json_mode_asks_for_json() {
+    let server = MockServer::reply(Reply::text("<html></html>"));
+    let run = rurl(&["-j", "GET", &server.url("/users")]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(server.single().header("accept"), Some("application/json"));
+    assert!(run
+        .stderr
+        .contains("asked for JSON, but the response is text/plain"));
+
+    let server = MockServer::reply(Reply::text("<html></html>"));
+    let run = rurl(&["-j", "GET", &server.url("/"), "--accept", "text/html"]);
+
+    assert_eq!(server.single().header("accept"), Some("text/html"));
+    assert!(!run.stderr.contains("asked for JSON"));
+}
+
+#[test]
+fn json_posts_without_data_send_an_empty_object() {
+    let server = MockServer::reply(Reply::json("{}"));
+    let run = rurl(&["-j", "POST", &server.url("/jobs")]);
+
+    assert!(run.success(), "{:?}", run);
+    let request = server.single();
+    assert_eq!(request.body_text(), "{}");
+    assert_eq!(request.header("content-type"), Some("application/json"));
+
+    let server = MockServer::reply(Reply::json("{}"));
+    let run = rurl(&["-j", "POST", &server.url("/jobs"), "--empty-body"]);
+
+    assert!(run.success(), "{:?}", run);
+    let request = server.single();
+    assert_eq!(request.body_text(), "");
+    assert_eq!(request.header("content-type"), None);
+}
+
+#[test]
+fn head_prints_no_body() {
+    let server = MockServer::reply(Reply::text("not sent"));
+    let run = rurl(&["HEAD", &server.url("/")]);
+
+    assert!(run.success());
+    assert_eq!(server.single().method, "HEAD");
+    assert!(run.stderr.contains("HTTP/1.1 200 OK"));
+    assert!(!run.stdout.contains("not sent"));
+}
+
+#[test]
+fn search_params_are_added_to_the_query() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let run = rurl(&["GET", &server.url("/search?page=2"), "q==rurl cli"]);
+
+    assert!(run.success());
+    assert_eq!(server.single().target, "/search?page=2&q=rurl%20cli");
+}
+
+#[test]
+fn dot_segments_are_removed_unless_path_as_is() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let url = server.url("/static/../admin//./users?q=..");
+
+    assert!(rurl(&["GET", &url]).success());
+    assert!(rurl(&["GET", &url, "--path-as-is"]).success());
+
+    let targets: Vec<_> = server
+        .received()
+        .into_iter()
+        .map(|request| request.target)
+        .collect();
+    assert_eq!(
+        targets,
+        ["/admin//users?q=..", "/static/../admin//./users?q=.."]
+    );
+}
+
+#[test]
+fn methods_may_be_extensions_lowercase_or_after_the_uri() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let url = server.url("/");
+
+    assert!(rurl(&["mkcol", &url]).success());
+    assert!(rurl(&[&url, "-X", "purge"]).success());
+    assert!(rurl(&[&url, "--method=PROPFIND", "Depth:1"]).success());
+
+    let methods: Vec<_> = server
+        .received()
+        .into_iter()
+        .map(|request| request.method)
+        .collect();
+    assert_eq!(methods, ["MKCOL", "PURGE", "PROPFIND"]);
+}
+
+#[test]
+fn method_flags_cannot_be_combined_with_a_method() {
+    let run = rurl(&["GET", "http://127.0.0.1:9/x", "-X", "PUT", "--curl-only"]);
+
+    assert!(!run.success());
+    assert!(run.stdout.is_empty());
+    assert!(run
+        .stderr
+        .contains("--method PUT cannot be combined with a METHOD argument"));
+
+    let run = rurl(&["127.0.0.1:9/x", "-X", "PUT", "name=ezra", "--curl-only"]);
+    assert!(run.stdout.contains("  PUT \\\n"), "{:?}", run);
+    assert!(run.stdout.contains("http://127.0.0.1:9/x"), "{:?}", run);
+}
+
+#[test]
+fn uris_without_a_scheme_use_the_default_scheme() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let url = server.url("/users");
+    let bare = url.trim_start_matches("http://");
+
+    assert!(rurl(&["GET", bare]).success());
+    assert_eq!(server.single().target, "/users");
+
+    let run = rurl(&[
+        "GET",
+        "example.com/a",
+        "--default-scheme",
+        "https",
+        "--curl-only",
+    ]);
+    assert!(run.stdout.contains(" https://example.com/a "));
+
+    let run = rurls(&["GET", "example.com/a", "--curl-only"]);
+    assert!(run.stdout.contains(" https://example.com/a "));
+
+    let run = rurls(&["GET", "http://example.com/a", "--curl-only"]);
+    assert!(run.stdout.contains(" http://example.com/a "));
+}
+
+#[test]
+fn cache_dir_revalidates_and_serves_304s_from_the_cache() {
+    let server = MockServer::start(|request| match request.header("if-none-match") {
+        Some("\"v1\"") => Reply::new(304).header("ETag", "\"v1\""),
+        _ => Reply::text("fresh").header("ETag", "\"v1\""),
+    });
+    let url = server.url("/catalog");
+    let dir = scratch_dir().join("responses");
+    let dir = dir.to_str().unwrap();
+
+    let first = rurl(&["GET", &url, "--cache-dir", dir]);
+    let second = rurl(&["GET", &url, "--cache-dir", dir]);
+    let requests = server.received();
+
+    assert!(first.success() && second.success());
+    assert_eq!(requests[0].header("if-none-match"), None);
+    assert_eq!(requests[1].header("if-none-match"), Some("\"v1\""));
+    assert!(!first.stderr.contains("served from cache"));
+    assert!(second
+        .stderr
+        .contains("304 Not Modified (served from cache)"));
+    assert!(second.stderr.contains("200 OK"));
+    assert_eq!(second.stdout.trim_end(), "fresh");
+}
+
+#[test]
+fn curl_only_sends_nothing() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let url = server.url("/users");
+    let run = rurl(&["POST", &url, "--curl-only", "name=ezra"]);
+
+    assert!(run.success());
+    assert!(server.received().is_empty());
+    assert!(run.stdout.starts_with("curl"));
+    assert!(run.stdout.contains(&url));
+    assert!(run.stdout.contains(r#"{"name":"ezra"}"#));
+}
+
+#[test]
+fn latin1_bodies_are_transcoded() {
+    let server = MockServer::reply(
+        Reply::new(200)
+            .header("Content-Type", "text/plain; charset=ISO-8859-1")
+            .body(b"caf\xe9"),
+    );
+    let run = rurl(&["GET", &server.url("/")]);
+
+    assert!(run.success());
+    assert_eq!(run.stdout.trim_end(), "café");
+    assert!(!run.stderr.contains("warning"));
+}
+
+#[test]
+fn msgpack_bodies_are_encoded_and_responses_shown_as_json() {
+    let server = MockServer::reply(
+        Reply::new(200)
+            .header("Content-Type", "application/msgpack")
+            .body(b"\x81\xa2ok\xc3"),
+    );
+    let run = rurl(&["POST", &server.url("/"), "--msgpack", "name=ezra", "n:=1"]);
+    let request = server.single();
+
+    assert!(run.success());
+    assert_eq!(request.header("content-type"), Some("application/msgpack"));
+    assert_eq!(request.body, b"\x82\xa4name\xa4ezra\xa1n\x01");
+    assert!(run.stdout.contains(r#""ok": true"#));
+}
+
+/// A server that reads one request head, answers it with `first`, and then
+/// reads whatever follows: the head, how much body came, and how long after
+/// `first` the last of it did.
+fn expect_continue_server(
+    first: &'static [u8],
+) -> (
+    String,
+    std::thread::JoinHandle<(String, usize, std::time::Duration)>,
+) {
+    use std::io::{BufRead as _, BufReader, Read as _, Write as _};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/upload", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut head = String::new();
+
+        while !head.ends_with("\r\n\r\n") {
+            if reader.read_line(&mut head).unwrap() == 0 {
+                break;
+            }
+        }
+
+        stream.write_all(first).unwrap();
+        let answered = std::time::Instant::now();
+
+        let length = head
+            .lines()
+            .find_map(|line| {
+                line.to_ascii_lowercase()
+                    .strip_prefix("content-length:")
+                    .map(|value| value.trim().parse().unwrap())
+            })
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        let mut uploaded = 0;
+        while uploaded < length {
+            match reader.read(&mut body[uploaded..]) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => uploaded += read,
+            }
+        }
+        let elapsed = answered.elapsed();
+
+        if uploaded == length {
+            let _ = stream.write_all(b"HTTP/1.1 201 Created\r\ncontent-length: 0\r\n\r\n");
+        }
+
+        (head.to_ascii_lowercase(), uploaded, elapsed)
+    });
+
+    (url, server)
+}
+
+#[test]
+fn bodies_expecting_100_continue_are_not_sent_once_the_server_refuses_them() {
+    // answers from the head alone, as a server enforcing a size limit would
+    let (url, server) = expect_continue_server(
+        b"HTTP/1.1 413 Payload Too Large\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+    );
+
+    let path = scratch_file("disk.img", &[0xff; 16]);
+    let run = rurl(&[
+        "PUT",
+        &url,
+        &format!("@{}", path.display()),
+        "Expect:100-continue",
+    ]);
+    let (head, uploaded, _) = server.join().unwrap();
+
+    assert!(head.contains("expect: 100-continue"), "{}", head);
+    assert_eq!(uploaded, 0);
+    assert!(run.stderr.contains("413 Payload Too Large"), "{:?}", run);
+}
+
+#[test]
+fn large_bodies_are_sent_as_soon_as_the_server_says_100() {
+    let (url, server) = expect_continue_server(b"HTTP/1.1 100 Continue\r\n\r\n");
+
+    let path = scratch_file("continue.img", &vec![0xff; 2 << 20]);
+    let run = rurl(&["PUT", &url, &format!("@{}", path.display())]);
+    let (head, uploaded, elapsed) = server.join().unwrap();
+
+    assert!(run.success(), "{:?}", run);
+    assert!(head.contains("expect: 100-continue"), "{}", head);
+    assert_eq!(uploaded, 2 << 20);
+    // well within the second the body waits for a server that never answers
+    assert!(
+        elapsed < std::time::Duration::from_millis(800),
+        "{:?}",
+        elapsed
+    );
+    assert!(run.stderr.contains("201 Created"), "{:?}", run);
+}
+
+#[test]
+fn large_bodies_are_dropped_when_the_server_refuses_them() {
+    for refusal in [
+        &b"HTTP/1.1 417 Expectation Failed\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"[..],
+        &b"HTTP/1.1 401 Unauthorized\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"[..],
+    ]
+    .iter()
+    {
+        let (url, server) = expect_continue_server(refusal);
+
+        let path = scratch_file("refused.img", &vec![0xff; 2 << 20]);
+        let run = rurl(&["PUT", &url, &format!("@{}", path.display())]);
+        let (head, uploaded, _) = server.join().unwrap();
+
+        assert!(head.contains("expect: 100-continue"), "{}", head);
+        assert_eq!(uploaded, 0, "{:?}", run);
+    }
+}
+
+#[test]
+fn trailers_are_sent_and_printed_over_http2() {
+    use hyper::{
+        body::{Bytes, HttpBody},
+        service::{make_service_fn, service_fn},
+        Body, HeaderMap, Request, Response, Server,
+    };
+    use std::{
+        convert::Infallible,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    /// `ok`, then the trailers the request ended with.
+    struct Echo(Option<Bytes>, Option<HeaderMap>);
+
+    impl HttpBody for Echo {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_data(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Bytes, Infallible>>> {
+            Poll::Ready(self.0.take().map(Ok))
+        }
+
+        fn poll_trailers(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Result<Option<HeaderMap>, Infallible>> {
+            Poll::Ready(Ok(self.1.take()))
+        }
+    }
+
+    let (address_tx, address_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let make = make_service_fn(|_| async {
+                Ok::<_, Infallible>(service_fn(|mut req: Request<Body>| async move {
+                    while req.body_mut().data().await.is_some() {}
+                    let trailers = req.body_mut().trailers().await.unwrap();
+
+                    Ok::<_, Infallible>(Response::new(Echo(Some("ok".into()), trailers)))
+                }))
+            });
+            let server = Server::bind(&([127, 0, 0, 1], 0).into())
+                .http2_only(true)
+                .serve(make);
+
+            address_tx.send(server.local_addr()).unwrap();
+            server.await.unwrap();
+        });
+    });
+
+    let url = format!("http://{}/", address_rx.recv().unwrap());
+    let run = rurl(&[
+        "--http2-prior-knowledge",
+        "--trailer",
+        "Grpc-Status:0",
+        "POST",
+        &url,
+        "name=ezra",
+    ]);
+
+    assert!(run.success(), "{:?}", run);
+    assert!(run.stderr.contains("trailer: grpc-status"), "{:?}", run);
+    assert!(run.stderr.contains("grpc-status: 0"), "{:?}", run);
+}
+
+#[test]
+fn proto_encodes_data_items_and_decodes_the_response() {
+    // a length-delimited protobuf field, for lengths under 128
+    fn len(number: u8, bytes: &[u8]) -> Vec<u8> {
+        [&[number << 3 | 2, bytes.len() as u8][..], bytes].concat()
+    }
+
+    // users.proto: message User { string name = 1; int32 id = 2; }
+    // service Users { rpc Get(User) returns (User); }
+    let user = [
+        len(1, b"User"),
+        len(
+            2,
+            &[len(1, b"name"), vec![0x18, 1, 0x20, 1, 0x28, 9]].concat(),
+        ),
+        len(
+            2,
+            &[len(1, b"id"), vec![0x18, 2, 0x20, 1, 0x28, 5]].concat(),
+        ),
+    ]
+    .concat();
+    let get = [
+        len(1, b"Get"),
+        len(2, b".users.User"),
+        len(3, b".users.User"),
+    ]
+    .concat();
+    let file = [
+        len(1, b"users.proto"),
+        len(2, b"users"),
+        len(4, &user),
+        len(6, &[len(1, b"Users"), len(2, &get)].concat()),
+        len(12, b"proto3"),
+    ]
+    .concat();
+    let descriptors = scratch_file("users.pb", &len(1, &file));
+
+    let server = MockServer::reply(
+        Reply::new(200)
+            .header("Content-Type", "application/protobuf")
+            .body(b"\x0a\x04ezra\x10\x07"),
+    );
+    let run = rurl(&[
+        "--proto",
+        descriptors.to_str().unwrap(),
+        "POST",
+        &server.url("/twirp/users.Users/Get"),
+        "name=ezra",
+        "id:=7",
+    ]);
+    let request = server.single();
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(request.header("content-type"), Some("application/protobuf"));
+    assert_eq!(request.body, b"\x0a\x04ezra\x10\x07");
+    assert_eq!(
+        json::parse(&run.stdout).unwrap(),
+        json::parse(r#"{"name": "ezra", "id": 7}"#).unwrap()
+    );
+}
+
+#[test]
+fn ndjson_lines_are_shown_and_filtered_one_by_one() {
+    let body = "{\"id\": 1, \"name\": \"a\"}\n{\"id\": 2}\n{\"name\": \"c\"}\n";
+
+    for content_type in &["application/x-ndjson", "text/plain"] {
+        let server = MockServer::reply(
+            Reply::new(200)
+                .header("Content-Type", content_type)
+                .body(body.as_bytes()),
+        );
+        let run = rurl(&["GET", &server.url("/events"), "--filter", "$.id"]);
+
+        assert!(run.success(), "{:?}", run);
+        assert_eq!(run.stdout, "1\n2\n", "{}", content_type);
+    }
+}
+
+#[test]
+fn table_aligns_csv_and_json_arrays() {
+    let csv = MockServer::reply(
+        Reply::new(200)
+            .header("Content-Type", "text/csv")
+            .body(&b"id,name\n7,\"Lovelace, Ada\"\n12,Hopper\n"[..]),
+    );
+    let run = rurl(&["GET", &csv.url("/users.csv"), "--table"]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout, "id  name\n 7  Lovelace, Ada\n12  Hopper\n");
+
+    let json = MockServer::reply(Reply::json(r#"[{"id": 7, "tags": ["a"]}, {"id": 12}]"#));
+    let run = rurl(&["GET", &json.url("/users"), "--table"]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout, "id  tags\n 7  [\"a\"]\n12\n");
+}
+
+#[test]
+fn paginate_follows_next_links_and_joins_json_arrays() {
+    let server = MockServer::start(|req| match req.target.as_str() {
+        "/items" => Reply::json(r#"[{"id": 1}, {"id": 2}]"#).header(
+            "Link",
+            r#"</items?page=2>; rel="next", </items?page=3>; rel="last""#,
+        ),
+        "/items?page=2" => {
+            Reply::json(r#"[{"id": 3}]"#).header("Link", r#"</items?page=3>; rel="next""#)
+        }
+        _ => Reply::json(r#"[{"id": 4}]"#),
+    });
+    let run = rurl(&[
+        "GET",
+        &server.url("/items"),
+        "--paginate",
+        "--filter",
+        "$[*].id",
+    ]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout, "[\n  1,\n  2,\n  3,\n  4\n]\n");
+    assert_eq!(
+        server
+            .received()
+            .iter()
+            .map(|req| req.target.as_str())
+            .collect::<Vec<_>>(),
+        ["/items", "/items?page=2", "/items?page=3"]
+    );
+}
+
+#[test]
+fn paginate_fails_when_a_page_fails() {
+    let server = MockServer::start(|req| match req.target.as_str() {
+        "/items" => Reply::json("[1]").header("Link", r#"</items?page=2>; rel="next""#),
+        _ => Reply::new(503),
+    });
+    let run = rurl(&["GET", &server.url("/items"), "--paginate"]);
+
+    assert!(!run.success(), "{:?}", run);
+    assert!(run.stderr.contains("page 2 failed"), "{:?}", run);
+}
+
+#[test]
+fn redirect_loops_are_a_method_and_uri_seen_before() {
+    let server = MockServer::start(|req| match (req.method.as_str(), req.target.as_str()) {
+        ("POST", "/form") => Reply::new(303).header("Location", "/form"),
+        ("GET", "/form") => Reply::text("done"),
+        ("GET", "/a") => Reply::new(302).header("Location", "/b"),
+        _ => Reply::new(302).header("Location", "/a"),
+    });
+
+    let run = rurl(&["POST", &server.url("/form"), "--follow", "name=ezra"]);
+    assert!(run.success(), "{:?}", run);
+    assert!(run.stdout.contains("done"));
+
+    let run = rurl(&["GET", &server.url("/a"), "--follow"]);
+    assert!(!run.success());
+    assert!(run.stderr.contains("redirect loop: GET "), "{:?}", run);
+}
+
+#[test]
+fn pages_and_redirects_are_signed_again() {
+    let server = MockServer::start(|req| match req.target.as_str() {
+        "/old" => Reply::new(307).header("Location", "/items"),
+        "/items" => Reply::json("[1]").header("Link", r#"</items?page=2>; rel="next""#),
+        _ => Reply::json("[2]"),
+    });
+    let run = rurl(&[
+        "--auth-type",
+        "hmac",
+        "--hmac-key",
+        "secret",
+        "GET",
+        &server.url("/old"),
+        "--follow",
+        "--paginate",
+    ]);
+
+    assert!(run.success(), "{:?}", run);
+
+    let received = server.received();
+    assert_eq!(received.len(), 3);
+    for request in received {
+        let canonical = rurl::hmac::canonical(
+            "GET",
+            &request.target,
+            request.header("x-date").unwrap(),
+            b"",
+        );
+        let signature = rurl::digest::hmac_sha256(b"secret", canonical.as_bytes());
+
+        assert_eq!(
+            request.header("authorization"),
+            Some(format!("HMAC-SHA256 {}", rurl::digest::hex(&signature)).as_str()),
+            "{}",
+            request.target
+        );
+    }
+}
+
+#[test]
+fn if_match_auto_fetches_with_the_same_headers_and_credentials() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => Reply::new(200).header("ETag", "\"v7\""),
+        _ => Reply::json("{}"),
+    });
+    let run = rurl(&[
+        "PUT",
+        &server.url("/doc"),
+        "--auth",
+        "ezra:pw",
+        "--if-match",
+        "auto",
+        "X-Tenant:acme",
+        "name=ezra",
+    ]);
+
+    assert!(run.success(), "{:?}", run);
+
+    let received = server.received();
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[0].method, "HEAD");
+    assert_eq!(received[0].header("x-tenant"), Some("acme"));
+    assert_eq!(
+        received[0].header("authorization"),
+        Some("Basic ZXpyYTpwdw==")
+    );
+    assert_eq!(received[0].header("content-type"), None);
+    assert!(received[0].body.is_empty());
+    assert_eq!(received[1].method, "PUT");
+    assert_eq!(received[1].header("if-match"), Some("\"v7\""));
+}
+
+#[test]
+fn history_keeps_no_credentials() {
+    let server = MockServer::reply(Reply::json("{}"));
+    let data = scratch_dir();
+    let run = rurl_env(
+        &[
+            "--history",
+            "--auth",
+            "ezra:hunter2",
+            "GET",
+            &server.url("/"),
+            "Authorization:Bearer s3cret",
+        ],
+        &[("RURL_DATA_DIR", data.to_str().unwrap())],
+    );
+
+    assert!(run.success(), "{:?}", run);
+
+    let path = data.join("history").join("history.jsonl");
+    let history = std::fs::read_to_string(&path).unwrap();
+    assert!(history.contains("ezra:REDACTED"), "{}", history);
+    assert!(!history.contains("hunter2") && !history.contains("s3cret"));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mode = std::fs::metadata(path.parent().unwrap())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
+
+#[test]
+fn diff_compares_two_responses() {
+    let prod = MockServer::reply(
+        Reply::json(r#"{"id": 7, "name": "Ada", "tags": ["a"]}"#).header("X-Version", "1"),
+    );
+    let staging = MockServer::reply(
+        Reply::json(r#"{"tags": ["a", "b"], "name": "Ada L.", "id": 7}"#).header("X-Version", "2"),
+    );
+    let run = rurl(&[
+        "diff",
+        "GET",
+        &prod.url("/users/7"),
+        &staging.url("/users/7"),
+        "X-Trace:1",
+    ]);
+
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(
+        run.stdout.contains("- x-version: 1\n+ x-version: 2\n"),
+        "{}",
+        run.stdout
+    );
+    assert!(run
+        .stdout
+        .contains("~ $.name: \"Ada\" → \"Ada L.\"\n+ $.tags[1]: \"b\"\n"));
+    assert_eq!(staging.single().header("x-trace"), Some("1"));
+
+    let run = rurl(&["diff", "GET", &prod.url("/users/7")]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout, "");
+}
+
+#[test]
+fn edit_sends_the_body_as_saved_in_the_editor() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let url = server.url("/users");
+    let args = ["POST", url.as_str(), "name=ezra", "--edit"];
+
+    let run = rurl_env(&args, &[("VISUAL", "sed -i 's/\"ezra\"/\"ada\"/'")]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(server.single().body_text(), r#"{"name":"ada"}"#);
+
+    let run = rurl_env(&args, &[("VISUAL", "truncate -s 0")]);
+
+    assert!(!run.success());
+    assert!(run
+        .stderr
+        .contains("the body is empty, not sending the request"));
+    assert_eq!(server.received().len(), 1);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn clipboard_without_a_display_fails_only_what_needs_it() {
+    let server = MockServer::reply(Reply::json(r#"{"ok": true}"#));
+    let no_display = [("DISPLAY", ""), ("WAYLAND_DISPLAY", "")];
+
+    let run = rurl_env(&["POST", &server.url("/users"), "@clipboard"], &no_display);
+    assert!(!run.success(), "{:?}", run);
+    assert!(
+        run.stderr.contains("the clipboard is unavailable"),
+        "{}",
+        run.stderr
+    );
+    assert!(server.received().is_empty());
+
+    // the response still prints when it can't be copied
+    let run = rurl_env(&["POST", &server.url("/users"), "--copy"], &no_display);
+    assert!(run.success(), "{:?}", run);
+    assert!(run.stdout.contains("\"ok\": true"));
+    assert!(run.stderr.contains("warning: the clipboard is unavailable"));
+}
+
+#[test]
+fn run_rolls_back_earlier_steps_when_one_fails() {
+    let server = MockServer::start(|request| match request.method.as_str() {
+        "POST" if request.target == "/invites" => Reply::new(500),
+        "POST" => Reply::json(r#"{"id": 7}"#),
+        _ => Reply::new(204),
+    });
+    let file = scratch_file(
+        "smoke.http",
+        format!(
+            "\
+@host = {}
+
+### user
+POST {{{{host}}}}/users
+
+### rollback:
+DELETE {{{{host}}}}/users/{{{{user.response.body.$.id}}}}
+
+### login
+POST {{{{host}}}}/login
+
+### team
+POST {{{{host}}}}/teams
+
+### rollback:
+DELETE {{{{host}}}}/teams/{{{{team.response.body.$.id}}}}
+
+### invite
+POST {{{{host}}}}/invites
+
+### rollback:
+DELETE {{{{host}}}}/invites
+",
+            server.url("")
+        )
+        .as_bytes(),
+    );
+
+    let run = rurl(&["run", file.to_str().unwrap()]);
+
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(run.stdout.contains("3 of 4 requests succeeded"));
+    assert!(run.stderr.contains("step invite failed with 500"));
+
+    let sent: Vec<String> = server
+        .received()
+        .iter()
+        .map(|request| format!("{} {}", request.method, request.target))
+        .collect();
+    assert_eq!(
+        sent,
+        [
+            "POST /users",
+            "POST /login",
+            "POST /teams",
+            "POST /invites",
+            "DELETE /teams/7",
+            "DELETE /users/7",
+        ]
+    );
+}
+
+#[test]
+fn watch_rings_when_the_status_changes_or_expectations_fail() {
+    // up for the first request, down after
+    let count = AtomicUsize::new(0);
+    let server = MockServer::start(move |_| match count.fetch_add(1, Ordering::SeqCst) {
+        0 => Reply::text("up"),
+        _ => Reply::new(503),
+    });
+
+    let run = rurl_for(
+        &[
+            "GET",
+            &server.url("/health"),
+            "--watch",
+            "0.2",
+            "--expect-status",
+            "2xx",
+            "--bell",
+        ],
+        std::time::Duration::from_millis(1500),
+    );
+
+    assert!(server.received().len() > 2, "{:?}", run);
+    assert!(run.stdout.contains("1 expectation failed"));
+    // once for the status change and once for the failing expectation,
+    // but not again while it stays down
+    assert_eq!(run.stderr.matches('\x07').count(), 2, "{:?}", run);
+}
+
+#[test]
+fn expand_env_fills_variables_in_items() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let run = rurl_env(
+        &[
+            "POST",
+            &server.url("/login"),
+            r#"auth:={"token": "$RURL_TOKEN", "cost": "$$5"}"#,
+            "X-User:${RURL_USER}",
+            "--expand-env",
+        ],
+        &[("RURL_TOKEN", "s3cret"), ("RURL_USER", "ezra")],
+    );
+
+    assert!(run.success(), "{:?}", run);
+    let request = server.single();
+    assert_eq!(
+        request.body_text(),
+        r#"{"auth":{"token":"s3cret","cost":"$5"}}"#
+    );
+    assert_eq!(request.header("x-user"), Some("ezra"));
+}
+
+#[test]
+fn output_decodes_gzip_unless_no_decompress() {
+    let body = "compressible ".repeat(1000);
+    let compressed = rurl::encoding::gzip(body.as_bytes());
+    let server = MockServer::reply(
+        Reply::text("")
+            .header("Content-Encoding", "gzip")
+            .body(&compressed),
+    );
+    let dir = scratch_dir();
+
+    let decoded = dir.join("decoded.txt");
+    let run = rurl(&["GET", &server.url("/"), "-o", decoded.to_str().unwrap()]);
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(std::fs::read_to_string(&decoded).unwrap(), body);
+
+    let kept = dir.join("kept.txt.gz");
+    let run = rurl(&[
+        "GET",
+        &server.url("/"),
+        "-o",
+        kept.to_str().unwrap(),
+        "--no-decompress",
+    ]);
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(std::fs::read(&kept).unwrap(), compressed);
+    assert!(run.stderr.contains(&format!(
+        "kept {} bytes of gzip as sent, {} bytes decoded",
+        compressed.len(),
+        body.len()
+    )));
+}
+
+#[test]
+fn checksum_checks_the_written_file() {
+    let server = MockServer::reply(Reply::text("release"));
+    let dir = scratch_dir();
+    let path = dir.join("release.txt");
+    let sha256 = rurl::digest::hex(&rurl::digest::sha256(b"release"));
+
+    let run = rurl(&[
+        "GET",
+        &server.url("/"),
+        "-o",
+        path.to_str().unwrap(),
+        "--checksum",
+        &format!("sha256:{}", sha256),
+    ]);
+    assert!(run.success(), "{:?}", run);
+    assert!(path.exists());
+
+    let run = rurl(&[
+        "GET",
+        &server.url("/"),
+        "-o",
+        path.to_str().unwrap(),
+        "--checksum",
+        &"0".repeat(64),
+    ]);
+    assert!(!run.success());
+    assert!(run.stderr.contains("checksum mismatch"));
+    assert!(!path.exists());
+}
+
+#[test]
+fn max_body_stops_large_responses() {
+    let server = MockServer::reply(Reply::text(&"x".repeat(2048)));
+
+    let run = rurl(&["GET", &server.url("/big"), "--max-body", "1k"]);
+    assert_eq!(run.code, Some(63), "{:?}", run);
+    assert!(run.stderr.contains("larger than --max-body 1024 bytes"));
+    assert!(!run.stdout.contains("xxx"));
+
+    let run = rurl(&["GET", &server.url("/big"), "--max-body", "2k"]);
+    assert!(run.success(), "{:?}", run);
+}
+
+#[test]
+fn speed_limit_gives_up_on_stalled_responses() {
+    use std::io::{BufRead as _, BufReader, Write as _};
+
+    // sends part of the body, then nothing more
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/slow", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut head = String::new();
+
+        while !head.ends_with("\r\n\r\n") {
+            if reader.read_line(&mut head).unwrap() == 0 {
+                break;
+            }
+        }
+
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 100\r\n\r\nstarted")
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_secs(10));
+    });
+
+    let start = std::time::Instant::now();
+    let run = rurl(&["GET", &url, "--speed-limit", "1k", "--speed-time", "1"]);
+
+    assert_eq!(run.code, Some(28), "{:?}", run);
+    assert!(run
+        .stderr
+        .contains("slower than 1024 bytes per second for 1s"));
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+}
+
+#[test]
+fn limit_rate_slows_uploads_and_downloads() {
+    let server = MockServer::reply(Reply::text(&"y".repeat(2048)));
+    let note = format!("note={}", "x".repeat(2000));
+
+    let start = std::time::Instant::now();
+    let run = rurl(&["POST", &server.url("/notes"), &note, "--limit-rate", "4k"]);
+
+    // about half a second each way at 4 KiB/s
+    assert!(run.success(), "{:?}", run);
+    assert!(start.elapsed() >= std::time::Duration::from_millis(800));
+    assert_eq!(
+        server.single().body_text(),
+        format!(r#"{{"note":"{}"}}"#, "x".repeat(2000))
+    );
+    assert_eq!(run.stdout.trim_end(), "y".repeat(2048));
+}
+
+#[test]
+fn raw_response_copies_the_bytes_as_received() {
+    let server = MockServer::reply(Reply::text("hello wire").header("X-Raw", "1"));
+
+    let run = rurl(&["GET", &server.url("/"), "--raw-response", "-"]);
+    assert!(run.success(), "{:?}", run);
+    assert!(run.stdout.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", run);
+    assert!(run.stdout.contains("X-Raw: 1\r\n"));
+    assert!(run.stdout.ends_with("\r\n\r\nhello wire"));
+
+    let path = scratch_dir().join("raw-response");
+    let run = rurl(&[
+        "GET",
+        &server.url("/"),
+        "--raw-response",
+        path.to_str().unwrap(),
+    ]);
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout.trim_end(), "hello wire");
+    assert!(std::fs::read_to_string(&path)
+        .unwrap()
+        .ends_with("\r\n\r\nhello wire"));
+}
+
+#[test]
+fn hmac_auth_signs_the_request() {
+    let server = MockServer::reply(Reply::text("ok"));
+    let run = rurl(&[
+        "--auth-type",
+        "hmac",
+        "--hmac-key",
+        "secret",
+        "POST",
+        &server.url("/orders?id=1"),
+        "X-Date:20240101T000000Z",
+        "sku=a1",
+    ]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(
+        server.single().header("authorization"),
+        Some("HMAC-SHA256 cd01d47c7709610a0ebe0a76f54ba3a70710b65a9b7a81a3da597bd433b49d83")
+    );
+}
+
+#[test]
+fn plugins_authenticate_and_format() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let dir = scratch_dir();
+    let plugins = [
+        (
+            "rurl-auth-stamp",
+            "#!/bin/sh\ncat > \"$(dirname \"$0\")/auth-input\"\n\
+             echo '{\"headers\": {\"X-Stamp\": [\"a\", \"b\"], \"X-Drop\": null}}'\n",
+        ),
+        (
+            "rurl-format-shout",
+            "#!/bin/sh\ncat > \"$(dirname \"$0\")/format-input\"\necho FORMATTED\n",
+        ),
+    ];
+    for (name, script) in &plugins {
+        let path = dir.join(name);
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let server = MockServer::reply(Reply::text("quiet"));
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap());
+    let run = rurl_env(
+        &[
+            "--auth-type",
+            "stamp",
+            "--formatter",
+            "shout",
+            "POST",
+            &server.url("/orders"),
+            "X-Drop:1",
+            "sku=a1",
+        ],
+        &[("PATH", &path)],
+    );
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout.trim_end(), "FORMATTED");
+
+    let request = server.single();
+    let stamps: Vec<_> = request
+        .headers
+        .iter()
+        .filter(|(name, _)| name == "x-stamp")
+        .map(|(_, value)| value.as_str())
+        .collect();
+    assert_eq!(stamps, ["a", "b"]);
+    assert_eq!(request.header("x-drop"), None);
+
+    let auth = json::parse(&std::fs::read_to_string(dir.join("auth-input")).unwrap()).unwrap();
+    assert_eq!(auth["request"]["method"], "POST");
+    assert_eq!(auth["request"]["body"], r#"{"sku":"a1"}"#);
+
+    let format = json::parse(&std::fs::read_to_string(dir.join("format-input")).unwrap()).unwrap();
+    assert_eq!(format["response"]["status"], 200);
+    assert_eq!(format["response"]["body"], "quiet");
+
+    let run = rurl_env(&["--version", "--verbose"], &[("PATH", &path)]);
+    assert!(run.success(), "{:?}", run);
+    assert!(
+        run.stdout.contains("rurl-auth-stamp, rurl-format-shout"),
+        "{}",
+        run.stdout
+    );
+}
+
+fn test_ca() -> String {
+    concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/ca.pem").to_owned()
+}
+
+#[test]
+fn trace_prints_the_tls_session_and_retries() {
+    let attempts = std::sync::atomic::AtomicUsize::new(0);
+    let server = MockServer::start_tls(move |_| {
+        match attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+            0 => Reply::new(503),
+            _ => Reply::text("secure"),
+        }
+    });
+
+    let run = rurl_env(
+        &["GET", &server.url("/"), "--trace", "--retries", "1"],
+        &[("SSL_CERT_FILE", &test_ca())],
+    );
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout.trim_end(), "secure");
+    assert!(
+        run.stderr
+            .contains("* TLS connection using TLSv1_3 / TLS13_"),
+        "{:?}",
+        run
+    );
+    assert!(
+        run.stderr.contains("* ALPN: server accepted http/1.1"),
+        "{:?}",
+        run
+    );
+    assert!(
+        run.stderr.contains("* retry 1 of GET https://localhost:"),
+        "{:?}",
+        run
+    );
+    assert!(run.stderr.contains("* using HTTP/1.1"), "{:?}", run);
+}
+
+#[test]
+fn https_requests_log_session_keys() {
+    let server = MockServer::start_tls(|_| Reply::text("secure"));
+    let keylog = scratch_dir().join("keys.log");
+
+    let run = rurl_env(
+        &[
+            "--keylog-file",
+            keylog.to_str().unwrap(),
+            "GET",
+            &server.url("/private"),
+        ],
+        &[("SSL_CERT_FILE", &test_ca())],
+    );
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout.trim_end(), "secure");
+    assert_eq!(server.single().target, "/private");
+
+    let keys = std::fs::read_to_string(&keylog).unwrap();
+    assert!(!keys.is_empty());
+    for line in keys.lines() {
+        let fields: Vec<_> = line.split(' ').collect();
+        assert_eq!(fields.len(), 3, "{}", line);
+        assert_eq!(fields[1].len(), 64, "{}", line);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let mode = std::fs::metadata(&keylog).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn untrusted_certificates_fail() {
+    let server = MockServer::start_tls(|_| Reply::text("secure"));
+    let run = rurl(&["GET", &server.url("/")]);
+
+    assert!(!run.success(), "{:?}", run);
+    assert!(server.received().is_empty());
+}
+
+#[test]
+fn tls_info_shows_the_session_and_certificate() {
+    let server = MockServer::start_tls(|_| Reply::text("secure"));
+    let run = rurl_env(
+        &["--tls-info", "GET", &server.url("/")],
+        &[("SSL_CERT_FILE", &test_ca())],
+    );
+
+    assert!(run.success(), "{:?}", run);
+    assert!(
+        run.stderr.contains("tls        TLSv1_3\n"),
+        "{}",
+        run.stderr
+    );
+    assert!(
+        run.stderr.contains("alpn       http/1.1\n"),
+        "{}",
+        run.stderr
+    );
+    assert!(
+        run.stderr
+            .contains("issuer     O=rurl tests, CN=rurl test CA\n"),
+        "{}",
+        run.stderr
+    );
+    assert!(run.stderr.contains("names      localhost, 127.0.0.1\n"));
+    assert!(!run.stderr.contains("warning"));
+}
+
+#[test]
+fn pinnedpubkey_accepts_only_the_pinned_key() {
+    let server = MockServer::start_tls(|_| Reply::text("secure"));
+    let ca_file = test_ca();
+    let ca = [("SSL_CERT_FILE", ca_file.as_str())];
+    let pinned = "sha256//x0zdUa1BCNXvSnXz/X/5cGHJEiTHdwlAfH0XHPYOoDY=";
+    let key_file = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/localhost.pub.pem");
+
+    for pin in &[
+        pinned,
+        &format!("sha256//{};{}", "A".repeat(43) + "=", pinned),
+        key_file,
+    ] {
+        let run = rurl_env(&["--pinnedpubkey", pin, "GET", &server.url("/")], &ca);
+        assert!(run.success(), "{}: {:?}", pin, run);
+        assert_eq!(run.stdout.trim_end(), "secure");
+    }
+
+    let wrong = format!("sha256//{}", "A".repeat(43) + "=");
+    let run = rurl_env(&["--pinnedpubkey", &wrong, "GET", &server.url("/")], &ca);
+    assert!(!run.success(), "{:?}", run);
+    assert!(
+        run.stderr.contains(&format!("{} is not pinned", pinned)),
+        "{}",
+        run.stderr
+    );
+    assert_eq!(server.received().len(), 3);
+}
+
+#[test]
+fn preflight_sends_the_cors_request_headers_and_reports_the_answer() {
+    let server = MockServer::reply(
+        Reply::new(204)
+            .header("Access-Control-Allow-Origin", "https://app.test")
+            .header("Access-Control-Allow-Methods", "GET, PUT")
+            .header("Access-Control-Allow-Headers", "Content-Type"),
+    );
+    let url = server.url("/items");
+
+    let run = rurl(&[
+        "OPTIONS",
+        &url,
+        "--preflight",
+        "origin=https://app.test,method=PUT,headers=Content-Type",
+    ]);
+    let request = server.single();
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(request.method, "OPTIONS");
+    assert_eq!(request.header("origin"), Some("https://app.test"));
+    assert_eq!(request.header("access-control-request-method"), Some("PUT"));
+    assert_eq!(
+        request.header("access-control-request-headers"),
+        Some("content-type")
+    );
+    assert!(run
+        .stderr
+        .contains("preflight          PUT from https://app.test is allowed"));
+
+    let run = rurl(&[
+        "OPTIONS",
+        &url,
+        "--preflight",
+        "origin=https://evil.test,method=DELETE,headers=X-Token",
+    ]);
+    assert!(run.success(), "{:?}", run);
+    assert!(run.stderr.contains(
+        "preflight          denied: origin https://evil.test, method DELETE, header x-token"
+    ));
+
+    let run = rurl(&["GET", &url, "--preflight", "origin=https://app.test"]);
+    assert!(!run.success());
+    assert!(run
+        .stderr
+        .contains("--preflight sends an OPTIONS request, not GET"));
+    assert_eq!(server.received().len(), 2);
+}
+
+#[test]
+fn no_keepalive_opens_a_connection_per_request() {
+    let pooled = MockServer::reply(Reply::text("ok"));
+    let run = rurl(&["GET", &pooled.url("/"), "--repeat", "3"]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(pooled.received().len(), 3);
+    assert_eq!(pooled.connections(), 1);
+
+    let fresh = MockServer::reply(Reply::text("ok"));
+    let run = rurl(&["GET", &fresh.url("/"), "--repeat", "3", "--no-keepalive"]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(fresh.received().len(), 3);
+    assert_eq!(fresh.connections(), 3);
+
+    let run = rurl(&["GET", &fresh.url("/"), "--keepalive-timeout", "soon"]);
+    assert!(!run.success());
+    assert!(run.stderr.contains("keepalive-timeout"), "{}", run.stderr);
+}
+
+#[test]
+fn preconnect_reports_the_setup_time() {
+    let server = MockServer::reply(Reply::text("warm"));
+    let run = rurl(&["--preconnect", "GET", &server.url("/")]);
+
+    assert!(run.success(), "{:?}", run);
+    assert!(run.stderr.contains("preconnected: "), "{}", run.stderr);
+    assert_eq!(run.stdout.trim_end(), "warm");
+    assert_eq!(server.single().target, "/");
+
+    let run = rurl(&["--preconnect", "GET", "http://127.0.0.1:1/"]);
+    assert!(!run.success());
+    assert!(!run.stderr.contains("preconnected: "));
+}
+
+#[test]
+fn hosts_file_resolves_names_before_dns() {
+    let server = MockServer::reply(Reply::text("resolved"));
+    let port = server.url("").rsplit(':').next().unwrap().to_owned();
+    let hosts = scratch_file("hosts", b"# for tests\n127.0.0.1 api.rurl.test\n");
+    let url = format!("http://API.rurl.test:{}/", port);
+
+    let run = rurl(&["--hosts-file", hosts.to_str().unwrap(), "GET", &url]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout.trim_end(), "resolved");
+    assert_eq!(
+        server.single().header("host"),
+        Some(&*format!("API.rurl.test:{}", port))
+    );
+
+    let hosts = scratch_file("hosts", b"127.0.0.1 api.rurl.test\nlocalhost other.test\n");
+    let run = rurl(&["--hosts-file", hosts.to_str().unwrap(), "GET", &url]);
+
+    assert!(!run.success());
+    assert!(
+        run.stderr.contains(":2: invalid address localhost"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn doh_url_resolves_names_over_https() {
+    use trust_dns_resolver::proto::{
+        op::{Message, MessageType},
+        rr::{RData, Record, RecordType},
+    };
+
+    let doh = MockServer::start_tls(|request| {
+        let mut message = Message::from_vec(&request.body).unwrap();
+        let query = message.queries()[0].clone();
+        message.set_message_type(MessageType::Response);
+        if query.query_type() == RecordType::A {
+            let address = RData::A("127.0.0.1".parse().unwrap());
+            message.add_answer(Record::from_rdata(query.name().clone(), 60, address));
+        }
+
+        Reply::new(200)
+            .header("content-type", "application/dns-message")
+            .body(&message.to_vec().unwrap())
+    });
+    let server = MockServer::reply(Reply::text("resolved"));
+    let port = server.url("").rsplit(':').next().unwrap().to_owned();
+
+    let run = rurl_env(
+        &[
+            "--doh-url",
+            &doh.url("/dns-query"),
+            "GET",
+            &format!("http://api.rurl.test:{}/", port),
+        ],
+        &[("SSL_CERT_FILE", &test_ca())],
+    );
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(run.stdout.trim_end(), "resolved");
+    assert_eq!(
+        server.single().header("host"),
+        Some(&*format!("api.rurl.test:{}", port))
+    );
+
+    let queries = doh.received();
+    assert_eq!(queries.len(), 2);
+    assert!(queries.iter().all(|query| query.method == "POST"
+        && query.header("content-type") == Some("application/dns-message")));
+}
+
+#[test]
+fn doctor_finds_tokens_others_can_read() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let cache = scratch_dir();
+    let tokens = cache.join("oauth2");
+    std::fs::create_dir_all(&tokens).unwrap();
+    let token = tokens.join("cached.json");
+    std::fs::write(&token, r#"{"access_token": "t", "expires_at": 0}"#).unwrap();
+    std::fs::set_permissions(&token, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+    let env = [("RURL_CACHE_DIR", cache.to_str().unwrap())];
+    let run = rurl_env(&["doctor"], &env);
+    assert!(
+        run.stdout
+            .contains("ok     keyring    1 token and history files, private to you"),
+        "{}",
+        run.stdout
+    );
+
+    std::fs::set_permissions(&token, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let run = rurl_env(&["doctor"], &env);
+    assert!(!run.success());
+    assert!(
+        run.stdout.contains(&format!(
+            "error  keyring    {} is readable by other users",
+            token.display()
+        )),
+        "{}",
+        run.stdout
+    );
+}
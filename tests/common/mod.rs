@@ -0,0 +1,386 @@
+//! A mock HTTP server and a runner for the compiled binary, shared by the
+//! integration tests.
+
+#![allow(dead_code)]
+
+use hyper::StatusCode;
+use rustls::{internal::pemfile, NoClientAuth, ServerConfig, ServerSession, StreamOwned};
+use std::{
+    io::{BufRead as _, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// A request as the mock server received it.
+#[derive(Clone, Debug)]
+pub struct Received {
+    pub method: String,
+    /// The path and query.
+    pub target: String,
+    /// Lowercase names, in the order sent.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Received {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn body_text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// A canned response.
+#[derive(Clone, Debug)]
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Reply {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn text(body: &str) -> Self {
+        Self::new(200)
+            .header("Content-Type", "text/plain")
+            .body(body.as_bytes())
+    }
+
+    pub fn json(body: &str) -> Self {
+        Self::new(200)
+            .header("Content-Type", "application/json")
+            .body(body.as_bytes())
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: &[u8]) -> Self {
+        self.body = body.to_vec();
+        self
+    }
+}
+
+type Handler = dyn Fn(&Received) -> Reply + Send + Sync;
+
+/// Serves every connection on a background thread, answering each
+/// request with what `handler` returns and recording it.
+pub struct MockServer {
+    address: SocketAddr,
+    received: Arc<Mutex<Vec<Received>>>,
+    connections: Arc<AtomicUsize>,
+    tls: bool,
+}
+
+impl MockServer {
+    pub fn start(handler: impl Fn(&Received) -> Reply + Send + Sync + 'static) -> Self {
+        Self::listen(handler, None)
+    }
+
+    /// Serves https as `localhost`, with the certificate in tests/tls, which
+    /// is trusted with `SSL_CERT_FILE=tests/tls/ca.pem`.
+    pub fn start_tls(handler: impl Fn(&Received) -> Reply + Send + Sync + 'static) -> Self {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/tls");
+        let read = |name| BufReader::new(std::fs::File::open(dir.join(name)).unwrap());
+
+        let certs = pemfile::certs(&mut read("localhost.pem")).unwrap();
+        let key = pemfile::pkcs8_private_keys(&mut read("localhost.key")).unwrap();
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.set_single_cert(certs, key[0].clone()).unwrap();
+        config.set_protocols(&[b"http/1.1".to_vec()]);
+
+        Self::listen(handler, Some(Arc::new(config)))
+    }
+
+    fn listen(
+        handler: impl Fn(&Received) -> Reply + Send + Sync + 'static,
+        tls: Option<Arc<ServerConfig>>,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let https = tls.is_some();
+
+        let log = received.clone();
+        let accepted = connections.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let (stream, log, handler) = match stream {
+                    Ok(stream) => (stream, log.clone(), handler.clone()),
+                    Err(_) => break,
+                };
+                accepted.fetch_add(1, Ordering::SeqCst);
+
+                let tls = tls.clone();
+                std::thread::spawn(move || match tls {
+                    Some(config) => {
+                        let session = ServerSession::new(&config);
+                        serve(StreamOwned::new(session, stream), &log, &*handler)
+                    }
+                    None => serve(stream, &log, &*handler),
+                });
+            }
+        });
+
+        Self {
+            address,
+            received,
+            connections,
+            tls: https,
+        }
+    }
+
+    /// Answers every request with the same reply.
+    pub fn reply(reply: Reply) -> Self {
+        Self::start(move |_| reply.clone())
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        if self.tls {
+            // the certificate names localhost, and rustls checks names only
+            format!("https://localhost:{}{}", self.address.port(), path)
+        } else {
+            format!("http://{}{}", self.address, path)
+        }
+    }
+
+    pub fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// How many connections were accepted.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// The only request received, failing the test otherwise.
+    pub fn single(&self) -> Received {
+        let received = self.received();
+        assert_eq!(
+            received.len(),
+            1,
+            "expected one request, got {:?}",
+            received
+        );
+        received.into_iter().next().unwrap()
+    }
+}
+
+fn read_request(reader: &mut BufReader<impl Read>) -> Option<Received> {
+    let mut line = String::new();
+    reader.read_line(&mut line).ok().filter(|read| *read > 0)?;
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_owned();
+    let target = parts.next()?.to_owned();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            break;
+        }
+
+        let colon = line.find(':')?;
+        headers.push((
+            line[..colon].trim().to_ascii_lowercase(),
+            line[colon + 1..].trim().to_owned(),
+        ));
+    }
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+
+    Some(Received {
+        method,
+        target,
+        headers,
+        body,
+    })
+}
+
+fn serve(stream: impl Read + Write, log: &Mutex<Vec<Received>>, handler: &Handler) {
+    let mut reader = BufReader::new(stream);
+
+    // keep the connection open, so pooled connections behave as usual
+    while let Some(request) = read_request(&mut reader) {
+        let reply = handler(&request);
+        let head_only = request.method == "HEAD";
+        log.lock().unwrap().push(request);
+
+        let mut response = format!(
+            "HTTP/1.1 {} {}\r\ncontent-length: {}\r\n",
+            reply.status,
+            StatusCode::from_u16(reply.status)
+                .ok()
+                .and_then(|status| status.canonical_reason())
+                .unwrap_or_default(),
+            reply.body.len()
+        );
+        for (name, value) in &reply.headers {
+            response += &format!("{}: {}\r\n", name, value);
+        }
+        response += "\r\n";
+
+        let mut bytes = response.into_bytes();
+        if !head_only {
+            bytes.extend_from_slice(&reply.body);
+        }
+
+        let writer = reader.get_mut();
+        if writer
+            .write_all(&bytes)
+            .and_then(|_| writer.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// A finished run of the binary, with color codes removed from its output
+/// so assertions see the text.
+#[derive(Debug)]
+pub struct Run {
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Run {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+fn plain(output: &[u8]) -> String {
+    let text = String::from_utf8_lossy(output);
+    let mut plain = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+
+    plain
+}
+
+/// Writes a file for a run to read, e.g. for `@file` items.
+pub fn scratch_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = scratch_dir().join(name);
+    std::fs::write(&path, contents).unwrap();
+
+    path
+}
+
+/// A directory of its own for each run's config, data, and cache.
+pub fn scratch_dir() -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!(
+        "run-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+/// Runs the binary with `args`, isolated from the user's configuration,
+/// variables, and history.
+pub fn rurl(args: &[&str]) -> Run {
+    run(env!("CARGO_BIN_EXE_rurl"), args)
+}
+
+/// Runs the https-by-default `rurls` alias the same way.
+pub fn rurls(args: &[&str]) -> Run {
+    run(env!("CARGO_BIN_EXE_rurls"), args)
+}
+
+/// Runs the binary with `args` and the environment variables in `env`.
+pub fn rurl_env(args: &[&str], env: &[(&str, &str)]) -> Run {
+    run_env(env!("CARGO_BIN_EXE_rurl"), args, env)
+}
+
+/// Runs the binary with `args` for `duration`, then stops it, for modes
+/// like `--watch` that run until interrupted.
+pub fn rurl_for(args: &[&str], duration: Duration) -> Run {
+    let dir = scratch_dir();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rurl"))
+        .args(args)
+        .env("RURL_CONFIG_DIR", dir.join("config"))
+        .env("RURL_DATA_DIR", dir.join("data"))
+        .env("RURL_CACHE_DIR", dir.join("cache"))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    std::thread::sleep(duration);
+    let _ = child.kill();
+    let output = child.wait_with_output().unwrap();
+
+    Run {
+        code: output.status.code(),
+        stdout: plain(&output.stdout),
+        stderr: plain(&output.stderr),
+    }
+}
+
+fn run(exe: &str, args: &[&str]) -> Run {
+    run_env(exe, args, &[])
+}
+
+fn run_env(exe: &str, args: &[&str], env: &[(&str, &str)]) -> Run {
+    let dir = scratch_dir();
+    let output = Command::new(exe)
+        .args(args)
+        .env("RURL_CONFIG_DIR", dir.join("config"))
+        .env("RURL_DATA_DIR", dir.join("data"))
+        .env("RURL_CACHE_DIR", dir.join("cache"))
+        .envs(env.iter().copied())
+        .output()
+        .unwrap();
+
+    Run {
+        code: output.status.code(),
+        stdout: plain(&output.stdout),
+        stderr: plain(&output.stderr),
+    }
+}
@@ -0,0 +1,72 @@
+//! The output contract: the response body on stdout, everything else on
+//! stderr, unless `--all-to-stdout` is given.
+
+mod common;
+
+use common::{rurl, MockServer, Reply};
+
+#[test]
+fn body_goes_to_stdout_and_the_rest_to_stderr() {
+    let server = MockServer::reply(Reply::text("hello streams"));
+    let run = rurl(&["GET", &server.url("/"), "--meta"]);
+
+    assert!(run.success());
+    assert_eq!(run.stdout.trim_end(), "hello streams");
+    assert!(run.stderr.contains("GET / HTTP/1.1"));
+    assert!(run.stderr.contains("HTTP/1.1 200 OK"));
+    assert!(run.stderr.contains("ttfb:"));
+    assert!(!run.stderr.contains("hello streams"));
+}
+
+#[test]
+fn all_to_stdout_leaves_stderr_empty() {
+    let server = MockServer::reply(Reply::text("hello streams"));
+    let run = rurl(&["GET", &server.url("/"), "--meta", "--all-to-stdout"]);
+
+    assert!(run.success());
+    assert!(run.stdout.contains("GET / HTTP/1.1"));
+    assert!(run.stdout.contains("HTTP/1.1 200 OK"));
+    assert!(run.stdout.contains("hello streams"));
+    assert!(run.stdout.contains("ttfb:"));
+    assert_eq!(run.stderr, "");
+}
+
+#[test]
+fn all_to_stdout_covers_redirects() {
+    let server = MockServer::start(|req| match req.target.as_str() {
+        "/a" => Reply::new(302).header("Location", "/b"),
+        _ => Reply::text("arrived"),
+    });
+    let run = rurl(&["GET", &server.url("/a"), "--follow", "--all-to-stdout"]);
+
+    assert!(run.success(), "{:?}", run);
+    assert!(run.stdout.contains(" 1. 302 "), "{:?}", run);
+    assert!(run.stdout.contains("arrived"));
+    assert_eq!(run.stderr, "");
+}
+
+#[test]
+fn all_to_stdout_covers_retries() {
+    let server = MockServer::reply(Reply::new(503));
+    let run = rurl(&["GET", &server.url("/"), "--retries", "1", "--all-to-stdout"]);
+
+    assert!(run.success(), "{:?}", run);
+    assert_eq!(server.received().len(), 2);
+    assert!(run.stdout.contains("retrying after 503"), "{:?}", run);
+    assert_eq!(run.stderr, "");
+}
+
+#[test]
+fn all_to_stdout_covers_server_sent_events() {
+    let server = MockServer::reply(
+        Reply::new(200)
+            .header("Content-Type", "text/event-stream")
+            .body(b"event: tick\nid: 1\ndata: {\"n\": 1}\n\n"),
+    );
+    let run = rurl(&["GET", &server.url("/events"), "--all-to-stdout"]);
+
+    assert!(run.success(), "{:?}", run);
+    assert!(run.stdout.contains("event: tick"), "{:?}", run);
+    assert!(run.stdout.contains("\"n\": 1"));
+    assert_eq!(run.stderr, "");
+}